@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http::{header, Request};
+use http_body::Body as _;
+use http_dir::fs::disk::DiskFilesystem;
+use http_dir::ServeDir;
+use hyper::Body;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+const LARGE_FILE_LEN: usize = 16 * 1024 * 1024;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+// read the whole body, like the server writing it to the socket
+async fn serve(svc: ServeDir<DiskFilesystem>, req: Request<Body>) -> usize {
+    let mut body = svc.oneshot(req).await.unwrap().into_body();
+    let mut len = 0;
+    while let Some(data) = body.data().await {
+        len += data.unwrap().len();
+    }
+
+    len
+}
+
+fn large_file_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("http_dir-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let content = (0..LARGE_FILE_LEN).map(|i| i as u8).collect::<Vec<_>>();
+    std::fs::write(dir.join("large.bin"), content).unwrap();
+
+    dir
+}
+
+fn small_file(c: &mut Criterion) {
+    let runtime = runtime();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+
+    c.bench_function("small_file", |b| {
+        b.to_async(&runtime).iter(|| {
+            let req = Request::get("/index.html").body(Body::empty()).unwrap();
+            serve(svc.clone(), req)
+        })
+    });
+
+    #[cfg(unix)]
+    {
+        let svc = ServeDir::new(DiskFilesystem::from("test-files").positional_reads(true));
+
+        c.bench_function("small_file_positional_reads", |b| {
+            b.to_async(&runtime).iter(|| {
+                let req = Request::get("/index.html").body(Body::empty()).unwrap();
+                serve(svc.clone(), req)
+            })
+        });
+    }
+}
+
+fn large_file(c: &mut Criterion) {
+    let runtime = runtime();
+    let dir = large_file_dir();
+
+    let mut group = c.benchmark_group("large_file");
+    group.throughput(Throughput::Bytes(LARGE_FILE_LEN as u64));
+    for chunk_size in [16 * 1024, 64 * 1024, 256 * 1024] {
+        let svc =
+            ServeDir::new(DiskFilesystem::from(dir.as_path())).with_buf_chunk_size(chunk_size);
+
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &svc, |b, svc| {
+            b.to_async(&runtime).iter(|| {
+                let req = Request::get("/large.bin").body(Body::empty()).unwrap();
+                serve(svc.clone(), req)
+            })
+        });
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn range(c: &mut Criterion) {
+    let runtime = runtime();
+    let dir = large_file_dir();
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()));
+
+    let mut group = c.benchmark_group("range");
+    for (name, range) in [
+        ("head", "bytes=0-1023"),
+        ("middle", "bytes=8000000-8999999"),
+        ("suffix", "bytes=-4096"),
+    ] {
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| {
+                let req = Request::get("/large.bin")
+                    .header(header::RANGE, range)
+                    .body(Body::empty())
+                    .unwrap();
+                serve(svc.clone(), req)
+            })
+        });
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, small_file, large_file, range);
+criterion_main!(benches);
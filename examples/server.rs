@@ -0,0 +1,16 @@
+use http_dir::fs::disk::DiskFilesystem;
+use http_dir::server::Server;
+use http_dir::ServeDir;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let service = ServeDir::new(DiskFilesystem::from("examples"));
+
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], 3000));
+    Server::bind(addr)
+        .serve(service, async {
+            tokio::signal::ctrl_c().await.expect("wait ctrl-c failed");
+        })
+        .await
+        .expect("server error");
+}
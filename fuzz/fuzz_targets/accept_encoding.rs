@@ -0,0 +1,35 @@
+#![no_main]
+
+use http::{header, Request, StatusCode};
+use http_body::Empty;
+use http_dir_fuzz::FILES;
+use libfuzzer_sys::fuzz_target;
+
+// the Accept-Encoding negotiation of the precompressed variants
+fuzz_target!(|input: (u8, &str)| {
+    let (file, accept_encoding) = input;
+    let req = Request::builder()
+        .uri(FILES[file as usize % FILES.len()])
+        .header(header::ACCEPT_ENCODING, accept_encoding)
+        .body(Empty::new());
+    let Ok(req) = req else {
+        return;
+    };
+
+    let (parts, _) = http_dir_fuzz::serve(req);
+    if parts.status != StatusCode::OK {
+        return;
+    }
+
+    if let Some(encoding) = parts.headers.get(header::CONTENT_ENCODING) {
+        let encoding = encoding.to_str().unwrap();
+        assert!(
+            ["gzip", "br", "deflate"].contains(&encoding),
+            "unexpected encoding {encoding}"
+        );
+        assert!(
+            accept_encoding.contains(encoding) || accept_encoding.contains('*'),
+            "{encoding} isn't accepted by {accept_encoding}"
+        );
+    }
+});
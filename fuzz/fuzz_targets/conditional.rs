@@ -0,0 +1,48 @@
+#![no_main]
+
+use http::{header, HeaderName, Request, StatusCode};
+use http_body::Empty;
+use http_dir_fuzz::FILES;
+use libfuzzer_sys::fuzz_target;
+
+const CONDITIONAL_HEADERS: [HeaderName; 5] = [
+    header::IF_MODIFIED_SINCE,
+    header::IF_UNMODIFIED_SINCE,
+    header::IF_NONE_MATCH,
+    header::IF_MATCH,
+    header::IF_RANGE,
+];
+
+// the conditional headers, combined with the Range
+fuzz_target!(|input: (u8, [&str; 5], Option<&str>)| {
+    let (file, values, range) = input;
+    let mut req = Request::builder().uri(FILES[file as usize % FILES.len()]);
+    for (name, value) in CONDITIONAL_HEADERS.into_iter().zip(values) {
+        if !value.is_empty() {
+            req = req.header(name, value);
+        }
+    }
+    if let Some(range) = range {
+        req = req.header(header::RANGE, range);
+    }
+    let Ok(req) = req.body(Empty::new()) else {
+        return;
+    };
+
+    let (parts, body) = http_dir_fuzz::serve(req);
+    assert!(
+        [
+            StatusCode::OK,
+            StatusCode::PARTIAL_CONTENT,
+            StatusCode::NOT_MODIFIED,
+            StatusCode::PRECONDITION_FAILED,
+            StatusCode::RANGE_NOT_SATISFIABLE,
+        ]
+        .contains(&parts.status),
+        "unexpected status {}",
+        parts.status
+    );
+    if parts.status == StatusCode::NOT_MODIFIED {
+        assert!(body.is_empty());
+    }
+});
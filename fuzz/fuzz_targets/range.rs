@@ -0,0 +1,41 @@
+#![no_main]
+
+use http::{header, Request, StatusCode};
+use http_body::Empty;
+use http_dir_fuzz::FILES;
+use libfuzzer_sys::fuzz_target;
+
+// the Range parsing, and the partial responses built from it
+fuzz_target!(|input: (u8, &str)| {
+    let (file, range) = input;
+    let req = Request::builder()
+        .uri(FILES[file as usize % FILES.len()])
+        .header(header::RANGE, range)
+        .body(Empty::new());
+    let Ok(req) = req else {
+        return;
+    };
+
+    let (parts, body) = http_dir_fuzz::serve(req);
+    match parts.status {
+        StatusCode::PARTIAL_CONTENT => {
+            let content_type = parts.headers[header::CONTENT_TYPE].to_str().unwrap();
+            if !content_type.starts_with("multipart/byteranges") {
+                let content_range = parts.headers[header::CONTENT_RANGE].to_str().unwrap();
+                let (start, end) = content_range
+                    .strip_prefix("bytes ")
+                    .and_then(|range| range.split_once('/'))
+                    .and_then(|(range, _)| range.split_once('-'))
+                    .unwrap();
+                let start = start.parse::<usize>().unwrap();
+                let end = end.parse::<usize>().unwrap();
+                assert_eq!(body.len(), end - start + 1, "{content_range}");
+            }
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            let content_range = parts.headers[header::CONTENT_RANGE].to_str().unwrap();
+            assert!(content_range.starts_with("bytes */"), "{content_range}");
+        }
+        status => assert_eq!(status, StatusCode::OK),
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use bytes::Bytes;
+use http::{Method, Request, Uri};
+use http_body::Empty;
+use libfuzzer_sys::fuzz_target;
+
+// the path decoding and validation, and the directory redirects
+fuzz_target!(|input: (bool, &[u8])| {
+    let (head, path) = input;
+    let mut uri = b"/".to_vec();
+    uri.extend_from_slice(path);
+    let Ok(uri) = Uri::from_maybe_shared(Bytes::from(uri)) else {
+        return;
+    };
+
+    let method = if head { Method::HEAD } else { Method::GET };
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Empty::new())
+        .unwrap();
+
+    http_dir_fuzz::serve(req);
+});
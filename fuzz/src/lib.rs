@@ -0,0 +1,90 @@
+//! The shared setup of the fuzz targets, the requests are served from an in-memory copy of the
+//! `test-files` directory, so the runs don't touch the disk.
+
+use std::future::poll_fn;
+use std::path::Component;
+
+use bytes::{Bytes, BytesMut};
+use http::response::Parts;
+use http::{header, Method, Request, StatusCode};
+use http_body::{Body, Empty};
+use http_dir::fs::include_dir::IncludeDirFilesystem;
+use http_dir::{ServeDir, ServedFile};
+use include_dir::{include_dir, Dir};
+use tokio::runtime::Runtime;
+use tower_service::Service;
+
+static ROOT: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/../test-files");
+
+/// The files of the `test-files` directory, to pick the requested one from the fuzz input.
+pub const FILES: &[&str] = &[
+    "/index.html",
+    "/precompressed.txt",
+    "/precompressed_br.txt",
+    "/missing_precompressed.txt",
+    "/filename%20with%20space.txt",
+    "/%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt",
+];
+
+thread_local! {
+    static RUNTIME: Runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("build runtime failed");
+}
+
+/// Serve the `req` with all the precompressed variants enabled, and check the invariants which
+/// hold for any request.
+pub fn serve(req: Request<Empty<Bytes>>) -> (Parts, Bytes) {
+    let mut service = ServeDir::new(IncludeDirFilesystem::new(ROOT.clone()))
+        .precompressed_gzip()
+        .precompressed_br()
+        .precompressed_deflate();
+
+    let head = req.method() == Method::HEAD;
+    let (parts, body) = RUNTIME.with(|runtime| {
+        runtime.block_on(async {
+            poll_fn(|cx| Service::<Request<Empty<Bytes>>>::poll_ready(&mut service, cx))
+                .await
+                .expect("ServeDir is infallible");
+            let res = service.call(req).await.expect("ServeDir is infallible");
+            let (parts, mut body) = res.into_parts();
+
+            let mut buf = BytesMut::new();
+            while let Some(data) = body.data().await {
+                buf.extend_from_slice(&data.expect("read the in-memory file failed"));
+            }
+
+            (parts, buf.freeze())
+        })
+    });
+
+    assert_ne!(parts.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    if let Some(location) = parts.headers.get(header::LOCATION) {
+        let location = location.to_str().expect("the location isn't visible ASCII");
+        assert!(
+            !location.starts_with("//"),
+            "the location {location} points at another host"
+        );
+    }
+
+    if let Some(len) = parts.headers.get(header::CONTENT_LENGTH).filter(|_| !head) {
+        let len = len.to_str().unwrap().parse::<usize>().unwrap();
+        assert_eq!(len, body.len(), "the content length doesn't match the body");
+    }
+
+    // the requested path is recorded as is when the file isn't served
+    let served = parts.status.is_success() || parts.status == StatusCode::NOT_MODIFIED;
+    if let Some(served_file) = parts.extensions.get::<ServedFile>().filter(|_| served) {
+        assert!(
+            served_file
+                .path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir)),
+            "{} escapes from the root",
+            served_file.path.display()
+        );
+    }
+
+    (parts, body)
+}
@@ -0,0 +1,263 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io;
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+
+use crate::fs::Filesystem;
+use crate::manifest::RouteManifest;
+
+#[derive(Debug, Default)]
+struct Hashes {
+    // the original path and the hashed one, both relative to the root
+    hashed: HashMap<String, String>,
+    originals: HashMap<String, String>,
+}
+
+/// The content hashed names of the assets of a [`Filesystem`], like `css/site.3f2a1b9c.css` for
+/// `css/site.css`, so the pages can reference the assets by the names changing with the content,
+/// and the assets can be cached forever.
+///
+/// The HTML pages and the precompressed variants aren't renamed. Use
+/// [`AssetHashes::rewrite_html`] to rewrite the references of the pages, or
+/// [`AssetHashes::iter`] to get the mapping for the build tools, and
+/// [`ServeDir::hashed_assets`](crate::ServeDir::hashed_assets) to serve the hashed names.
+///
+/// The clones share the same hashes.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::{AssetHashes, ServeDir};
+/// use http_dir::fs::disk::DiskFilesystem;
+///
+/// # async {
+/// let mut filesystem = DiskFilesystem::from("assets");
+/// let hashes = AssetHashes::build(&mut filesystem).await.expect("hash assets failed");
+///
+/// let page = hashes.rewrite_html("/index.html", r#"<link rel="stylesheet" href="/css/site.css">"#);
+///
+/// let service = ServeDir::new(filesystem).hashed_assets(hashes);
+/// # };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AssetHashes {
+    hashes: Arc<Hashes>,
+}
+
+impl AssetHashes {
+    /// Walk the whole `filesystem` and hash the content of all the assets.
+    ///
+    /// Sub dirs which can't be read are skipped.
+    pub async fn build<FS: Filesystem>(filesystem: &mut FS) -> io::Result<Self> {
+        let manifest = RouteManifest::build(filesystem).await?;
+        let mut hashes = Hashes::default();
+        for (path, _) in manifest.files() {
+            if manifest.is_precompressed_variant(&path) {
+                continue;
+            }
+            let Some(original) = path.to_str().map(|path| path.replace('\\', "/")) else {
+                continue;
+            };
+            if original.ends_with(".html") || original.ends_with(".htm") {
+                continue;
+            }
+
+            let mut file = filesystem.open(&path).await?;
+            let mut hasher = DefaultHasher::new();
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+
+                hasher.write(&buf[..n]);
+            }
+
+            let hashed = hashed_name(&original, hasher.finish());
+            hashes.originals.insert(hashed.clone(), original.clone());
+            hashes.hashed.insert(original, hashed);
+        }
+
+        Ok(Self {
+            hashes: Arc::new(hashes),
+        })
+    }
+
+    /// Get the hashed path of the asset at the `path`, both relative to the root.
+    pub fn hashed(&self, path: &str) -> Option<&str> {
+        self.hashes
+            .hashed
+            .get(path.trim_start_matches('/'))
+            .map(String::as_str)
+    }
+
+    /// Get the original path of the `hashed` path, both relative to the root.
+    pub fn original(&self, hashed: &str) -> Option<&str> {
+        self.hashes
+            .originals
+            .get(hashed.trim_start_matches('/'))
+            .map(String::as_str)
+    }
+
+    /// The original paths and their hashed paths, relative to the root.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.hashes
+            .hashed
+            .iter()
+            .map(|(original, hashed)| (original.as_str(), hashed.as_str()))
+    }
+
+    /// Rewrite the `src` and `href` of the page at the `page_path` referencing the assets to their
+    /// hashed names, the relative references are resolved against the `page_path`, the query and
+    /// the fragment are kept, and the other references are left as is.
+    pub fn rewrite_html(&self, page_path: &str, html: &str) -> String {
+        let page_dir = page_path
+            .trim_start_matches('/')
+            .rsplit_once('/')
+            .map_or("", |(dir, _)| dir);
+
+        let mut out = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some((start, end)) = next_reference(rest) {
+            out.push_str(&rest[..start]);
+            let reference = &rest[start..end];
+            match self.rewrite_reference(page_dir, reference) {
+                Some(rewritten) => out.push_str(&rewritten),
+                None => out.push_str(reference),
+            }
+
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+
+        out
+    }
+
+    fn rewrite_reference(&self, page_dir: &str, reference: &str) -> Option<String> {
+        if reference.starts_with("//") || reference.contains(':') {
+            return None;
+        }
+
+        let path_end = reference.find(['?', '#']).unwrap_or(reference.len());
+        let (path, suffix) = reference.split_at(path_end);
+
+        let mut segments = match path.strip_prefix('/') {
+            Some(_) => vec![],
+            None => page_dir
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .collect(),
+        };
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop()?;
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        let hashed = self.hashed(&segments.join("/"))?;
+        // only the name is changed, so the reference keeps its form
+        let hashed_name = hashed.rsplit('/').next()?;
+        match path.rsplit_once('/') {
+            Some((dir, _)) => Some(format!("{dir}/{hashed_name}{suffix}")),
+            None => Some(format!("{hashed_name}{suffix}")),
+        }
+    }
+}
+
+// the hash is put before the extension, `site.css` is `site.3f2a1b9c.css`
+fn hashed_name(path: &str, hash: u64) -> String {
+    let hash = format!("{:08x}", hash >> 32);
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, path),
+    };
+    let name = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}.{hash}.{extension}"),
+        _ => format!("{name}.{hash}"),
+    };
+
+    match dir {
+        Some(dir) => format!("{dir}/{name}"),
+        None => name,
+    }
+}
+
+// the range of the next quoted value of a `src` or `href` attribute
+fn next_reference(html: &str) -> Option<(usize, usize)> {
+    let bytes = html.as_bytes();
+    for i in 1..bytes.len() {
+        if !bytes[i - 1].is_ascii_whitespace() {
+            continue;
+        }
+
+        for name in [&b"src="[..], b"href="] {
+            let Some(quote) = bytes.get(i + name.len()) else {
+                continue;
+            };
+            if !bytes[i..i + name.len()].eq_ignore_ascii_case(name)
+                || !matches!(quote, b'"' | b'\'')
+            {
+                continue;
+            }
+
+            let start = i + name.len() + 1;
+            let len = html[start..].find(char::from(*quote))?;
+            return Some((start, start + len));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_names() {
+        assert_eq!(
+            hashed_name("site.css", 0x3f2a_1b9c << 32),
+            "site.3f2a1b9c.css"
+        );
+        assert_eq!(
+            hashed_name("js/app.min.js", 1 << 32),
+            "js/app.min.00000001.js"
+        );
+        assert_eq!(hashed_name("LICENSE", 0), "LICENSE.00000000");
+        assert_eq!(hashed_name(".nojekyll", 0), ".nojekyll.00000000");
+    }
+
+    #[test]
+    fn rewrite_html() {
+        let mut hashes = Hashes::default();
+        for (original, hashed) in [("css/site.css", "css/site.1.css"), ("app.js", "app.2.js")] {
+            hashes.hashed.insert(original.into(), hashed.into());
+            hashes.originals.insert(hashed.into(), original.into());
+        }
+        let hashes = AssetHashes {
+            hashes: Arc::new(hashes),
+        };
+
+        let html = r#"<link href="/css/site.css"><script src='../app.js?v=1'></script>
+<a href="site.css">x</a> <img SRC="css/site.css#top"> <a href="https://cdn/app.js"></a>
+<a data-href="/app.js"></a> <a href="../../app.js"></a>"#;
+        assert_eq!(
+            hashes.rewrite_html("/docs/index.html", html),
+            r#"<link href="/css/site.1.css"><script src='../app.2.js?v=1'></script>
+<a href="site.css">x</a> <img SRC="css/site.css#top"> <a href="https://cdn/app.js"></a>
+<a data-href="/app.js"></a> <a href="../../app.js"></a>"#
+        );
+        assert_eq!(
+            hashes.rewrite_html("index.html", r#"<img src="css/site.css#top" src="app.js">"#),
+            r#"<img src="css/site.1.css#top" src="app.2.js">"#
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode};
+#[cfg(feature = "watch")]
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use crate::filter::PathFilter;
+use crate::fs::Filesystem;
+use crate::json;
+use crate::manifest::RouteManifest;
+use crate::serve_dir::{body_from_bytes, empty_body, response_with_status};
+#[cfg(feature = "watch")]
+use crate::watch::FileWatcher;
+use crate::ResponseBody;
+
+#[derive(Debug, Clone)]
+struct Generated {
+    json: Bytes,
+    etag: HeaderValue,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    generated: Option<Generated>,
+    #[cfg(feature = "watch")]
+    events: Option<broadcast::Receiver<String>>,
+}
+
+/// The JSON manifest of the served files, generated on the first request and kept until the
+/// files change, see [`ServeDir::asset_manifest`](crate::ServeDir::asset_manifest).
+///
+/// The clones share the same manifest.
+#[derive(Debug, Clone)]
+pub(crate) struct AssetManifest {
+    path: Arc<str>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl AssetManifest {
+    pub(crate) fn new(path: &str) -> Self {
+        Self {
+            path: format!("/{}", path.trim_start_matches('/')).into(),
+            inner: Default::default(),
+        }
+    }
+
+    pub(crate) fn is_request(&self, path: &str) -> bool {
+        *self.path == *path
+    }
+
+    /// Generate the manifest again when the files in the watched directory change.
+    #[cfg(feature = "watch")]
+    pub(crate) fn watch(&self, watcher: &FileWatcher) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generated = None;
+        inner.events = Some(watcher.subscribe());
+    }
+
+    pub(crate) fn invalidate(&self) {
+        self.inner.lock().unwrap().generated = None;
+    }
+
+    pub(crate) async fn response<FS: Filesystem>(
+        &self,
+        filesystem: &mut FS,
+        filter: &PathFilter,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> io::Result<Response<ResponseBody>> {
+        #[cfg(feature = "watch")]
+        self.inner.lock().unwrap().drain_events();
+        let cached = self.inner.lock().unwrap().generated.clone();
+        let generated = match cached {
+            Some(generated) => generated,
+            None => {
+                let generated = generate(filesystem, filter).await?;
+                self.inner.lock().unwrap().generated = Some(generated.clone());
+
+                generated
+            }
+        };
+
+        let not_modified = headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|etag| etag.trim() == "*" || etag.trim() == generated.etag);
+        let mut res = if not_modified {
+            response_with_status(StatusCode::NOT_MODIFIED)
+        } else {
+            let body = if method == Method::HEAD {
+                empty_body()
+            } else {
+                body_from_bytes(generated.json.clone())
+            };
+            let mut res = Response::new(body);
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            res.headers_mut().insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(generated.json.len()),
+            );
+
+            res
+        };
+        res.headers_mut().insert(header::ETAG, generated.etag);
+        // the clients revalidate it every time, so a changed file is seen right away
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        Ok(res)
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Inner {
+    fn drain_events(&mut self) {
+        let Some(events) = &mut self.events else {
+            return;
+        };
+
+        loop {
+            match events.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => self.generated = None,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Closed) => {
+                    self.events = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// `{"files":{"css/site.css":{"size":1024,"etag":"\"...\"","hash":"sha256-..."}}}`, sorted by
+// the paths, without the filtered files and the precompressed variants
+async fn generate<FS: Filesystem>(
+    filesystem: &mut FS,
+    filter: &PathFilter,
+) -> io::Result<Generated> {
+    let manifest = RouteManifest::build(filesystem).await?;
+    let mut files = manifest
+        .files()
+        .filter(|(path, _)| !manifest.is_precompressed_variant(path))
+        .filter_map(|(path, entry)| Some((path.to_str()?.replace('\\', "/"), entry)))
+        .filter(|(path, _)| filter.is_allowed(path))
+        .collect::<Vec<_>>();
+    files.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut out = String::from("{\"files\":{");
+    for (i, (path, entry)) in files.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        json::write_str(&mut out, path);
+        out.push_str(":{\"size\":");
+        match entry.size() {
+            None => out.push_str("null"),
+            Some(size) => out.push_str(&size.to_string()),
+        }
+        out.push_str(",\"etag\":");
+        match entry.etag() {
+            None => out.push_str("null"),
+            Some(etag) => json::write_str(&mut out, etag),
+        }
+        #[cfg(feature = "content-digest")]
+        {
+            out.push_str(",\"hash\":");
+            let hash =
+                crate::digest::sha256_integrity(filesystem, std::path::Path::new(path)).await?;
+            json::write_str(&mut out, &hash);
+        }
+        out.push('}');
+    }
+    out.push_str("}}");
+
+    let mut hasher = DefaultHasher::new();
+    out.hash(&mut hasher);
+    let etag = HeaderValue::try_from(format!("\"{:016x}\"", hasher.finish()))
+        .expect("the hex etag is a valid header value");
+
+    Ok(Generated {
+        json: Bytes::from(out),
+        etag,
+    })
+}
@@ -1,43 +1,68 @@
 use std::io;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
 
-use bytes::Bytes;
-use futures_util::Stream;
+use bytes::{BufMut, Bytes, BytesMut};
 use http::HeaderMap;
-use http_body::Body;
+use http_body::{Body, SizeHint};
 use pin_project::pin_project;
-use tokio::io::{AsyncRead, AsyncReadExt, Take};
-use tokio_util::io::ReaderStream;
+use tokio::io::AsyncRead;
+use tokio_util::io::poll_read_buf;
 
 // NOTE: This could potentially be upstreamed to `http-body`.
 /// Adapter that turns an `impl AsyncRead` to an `impl Body`.
+///
+/// The body ends with an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) error when the reader
+/// ends before the announced length, like a file truncated while streaming, so the connection
+/// is aborted instead of the response silently ending short. The body of unknown length is read
+/// until the end of the reader.
+///
+/// The size hint of the body of known length is exact, and the empty body is at the end of the
+/// stream without being polled.
+///
+/// The chunks are read into a single buffer, whose allocation is reused once the previous chunks
+/// are written and dropped, and no more than the announced length is read, so a small file only
+/// allocates its own size instead of a full chunk.
+///
+/// The next chunk is read ahead while the previous one is written, and returned right away when
+/// it is ready, so hyper can write the queued chunks by one vectored write.
 #[pin_project]
 #[derive(Debug)]
 pub struct AsyncReadBody<T> {
     #[pin]
-    reader: ReaderStream<T>,
+    reader: T,
+    buf: BytesMut,
+    capacity: usize,
+    remaining: Option<u64>,
+    // the result of the read ahead
+    next: Option<Option<io::Result<Bytes>>>,
 }
 
 impl<T> AsyncReadBody<T>
 where
     T: AsyncRead,
 {
-    /// Create a new [`AsyncReadBody`] wrapping the given reader,
-    /// with a specific read buffer capacity
-    pub(crate) fn with_capacity(read: T, capacity: usize) -> Self {
+    /// Create a new [`AsyncReadBody`] wrapping the given reader, with a specific read buffer
+    /// capacity, reading exactly `len` bytes, the extra bytes of a grown file are not read.
+    pub(crate) fn with_capacity_limited(read: T, capacity: usize, len: u64) -> Self {
         Self {
-            reader: ReaderStream::with_capacity(read, capacity),
+            reader: read,
+            buf: BytesMut::new(),
+            capacity,
+            remaining: Some(len),
+            next: None,
         }
     }
 
-    pub(crate) fn with_capacity_limited(
-        read: T,
-        capacity: usize,
-        max_read_bytes: u64,
-    ) -> AsyncReadBody<Take<T>> {
-        AsyncReadBody {
-            reader: ReaderStream::with_capacity(read.take(max_read_bytes), capacity),
+    /// Create a new [`AsyncReadBody`] wrapping the given reader of unknown length, with a
+    /// specific read buffer capacity.
+    pub(crate) fn with_capacity(read: T, capacity: usize) -> Self {
+        Self {
+            reader: read,
+            buf: BytesMut::new(),
+            capacity,
+            remaining: None,
+            next: None,
         }
     }
 }
@@ -53,7 +78,51 @@ where
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        self.project().reader.poll_next(cx)
+        let mut this = self.project();
+        let chunk = match this.next.take() {
+            Some(chunk) => chunk,
+            None => ready!(poll_chunk(
+                this.reader.as_mut(),
+                this.buf,
+                *this.capacity,
+                this.remaining,
+                cx
+            )),
+        };
+
+        // a pending read keeps going in the background, like the blocking read of a tokio file
+        if matches!(chunk, Some(Ok(_))) {
+            if let Poll::Ready(next) =
+                poll_chunk(this.reader, this.buf, *this.capacity, this.remaining, cx)
+            {
+                *this.next = Some(next);
+            }
+        }
+
+        Poll::Ready(chunk)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.next {
+            Some(next) => next.is_none(),
+            None => self.remaining == Some(0),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let next = match &self.next {
+            Some(Some(Ok(chunk))) => chunk.len() as u64,
+            _ => 0,
+        };
+
+        match self.remaining {
+            Some(remaining) => SizeHint::with_exact(remaining + next),
+            None => {
+                let mut hint = SizeHint::new();
+                hint.set_lower(next);
+                hint
+            }
+        }
     }
 
     fn poll_trailers(
@@ -63,3 +132,40 @@ where
         Poll::Ready(Ok(None))
     }
 }
+
+fn poll_chunk<T: AsyncRead>(
+    reader: Pin<&mut T>,
+    buf: &mut BytesMut,
+    capacity: usize,
+    remaining: &mut Option<u64>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<io::Result<Bytes>>> {
+    let limit = match *remaining {
+        Some(0) => return Poll::Ready(None),
+        Some(remaining) => remaining.min(capacity as u64) as usize,
+        None => capacity,
+    };
+
+    // reclaims the allocation when the previous chunks are dropped
+    buf.reserve(limit);
+    let n = match ready!(poll_read_buf(reader, cx, &mut (&mut *buf).limit(limit))) {
+        Ok(n) => n,
+        Err(err) => return Poll::Ready(Some(Err(err))),
+    };
+
+    if n == 0 {
+        return match remaining.take() {
+            Some(missing) => Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("file is {missing} bytes shorter than expected, it may be truncated"),
+            )))),
+            None => Poll::Ready(None),
+        };
+    }
+
+    if let Some(remaining) = remaining {
+        *remaining -= n as u64;
+    }
+
+    Poll::Ready(Some(Ok(buf.split().freeze())))
+}
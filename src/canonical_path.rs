@@ -0,0 +1,81 @@
+/// The path in the canonical percent-encoded form of RFC 3986, the escaped unreserved characters
+/// are decoded and the other escapes use the uppercase hex digits, or `None` if the path is
+/// already canonical or has an invalid escape.
+pub(crate) fn canonical_path(path: &str) -> Option<String> {
+    if !path.contains('%') {
+        return None;
+    }
+
+    let mut canonical = String::with_capacity(path.len());
+    for (i, segment) in path.split('/').enumerate() {
+        if i > 0 {
+            canonical.push('/');
+        }
+
+        let decoded = normalize_segment(segment, true)?;
+        // a decoded `%2E%2E` would be a dot segment, which the clients remove from the URL
+        if decoded == "." || decoded == ".." {
+            canonical.push_str(&normalize_segment(segment, false)?);
+        } else {
+            canonical.push_str(&decoded);
+        }
+    }
+
+    // a path starting with `//` would be read as a URL of another host
+    (canonical != path && !canonical.starts_with("//")).then_some(canonical)
+}
+
+fn normalize_segment(segment: &str, decode_unreserved: bool) -> Option<String> {
+    let mut normalized = String::with_capacity(segment.len());
+    let mut rest = segment;
+    while let Some(at) = rest.find('%') {
+        normalized.push_str(&rest[..at]);
+
+        let hex = rest
+            .get(at + 1..at + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+        let byte = u8::from_str_radix(hex, 16).ok()?;
+        if decode_unreserved && (byte.is_ascii_alphanumeric() || b"-._~".contains(&byte)) {
+            normalized.push(char::from(byte));
+        } else {
+            normalized.push_str(&format!("%{byte:02X}"));
+        }
+
+        rest = &rest[at + 3..];
+    }
+    normalized.push_str(rest);
+
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_paths() {
+        for (path, canonical) in [
+            ("/%69ndex.html", "/index.html"),
+            ("/a%2fb%3f.txt", "/a%2Fb%3F.txt"),
+            ("/%7euser/%2Dx%5F", "/~user/-x_"),
+            ("/%e4%bd%a0.txt", "/%E4%BD%A0.txt"),
+            ("/%2e%2e/%2E/x%2e", "/%2E%2E/%2E/x."),
+            ("/%2e%2efoo", "/..foo"),
+        ] {
+            assert_eq!(canonical_path(path).as_deref(), Some(canonical), "{path}");
+        }
+
+        for path in [
+            "/index.html",
+            "/a%20b.txt",
+            "/%E4%BD%A0.txt",
+            "/%2E%2E/x",
+            "/100%",
+            "/%zz",
+            "/%+1",
+            "//%61",
+        ] {
+            assert_eq!(canonical_path(path), None, "{path}");
+        }
+    }
+}
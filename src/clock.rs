@@ -0,0 +1,33 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+type Now = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+/// The clock of the conditional requests evaluation, the system clock by default
+#[derive(Clone, Default)]
+pub(crate) struct Clock(Option<Now>);
+
+impl Debug for Clock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Clock")
+            .field(&if self.0.is_some() { "custom" } else { "system" })
+            .finish()
+    }
+}
+
+impl Clock {
+    pub(crate) fn new<N>(now: N) -> Self
+    where
+        N: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        Self(Some(Arc::new(now)))
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        match &self.0 {
+            None => SystemTime::now(),
+            Some(now) => now(),
+        }
+    }
+}
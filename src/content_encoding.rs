@@ -18,7 +18,7 @@ pub(crate) enum Encoding {
 
 impl Encoding {
     #[allow(dead_code)]
-    fn to_str(self) -> &'static str {
+    pub(crate) fn to_str(self) -> &'static str {
         match self {
             #[cfg(feature = "compression-gzip")]
             Encoding::Gzip => "gzip",
@@ -101,12 +101,17 @@ pub(crate) struct QValue(u16);
 
 impl QValue {
     #[inline]
-    fn one() -> Self {
+    pub(crate) fn one() -> Self {
         Self(1000)
     }
 
+    #[inline]
+    pub(crate) fn zero() -> Self {
+        Self(0)
+    }
+
     // Parse a q-value as specified in RFC 7231 section 5.3.1.
-    fn parse(s: &str) -> Option<Self> {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
         let mut c = s.chars();
         // Parse "q=" (case-insensitively).
         match c.next() {
@@ -0,0 +1,137 @@
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use http_body::{Body, SizeHint};
+use pin_project::pin_project;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::fs::Filesystem;
+
+pub(crate) const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A body computing the SHA-256 of the data while streaming, and sending it as the
+/// `Repr-Digest` trailer, as RFC 9530 defines.
+#[pin_project]
+pub(crate) struct DigestBody<B> {
+    #[pin]
+    body: B,
+    hasher: Sha256,
+}
+
+impl<B> DigestBody<B> {
+    pub(crate) fn new(body: B) -> Self {
+        Self {
+            body,
+            hasher: Sha256::new(),
+        }
+    }
+}
+
+impl<B: Body<Data = Bytes>> Body for DigestBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let result = ready!(this.body.poll_data(cx));
+        if let Some(Ok(data)) = &result {
+            this.hasher.update(data);
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let mut trailers = ready!(this.body.poll_trailers(cx))?.unwrap_or_default();
+
+        let digest = std::mem::take(this.hasher).finalize();
+        let value = format!("sha-256=:{}:", base64(&digest));
+        trailers.insert(
+            REPR_DIGEST,
+            HeaderValue::from_str(&value).expect("base64 is a valid header value"),
+        );
+
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // the trailers are always sent
+        false
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// The SHA-256 of the whole file in the format of the subresource integrity, like
+/// `sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=`.
+pub(crate) async fn sha256_integrity<FS: Filesystem>(
+    filesystem: &mut FS,
+    path: &Path,
+) -> io::Result<String> {
+    let mut file = filesystem.open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(format!("sha256-{}", base64(&hasher.finalize())))
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+    }
+}
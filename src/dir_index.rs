@@ -0,0 +1,82 @@
+use std::fmt::{Debug, Formatter};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use http::HeaderMap;
+
+use crate::fs::DynFilesystem;
+
+const DEFAULT_INDEX: &str = "index.html";
+
+type Pick =
+    Arc<dyn for<'a> Fn(&'a IndexRequest<'a>) -> BoxFuture<'a, Option<PathBuf>> + Send + Sync>;
+
+/// A directory request passed to the [`ServeDir::directory_index`](crate::ServeDir::directory_index)
+/// closure
+#[non_exhaustive]
+pub struct IndexRequest<'a> {
+    /// The path of the directory passed to the filesystem
+    pub dir: &'a Path,
+    /// The request headers, like `Accept-Language`
+    pub headers: &'a HeaderMap,
+    /// The filesystem the directory is served from
+    pub filesystem: &'a dyn DynFilesystem,
+}
+
+impl Debug for IndexRequest<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexRequest")
+            .field("dir", &self.dir)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Picks the index document of the directories, `index.html` by default
+#[derive(Clone, Default)]
+pub(crate) struct DirectoryIndex(Option<Pick>);
+
+impl Debug for DirectoryIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DirectoryIndex")
+            .field(&if self.0.is_some() {
+                "custom"
+            } else {
+                "index.html"
+            })
+            .finish()
+    }
+}
+
+impl DirectoryIndex {
+    pub(crate) fn new<I>(pick: I) -> Self
+    where
+        I: for<'a> Fn(&'a IndexRequest<'a>) -> BoxFuture<'a, Option<PathBuf>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self(Some(Arc::new(pick)))
+    }
+
+    /// The index document of every directory, `None` if it is picked by the closure.
+    pub(crate) fn static_index(&self) -> Option<&'static Path> {
+        self.0.is_none().then(|| Path::new(DEFAULT_INDEX))
+    }
+
+    /// The path of the index document relative to the directory, `None` if the directory has no
+    /// index, or the picked path isn't inside of the directory.
+    pub(crate) async fn pick(&self, request: &IndexRequest<'_>) -> Option<PathBuf> {
+        let Some(pick) = &self.0 else {
+            return Some(PathBuf::from(DEFAULT_INDEX));
+        };
+
+        pick(request).await.filter(|index| {
+            index.components().next().is_some()
+                && index
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+        })
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Response, StatusCode};
+use http_body::Body;
+
+use crate::content_encoding::QValue;
+use crate::json;
+use crate::serve_dir::{body_from_bytes, empty_body};
+use crate::ResponseBody;
+
+const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const DEFAULT_JSON_TEMPLATE: &str = r#"{"error":"{reason}"}"#;
+
+/// The templates of the error response bodies, see
+/// [`ServeDir::error_pages`](crate::ServeDir::error_pages).
+///
+/// The templates can use the variables:
+///
+/// - `{path}`: the percent-encoded path of the request
+/// - `{status}`: the status code, like `404`
+/// - `{reason}`: the reason of the status, like `Not Found`
+/// - `{request_id}`: the `X-Request-Id` of the [`ServeDir::request_id`](crate::ServeDir::request_id),
+///   empty when it is disabled
+///
+/// The values are escaped for the HTML or inside a JSON string, and the other `{...}` are kept as
+/// is.
+///
+/// The HTML or the JSON body is chosen by the `Accept` header of the request, the JSON one is only
+/// responded when the client prefers `application/json` over `text/html`, so the browsers get the
+/// HTML and the API clients get the JSON. A status with only the HTML template has the
+/// `{"error":"{reason}"}` JSON template, and a status with only the JSON template always responds
+/// the JSON.
+///
+/// # Example
+///
+/// ```rust
+/// use http::StatusCode;
+/// use http_dir::ErrorPages;
+///
+/// let pages = ErrorPages::new()
+///     .html(StatusCode::NOT_FOUND, "<h1>{path} is not found</h1>")
+///     .json(StatusCode::FORBIDDEN, r#"{"status":{status},"reason":"{reason}"}"#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ErrorPages {
+    html: HashMap<StatusCode, String>,
+    json: HashMap<StatusCode, String>,
+}
+
+/// The values of the template variables.
+pub(crate) struct ErrorContext<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) accept: Option<&'a HeaderValue>,
+    pub(crate) request_id: Option<&'a HeaderValue>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Html,
+    Json,
+}
+
+impl ErrorPages {
+    /// Create the [`ErrorPages`] without any template.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the HTML `template` of the `status`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `status` isn't a client or server error.
+    pub fn html(mut self, status: StatusCode, template: impl Into<String>) -> Self {
+        assert_error(status);
+        self.html.insert(status, template.into());
+        self
+    }
+
+    /// Set the JSON `template` of the `status`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `status` isn't a client or server error.
+    pub fn json(mut self, status: StatusCode, template: impl Into<String>) -> Self {
+        assert_error(status);
+        self.json.insert(status, template.into());
+        self
+    }
+
+    pub(crate) fn has(&self, status: StatusCode) -> bool {
+        self.html.contains_key(&status) || self.json.contains_key(&status)
+    }
+
+    /// Render the template of the `res` status into its body, the responses with a body are kept
+    /// as is.
+    pub(crate) fn render(
+        &self,
+        res: &mut Response<ResponseBody>,
+        method: &Method,
+        context: &ErrorContext<'_>,
+    ) {
+        if res.body().size_hint().exact() != Some(0) {
+            return;
+        }
+
+        let status = res.status();
+        let (format, template) = match (self.html.get(&status), self.json.get(&status)) {
+            (Some(html), json) => {
+                res.headers_mut()
+                    .append(header::VARY, HeaderValue::from_static("accept"));
+                if prefers_json(context.accept) {
+                    (
+                        Format::Json,
+                        json.map_or(DEFAULT_JSON_TEMPLATE, String::as_str),
+                    )
+                } else {
+                    (Format::Html, html.as_str())
+                }
+            }
+            (None, Some(json)) => (Format::Json, json.as_str()),
+            (None, None) => return,
+        };
+
+        let body = render(template, format, status, context);
+        let content_type = match format {
+            Format::Html => HTML_CONTENT_TYPE,
+            Format::Json => JSON_CONTENT_TYPE,
+        };
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        res.headers_mut()
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        *res.body_mut() = if method == Method::HEAD {
+            empty_body()
+        } else {
+            body_from_bytes(Bytes::from(body))
+        };
+    }
+}
+
+// the q-value of a format is the one of the most specific media range matching it, the HTML wins
+// the ties, like without the `Accept`
+fn prefers_json(accept: Option<&HeaderValue>) -> bool {
+    let Some(accept) = accept.and_then(|accept| accept.to_str().ok()) else {
+        return false;
+    };
+
+    let qvalue = |(type_, subtype): (&str, &str)| {
+        let mut best: Option<(u8, QValue)> = None;
+        for media_range in accept.split(',') {
+            let mut parts = media_range.split(';');
+            let media_type = parts.next().unwrap().trim();
+            let specificity = match media_type.split_once('/') {
+                Some(("*", "*")) => 0,
+                Some((t, "*")) if t.eq_ignore_ascii_case(type_) => 1,
+                Some((t, s))
+                    if t.eq_ignore_ascii_case(type_) && s.eq_ignore_ascii_case(subtype) =>
+                {
+                    2
+                }
+                _ => continue,
+            };
+            let qvalue = parts
+                .find_map(|param| QValue::parse(param.trim()))
+                .unwrap_or_else(QValue::one);
+            if best.map_or(true, |(best, _)| specificity > best) {
+                best = Some((specificity, qvalue));
+            }
+        }
+
+        best.map_or(QValue::zero(), |(_, qvalue)| qvalue)
+    };
+
+    qvalue(("application", "json")) > qvalue(("text", "html"))
+}
+
+fn assert_error(status: StatusCode) {
+    assert!(
+        status.is_client_error() || status.is_server_error(),
+        "{status} is not an error status"
+    );
+}
+
+fn render(
+    template: &str,
+    format: Format,
+    status: StatusCode,
+    context: &ErrorContext<'_>,
+) -> String {
+    let request_id = context
+        .request_id
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "path" => context.path,
+                "status" => status.as_str(),
+                "reason" => status.canonical_reason().unwrap_or_default(),
+                "request_id" => request_id,
+                _ => return None,
+            };
+
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                escape(&mut out, value, format);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn escape(out: &mut String, value: &str, format: Format) {
+    match format {
+        Format::Json => json::write_escaped(out, value),
+        Format::Html => {
+            for c in value.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' => out.push_str("&quot;"),
+                    '\'' => out.push_str("&#39;"),
+                    c => out.push(c),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_variables() {
+        let request_id = HeaderValue::from_static("ticket-42");
+        let context = ErrorContext {
+            path: "/<a>\"b\"",
+            accept: None,
+            request_id: Some(&request_id),
+        };
+
+        assert_eq!(
+            render(
+                "<p>{status} {reason}: {path} ({request_id}) {unknown} {x</p>",
+                Format::Html,
+                StatusCode::NOT_FOUND,
+                &context,
+            ),
+            "<p>404 Not Found: /&lt;a&gt;&quot;b&quot; (ticket-42) {unknown} {x</p>"
+        );
+        assert_eq!(
+            render(
+                r#"{"error":{"status":{status},"path":"{path}","id":"{request_id}"}}"#,
+                Format::Json,
+                StatusCode::FORBIDDEN,
+                &ErrorContext {
+                    path: "/a\"b",
+                    accept: None,
+                    request_id: None,
+                },
+            ),
+            r#"{"error":{"status":403,"path":"/a\"b","id":""}}"#
+        );
+    }
+
+    #[test]
+    fn negotiate_format() {
+        for (accept, json) in [
+            ("application/json", true),
+            ("application/*", true),
+            ("text/html, application/json", false),
+            ("application/json, text/html;q=0.9", true),
+            (
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+                false,
+            ),
+            ("*/*", false),
+            ("application/json;q=0, */*", false),
+            ("text/*;q=0.5, application/json;q=0.6", true),
+            ("image/png", false),
+        ] {
+            let accept = HeaderValue::from_static(accept);
+            assert_eq!(prefers_json(Some(&accept)), json, "{accept:?}");
+        }
+        assert!(!prefers_json(None));
+    }
+}
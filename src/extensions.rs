@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+#[cfg(feature = "timeout")]
+use std::time::Instant;
+use std::time::SystemTime;
+
+#[cfg(doc)]
+use crate::fs::Filesystem;
+use crate::fs::Metadata;
+use crate::headers::ETag;
+
+/// Inserted into the extensions of the file responses, so the outer middlewares, like logging,
+/// metrics and cache layers, can know which file is served.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPath {
+    /// the path passed to the [`Filesystem`](crate::fs::Filesystem), relative to its root,
+    /// including the appended `index.html` and the precompressed file extension
+    pub path: PathBuf,
+
+    /// the `Content-Encoding` of the response, like `gzip`
+    pub encoding: Option<&'static str>,
+}
+
+/// Inserted into the extensions of the responses of the file requests, including the `304`,
+/// `412` and `404` ones, so the outer middlewares can log them without stat-ing the file again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedFile {
+    /// the path passed to the [`Filesystem`](crate::fs::Filesystem), or the requested path when
+    /// the file is not found
+    pub path: PathBuf,
+
+    /// file size, `None` when the file is not found or its size is unknown
+    pub len: Option<u64>,
+
+    /// file last modified time
+    pub mtime: Option<SystemTime>,
+
+    /// the `ETag` header of the file
+    pub etag: Option<String>,
+
+    /// why the response has its status
+    pub status_reason: StatusReason,
+}
+
+impl ServedFile {
+    pub(crate) fn new(
+        path: PathBuf,
+        metadata: Option<&Metadata>,
+        status_reason: StatusReason,
+    ) -> Self {
+        Self {
+            path,
+            len: metadata.and_then(|metadata| metadata.len),
+            mtime: metadata.and_then(|metadata| metadata.modified),
+            etag: metadata.and_then(ETag::new).map(ETag::into_string),
+            status_reason,
+        }
+    }
+}
+
+/// The reason of the response status, see [`ServedFile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StatusReason {
+    /// the file content, or part of it for the range requests, is served
+    Served,
+
+    /// the file is not modified since the `If-Modified-Since` time
+    NotModified,
+
+    /// the file is modified since the `If-Unmodified-Since` time
+    PreconditionFailed,
+
+    /// the path is redirected to the directory path with a trailing slash
+    Redirect,
+
+    /// the file doesn't exist or can't be read, the fallback is called if any
+    NotFound,
+
+    /// the range request is rejected by the [`RangeGuard`](crate::RangeGuard)
+    RangeRejected,
+}
+
+/// Insert it into the request extensions, like in the authentication middleware, to serve the
+/// request from another root of the filesystem, like the home directory of the signed in user.
+///
+/// The [`ServeDir`](crate::ServeDir) serves it with the [`Filesystem::reroot`] of its
+/// filesystem, the [`DiskFilesystem`](crate::fs::disk::DiskFilesystem) accepts the absolute
+/// paths of the existing directories without `.` or `..`, and doesn't follow the symlinks
+/// leaving them, the other filesystems reject any root. The request is answered with
+/// `404 Not Found` when the root is rejected, the fallback isn't called.
+///
+/// The root is chosen by the middleware, never insert a root taken from the request unchecked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootOverride(pub PathBuf);
+
+/// Insert it into the request extensions, like in the timeout middleware, and the file response
+/// body will be aborted with [`io::ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut) when the
+/// deadline passes, so the long downloads are terminated too, instead of only timing out the
+/// header phase.
+#[cfg(feature = "timeout")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
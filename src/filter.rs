@@ -0,0 +1,94 @@
+use crate::glob::Glob;
+
+const WELL_KNOWN: &str = ".well-known";
+
+/// Decide which paths can be served, the paths are relative to the root and separated by `/`
+#[derive(Debug, Clone)]
+pub(crate) struct PathFilter {
+    pub(crate) hide_dot_files: bool,
+    pub(crate) exclude: Vec<Glob>,
+    pub(crate) well_known_passthrough: bool,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        Self {
+            hide_dot_files: false,
+            exclude: vec![],
+            well_known_passthrough: true,
+        }
+    }
+}
+
+impl PathFilter {
+    pub(crate) fn is_allowed(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+
+        if self.hide_dot_files {
+            // the `.well-known` itself is not hidden, but the dot files under it still are
+            if self.well_known_passthrough && path.split('/').next() == Some(WELL_KNOWN) {
+                segments.next();
+            }
+            if segments.any(|segment| segment.starts_with('.')) {
+                return false;
+            }
+        }
+
+        !self.exclude.iter().any(|glob| glob.is_match(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_all_by_default() {
+        let filter = PathFilter::default();
+
+        assert!(filter.is_allowed(""));
+        assert!(filter.is_allowed(".git/config"));
+        assert!(filter.is_allowed("keys/server.pem"));
+    }
+
+    #[test]
+    fn hide_dot_files() {
+        let filter = PathFilter {
+            hide_dot_files: true,
+            ..Default::default()
+        };
+
+        assert!(filter.is_allowed("index.html"));
+        assert!(filter.is_allowed("a/b.c/index.html"));
+        assert!(!filter.is_allowed(".git/config"));
+        assert!(!filter.is_allowed("a/.env"));
+        assert!(filter.is_allowed(".well-known/acme-challenge/token"));
+        assert!(!filter.is_allowed(".well-known/.secret"));
+    }
+
+    #[test]
+    fn exclude() {
+        let filter = PathFilter {
+            exclude: vec![Glob::new("*.pem"), Glob::new("private/*")],
+            ..Default::default()
+        };
+
+        assert!(filter.is_allowed("index.html"));
+        assert!(!filter.is_allowed("server.pem"));
+        assert!(!filter.is_allowed("keys/server.pem"));
+        assert!(!filter.is_allowed("private/a.txt"));
+        assert!(!filter.is_allowed(".well-known/server.pem"));
+    }
+
+    #[test]
+    fn without_well_known_passthrough() {
+        let filter = PathFilter {
+            hide_dot_files: true,
+            well_known_passthrough: false,
+            ..Default::default()
+        };
+
+        assert!(!filter.is_allowed(".well-known/acme-challenge/token"));
+    }
+}
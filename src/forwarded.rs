@@ -0,0 +1,148 @@
+use http::uri::{Authority, Scheme};
+use http::{header, HeaderMap, HeaderValue};
+
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// The scheme and the host the client used before the proxy, from the `Forwarded` header, or the
+/// `X-Forwarded-Proto` and `X-Forwarded-Host` headers if it is missing.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ForwardedOrigin {
+    pub(crate) proto: Option<Scheme>,
+    pub(crate) host: Option<Authority>,
+}
+
+impl ForwardedOrigin {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        if let Some(forwarded) = headers.get(header::FORWARDED) {
+            return Self::from_forwarded(forwarded);
+        }
+
+        Self {
+            proto: first_value(headers.get(X_FORWARDED_PROTO)).and_then(parse_proto),
+            host: first_value(headers.get(X_FORWARDED_HOST)).and_then(parse_host),
+        }
+    }
+
+    // like `for=192.0.2.60;proto=https;host=example.com, for=198.51.100.17`, only the first
+    // element is used, which is added by the proxy closest to the client
+    fn from_forwarded(value: &HeaderValue) -> Self {
+        let mut origin = Self::default();
+        let Some(element) = first_value(Some(value)) else {
+            return origin;
+        };
+
+        for pair in element.split(';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "proto" => origin.proto = parse_proto(value),
+                "host" => origin.host = parse_host(value),
+                _ => {}
+            }
+        }
+
+        origin
+    }
+}
+
+fn first_value(value: Option<&HeaderValue>) -> Option<&str> {
+    let value = value?.to_str().ok()?;
+    let first = value.split(',').next()?.trim();
+
+    (!first.is_empty()).then_some(first)
+}
+
+// only the web schemes, so the redirects can't point at something like `javascript:`
+fn parse_proto(proto: &str) -> Option<Scheme> {
+    if proto.eq_ignore_ascii_case("https") {
+        Some(Scheme::HTTPS)
+    } else if proto.eq_ignore_ascii_case("http") {
+        Some(Scheme::HTTP)
+    } else {
+        None
+    }
+}
+
+// the user info is rejected, it could disguise the redirect target
+fn parse_host(host: &str) -> Option<Authority> {
+    if host.contains('@') {
+        return None;
+    }
+
+    host.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(headers: &[(&'static str, &'static str)]) -> ForwardedOrigin {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+
+        ForwardedOrigin::from_headers(&map)
+    }
+
+    #[test]
+    fn forwarded() {
+        assert_eq!(
+            origin(&[(
+                "forwarded",
+                "for=192.0.2.60;Proto=HTTPS;host=\"example.com:8443\", proto=http"
+            )]),
+            ForwardedOrigin {
+                proto: Some(Scheme::HTTPS),
+                host: Some(Authority::from_static("example.com:8443")),
+            }
+        );
+
+        // the `Forwarded` header takes precedence
+        assert_eq!(
+            origin(&[("forwarded", "proto=http"), ("x-forwarded-proto", "https")]),
+            ForwardedOrigin {
+                proto: Some(Scheme::HTTP),
+                host: None,
+            }
+        );
+    }
+
+    #[test]
+    fn x_forwarded() {
+        assert_eq!(
+            origin(&[
+                ("x-forwarded-proto", "https, http"),
+                ("x-forwarded-host", "example.com")
+            ]),
+            ForwardedOrigin {
+                proto: Some(Scheme::HTTPS),
+                host: Some(Authority::from_static("example.com")),
+            }
+        );
+        assert_eq!(origin(&[]), ForwardedOrigin::default());
+    }
+
+    #[test]
+    fn reject_invalid_origin() {
+        assert_eq!(
+            origin(&[
+                ("x-forwarded-proto", "javascript"),
+                ("x-forwarded-host", "evil.com@example.com")
+            ]),
+            ForwardedOrigin::default()
+        );
+        assert_eq!(
+            origin(&[("forwarded", "proto=ftp;host=\"a b\"")]),
+            ForwardedOrigin::default()
+        );
+    }
+}
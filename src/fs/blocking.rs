@@ -0,0 +1,284 @@
+use std::future::{ready, Future, Ready};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::sync::Semaphore;
+
+use crate::fs::{DirEntry, FileExt, Filesystem, Metadata, ReadAt, ReadDir};
+
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// A synchronous storage, like the `std::fs` or a blocking storage SDK, served by the
+/// [`BlockingFilesystem`]
+///
+/// The methods are called on the blocking threads of the tokio runtime, so they can block.
+pub trait BlockingStorage: Send + Sync + 'static {
+    /// the opened file
+    type File: Read + Seek + Send + 'static;
+
+    /// open a [`file`](BlockingStorage::File) by path
+    fn open(&self, path: &Path) -> io::Result<Self::File>;
+
+    /// check the path is a dir or not
+    fn is_dir(&self, path: &Path) -> io::Result<bool>;
+
+    /// get [`Metadata`] by path
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// list the [`entries`](DirEntry) of the dir
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+}
+
+/// A filesystem running the [`BlockingStorage`] on the blocking threads with `spawn_blocking`,
+/// up to 64 operations at once by default, so a slow storage can't take all the blocking
+/// threads of the runtime.
+///
+/// The clones share the same storage and limit.
+#[derive(Debug)]
+pub struct BlockingFilesystem<T> {
+    storage: Arc<T>,
+    permits: Arc<Semaphore>,
+}
+
+impl<T> Clone for BlockingFilesystem<T> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            permits: self.permits.clone(),
+        }
+    }
+}
+
+impl<T: BlockingStorage> BlockingFilesystem<T> {
+    /// Create a new [`BlockingFilesystem`] of the `storage`.
+    pub fn new(storage: T) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            permits: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+        }
+    }
+
+    /// Run up to `limit` operations of the storage at once, the others wait for their turn,
+    /// including the reads of the opened files.
+    ///
+    /// Defaults to `64`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `limit` is `0`.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "concurrency limit must be greater than 0");
+
+        self.permits = Arc::new(Semaphore::new(limit));
+        self
+    }
+
+    fn run<R, F>(&self, f: F) -> impl Future<Output = io::Result<R>> + Send + Sync + 'static
+    where
+        R: Send + 'static,
+        F: FnOnce(&T) -> io::Result<R> + Send + Sync + 'static,
+    {
+        let storage = self.storage.clone();
+
+        run_blocking(self.permits.clone(), move || f(&storage))
+    }
+}
+
+// the permit is held until the blocking call returns, even if the caller is gone
+async fn run_blocking<R, F>(permits: Arc<Semaphore>, f: F) -> io::Result<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> io::Result<R> + Send + Sync + 'static,
+{
+    let permit = permits
+        .acquire_owned()
+        .await
+        .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        f()
+    })
+    .await
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))?
+}
+
+impl<T: BlockingStorage> Filesystem for BlockingFilesystem<T> {
+    type File = BlockingFile<T::File>;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        let path = path.to_path_buf();
+        let permits = self.permits.clone();
+        let opened = self.run(move |storage| {
+            let file = storage.open(&path)?;
+            let metadata = storage.metadata(&path)?;
+
+            Ok((file, metadata))
+        });
+
+        async move {
+            let (file, metadata) = opened.await?;
+
+            Ok(BlockingFile {
+                file: Arc::new(Mutex::new(file)),
+                metadata,
+                permits,
+                pos: 0,
+                read: None,
+            })
+        }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        let path = path.to_path_buf();
+
+        self.run(move |storage| storage.is_dir(&path))
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        let path = path.to_path_buf();
+
+        self.run(move |storage| storage.metadata(&path))
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        let path = path.to_path_buf();
+
+        Box::pin(self.run(move |storage| storage.read_dir(&path)))
+    }
+}
+
+/// A file of the [`BlockingFilesystem`], it is read on the blocking threads, every read seeks to
+/// its own offset, so the [`FileExt::read_at`] is supported.
+pub struct BlockingFile<F> {
+    file: Arc<Mutex<F>>,
+    metadata: Metadata,
+    permits: Arc<Semaphore>,
+    pos: u64,
+    read: Option<ReadAt>,
+}
+
+impl<F> std::fmt::Debug for BlockingFile<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockingFile")
+            .field("metadata", &self.metadata)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: Read + Seek + Send + 'static> BlockingFile<F> {
+    fn read_from(&self, offset: u64, mut buf: BytesMut) -> ReadAt {
+        let file = self.file.clone();
+
+        Box::pin(run_blocking(self.permits.clone(), move || {
+            let mut file = file.lock().unwrap();
+            file.seek(SeekFrom::Start(offset))?;
+
+            let filled = buf.len();
+            buf.resize(buf.capacity(), 0);
+            let n = file.read(&mut buf[filled..])?;
+            buf.truncate(filled + n);
+
+            Ok(buf)
+        }))
+    }
+}
+
+impl<F: Read + Seek + Send + 'static> AsyncRead for BlockingFile<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(read) = &mut this.read {
+                let result = ready!(read.as_mut().poll(cx));
+                this.read = None;
+                let data = result?;
+
+                // the buf may be smaller than the one the read started with, the rest is read
+                // again from the new position
+                let n = data.len().min(buf.remaining());
+                buf.put_slice(&data[..n]);
+                this.pos += n as u64;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            this.read = Some(this.read_from(this.pos, BytesMut::with_capacity(buf.remaining())));
+        }
+    }
+}
+
+impl<F: Read + Seek + Send + 'static> AsyncSeek for BlockingFile<F> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.read.is_some() {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                "other file operation is pending, call poll_complete before start_seek",
+            ));
+        }
+
+        let pos = match position {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => {
+                let len = this.metadata.len.ok_or_else(|| {
+                    io::Error::new(ErrorKind::Unsupported, "the file length is unknown")
+                })?;
+                len.checked_add_signed(offset)
+            }
+            SeekFrom::Current(offset) => this.pos.checked_add_signed(offset),
+        };
+        this.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+impl<F: Read + Seek + Send + 'static> FileExt for BlockingFile<F> {
+    type Metadata<'a>
+        = Ready<io::Result<Metadata>>
+    where
+        Self: 'a;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        ready(Ok(self.metadata.clone()))
+    }
+
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        Some(self.read_from(offset, buf))
+    }
+}
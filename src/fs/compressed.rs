@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+/// The encoding of the compressed only embedded files, see [`compress_dir`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressedEncoding {
+    /// stored as `.gz`
+    Gzip,
+    /// stored as `.br`
+    Brotli,
+}
+
+impl CompressedEncoding {
+    /// The encodings are looked up in this order when the uncompressed file is missing
+    pub(crate) const ALL: [CompressedEncoding; 2] =
+        [CompressedEncoding::Brotli, CompressedEncoding::Gzip];
+
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            CompressedEncoding::Gzip => ".gz",
+            CompressedEncoding::Brotli => ".br",
+        }
+    }
+}
+
+pub(crate) fn decompress(data: &[u8], encoding: CompressedEncoding) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    match encoding {
+        CompressedEncoding::Gzip => GzDecoder::new(data).read_to_end(&mut out)?,
+        CompressedEncoding::Brotli => {
+            brotli::Decompressor::new(data, BROTLI_BUFFER_SIZE).read_to_end(&mut out)?
+        }
+    };
+
+    Ok(out)
+}
+
+fn compress(data: &[u8], encoding: CompressedEncoding) -> io::Result<Vec<u8>> {
+    match encoding {
+        CompressedEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], Compression::best());
+            encoder.write_all(data)?;
+
+            encoder.finish()
+        }
+
+        CompressedEncoding::Brotli => {
+            let mut out = vec![];
+            {
+                let mut writer = brotli::CompressorWriter::new(
+                    &mut out,
+                    BROTLI_BUFFER_SIZE,
+                    BROTLI_QUALITY,
+                    BROTLI_LG_WINDOW_SIZE,
+                );
+                writer.write_all(data)?;
+            }
+
+            Ok(out)
+        }
+    }
+}
+
+/// Compress every file in the `src` directory into the `dst` directory, like `app.js` into
+/// `app.js.br`, the files which don't get smaller are copied as is.
+///
+/// It is meant to be called in the `build.rs`, and the `dst` is embedded by `include_dir`, so
+/// the binary only contains the compressed files. The [`IncludeDirFilesystem`] serves the
+/// compressed files to the clients which accept them with the
+/// [`ServeDir::precompressed_br`](crate::ServeDir::precompressed_br) or
+/// [`ServeDir::precompressed_gzip`](crate::ServeDir::precompressed_gzip), and decompresses them
+/// in memory for the other clients.
+///
+/// # Example
+///
+/// In the `build.rs`:
+///
+/// ```rust,no_run
+/// use http_dir::fs::include_dir::{compress_dir, CompressedEncoding};
+///
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// compress_dir("assets", format!("{out_dir}/assets"), CompressedEncoding::Brotli).unwrap();
+/// println!("cargo:rerun-if-changed=assets");
+/// ```
+///
+/// Then embed it with `include_dir!("$OUT_DIR/assets")`.
+///
+/// [`IncludeDirFilesystem`]: crate::fs::include_dir::IncludeDirFilesystem
+pub fn compress_dir<S: AsRef<Path>, D: AsRef<Path>>(
+    src: S,
+    dst: D,
+    encoding: CompressedEncoding,
+) -> io::Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let src_path = entry.path();
+        let mut dst_path = dst.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            compress_dir(&src_path, &dst_path, encoding)?;
+            continue;
+        }
+
+        let data = fs::read(&src_path)?;
+        let compressed = compress(&data, encoding)?;
+        if compressed.len() < data.len() {
+            let mut file_name = entry.file_name();
+            file_name.push(encoding.extension());
+            dst_path.set_file_name(file_name);
+
+            fs::write(dst_path, compressed)?;
+        } else {
+            fs::write(dst_path, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = "compress me ".repeat(100);
+
+        for encoding in CompressedEncoding::ALL {
+            let compressed = compress(data.as_bytes(), encoding).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(decompress(&compressed, encoding).unwrap(), data.as_bytes());
+        }
+    }
+}
@@ -1,19 +1,44 @@
-use std::future::Future;
+use std::ffi::OsStr;
+use std::future::{ready, Future};
 use std::io;
 use std::io::{ErrorKind, SeekFrom};
 use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
+#[cfg(unix)]
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use bytes::BytesMut;
+use http::HeaderMap;
+#[cfg(feature = "xattr")]
+use http::{header, HeaderName, HeaderValue};
 use tokio::fs;
 use tokio::fs::File;
 use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
 
-use crate::fs::{FileExt, Filesystem, Metadata};
+#[cfg(unix)]
+use crate::fs::handle_cache::{CachedFile, HandleCache};
+#[cfg(unix)]
+use crate::fs::{is_purged_by, is_purged_by_prefix};
+use crate::fs::{DirEntry, FileExt, Filesystem, Metadata, Purge, ReadAt, ReadDir, Reroot};
 
 /// A [`tokio`](https://docs.rs/tokio/latest/tokio/) based disk file wrapper
 #[derive(Debug)]
-pub struct DiskFile(File);
+pub struct DiskFile {
+    file: DiskFileInner,
+    // read when the file is opened, as the xattrs are read by path
+    headers: HeaderMap,
+}
+
+#[derive(Debug)]
+enum DiskFileInner {
+    File(File),
+    // a cursor of a handle shared with the other requests, see `DiskFilesystem::handle_cache`, or
+    // of a handle opened by `DiskFilesystem::positional_reads`
+    #[cfg(unix)]
+    Cached(CachedFile),
+}
 
 impl AsyncRead for DiskFile {
     #[inline]
@@ -22,42 +47,156 @@ impl AsyncRead for DiskFile {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        match &mut self.get_mut().file {
+            DiskFileInner::File(file) => Pin::new(file).poll_read(cx, buf),
+            #[cfg(unix)]
+            DiskFileInner::Cached(file) => file.poll_read(cx, buf),
+        }
     }
 }
 
 impl AsyncSeek for DiskFile {
     #[inline]
     fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
-        Pin::new(&mut self.get_mut().0).start_seek(position)
+        match &mut self.get_mut().file {
+            DiskFileInner::File(file) => Pin::new(file).start_seek(position),
+            #[cfg(unix)]
+            DiskFileInner::Cached(file) => file.start_seek(position),
+        }
     }
 
     #[inline]
     fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
-        Pin::new(&mut self.get_mut().0).poll_complete(cx)
+        match &mut self.get_mut().file {
+            DiskFileInner::File(file) => Pin::new(file).poll_complete(cx),
+            #[cfg(unix)]
+            DiskFileInner::Cached(file) => Poll::Ready(Ok(file.position())),
+        }
     }
 }
 
 impl FileExt for DiskFile {
-    type Metadata<'a> = impl Future<Output=io::Result<Metadata>> + Send + Sync + 'a where Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
 
     fn metadata(&self) -> Self::Metadata<'_> {
         async move {
-            let raw_metadata = self.0.metadata().await?;
-            let modified = raw_metadata.modified().ok();
+            let raw_metadata = match &self.file {
+                DiskFileInner::File(file) => file.metadata().await?,
+                // read when it is opened, the cached handle is dropped once the file changes
+                #[cfg(unix)]
+                DiskFileInner::Cached(file) => file.metadata().clone(),
+            };
 
-            Ok(Metadata {
-                modified,
-                len: raw_metadata.len(),
-            })
+            Ok(to_metadata(&raw_metadata, self.headers.clone()))
+        }
+    }
+
+    fn read_at(&self, _offset: u64, _buf: BytesMut) -> Option<ReadAt> {
+        match &self.file {
+            // the position of the tokio file is moved by the reads
+            DiskFileInner::File(_) => None,
+            #[cfg(unix)]
+            DiskFileInner::Cached(file) => Some(file.read_at(_offset, _buf)),
         }
     }
 }
 
+fn to_metadata(raw_metadata: &std::fs::Metadata, headers: HeaderMap) -> Metadata {
+    let modified = raw_metadata.modified().ok();
+
+    Metadata {
+        modified,
+        len: Some(raw_metadata.len()),
+        etag: modified.map(|modified| etag(raw_metadata, modified)),
+        headers,
+    }
+}
+
+// like Apache, the ETag is made of the device, inode, size and modified time, so it is stable
+// without hashing the content, but differs between the hosts serving the same files
+fn etag(raw_metadata: &std::fs::Metadata, modified: SystemTime) -> String {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        format!(
+            "\"{:x}-{:x}-{:x}-{modified:x}\"",
+            raw_metadata.dev(),
+            raw_metadata.ino(),
+            raw_metadata.len()
+        )
+    }
+
+    #[cfg(not(unix))]
+    {
+        format!("\"{:x}-{modified:x}\"", raw_metadata.len())
+    }
+}
+
+// Windows opens the same file for the segments with a trailing dot or space, or an alternate data
+// stream like `key.pem::$DATA`, and the devices for the reserved names like `nul.txt`, so they are
+// rejected, or they would slip through the exclude patterns of the `ServeDir`
+fn is_windows_segment(segment: &OsStr) -> bool {
+    const RESERVED: &[&str] = &[
+        "con", "prn", "aux", "nul", "conin$", "conout$", "com1", "com2", "com3", "com4", "com5",
+        "com6", "com7", "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7",
+        "lpt8", "lpt9",
+    ];
+
+    let Some(segment) = segment.to_str() else {
+        return false;
+    };
+    if segment.ends_with(['.', ' ']) || segment.contains([':', '<', '>', '"', '|', '?', '*']) {
+        return false;
+    }
+
+    let stem = segment.split('.').next().unwrap_or_default().trim_end();
+    !RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// The extended attributes read by [`DiskFilesystem::xattr_headers`], and the response headers
+/// they are mapped to
+#[cfg(feature = "xattr")]
+const XATTR_HEADERS: &[(&str, HeaderName)] = &[
+    ("user.mime_type", header::CONTENT_TYPE),
+    ("user.cache_control", header::CACHE_CONTROL),
+    ("user.content_disposition", header::CONTENT_DISPOSITION),
+    ("user.content_language", header::CONTENT_LANGUAGE),
+];
+
 /// A [`tokio`](https://docs.rs/tokio/latest/tokio/) based disk filesystem implement
+///
+/// The [`Metadata::etag`] is generated from the device, inode, size and modified time of the
+/// file, the inode and device are not used on the non-unix platforms.
+///
+/// On Windows, the base can be a verbatim `\\?\` or UNC path, and the paths longer than
+/// `MAX_PATH` are opened by the `std::fs` as verbatim paths. The path segments Windows would open
+/// as another file or a device, like `key.pem.`, `key.pem::$DATA` or `nul.txt`, are not found.
 #[derive(Debug, Clone)]
 pub struct DiskFilesystem {
     base: PathBuf,
+    // the canonical root of a rerooted filesystem, the files are served only when they are in it
+    confined_root: Option<PathBuf>,
+    #[cfg(feature = "xattr")]
+    xattr_headers: bool,
+    #[cfg(unix)]
+    required_mode: u32,
+    #[cfg(unix)]
+    denied_mode: u32,
+    #[cfg(unix)]
+    handle_cache: Option<Arc<HandleCache>>,
+    #[cfg(unix)]
+    positional_reads: bool,
 }
 
 impl From<&str> for DiskFilesystem {
@@ -81,7 +220,214 @@ impl From<PathBuf> for DiskFilesystem {
 impl DiskFilesystem {
     /// create [`DiskFilesystem`] by base path
     pub fn new(base: PathBuf) -> Self {
-        Self { base }
+        Self {
+            base,
+            confined_root: None,
+            #[cfg(feature = "xattr")]
+            xattr_headers: false,
+            #[cfg(unix)]
+            required_mode: 0,
+            #[cfg(unix)]
+            denied_mode: 0,
+            #[cfg(unix)]
+            handle_cache: None,
+            #[cfg(unix)]
+            positional_reads: false,
+        }
+    }
+
+    /// Refuse to serve the files which aren't readable by others, so the private files dropped
+    /// into the web root, like the `0600` private keys, won't be exposed by accident.
+    ///
+    /// The refused files are treated as permission denied, which is `404 Not Found` for
+    /// [`ServeDir`](crate::ServeDir).
+    ///
+    /// Defaults to `false`.
+    #[cfg(unix)]
+    pub fn world_readable_only(mut self, enable: bool) -> Self {
+        if enable {
+            self.required_mode |= 0o004;
+        } else {
+            self.required_mode &= !0o004;
+        }
+
+        self
+    }
+
+    /// Refuse to serve the files whose mode has any bit of the `mask`, like `0o002` refuses the
+    /// world writable files.
+    ///
+    /// The refused files are treated as permission denied, which is `404 Not Found` for
+    /// [`ServeDir`](crate::ServeDir).
+    ///
+    /// Defaults to `0`, which refuses nothing.
+    #[cfg(unix)]
+    pub fn deny_mode(mut self, mask: u32) -> Self {
+        self.denied_mode = mask;
+        self
+    }
+
+    /// Keep the handles of up to `capacity` recently served files open, and share them between
+    /// the requests, so the hot files aren't opened and closed for every request under heavy
+    /// load. The clones of the filesystem share the same cache.
+    ///
+    /// The shared handles are read with `pread`, so every request keeps its own position. The
+    /// path is still checked with a `stat` for every request, a cached handle is reopened when the
+    /// file is replaced, modified, or its mode or extended attributes are changed.
+    ///
+    /// Defaults to `0`, which disables the cache.
+    #[cfg(unix)]
+    pub fn handle_cache(mut self, capacity: usize) -> Self {
+        self.handle_cache = (capacity > 0).then(|| Arc::new(HandleCache::new(capacity)));
+        self
+    }
+
+    /// Open the files with the `std::fs` on the blocking threads, and read them with `pread`,
+    /// instead of the `tokio::fs`, it takes less thread hops to open a file and to serve a range,
+    /// which is often faster for the small files. Benchmark it with your files before enabling
+    /// it.
+    ///
+    /// The [`DiskFilesystem::handle_cache`] reads with `pread` anyway.
+    ///
+    /// Defaults to `false`.
+    #[cfg(unix)]
+    pub fn positional_reads(mut self, enable: bool) -> Self {
+        self.positional_reads = enable;
+        self
+    }
+
+    // open the file and read its metadata in one blocking call
+    #[cfg(unix)]
+    async fn open_positional(&self, path: PathBuf) -> io::Result<DiskFile> {
+        let (file, raw_metadata, path) = tokio::task::spawn_blocking(move || {
+            let file = std::fs::File::open(&path)?;
+            let raw_metadata = file.metadata()?;
+
+            Ok::<_, io::Error>((file, raw_metadata, path))
+        })
+        .await
+        .map_err(|err| io::Error::new(ErrorKind::Other, err))??;
+        self.check_mode(&raw_metadata)?;
+        let headers = self.headers(&path).await;
+
+        Ok(DiskFile {
+            file: DiskFileInner::Cached(CachedFile::new(Arc::new(file), raw_metadata)),
+            headers,
+        })
+    }
+
+    #[cfg(unix)]
+    async fn open_cached(&self, handle_cache: &HandleCache, path: PathBuf) -> io::Result<DiskFile> {
+        let raw_metadata = fs::metadata(&path).await?;
+        self.check_mode(&raw_metadata)?;
+        if let Some((file, headers)) = handle_cache.get(&path, &raw_metadata) {
+            return Ok(DiskFile {
+                file: DiskFileInner::Cached(file),
+                headers,
+            });
+        }
+
+        let file = File::open(&path).await?.into_std().await;
+        // check the opened file, so it can't be replaced after the check
+        let raw_metadata = file.metadata()?;
+        self.check_mode(&raw_metadata)?;
+        let headers = self.headers(&path).await;
+        let file = handle_cache.insert(path, file, raw_metadata, headers.clone());
+
+        Ok(DiskFile {
+            file: DiskFileInner::Cached(file),
+            headers,
+        })
+    }
+
+    fn check_mode(&self, _raw_metadata: &std::fs::Metadata) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = _raw_metadata.permissions().mode();
+            if mode & self.required_mode != self.required_mode || mode & self.denied_mode != 0 {
+                return Err(io::Error::from(ErrorKind::PermissionDenied));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the extended attributes of the files, and respond them as the headers, so the per
+    /// file overrides can live alongside the files:
+    ///
+    /// | attribute                  | header                |
+    /// |----------------------------|-----------------------|
+    /// | `user.mime_type`           | `Content-Type`        |
+    /// | `user.cache_control`       | `Cache-Control`       |
+    /// | `user.content_disposition` | `Content-Disposition` |
+    /// | `user.content_language`    | `Content-Language`    |
+    ///
+    /// The attributes are read from the served file, so the precompressed variants need them too.
+    /// Invalid header values are ignored.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "xattr")]
+    pub fn xattr_headers(mut self, enable: bool) -> Self {
+        self.xattr_headers = enable;
+        self
+    }
+
+    async fn headers(&self, _path: &Path) -> HeaderMap {
+        #[cfg(feature = "xattr")]
+        if self.xattr_headers {
+            let path = _path.to_path_buf();
+
+            return tokio::task::spawn_blocking(move || read_xattr_headers(&path))
+                .await
+                .unwrap_or_default();
+        }
+
+        HeaderMap::new()
+    }
+
+    // read up to `limit` entries of the dir, the `deadline` is checked before every entry
+    async fn read_entries(
+        &self,
+        path: &Path,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> io::Result<Vec<DirEntry>> {
+        let path = self.resolve_path(path).await?;
+
+        let mut read_dir = fs::read_dir(&path).await?;
+        let mut entries = vec![];
+        while entries.len() < limit {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(io::Error::from(ErrorKind::TimedOut));
+            }
+            let Some(entry) = read_dir.next_entry().await? else {
+                break;
+            };
+
+            entries.push(DirEntry {
+                name: entry.file_name(),
+                is_dir: entry.file_type().await?.is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    // build the path of the file, and check the symlinks in it don't leave the root of a rerooted
+    // filesystem, see `DiskFilesystem::reroot`
+    async fn resolve_path(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = self
+            .build_and_validate_path(path)
+            .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+        if let Some(confined_root) = &self.confined_root {
+            if !fs::canonicalize(&path).await?.starts_with(confined_root) {
+                return Err(io::Error::from(ErrorKind::NotFound));
+            }
+        }
+
+        Ok(path)
     }
 
     fn build_and_validate_path(&self, path: &Path) -> Option<PathBuf> {
@@ -93,6 +439,7 @@ impl DiskFilesystem {
                     if Path::new(&comp)
                         .components()
                         .all(|c| matches!(c, Component::Normal(_)))
+                        && (!cfg!(windows) || is_windows_segment(comp))
                     {
                         path_to_file.push(comp)
                     } else {
@@ -109,31 +456,83 @@ impl DiskFilesystem {
     }
 }
 
+/// Only the handle cache is purged, the other lookups always read the disk. The cached handles are
+/// reopened when the files change anyway, purging them closes the handles of the removed files
+/// earlier.
+impl Purge for DiskFilesystem {
+    fn purge(&self, _path: &Path) {
+        #[cfg(unix)]
+        if let Some((handle_cache, path)) = self
+            .handle_cache
+            .as_ref()
+            .zip(self.build_and_validate_path(_path))
+        {
+            handle_cache.retain(|cached| !is_purged_by(cached, &path));
+        }
+    }
+
+    fn purge_prefix(&self, _prefix: &Path) {
+        #[cfg(unix)]
+        if let Some((handle_cache, prefix)) = self
+            .handle_cache
+            .as_ref()
+            .zip(self.build_and_validate_path(_prefix))
+        {
+            handle_cache.retain(|cached| !is_purged_by_prefix(cached, &prefix));
+        }
+    }
+
+    fn purge_all(&self) {
+        #[cfg(unix)]
+        if let Some(handle_cache) = &self.handle_cache {
+            handle_cache.retain(|_| false);
+        }
+    }
+}
+
 impl Filesystem for DiskFilesystem {
     type File = DiskFile;
-    type OpenFile<'a> = impl Future<Output=io::Result<Self::File>> + Send + Sync + 'a where Self: 'a;
-    type IsDir<'a> = impl Future<Output=io::Result<bool>> + Send + Sync + 'a where Self: 'a;
-    type Metadata<'a> = impl Future<Output=io::Result<Metadata>> + Send + Sync + 'a where Self: 'a;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
 
     fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
         async move {
-            let path = match self.build_and_validate_path(path) {
-                None => return Err(io::Error::from(ErrorKind::NotFound)),
-                Some(path) => path,
-            };
+            let path = self.resolve_path(path).await?;
+
+            #[cfg(unix)]
+            if let Some(handle_cache) = &self.handle_cache {
+                return self.open_cached(handle_cache, path).await;
+            }
+            #[cfg(unix)]
+            if self.positional_reads {
+                return self.open_positional(path).await;
+            }
 
             let file = File::open(&path).await?;
+            // check the opened file, so it can't be replaced after the check
+            self.check_mode(&file.metadata().await?)?;
+            let headers = self.headers(&path).await;
 
-            Ok(DiskFile(file))
+            Ok(DiskFile {
+                file: DiskFileInner::File(file),
+                headers,
+            })
         }
     }
 
     fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
         async move {
-            let path = match self.build_and_validate_path(path) {
-                None => return Err(io::Error::from(ErrorKind::NotFound)),
-                Some(path) => path,
-            };
+            let path = self.resolve_path(path).await?;
 
             Ok(fs::metadata(&path).await?.is_dir())
         }
@@ -141,19 +540,131 @@ impl Filesystem for DiskFilesystem {
 
     fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
         async move {
-            let path = match self.build_and_validate_path(path) {
-                None => return Err(io::Error::from(ErrorKind::NotFound)),
-                Some(path) => path,
-            };
+            let path = self.resolve_path(path).await?;
 
             let raw_metadata = fs::metadata(&path).await?;
+            self.check_mode(&raw_metadata)?;
+            let headers = self.headers(&path).await;
+
+            Ok(to_metadata(&raw_metadata, headers))
+        }
+    }
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        Box::pin(self.read_entries(path, usize::MAX, None))
+    }
 
-            let modified = raw_metadata.modified().ok();
+    fn read_dir_bounded<'a>(
+        &'a self,
+        path: &'a Path,
+        max_entries: usize,
+        deadline: Option<Instant>,
+    ) -> ReadDir<'a> {
+        Box::pin(self.read_entries(path, max_entries.saturating_add(1), deadline))
+    }
 
-            Ok(Metadata {
-                modified,
-                len: raw_metadata.len(),
+    /// The `root` must be an absolute path of an existing directory without `..`, the other
+    /// settings and the handle cache are kept.
+    ///
+    /// The rerooted filesystem doesn't serve the files which are out of the root after following
+    /// the symlinks, like the ones under a `ln -s / link` in the root. On unix, the root is
+    /// rejected when it is reached by a symlink pointing to a file of another owner, unless the
+    /// symlink is owned by `root`, like the `SymLinksIfOwnerMatch` of Apache, so a user can't link
+    /// the own directory to the files of others.
+    fn reroot<'a>(&'a self, root: &'a Path) -> Reroot<'a, Self> {
+        let valid = root.is_absolute()
+            && root.components().all(|component| match component {
+                Component::Prefix(_) | Component::RootDir => true,
+                Component::Normal(comp) => !cfg!(windows) || is_windows_segment(comp),
+                Component::CurDir | Component::ParentDir => false,
+            });
+        if !valid {
+            return Box::pin(ready(None));
+        }
+
+        Box::pin(async move {
+            let canonical_root = root.to_path_buf();
+            let confined_root = tokio::task::spawn_blocking(move || {
+                #[cfg(unix)]
+                if !symlinks_owner_match(&canonical_root) {
+                    return None;
+                }
+
+                std::fs::canonicalize(canonical_root).ok()
             })
+            .await
+            .ok()??;
+
+            Some(Self {
+                base: root.to_path_buf(),
+                confined_root: Some(confined_root),
+                ..self.clone()
+            })
+        })
+    }
+}
+
+// every symlink on the path is owned by `root` or by the owner of its target
+#[cfg(unix)]
+fn symlinks_owner_match(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    path.ancestors()
+        .all(|ancestor| match std::fs::symlink_metadata(ancestor) {
+            Ok(link) if link.file_type().is_symlink() => {
+                link.uid() == 0
+                    || std::fs::metadata(ancestor).is_ok_and(|target| target.uid() == link.uid())
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        })
+}
+
+#[cfg(feature = "xattr")]
+fn read_xattr_headers(path: &Path) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, header_name) in XATTR_HEADERS {
+        let value = match xattr::get(path, name) {
+            Ok(Some(value)) => value,
+            _ => continue,
+        };
+
+        if let Ok(value) = HeaderValue::from_bytes(&value) {
+            headers.insert(header_name.clone(), value);
+        }
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_segments() {
+        for segment in [
+            "index.html",
+            "a b.txt",
+            ".well-known",
+            "console.log",
+            "com10",
+            "\u{4f60}",
+        ] {
+            assert!(is_windows_segment(OsStr::new(segment)), "{segment}");
+        }
+
+        for segment in [
+            "key.pem.",
+            "key.pem ",
+            "key.pem::$DATA",
+            "key.pem:stream",
+            "NUL",
+            "nul.txt",
+            "Com1.tar.gz",
+            "con .txt",
+            "a?b",
+        ] {
+            assert!(!is_windows_segment(OsStr::new(segment)), "{segment}");
         }
     }
 }
@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::io;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+use crate::fs::disk::{DiskFile, DiskFilesystem};
+use crate::fs::include_dir::{IncludeDirFile, IncludeDirFilesystem};
+use crate::fs::{FileExt, Filesystem, Metadata, ReadAt, ReadDir};
+
+/// The file of the [`EmbeddedFilesystem`], a file on the disk or an embedded one
+pub struct EmbeddedFile(Inner);
+
+enum Inner {
+    Disk(Box<DiskFile>),
+    Embedded(IncludeDirFile),
+}
+
+impl Debug for EmbeddedFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Inner::Disk(file) => f.debug_tuple("Disk").field(file).finish(),
+            Inner::Embedded(_) => f.debug_tuple("Embedded").finish(),
+        }
+    }
+}
+
+impl AsyncRead for EmbeddedFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().0 {
+            Inner::Disk(file) => Pin::new(&mut **file).poll_read(cx, buf),
+            Inner::Embedded(file) => Pin::new(file).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncSeek for EmbeddedFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match &mut self.get_mut().0 {
+            Inner::Disk(file) => Pin::new(&mut **file).start_seek(position),
+            Inner::Embedded(file) => Pin::new(file).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match &mut self.get_mut().0 {
+            Inner::Disk(file) => Pin::new(&mut **file).poll_complete(cx),
+            Inner::Embedded(file) => Pin::new(file).poll_complete(cx),
+        }
+    }
+}
+
+impl FileExt for EmbeddedFile {
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        async move {
+            match &self.0 {
+                Inner::Disk(file) => file.metadata().await,
+                Inner::Embedded(file) => file.metadata().await,
+            }
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        match &self.0 {
+            Inner::Disk(file) => file.read_at(offset, buf),
+            Inner::Embedded(file) => file.read_at(offset, buf),
+        }
+    }
+}
+
+/// A filesystem serving the embedded [`IncludeDirFilesystem`] in the release builds, and the
+/// source directory of it on the disk in the debug builds, so the assets can be edited without
+/// building again during the development, and are built into the binary for the production.
+///
+/// When the disk is used, it is an overlay of the embedded files, the files on the disk are
+/// served first, and the ones missing on the disk, like when the binary runs out of the source
+/// tree, are served from the embedded files. The directory listings are merged.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::ServeDir;
+/// use http_dir::fs::embedded::EmbeddedFilesystem;
+/// use include_dir::{include_dir, Dir};
+///
+/// static ASSETS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/test-files");
+///
+/// let filesystem = EmbeddedFilesystem::new(
+///     ASSETS.clone(),
+///     concat!(env!("CARGO_MANIFEST_DIR"), "/test-files"),
+/// );
+/// let service = ServeDir::new(filesystem);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmbeddedFilesystem {
+    embedded: IncludeDirFilesystem,
+    disk: DiskFilesystem,
+    from_disk: bool,
+}
+
+impl EmbeddedFilesystem {
+    /// Create a new [`EmbeddedFilesystem`] of the `embedded` files and their `source` directory,
+    /// the disk is used in the debug builds, when the `debug_assertions` are enabled.
+    pub fn new(
+        embedded: impl Into<IncludeDirFilesystem>,
+        source: impl Into<DiskFilesystem>,
+    ) -> Self {
+        Self {
+            embedded: embedded.into(),
+            disk: source.into(),
+            from_disk: cfg!(debug_assertions),
+        }
+    }
+
+    /// Serve the files on the disk over the embedded ones or not, whatever the build is.
+    pub fn from_disk(mut self, from_disk: bool) -> Self {
+        self.from_disk = from_disk;
+        self
+    }
+
+    /// Check the files on the disk are served over the embedded ones.
+    pub fn is_from_disk(&self) -> bool {
+        self.from_disk
+    }
+}
+
+// the embedded files are only served when the disk doesn't have the path
+fn is_missing<T>(result: &io::Result<T>) -> bool {
+    matches!(result, Err(err) if err.kind() == ErrorKind::NotFound)
+}
+
+impl Filesystem for EmbeddedFilesystem {
+    type File = EmbeddedFile;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        async move {
+            if self.from_disk {
+                let file = self.disk.open(path).await;
+                if !is_missing(&file) {
+                    return file.map(|file| EmbeddedFile(Inner::Disk(Box::new(file))));
+                }
+            }
+
+            let file = self.embedded.open(path).await?;
+
+            Ok(EmbeddedFile(Inner::Embedded(file)))
+        }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        async move {
+            if self.from_disk {
+                let is_dir = self.disk.is_dir(path).await;
+                if !is_missing(&is_dir) {
+                    return is_dir;
+                }
+            }
+
+            self.embedded.is_dir(path).await
+        }
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        async move {
+            if self.from_disk {
+                let metadata = self.disk.metadata(path).await;
+                if !is_missing(&metadata) {
+                    return metadata;
+                }
+            }
+
+            self.embedded.metadata(path).await
+        }
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        Box::pin(async move {
+            let embedded = self.embedded.read_dir(path).await;
+            if !self.from_disk {
+                return embedded;
+            }
+
+            let mut entries = match self.disk.read_dir(path).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == ErrorKind::NotFound => return embedded,
+                Err(err) => return Err(err),
+            };
+            if let Ok(embedded) = embedded {
+                let on_disk = entries
+                    .iter()
+                    .map(|entry| entry.name.clone())
+                    .collect::<HashSet<_>>();
+                entries.extend(
+                    embedded
+                        .into_iter()
+                        .filter(|entry| !on_disk.contains(&entry.name)),
+                );
+            }
+
+            Ok(entries)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use include_dir::{include_dir, Dir};
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    static TEST_FILES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/test-files");
+
+    async fn read(filesystem: &mut EmbeddedFilesystem, path: &str) -> io::Result<String> {
+        let mut file = filesystem.open(Path::new(path)).await?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await?;
+
+        Ok(contents)
+    }
+
+    #[tokio::test]
+    async fn overlay() {
+        let dir = std::env::temp_dir().join(format!("http_dir-embedded-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "edited").unwrap();
+        std::fs::write(dir.join("new.txt"), "new").unwrap();
+
+        let filesystem = EmbeddedFilesystem::new(TEST_FILES.clone(), dir.as_path());
+        assert_eq!(filesystem.is_from_disk(), cfg!(debug_assertions));
+
+        let mut filesystem = filesystem.from_disk(true);
+        assert_eq!(read(&mut filesystem, "index.html").await.unwrap(), "edited");
+        assert_eq!(read(&mut filesystem, "new.txt").await.unwrap(), "new");
+        assert_eq!(
+            read(&mut filesystem, "precompressed.txt").await.unwrap(),
+            "\"This is a test file!\"\n"
+        );
+        let entries = filesystem.read_dir(Path::new("")).await.unwrap();
+        let count = |name: &str| entries.iter().filter(|entry| entry.name == name).count();
+        assert_eq!(count("index.html"), 1);
+        assert_eq!(count("new.txt"), 1);
+        assert_eq!(count("precompressed.txt"), 1);
+
+        let mut filesystem = filesystem.from_disk(false);
+        assert_eq!(
+            read(&mut filesystem, "index.html").await.unwrap(),
+            "<b>HTML!</b>\n"
+        );
+        assert_eq!(
+            read(&mut filesystem, "new.txt").await.unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::hash::Hasher;
+use std::io;
+use std::io::{Cursor, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::Stream;
+use http::HeaderMap;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio_util::io::StreamReader;
+
+use crate::fs::{read_slice_at, FileExt, Filesystem, Metadata, ReadAt, ReadDir};
+
+type BoxStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+#[derive(Clone)]
+enum Content {
+    Generator(Arc<dyn Fn() -> BoxStream + Send + Sync>),
+    Bytes(Bytes),
+}
+
+#[derive(Clone)]
+struct Route {
+    content: Content,
+    metadata: Metadata,
+}
+
+/// The file of the [`GeneratedFilesystem`], a file of the inner filesystem or a generated content
+pub struct GeneratedFile<F>(Inner<F>);
+
+enum Inner<F> {
+    File(F),
+    Generated(Box<(StreamReader<BoxStream, Bytes>, Metadata)>),
+    InMemory(Box<(Cursor<Bytes>, Metadata)>),
+}
+
+impl<F: Debug> Debug for GeneratedFile<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Inner::File(file) => f.debug_tuple("File").field(file).finish(),
+            Inner::Generated(generated) => f.debug_tuple("Generated").field(&generated.1).finish(),
+            Inner::InMemory(in_memory) => f.debug_tuple("InMemory").field(&in_memory.1).finish(),
+        }
+    }
+}
+
+impl<F: AsyncRead + Unpin> AsyncRead for GeneratedFile<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().0 {
+            Inner::File(file) => Pin::new(file).poll_read(cx, buf),
+            Inner::Generated(generated) => Pin::new(&mut generated.0).poll_read(cx, buf),
+            Inner::InMemory(in_memory) => Pin::new(&mut in_memory.0).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<F: AsyncSeek + Unpin> AsyncSeek for GeneratedFile<F> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match &mut self.get_mut().0 {
+            Inner::File(file) => Pin::new(file).start_seek(position),
+            Inner::Generated(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "the generated content can't be seeked",
+            )),
+            Inner::InMemory(in_memory) => Pin::new(&mut in_memory.0).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match &mut self.get_mut().0 {
+            Inner::File(file) => Pin::new(file).poll_complete(cx),
+            Inner::Generated(_) => Poll::Ready(Ok(0)),
+            Inner::InMemory(in_memory) => Pin::new(&mut in_memory.0).poll_complete(cx),
+        }
+    }
+}
+
+impl<F: FileExt + Send + Sync> FileExt for GeneratedFile<F> {
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        async move {
+            match &self.0 {
+                Inner::File(file) => file.metadata().await,
+                Inner::Generated(generated) => Ok(generated.1.clone()),
+                Inner::InMemory(in_memory) => Ok(in_memory.1.clone()),
+            }
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        match &self.0 {
+            Inner::File(file) => file.read_at(offset, buf),
+            Inner::Generated(_) => None,
+            Inner::InMemory(in_memory) => Some(read_slice_at(in_memory.0.get_ref(), offset, buf)),
+        }
+    }
+}
+
+/// A filesystem wrapper serving the contents produced by the generators on some paths, like
+/// `/healthz` or `/version.json`, or the in-memory files like `robots.txt`, the other paths are
+/// served by the inner filesystem.
+///
+/// The generator is called for every `GET` request of its path, the content is sent without
+/// `Content-Length`, `Last-Modified` and `ETag`, and the range requests are answered with the
+/// full content. The in-memory files are served like the regular files, their `Last-Modified`
+/// is the time they are added. The `Content-Type` is guessed from the path unless it is set by
+/// the headers.
+///
+/// # Example
+///
+/// ```rust
+/// use bytes::Bytes;
+/// use futures_util::stream;
+/// use http_dir::ServeDir;
+/// use http_dir::fs::disk::DiskFilesystem;
+/// use http_dir::fs::generated::GeneratedFilesystem;
+///
+/// let filesystem = GeneratedFilesystem::new(DiskFilesystem::from("assets"))
+///     .route("/version.json", || {
+///         stream::once(async { Ok(Bytes::from(r#"{"version":"1.0.0"}"#)) })
+///     })
+///     .file("/robots.txt", "User-agent: *\nDisallow: /private/\n");
+/// let service = ServeDir::new(filesystem);
+/// ```
+#[derive(Clone)]
+pub struct GeneratedFilesystem<FS> {
+    filesystem: FS,
+    routes: Arc<HashMap<PathBuf, Route>>,
+}
+
+impl<FS: Debug> Debug for GeneratedFilesystem<FS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratedFilesystem")
+            .field("filesystem", &self.filesystem)
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<FS> GeneratedFilesystem<FS> {
+    /// Create a new [`GeneratedFilesystem`] without any route.
+    pub fn new(filesystem: FS) -> Self {
+        Self {
+            filesystem,
+            routes: Default::default(),
+        }
+    }
+
+    /// Serve the content produced by the `generator` on the `path`, like `/healthz`.
+    pub fn route<G, S>(self, path: &str, generator: G) -> Self
+    where
+        G: Fn() -> S + Send + Sync + 'static,
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        self.route_with_headers(path, HeaderMap::new(), generator)
+    }
+
+    /// Like [`route`](GeneratedFilesystem::route), but the `headers` are added to the responses,
+    /// they override the generated ones like `Content-Type`.
+    pub fn route_with_headers<G, S>(self, path: &str, headers: HeaderMap, generator: G) -> Self
+    where
+        G: Fn() -> S + Send + Sync + 'static,
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let route = Route {
+            content: Content::Generator(Arc::new(move || Box::pin(generator()) as BoxStream)),
+            metadata: Metadata {
+                modified: None,
+                len: None,
+                etag: None,
+                headers,
+            },
+        };
+
+        self.insert(path, route)
+    }
+
+    /// Serve the in-memory `contents` on the `path`, like `/robots.txt` or
+    /// `/.well-known/security.txt`, even if the inner filesystem doesn't have it.
+    pub fn file(self, path: &str, contents: impl Into<Bytes>) -> Self {
+        self.file_with_headers(path, HeaderMap::new(), contents)
+    }
+
+    /// Like [`file`](GeneratedFilesystem::file), but the `headers` are added to the responses,
+    /// they override the generated ones like `Content-Type`.
+    pub fn file_with_headers(
+        self,
+        path: &str,
+        headers: HeaderMap,
+        contents: impl Into<Bytes>,
+    ) -> Self {
+        let contents = contents.into();
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&contents);
+
+        let route = Route {
+            metadata: Metadata {
+                modified: Some(SystemTime::now()),
+                len: Some(contents.len() as u64),
+                etag: Some(format!("\"{:x}-{:x}\"", contents.len(), hasher.finish())),
+                headers,
+            },
+            content: Content::Bytes(contents),
+        };
+
+        self.insert(path, route)
+    }
+
+    fn insert(mut self, path: &str, route: Route) -> Self {
+        // the paths passed to the filesystem are relative
+        Arc::make_mut(&mut self.routes).insert(PathBuf::from(path.trim_start_matches('/')), route);
+        self
+    }
+}
+
+impl<FS> Filesystem for GeneratedFilesystem<FS>
+where
+    FS: Filesystem + Send + Sync,
+{
+    type File = GeneratedFile<FS::File>;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        async move {
+            if let Some(route) = self.routes.get(path) {
+                let metadata = route.metadata.clone();
+                let inner = match &route.content {
+                    Content::Generator(generator) => {
+                        Inner::Generated(Box::new((StreamReader::new(generator()), metadata)))
+                    }
+                    Content::Bytes(contents) => {
+                        Inner::InMemory(Box::new((Cursor::new(contents.clone()), metadata)))
+                    }
+                };
+
+                return Ok(GeneratedFile(inner));
+            }
+
+            let file = self.filesystem.open(path).await?;
+
+            Ok(GeneratedFile(Inner::File(file)))
+        }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        async move {
+            if self.routes.contains_key(path) {
+                return Ok(false);
+            }
+
+            self.filesystem.is_dir(path).await
+        }
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        async move {
+            if let Some(route) = self.routes.get(path) {
+                return Ok(route.metadata.clone());
+            }
+
+            self.filesystem.metadata(path).await
+        }
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        self.filesystem.read_dir(path)
+    }
+
+    fn read_dir_bounded<'a>(
+        &'a self,
+        path: &'a Path,
+        max_entries: usize,
+        deadline: Option<Instant>,
+    ) -> ReadDir<'a> {
+        self.filesystem
+            .read_dir_bounded(path, max_entries, deadline)
+    }
+}
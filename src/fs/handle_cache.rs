@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::future::Future;
+use std::io;
+use std::io::{ErrorKind, SeekFrom};
+use std::os::unix::fs::{FileExt as _, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use bytes::BytesMut;
+use http::HeaderMap;
+use tokio::io::ReadBuf;
+
+use crate::fs::ReadAt;
+
+// the scratch buffer and how many bytes are read into it
+type ScratchRead = Pin<Box<dyn Future<Output = io::Result<(Vec<u8>, usize)>> + Send + Sync>>;
+
+// the identity of the file behind a path, a cached handle is used only when the path still points
+// at the same unchanged file, the ctime catches the mode and the xattr changes too
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Identity {
+    dev: u64,
+    ino: u64,
+    len: u64,
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+}
+
+impl Identity {
+    fn new(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            len: metadata.len(),
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+            ctime: (metadata.ctime(), metadata.ctime_nsec()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    file: Arc<File>,
+    metadata: std::fs::Metadata,
+    identity: Identity,
+    headers: HeaderMap,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<PathBuf, Entry>,
+    tick: u64,
+}
+
+/// The open handles of the recently served files, shared by the clones of the
+/// [`DiskFilesystem`](super::disk::DiskFilesystem), the least recently used one is closed when
+/// it is full
+#[derive(Debug)]
+pub(super) struct HandleCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl HandleCache {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    /// Get the cached handle of the `path` if the `metadata` of the path still matches it.
+    pub(super) fn get(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+    ) -> Option<(CachedFile, HeaderMap)> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+
+        let entry = entries.map.get_mut(path)?;
+        if entry.identity != Identity::new(metadata) {
+            // the file is replaced or modified, the handle is reopened by the caller
+            entries.map.remove(path);
+
+            return None;
+        }
+        entry.last_used = tick;
+
+        Some((
+            CachedFile::new(entry.file.clone(), entry.metadata.clone()),
+            entry.headers.clone(),
+        ))
+    }
+
+    /// Close the cached handles whose paths aren't kept, the handles still used by the responses
+    /// are closed when they finish.
+    pub(super) fn retain(&self, keep: impl Fn(&Path) -> bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .map
+            .retain(|path, _| keep(path));
+    }
+
+    /// Cache the opened `file` of the `path`, and return a cursor of it.
+    pub(super) fn insert(
+        &self,
+        path: PathBuf,
+        file: File,
+        metadata: std::fs::Metadata,
+        headers: HeaderMap,
+    ) -> CachedFile {
+        let file = Arc::new(file);
+        let cached_file = CachedFile::new(file.clone(), metadata.clone());
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&path) {
+            let least_recently_used = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                entries.map.remove(&least_recently_used);
+            }
+        }
+
+        entries.tick += 1;
+        let last_used = entries.tick;
+        entries.map.insert(
+            path,
+            Entry {
+                file,
+                identity: Identity::new(&metadata),
+                metadata,
+                headers,
+                last_used,
+            },
+        );
+
+        cached_file
+    }
+}
+
+/// A cursor of a cached or positional handle, it reads with `pread`, so the requests sharing the
+/// handle don't move the position of each other
+pub(super) struct CachedFile {
+    file: Arc<File>,
+    metadata: std::fs::Metadata,
+    pos: u64,
+    read: Option<ScratchRead>,
+    // reused by the reads of `poll_read`, it is only zeroed when it grows
+    scratch: Vec<u8>,
+}
+
+impl Debug for CachedFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedFile")
+            .field("file", &self.file)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CachedFile {
+    pub(super) fn new(file: Arc<File>, metadata: std::fs::Metadata) -> Self {
+        Self {
+            file,
+            metadata,
+            pos: 0,
+            read: None,
+            scratch: vec![],
+        }
+    }
+
+    /// The metadata of the file when it was opened, the cached handle is dropped once it changes.
+    pub(super) fn metadata(&self) -> &std::fs::Metadata {
+        &self.metadata
+    }
+
+    pub(super) fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(read) = &mut self.read {
+                let result = ready!(read.as_mut().poll(cx));
+                self.read = None;
+                let (scratch, n) = result?;
+
+                // the buf may be smaller than the one the read started with, the rest is read
+                // again from the new position
+                let n = n.min(buf.remaining());
+                buf.put_slice(&scratch[..n]);
+                self.pos += n as u64;
+                self.scratch = scratch;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            let len = buf.remaining();
+            if len == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let file = self.file.clone();
+            let offset = self.pos;
+            let mut scratch = std::mem::take(&mut self.scratch);
+            self.read = Some(Box::pin(async move {
+                tokio::task::spawn_blocking(move || {
+                    if scratch.len() < len {
+                        scratch.resize(len, 0);
+                    }
+                    let n = file.read_at(&mut scratch[..len], offset)?;
+
+                    Ok((scratch, n))
+                })
+                .await
+                .map_err(|err| io::Error::new(ErrorKind::Other, err))?
+            }));
+        }
+    }
+
+    pub(super) fn read_at(&self, offset: u64, mut buf: BytesMut) -> ReadAt {
+        let file = self.file.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let filled = buf.len();
+                buf.resize(buf.capacity(), 0);
+                let n = file.read_at(&mut buf[filled..], offset)?;
+                buf.truncate(filled + n);
+
+                Ok(buf)
+            })
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::Other, err))?
+        })
+    }
+
+    pub(super) fn start_seek(&mut self, position: SeekFrom) -> io::Result<()> {
+        if self.read.is_some() {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                "other file operation is pending, call poll_complete before start_seek",
+            ));
+        }
+
+        let pos = match position {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.metadata.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(())
+    }
+
+    pub(super) fn position(&self) -> u64 {
+        self.pos
+    }
+}
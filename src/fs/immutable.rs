@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+use crate::fs::{
+    is_purged_by, is_purged_by_prefix, DirEntry, FileExt, Filesystem, FilesystemLayer, Metadata,
+    Purge, ReadAt, ReadDir,
+};
+
+// the not found paths are requested by the clients, so they are limited to keep the memory bound
+const MAX_NOT_FOUND_PATHS: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    // `None` means the path is not found
+    metadata: HashMap<PathBuf, Option<Metadata>>,
+    is_dir: HashMap<PathBuf, bool>,
+    read_dir: HashMap<PathBuf, Vec<DirEntry>>,
+    not_found_paths: usize,
+}
+
+impl Snapshot {
+    fn insert_result(&mut self, path: &Path, result: &io::Result<Metadata>) {
+        match result {
+            Ok(metadata) => {
+                self.metadata
+                    .insert(path.to_path_buf(), Some(metadata.clone()));
+            }
+
+            Err(err) => self.insert_error(path, err),
+        }
+    }
+
+    fn retain(&mut self, keep: impl Fn(&Path) -> bool) {
+        let not_found_paths = &mut self.not_found_paths;
+        self.metadata.retain(|path, metadata| {
+            let kept = keep(path);
+            if !kept && metadata.is_none() {
+                *not_found_paths -= 1;
+            }
+
+            kept
+        });
+        self.is_dir.retain(|path, _| keep(path));
+        self.read_dir.retain(|path, _| keep(path));
+    }
+
+    // only the not found paths are remembered, the other errors may be temporary
+    fn insert_error(&mut self, path: &Path, err: &io::Error) {
+        if err.kind() == ErrorKind::NotFound
+            && self.not_found_paths < MAX_NOT_FOUND_PATHS
+            && self.metadata.insert(path.to_path_buf(), None).is_none()
+        {
+            self.not_found_paths += 1;
+        }
+    }
+}
+
+type SharedSnapshot = Arc<RwLock<Snapshot>>;
+
+/// The file of the [`ImmutableFilesystem`], its [`Metadata`] is taken from the snapshot
+#[derive(Debug)]
+pub struct ImmutableFile<F> {
+    file: F,
+    path: PathBuf,
+    snapshot: SharedSnapshot,
+}
+
+impl<F: AsyncRead + Unpin> AsyncRead for ImmutableFile<F> {
+    #[inline]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().file).poll_read(cx, buf)
+    }
+}
+
+impl<F: AsyncSeek + Unpin> AsyncSeek for ImmutableFile<F> {
+    #[inline]
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        Pin::new(&mut self.get_mut().file).start_seek(position)
+    }
+
+    #[inline]
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.get_mut().file).poll_complete(cx)
+    }
+}
+
+impl<F: FileExt + Send + Sync> FileExt for ImmutableFile<F> {
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        async move {
+            if let Some(Some(metadata)) = self.snapshot.read().unwrap().metadata.get(&self.path) {
+                return Ok(metadata.clone());
+            }
+
+            let result = self.file.metadata().await;
+            self.snapshot
+                .write()
+                .unwrap()
+                .insert_result(&self.path, &result);
+
+            result
+        }
+    }
+
+    #[inline]
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        self.file.read_at(offset, buf)
+    }
+}
+
+/// A filesystem wrapper which assumes the files never change during the process lifetime, see
+/// [`ServeDir::immutable`](crate::ServeDir::immutable)
+///
+/// The metadata, the dir checks, the dir entries and the not found paths, including the missing
+/// precompressed variants, are read from the inner filesystem once and kept forever. The clones
+/// share the same snapshot. Up to 4096 not found paths are remembered, the others are still
+/// checked on the inner filesystem.
+///
+/// The snapshot can be dropped with the [`Purge`] methods when the files are changed anyway, like
+/// by a deploy.
+#[derive(Debug, Clone)]
+pub struct ImmutableFilesystem<FS> {
+    filesystem: FS,
+    snapshot: SharedSnapshot,
+}
+
+impl<FS> ImmutableFilesystem<FS> {
+    /// Create a new [`ImmutableFilesystem`] with an empty snapshot.
+    pub fn new(filesystem: FS) -> Self {
+        Self {
+            filesystem,
+            snapshot: Default::default(),
+        }
+    }
+
+    fn is_not_found(&self, path: &Path) -> bool {
+        matches!(self.snapshot.read().unwrap().metadata.get(path), Some(None))
+    }
+
+    // like the `Purge` methods, for the `ServeDir::purge_method` which purges the inner filesystem
+    // without knowing its type implements `Purge`
+    pub(crate) fn purge_with(
+        &self,
+        purge_inner: impl FnOnce(&FS),
+        is_purged: impl Fn(&Path) -> bool,
+    ) {
+        purge_inner(&self.filesystem);
+        self.snapshot
+            .write()
+            .unwrap()
+            .retain(|cached| !is_purged(cached));
+    }
+}
+
+/// A [`FilesystemLayer`] wrapping the filesystem with the [`ImmutableFilesystem`], each
+/// layered filesystem has its own snapshot
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImmutableLayer;
+
+impl ImmutableLayer {
+    /// Create a new [`ImmutableLayer`].
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<FS> FilesystemLayer<FS> for ImmutableLayer
+where
+    ImmutableFilesystem<FS>: Filesystem,
+{
+    type Filesystem = ImmutableFilesystem<FS>;
+
+    fn layer(&self, inner: FS) -> Self::Filesystem {
+        ImmutableFilesystem::new(inner)
+    }
+}
+
+// the inner filesystem is purged first, so the snapshot isn't refilled from its stale cache
+impl<FS: Purge> Purge for ImmutableFilesystem<FS> {
+    fn purge(&self, path: &Path) {
+        self.filesystem.purge(path);
+        self.snapshot
+            .write()
+            .unwrap()
+            .retain(|cached| !is_purged_by(cached, path));
+    }
+
+    fn purge_prefix(&self, prefix: &Path) {
+        self.filesystem.purge_prefix(prefix);
+        self.snapshot
+            .write()
+            .unwrap()
+            .retain(|cached| !is_purged_by_prefix(cached, prefix));
+    }
+
+    fn purge_all(&self) {
+        self.filesystem.purge_all();
+        *self.snapshot.write().unwrap() = Snapshot::default();
+    }
+}
+
+impl<FS> Filesystem for ImmutableFilesystem<FS>
+where
+    FS: Filesystem + Send + Sync,
+{
+    type File = ImmutableFile<FS::File>;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        async move {
+            if self.is_not_found(path) {
+                return Err(io::Error::from(ErrorKind::NotFound));
+            }
+
+            match self.filesystem.open(path).await {
+                Ok(file) => Ok(ImmutableFile {
+                    file,
+                    path: path.to_path_buf(),
+                    snapshot: self.snapshot.clone(),
+                }),
+
+                Err(err) => {
+                    self.snapshot.write().unwrap().insert_error(path, &err);
+
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        async move {
+            if let Some(is_dir) = self.snapshot.read().unwrap().is_dir.get(path) {
+                return Ok(*is_dir);
+            }
+
+            let is_dir = self.filesystem.is_dir(path).await?;
+            self.snapshot
+                .write()
+                .unwrap()
+                .is_dir
+                .insert(path.to_path_buf(), is_dir);
+
+            Ok(is_dir)
+        }
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        async move {
+            match self.snapshot.read().unwrap().metadata.get(path) {
+                Some(Some(metadata)) => return Ok(metadata.clone()),
+                Some(None) => return Err(io::Error::from(ErrorKind::NotFound)),
+                None => {}
+            }
+
+            let result = self.filesystem.metadata(path).await;
+            self.snapshot.write().unwrap().insert_result(path, &result);
+
+            result
+        }
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        Box::pin(async move {
+            if let Some(entries) = self.snapshot.read().unwrap().read_dir.get(path) {
+                return Ok(entries.clone());
+            }
+
+            let entries = self.filesystem.read_dir(path).await?;
+            self.snapshot
+                .write()
+                .unwrap()
+                .read_dir
+                .insert(path.to_path_buf(), entries.clone());
+
+            Ok(entries)
+        })
+    }
+}
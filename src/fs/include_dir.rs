@@ -1,19 +1,32 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::future::{ready, Future};
 use std::io;
 use std::io::{Error, ErrorKind, SeekFrom};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Once};
 use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
 
+use bytes::BytesMut;
 use include_dir::{Dir, DirEntry, File};
 use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
 
-use crate::fs::{FileExt, Filesystem, Metadata};
+#[cfg(feature = "include-dir-compressed")]
+use crate::fs::compressed;
+#[cfg(feature = "include-dir-compressed")]
+pub use crate::fs::compressed::{compress_dir, CompressedEncoding};
+use crate::fs::{
+    read_slice_at, DirEntry as FsDirEntry, FileExt, Filesystem, Metadata, ReadAt, ReadDir,
+};
 
 /// A [`include_dir`](https://docs.rs/include_dir/latest/include_dir) based file wrapper
 pub struct IncludeDirFile {
     index: usize,
-    file: &'static File<'static>,
+    // decompressed when only the compressed file is embedded
+    contents: Cow<'static, [u8]>,
+    modified: Option<SystemTime>,
 }
 
 impl AsyncRead for IncludeDirFile {
@@ -23,11 +36,12 @@ impl AsyncRead for IncludeDirFile {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        let mut data = self.file.contents();
-        if self.index >= data.len() {
+        let this = &mut *self;
+        let mut data = &*this.contents;
+        if this.index >= data.len() {
             return Poll::Ready(Ok(()));
         }
-        data = &data[self.index..];
+        data = &data[this.index..];
 
         let before_filled = buf.filled().len();
 
@@ -35,7 +49,7 @@ impl AsyncRead for IncludeDirFile {
 
         let filled = buf.filled().len() - before_filled;
 
-        self.index += filled;
+        this.index += filled;
 
         Poll::Ready(Ok(()))
     }
@@ -49,8 +63,7 @@ impl AsyncSeek for IncludeDirFile {
             }
             SeekFrom::End(end) => {
                 self.index = self
-                    .file
-                    .contents()
+                    .contents
                     .len()
                     .checked_add_signed(end as _)
                     .ok_or_else(|| {
@@ -80,43 +93,206 @@ impl AsyncSeek for IncludeDirFile {
 }
 
 impl FileExt for IncludeDirFile {
-    type Metadata<'a> = impl Future<Output=io::Result<Metadata>> + Send + Sync + 'a where Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
 
     fn metadata(&self) -> Self::Metadata<'_> {
         ready(Ok(self._metadata()))
     }
+
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        Some(read_slice_at(&self.contents, offset, buf))
+    }
 }
 
 impl IncludeDirFile {
     fn _metadata(&self) -> Metadata {
-        let len = self.file.contents().len() as u64;
-
-        self.file
-            .metadata()
-            .map(|raw_metadata| Metadata {
-                modified: Some(raw_metadata.modified()),
-                len,
-            })
-            .unwrap_or(Metadata {
-                modified: None,
-                len,
-            })
+        let len = self.contents.len() as u64;
+
+        Metadata::new(self.modified, Some(len))
     }
 }
 
 /// A [`include_dir`](https://docs.rs/include_dir/latest/include_dir) based filesystem implement
+///
+/// The `include-dir` feature enables the `metadata` feature of `include_dir`, so the files have
+/// the modified time, unless the [`Dir`] is built by other crates without it.
+///
+/// With the `include-dir-compressed` feature, a missing file is decompressed from the embedded
+/// `.br` or `.gz` file, see [`compress_dir`].
 #[derive(Debug, Clone)]
 pub struct IncludeDirFilesystem {
     dir: Dir<'static>,
+    build_time: Option<SystemTime>,
+    // shared by the clones, as the ServeDir is cloned for every request
+    modified: Arc<HashMap<PathBuf, SystemTime>>,
 }
 
 impl IncludeDirFilesystem {
     /// create [`IncludeDirFilesystem`] from a [`Dir`]
     pub fn new(dir: Dir<'static>) -> Self {
-        Self { dir }
+        Self {
+            dir,
+            build_time: None,
+            modified: Default::default(),
+        }
+    }
+
+    /// Set the modified time of the files without metadata, so the embedded files have a
+    /// reproducible `Last-Modified` and can be responded with `304 Not Modified`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// use http_dir::fs::include_dir::IncludeDirFilesystem;
+    /// use include_dir::{include_dir, Dir};
+    ///
+    /// static DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/test-files");
+    ///
+    /// // use the `SOURCE_DATE_EPOCH` of the build
+    /// let build_time = option_env!("SOURCE_DATE_EPOCH")
+    ///     .and_then(|epoch| epoch.parse().ok())
+    ///     .map(|epoch| UNIX_EPOCH + Duration::from_secs(epoch))
+    ///     .unwrap_or(UNIX_EPOCH);
+    ///
+    /// let filesystem = IncludeDirFilesystem::new(DIR.clone()).build_time(build_time);
+    /// ```
+    pub fn build_time(mut self, build_time: SystemTime) -> Self {
+        self.build_time = Some(build_time);
+        self
+    }
+
+    /// Set the modified time of the file at `path`, which is relative to the root of the [`Dir`],
+    /// it overrides the metadata of the file and the [`IncludeDirFilesystem::build_time`].
+    pub fn modified<P: Into<PathBuf>>(mut self, path: P, modified: SystemTime) -> Self {
+        Arc::make_mut(&mut self.modified).insert(path.into(), modified);
+        self
+    }
+
+    /// Check the files at the `paths` are embedded, or embedded compressed with the
+    /// `include-dir-compressed` feature, so a missing asset fails the startup instead of being
+    /// not found in the production, the error lists all the missing paths.
+    ///
+    /// The paths are relative to the root of the [`Dir`], the leading `/` is ignored. See
+    /// [`embedded_path!`](crate::embedded_path) to check them at the compile time instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::fs::include_dir::IncludeDirFilesystem;
+    /// use include_dir::{include_dir, Dir};
+    ///
+    /// static DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/test-files");
+    ///
+    /// let filesystem = IncludeDirFilesystem::new(DIR.clone());
+    /// filesystem
+    ///     .verify(["/index.html", "precompressed.txt"])
+    ///     .expect("the assets aren't embedded");
+    /// assert!(filesystem.verify(["missing.txt"]).is_err());
+    /// ```
+    pub fn verify<I, P>(&self, paths: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let missing = paths
+            .into_iter()
+            .map(|path| path.as_ref().trim_start_matches('/').to_string())
+            .filter(|path| !self.contains_file(Path::new(path)))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(Error::new(
+            ErrorKind::NotFound,
+            format!("files not embedded: {}", missing.join(", ")),
+        ))
+    }
+
+    fn contains_file(&self, path: &Path) -> bool {
+        if self.dir.get_file(path).is_some() {
+            return true;
+        }
+
+        #[cfg(feature = "include-dir-compressed")]
+        for encoding in CompressedEncoding::ALL {
+            let mut compressed_path = path.as_os_str().to_os_string();
+            compressed_path.push(encoding.extension());
+
+            if self.dir.get_file(&compressed_path).is_some() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn find_file(&self, path: &Path) -> io::Result<IncludeDirFile> {
+        if let Some(file) = self.dir.get_file(path) {
+            return Ok(self.open_file(path, file, Cow::Borrowed(file.contents())));
+        }
+
+        #[cfg(feature = "include-dir-compressed")]
+        for encoding in CompressedEncoding::ALL {
+            let mut compressed_path = path.as_os_str().to_os_string();
+            compressed_path.push(encoding.extension());
+
+            if let Some(file) = self.dir.get_file(&compressed_path) {
+                let contents = compressed::decompress(file.contents(), encoding)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+                return Ok(self.open_file(path, file, Cow::Owned(contents)));
+            }
+        }
+
+        Err(Error::from(ErrorKind::NotFound))
+    }
+
+    fn open_file(
+        &self,
+        path: &Path,
+        file: &'static File<'static>,
+        contents: Cow<'static, [u8]>,
+    ) -> IncludeDirFile {
+        let modified = self
+            .modified
+            .get(path)
+            .copied()
+            .or_else(|| file.metadata().map(|metadata| metadata.modified()))
+            .or(self.build_time);
+
+        if modified.is_none() {
+            warn_missing_metadata();
+        }
+
+        IncludeDirFile {
+            index: 0,
+            contents,
+            modified,
+        }
     }
 }
 
+// the `Last-Modified` is missing without the modified time, so the conditional requests can't
+// get `304 Not Modified`
+fn warn_missing_metadata() {
+    static WARN: Once = Once::new();
+
+    WARN.call_once(|| {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            "include_dir file has no metadata, enable the `metadata` feature of include_dir, or \
+             set the modified time by IncludeDirFilesystem::build_time or \
+             IncludeDirFilesystem::modified"
+        );
+    });
+}
+
 impl From<Dir<'static>> for IncludeDirFilesystem {
     fn from(value: Dir<'static>) -> Self {
         Self::new(value)
@@ -125,17 +301,21 @@ impl From<Dir<'static>> for IncludeDirFilesystem {
 
 impl Filesystem for IncludeDirFilesystem {
     type File = IncludeDirFile;
-    type OpenFile<'a> = impl Future<Output=io::Result<Self::File>> + Send + Sync + 'a where Self: 'a;
-    type IsDir<'a> = impl Future<Output=io::Result<bool>> + Send + Sync + 'a where Self: 'a;
-    type Metadata<'a> = impl Future<Output=io::Result<Metadata>> + Send + Sync + 'a where Self: 'a;
+    type OpenFile<'a>
+        = impl Future<Output = io::Result<Self::File>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type IsDir<'a>
+        = impl Future<Output = io::Result<bool>> + Send + Sync + 'a
+    where
+        Self: 'a;
+    type Metadata<'a>
+        = impl Future<Output = io::Result<Metadata>> + Send + Sync + 'a
+    where
+        Self: 'a;
 
     fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
-        ready(
-            self.dir
-                .get_file(path)
-                .ok_or_else(|| Error::from(ErrorKind::NotFound))
-                .map(|file| IncludeDirFile { index: 0, file }),
-        )
+        ready(self.find_file(path))
     }
 
     fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
@@ -154,19 +334,35 @@ impl Filesystem for IncludeDirFilesystem {
     }
 
     fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
-        let result = match self
-            .dir
-            .get_entry(path)
-            .ok_or_else(|| Error::from(ErrorKind::NotFound))
-            .map(|entry| match entry {
-                DirEntry::Dir(_) => Err(Error::from(ErrorKind::NotFound)),
-                DirEntry::File(file) => Ok(IncludeDirFile { index: 0, file }._metadata()),
-            }) {
-            Err(err) => Err(err),
-            Ok(Err(err)) => Err(err),
-            Ok(Ok(metadata)) => Ok(metadata),
+        let result = match self.dir.get_entry(path) {
+            Some(DirEntry::Dir(_)) => Err(Error::from(ErrorKind::NotFound)),
+            _ => self.find_file(path).map(|file| file._metadata()),
         };
 
         ready(result)
     }
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        // same as is_dir, empty path means the root dir
+        let dir = if path.as_os_str().is_empty() {
+            Some(&self.dir)
+        } else {
+            self.dir.get_dir(path)
+        };
+
+        let result = dir
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+            .map(|dir| {
+                dir.entries()
+                    .iter()
+                    .filter_map(|entry| {
+                        Some(FsDirEntry {
+                            name: entry.path().file_name()?.to_os_string(),
+                            is_dir: matches!(entry, DirEntry::Dir(_)),
+                        })
+                    })
+                    .collect()
+            });
+
+        Box::pin(ready(result))
+    }
 }
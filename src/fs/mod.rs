@@ -1,30 +1,91 @@
 //! Allow user implement own filesystem, to provide file for the [`ServeDir`](crate::ServeDir)
 
+use std::ffi::OsString;
 use std::future::Future;
 use std::io;
 use std::path::Path;
-use std::time::SystemTime;
+use std::pin::Pin;
+use std::time::{Instant, SystemTime};
 
+use bytes::BytesMut;
+use futures_util::future::BoxFuture;
+use http::HeaderMap;
 use tokio::io::{AsyncRead, AsyncSeek};
 
+#[cfg(feature = "blocking")]
+/// a wrapper running a synchronous storage on the blocking threads
+pub mod blocking;
+#[cfg(feature = "include-dir-compressed")]
+mod compressed;
 #[cfg(feature = "disk")]
 /// a [`tokio`](https://docs.rs/tokio/latest/tokio/) based implement
 pub mod disk;
+#[cfg(all(feature = "disk", feature = "include-dir"))]
+/// the embedded files in the release builds, and their source directory in the debug builds
+pub mod embedded;
+/// a wrapper serving the generated contents on some paths
+pub mod generated;
+#[cfg(all(feature = "disk", unix))]
+mod handle_cache;
+/// a wrapper caching the results of a filesystem whose files never change
+pub mod immutable;
 #[cfg(feature = "include-dir")]
 /// a [`include_dir`](https://docs.rs/include_dir/latest/include_dir) based implement
 pub mod include_dir;
-pub(crate) mod single_file;
 
 /// A simple Metadata
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Metadata {
     /// file last modified time
     pub modified: Option<SystemTime>,
 
-    /// file size
-    pub len: u64,
+    /// file size, `None` if the filesystem can't report it cheaply, like a pipe or a generated
+    /// content, then the file is sent without `Content-Length` and the range requests are
+    /// answered with the full file
+    pub len: Option<u64>,
+
+    /// the `ETag` of the file with the quotes, like `"2f-5e8a1b2c"`, `None` if the filesystem
+    /// can't generate it cheaply, then the `ETag` is made of the size and the modified time
+    pub etag: Option<String>,
+
+    /// the extra response headers of the file, they override the generated ones like
+    /// `Content-Type`
+    pub headers: HeaderMap,
+}
+
+impl Metadata {
+    /// Create a new [`Metadata`] without the `ETag` and the extra headers, they can be set on the
+    /// fields.
+    pub fn new(modified: Option<SystemTime>, len: Option<u64>) -> Self {
+        Self {
+            modified,
+            len,
+            etag: None,
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+/// A directory entry
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// entry file name
+    pub name: OsString,
+
+    /// entry is a dir or not
+    pub is_dir: bool,
 }
 
+/// The future of [`FileExt::read_at`], it resolves to the buffer with the read bytes appended
+pub type ReadAt = Pin<Box<dyn Future<Output = io::Result<BytesMut>> + Send + Sync>>;
+
+/// The future of [`Filesystem::read_dir`] and [`Filesystem::read_dir_bounded`]
+pub type ReadDir<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<DirEntry>>> + Send + Sync + 'a>>;
+
+/// The future of [`Filesystem::reroot`]
+pub type Reroot<'a, F> = Pin<Box<dyn Future<Output = Option<F>> + Send + Sync + 'a>>;
+
 /// File extension
 pub trait FileExt {
     type Metadata<'a>: Future<Output = io::Result<Metadata>> + Send + Sync + 'a
@@ -33,6 +94,28 @@ pub trait FileExt {
 
     /// get file [`Metadata`]
     fn metadata(&self) -> Self::Metadata<'_>;
+
+    /// Read up to the spare capacity of `buf` from the `offset` without moving the position of
+    /// the file, nothing is appended at the end of the file.
+    ///
+    /// The file bodies are read with it when it is supported, so the ranges are served without
+    /// seeking, like with `pread`, or with the range requests of an object store. Returns `None`
+    /// if it isn't supported, then the file is seeked to the start of the range and read, which
+    /// is the default.
+    fn read_at(&self, offset: u64, buf: BytesMut) -> Option<ReadAt> {
+        let _ = (offset, buf);
+
+        None
+    }
+}
+
+// `FileExt::read_at` of the in-memory contents
+pub(crate) fn read_slice_at(contents: &[u8], offset: u64, mut buf: BytesMut) -> ReadAt {
+    let start = usize::try_from(offset).map_or(contents.len(), |offset| offset.min(contents.len()));
+    let end = contents.len().min(start + (buf.capacity() - buf.len()));
+    buf.extend_from_slice(&contents[start..end]);
+
+    Box::pin(std::future::ready(Ok(buf)))
 }
 
 /// Define a filesystem trait
@@ -57,4 +140,201 @@ pub trait Filesystem {
 
     /// get [`Metadata`] by path
     fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a>;
+
+    /// list the [`entries`](DirEntry) of the dir
+    ///
+    /// Only the [`ServeDir::search`](crate::ServeDir::search) and the
+    /// [`RouteManifest`](crate::RouteManifest) list the dirs, so the default fails with
+    /// [`io::ErrorKind::Unsupported`], then the search responds with `501 Not Implemented`.
+    fn read_dir<'a>(&'a self, path: &'a Path) -> ReadDir<'a> {
+        let _ = path;
+
+        Box::pin(std::future::ready(Err(io::Error::from(
+            io::ErrorKind::Unsupported,
+        ))))
+    }
+
+    /// list the [`entries`](DirEntry) of the dir like [`Filesystem::read_dir`], but stop once
+    /// more than `max_entries` are read, so the caller knows there are more, and fail with
+    /// [`io::ErrorKind::TimedOut`] once the `deadline` passes, so a huge dir isn't read in full
+    /// by the [`ServeDir::search`](crate::ServeDir::search)
+    ///
+    /// The default lists the whole dir with [`Filesystem::read_dir`], the filesystems reading the
+    /// entries one by one, like the [`DiskFilesystem`](crate::fs::disk::DiskFilesystem), override
+    /// it.
+    fn read_dir_bounded<'a>(
+        &'a self,
+        path: &'a Path,
+        max_entries: usize,
+        deadline: Option<Instant>,
+    ) -> ReadDir<'a> {
+        let _ = deadline;
+        let read_dir = self.read_dir(path);
+
+        Box::pin(async move {
+            let mut entries = read_dir.await?;
+            entries.truncate(max_entries.saturating_add(1));
+
+            Ok(entries)
+        })
+    }
+
+    /// wrap the filesystem with the [`FilesystemLayer`], the layers are stacked from the inside
+    /// out, the last one sees the requests first
+    fn layer<L>(self, layer: L) -> L::Filesystem
+    where
+        L: FilesystemLayer<Self>,
+        Self: Sized,
+    {
+        layer.layer(self)
+    }
+
+    /// the filesystem serving the `root` instead, for the requests carrying a
+    /// [`RootOverride`](crate::RootOverride), `None` if the filesystem can't be rerooted or the
+    /// `root` is rejected
+    fn reroot<'a>(&'a self, root: &'a Path) -> Reroot<'a, Self>
+    where
+        Self: Sized + Send + Sync,
+    {
+        let _ = root;
+
+        Box::pin(std::future::ready(None))
+    }
+}
+
+/// Wrap a [`Filesystem`] with another one, like the `tower::Layer` of the services, so the
+/// wrappers, like the [`ImmutableLayer`](immutable::ImmutableLayer), can be stacked with
+/// [`Filesystem::layer`]:
+///
+/// ```rust
+/// use http_dir::fs::disk::DiskFilesystem;
+/// use http_dir::fs::generated::GeneratedFilesystem;
+/// use http_dir::fs::immutable::ImmutableLayer;
+/// use http_dir::fs::{layer_fn, Filesystem};
+///
+/// let filesystem = DiskFilesystem::from("assets")
+///     .layer(ImmutableLayer::new())
+///     .layer(layer_fn(|inner| {
+///         GeneratedFilesystem::new(inner).file("/version.txt", env!("CARGO_PKG_VERSION"))
+///     }));
+/// ```
+pub trait FilesystemLayer<FS> {
+    /// the wrapping filesystem
+    type Filesystem: Filesystem;
+
+    /// wrap the `inner` filesystem
+    fn layer(&self, inner: FS) -> Self::Filesystem;
+}
+
+/// A [`FilesystemLayer`] from a closure, see [`layer_fn`]
+#[derive(Debug, Clone, Copy)]
+pub struct LayerFn<F>(F);
+
+/// Create a [`FilesystemLayer`] wrapping the inner filesystem with the closure `f`.
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn(f)
+}
+
+impl<F, FS, Out> FilesystemLayer<FS> for LayerFn<F>
+where
+    F: Fn(FS) -> Out,
+    Out: Filesystem,
+{
+    type Filesystem = Out;
+
+    fn layer(&self, inner: FS) -> Self::Filesystem {
+        (self.0)(inner)
+    }
+}
+
+/// An object safe part of the [`Filesystem`], for the hooks which aren't generic over the
+/// filesystem, like [`ServeDir::directory_index`](crate::ServeDir::directory_index)
+pub trait DynFilesystem: Send + Sync {
+    /// check the path is a dir or not, see [`Filesystem::is_dir`]
+    fn is_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<bool>>;
+
+    /// get [`Metadata`] by path, see [`Filesystem::metadata`]
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Metadata>>;
+
+    /// list the [`entries`](DirEntry) of the dir, see [`Filesystem::read_dir`]
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirEntry>>>;
+}
+
+impl<FS: Filesystem + Send + Sync> DynFilesystem for FS {
+    fn is_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<bool>> {
+        Box::pin(Filesystem::is_dir(self, path))
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Metadata>> {
+        Box::pin(Filesystem::metadata(self, path))
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<Vec<DirEntry>>> {
+        Filesystem::read_dir(self, path)
+    }
+}
+
+/// A filesystem caching what it reads, its cached entries can be dropped after the files are
+/// changed, like by a deploy, without restarting the service, see
+/// [`ServeDir::purge_method`](crate::ServeDir::purge_method)
+pub trait Purge {
+    /// drop the cached entries of the file or dir, including its precompressed variants, like
+    /// `foo.txt.gz` of `foo.txt`, and the entries of its parent dir
+    fn purge(&self, path: &Path);
+
+    /// drop the cached entries of the `prefix` dir, everything under it, and its parent dir
+    fn purge_prefix(&self, prefix: &Path);
+
+    /// drop all the cached entries
+    fn purge_all(&self);
+}
+
+/// Check the `cached` path is dropped by the [`Purge::purge`] of the `path`.
+pub(crate) fn is_purged_by(cached: &Path, path: &Path) -> bool {
+    if cached == path || Some(cached) == path.parent() {
+        return true;
+    }
+
+    match (cached.file_name(), path.file_name()) {
+        (Some(cached_name), Some(name)) if cached.parent() == path.parent() => cached_name
+            .to_string_lossy()
+            .strip_prefix(&*name.to_string_lossy())
+            .is_some_and(|extension| extension.starts_with('.')),
+        _ => false,
+    }
+}
+
+/// Check the `cached` path is dropped by the [`Purge::purge_prefix`] of the `prefix`.
+pub(crate) fn is_purged_by_prefix(cached: &Path, prefix: &Path) -> bool {
+    cached.starts_with(prefix) || Some(cached) == prefix.parent()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn purged_paths() {
+        let path = Path::new("css/site.css");
+        for cached in ["css/site.css", "css/site.css.gz", "css/site.css.br", "css"] {
+            assert!(is_purged_by(Path::new(cached), path), "{cached}");
+        }
+        for cached in [
+            "css/site.cssx",
+            "css/other.css",
+            "site.css.gz",
+            "",
+            "css/site.css/a",
+        ] {
+            assert!(!is_purged_by(Path::new(cached), path), "{cached}");
+        }
+
+        let prefix = Path::new("css");
+        for cached in ["css", "css/site.css", "css/a/b", ""] {
+            assert!(is_purged_by_prefix(Path::new(cached), prefix), "{cached}");
+        }
+        for cached in ["cssx", "js/site.js"] {
+            assert!(!is_purged_by_prefix(Path::new(cached), prefix), "{cached}");
+        }
+    }
 }
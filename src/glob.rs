@@ -0,0 +1,84 @@
+/// A simple glob pattern, supports `*` (any sequence of chars) and `?` (any single char)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    pattern: Vec<char>,
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    /// Check if the pattern has any wildcard
+    pub(crate) fn has_wildcard(pattern: &str) -> bool {
+        pattern.contains(['*', '?'])
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+
+        let (mut p, mut t) = (0, 0);
+        // position of the last `*` in pattern, and the text position it is matching from
+        let mut backtrack = None;
+
+        while t < text.len() {
+            match self.pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                }
+                Some('?') => {
+                    p += 1;
+                    t += 1;
+                }
+                Some(c) if *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match backtrack {
+                    // let the last `*` eat one more char and try again
+                    Some((star_p, star_t)) => {
+                        backtrack = Some((star_p, star_t + 1));
+                        p = star_p + 1;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.pattern[p..].iter().all(|c| *c == '*')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal() {
+        assert!(Glob::new("foo.txt").is_match("foo.txt"));
+        assert!(!Glob::new("foo.txt").is_match("foo.txt.gz"));
+        assert!(!Glob::new("foo.txt").is_match("foo"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(Glob::new("*.txt").is_match("foo.txt"));
+        assert!(Glob::new("*.txt").is_match(".txt"));
+        assert!(Glob::new("*").is_match(""));
+        assert!(Glob::new("f*o*.txt").is_match("foo.txt"));
+        assert!(Glob::new("*precompressed*").is_match("only_precompressed.txt.gz"));
+        assert!(!Glob::new("*.txt").is_match("foo.txt.gz"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(Glob::new("?.txt").is_match("a.txt"));
+        assert!(Glob::new("你?.txt").is_match("你好.txt"));
+        assert!(!Glob::new("?.txt").is_match(".txt"));
+        assert!(!Glob::new("?.txt").is_match("ab.txt"));
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use http::HeaderMap;
+
+type Hook = Arc<dyn Fn(&mut HeaderMap) + Send + Sync>;
+
+/// Change the headers of all the responses right before they are returned, see
+/// [`ServeDir::map_response_headers`](crate::ServeDir::map_response_headers).
+#[derive(Clone)]
+pub(crate) struct HeaderHook(Hook);
+
+impl Debug for HeaderHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeaderHook").finish_non_exhaustive()
+    }
+}
+
+impl HeaderHook {
+    pub(crate) fn new<M>(hook: M) -> Self
+    where
+        M: Fn(&mut HeaderMap) + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        (self.0)(headers);
+    }
+}
@@ -1,13 +1,130 @@
-use std::time::SystemTime;
+use std::fmt::{self, Write};
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use http::header::HeaderValue;
+use http::header::{self, HeaderValue};
+use http::{HeaderMap, StatusCode};
 use httpdate::HttpDate;
 
+use crate::fs::Metadata;
+
+#[derive(Clone, Copy)]
 pub(super) struct LastModified(pub(super) HttpDate);
 
-impl From<SystemTime> for LastModified {
-    fn from(time: SystemTime) -> Self {
-        LastModified(time.into())
+impl LastModified {
+    /// The modified time later than `now` is replaced by `now`, as RFC 7232 requires.
+    pub(super) fn new(modified: SystemTime, now: SystemTime) -> Self {
+        LastModified(modified.min(now).into())
+    }
+
+    /// Parse the `Last-Modified` of a response, invalid values are ignored.
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<LastModified> {
+        std::str::from_utf8(value.as_bytes())
+            .ok()
+            .and_then(|value| httpdate::parse_http_date(value).ok())
+            .map(|time| LastModified(time.into()))
+    }
+
+    /// Format the header value without the intermediate `String`.
+    pub(super) fn header_value(&self) -> HeaderValue {
+        // the IMF-fixdate is always 29 bytes long
+        let mut buf = StackBuf::<29>::new();
+        write!(buf, "{}", self.0).expect("http date is longer than 29 bytes");
+
+        buf.header_value()
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct ETag(HeaderValue);
+
+impl ETag {
+    /// The `ETag` of the filesystem, or the one made of the size and the modified time of the
+    /// file, `None` if the file has neither.
+    pub(super) fn new(meta: &Metadata) -> Option<Self> {
+        if let Some(etag) = &meta.etag {
+            return HeaderValue::from_str(etag).ok().map(ETag);
+        }
+
+        let len = meta.len?;
+        let modified = meta.modified?.duration_since(UNIX_EPOCH).ok()?.as_micros();
+        // the quotes, a u64, "-" and a u128 in hex
+        let mut buf = StackBuf::<51>::new();
+        write!(buf, "\"{len:x}-{modified:x}\"").expect("etag is longer than 51 bytes");
+
+        Some(ETag(buf.header_value()))
+    }
+
+    /// The `ETag` of a response.
+    pub(super) fn from_header_value(value: &HeaderValue) -> ETag {
+        ETag(value.clone())
+    }
+
+    pub(super) fn header_value(&self) -> HeaderValue {
+        self.0.clone()
+    }
+
+    /// The `ETag` as reported by the [`ServedFile`](crate::ServedFile), the stat and the manifest.
+    pub(super) fn into_string(self) -> String {
+        String::from_utf8_lossy(self.0.as_bytes()).into_owned()
+    }
+}
+
+/// Format the `Content-Range` header value, the `range` is `None` for the unsatisfied range.
+pub(super) fn content_range(range: Option<&RangeInclusive<u64>>, size: u64) -> HeaderValue {
+    // "bytes " + 3 u64 + "-" + "/"
+    let mut buf = StackBuf::<68>::new();
+    buf.push(b"bytes ");
+    match range {
+        None => buf.push(b"*"),
+        Some(range) => {
+            buf.push_u64(*range.start());
+            buf.push(b"-");
+            buf.push_u64(*range.end());
+        }
+    }
+    buf.push(b"/");
+    buf.push_u64(size);
+
+    buf.header_value()
+}
+
+/// A fixed capacity buffer to format the header values on the stack.
+struct StackBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackBuf<N> {
+    fn new() -> Self {
+        StackBuf {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn push_u64(&mut self, n: u64) {
+        self.push(itoa::Buffer::new().format(n).as_bytes());
+    }
+
+    fn header_value(&self) -> HeaderValue {
+        HeaderValue::from_bytes(&self.buf[..self.len]).expect("formatted an invalid header value")
+    }
+}
+
+impl<const N: usize> Write for StackBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.len + s.len() > N {
+            return Err(fmt::Error);
+        }
+        self.push(s.as_bytes());
+
+        Ok(())
     }
 }
 
@@ -19,6 +136,11 @@ impl IfModifiedSince {
         self.0 < last_modified.0
     }
 
+    /// Check if the date is later than `now`.
+    pub(super) fn is_after(&self, now: SystemTime) -> bool {
+        self.0 > HttpDate::from(now)
+    }
+
     /// convert a header value into a IfModifiedSince, invalid values are silentely ignored
     pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfModifiedSince> {
         std::str::from_utf8(value.as_bytes())
@@ -44,3 +166,187 @@ impl IfUnmodifiedSince {
             .map(|time| IfUnmodifiedSince(time.into()))
     }
 }
+
+/// The conditional headers of a request, evaluated against the validators of the file or of the
+/// fallback response.
+pub(super) struct Conditions {
+    if_unmodified_since: Option<IfUnmodifiedSince>,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+}
+
+impl Conditions {
+    pub(super) fn new(headers: &HeaderMap, now: SystemTime) -> Self {
+        Conditions {
+            if_unmodified_since: headers
+                .get(header::IF_UNMODIFIED_SINCE)
+                .and_then(IfUnmodifiedSince::from_header_value),
+            if_none_match: IfNoneMatch::from_headers(headers),
+            if_modified_since: headers
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(IfModifiedSince::from_header_value)
+                // a date later than now is invalid
+                .filter(|since| !since.is_after(now)),
+        }
+    }
+
+    /// Evaluate the conditions in the order of RFC 9110, `None` if the response is sent as is,
+    /// `412 Precondition Failed` or `304 Not Modified` otherwise. The `If-Modified-Since` is
+    /// ignored when the `If-None-Match` is sent.
+    pub(super) fn evaluate(
+        self,
+        modified: Option<&LastModified>,
+        etag: Option<&ETag>,
+    ) -> Option<StatusCode> {
+        if let Some(since) = self.if_unmodified_since {
+            let precondition = modified
+                .map(|time| since.precondition_passes(time))
+                .unwrap_or(false);
+
+            if !precondition {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+
+        if let Some(if_none_match) = self.if_none_match {
+            return if_none_match
+                .matches(etag)
+                .then_some(StatusCode::NOT_MODIFIED);
+        }
+
+        if let Some(since) = self.if_modified_since {
+            let unmodified = modified
+                .map(|time| !since.is_modified(time))
+                // no last_modified means its always modified
+                .unwrap_or(false);
+            if unmodified {
+                return Some(StatusCode::NOT_MODIFIED);
+            }
+        }
+
+        None
+    }
+}
+
+pub(super) struct IfNoneMatch(Vec<HeaderValue>);
+
+impl IfNoneMatch {
+    /// Check the `etag` of the existing file is one of the listed, by the weak comparison, as
+    /// RFC 9110 requires for `If-None-Match`, `*` matches any file.
+    pub(super) fn matches(&self, etag: Option<&ETag>) -> bool {
+        let etag = etag.map(|etag| opaque_tag(etag.0.as_bytes()));
+
+        self.0.iter().any(|value| {
+            if value.to_str().is_ok_and(|value| value.trim() == "*") {
+                return true;
+            }
+
+            etag.is_some_and(|etag| entity_tags(value.as_bytes()).any(|tag| tag == etag))
+        })
+    }
+
+    /// Collect all the `If-None-Match` headers, `None` if there isn't any.
+    pub(super) fn from_headers(headers: &HeaderMap) -> Option<IfNoneMatch> {
+        let values = headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        (!values.is_empty()).then_some(IfNoneMatch(values))
+    }
+}
+
+// the tag with the quotes, without the weak prefix
+fn opaque_tag(etag: &[u8]) -> &[u8] {
+    etag.strip_prefix(b"W/").unwrap_or(etag)
+}
+
+// the opaque tags of a comma separated list of entity tags, the list is cut at the first invalid
+// one
+fn entity_tags(mut list: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        while let [b' ' | b'\t' | b',', rest @ ..] = list {
+            list = rest;
+        }
+        let tag = opaque_tag(list);
+        let end = tag.strip_prefix(b"\"")?.iter().position(|b| *b == b'"')? + 2;
+        let (tag, rest) = tag.split_at(end);
+        list = rest;
+
+        Some(tag)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn last_modified_header_value() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let last_modified = LastModified::new(time, SystemTime::now());
+
+        assert_eq!(
+            last_modified.header_value(),
+            httpdate::fmt_http_date(time).as_str()
+        );
+    }
+
+    #[test]
+    fn etag() {
+        let meta = |etag: Option<&str>| Metadata {
+            modified: Some(UNIX_EPOCH + Duration::from_micros(0x1234)),
+            len: Some(0x2f),
+            etag: etag.map(str::to_string),
+            headers: HeaderMap::new(),
+        };
+
+        assert_eq!(ETag::new(&meta(None)).unwrap().0, "\"2f-1234\"");
+        assert_eq!(ETag::new(&meta(Some("W/\"a\""))).unwrap().0, "W/\"a\"");
+        let no_len = Metadata {
+            len: None,
+            ..meta(None)
+        };
+        assert!(ETag::new(&no_len).is_none());
+    }
+
+    #[test]
+    fn if_none_match() {
+        let etag = ETag(HeaderValue::from_static("\"a,b\""));
+        let if_none_match = |values: &[&'static str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(header::IF_NONE_MATCH, HeaderValue::from_static(value));
+            }
+
+            IfNoneMatch::from_headers(&headers)
+        };
+
+        assert!(if_none_match(&[]).is_none());
+        let matches = |values| if_none_match(values).unwrap().matches(Some(&etag));
+        assert!(matches(&["\"a,b\""]));
+        assert!(matches(&["W/\"a,b\""]));
+        assert!(matches(&["\"x\", W/\"a,b\""]));
+        assert!(matches(&["\"x\"", "\"a,b\""]));
+        assert!(matches(&[" * "]));
+        assert!(!matches(&["\"a\""]));
+        assert!(!matches(&["a,b"]));
+        assert!(!matches(&["\"x\", invalid, \"a,b\""]));
+
+        assert!(if_none_match(&["*"]).unwrap().matches(None));
+        assert!(!if_none_match(&["\"a\""]).unwrap().matches(None));
+    }
+
+    #[test]
+    fn content_range_header_value() {
+        assert_eq!(content_range(Some(&(0..=99)), 1000), "bytes 0-99/1000");
+        assert_eq!(content_range(None, 1000), "bytes */1000");
+        assert_eq!(
+            content_range(Some(&(u64::MAX - 1..=u64::MAX)), u64::MAX),
+            format!("bytes {0}-{1}/{1}", u64::MAX - 1, u64::MAX).as_str()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Response, StatusCode};
+
+use crate::fs::Filesystem;
+use crate::serve_dir::{body_from_bytes, empty_body, ServeVariant};
+use crate::ResponseBody;
+
+/// The liveness endpoint, always `200 OK` while the service is running
+pub(crate) const HEALTHZ_PATH: &str = "/healthz";
+
+/// The readiness endpoint, `200 OK` if the filesystem is reachable, `503 Service Unavailable`
+/// otherwise
+pub(crate) const READYZ_PATH: &str = "/readyz";
+
+/// Check the filesystem is reachable by stat-ing the root dir, or the file of the single file
+/// service.
+pub(crate) async fn is_ready<FS: Filesystem>(filesystem: &FS, variant: &ServeVariant) -> bool {
+    match variant {
+        ServeVariant::Directory { .. } => filesystem.is_dir(Path::new("")).await.unwrap_or(false),
+        ServeVariant::SingleFile { file_path, .. } => filesystem.metadata(file_path).await.is_ok(),
+    }
+}
+
+pub(crate) fn health_response(method: &Method, ready: bool) -> Response<ResponseBody> {
+    let (status, text) = if ready {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    let body = if method == Method::HEAD {
+        empty_body()
+    } else {
+        body_from_bytes(Bytes::from_static(text.as_bytes()))
+    };
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::CONTENT_LENGTH, text.len())
+        .header(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))
+        .body(body)
+        .unwrap()
+}
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+
+use http::{header, Request};
+
+const HOST_PLACEHOLDER: &str = "{host}";
+const MAX_HOST_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// The per host directory template, like `sites/{host}/public`, relative to the root of the
+/// [`Filesystem`](crate::fs::Filesystem)
+#[derive(Debug, Clone)]
+pub(crate) struct HostTemplate {
+    template: String,
+}
+
+impl HostTemplate {
+    pub(crate) fn new(template: &str) -> Self {
+        assert!(
+            template.contains(HOST_PLACEHOLDER),
+            "host template {template} has no {HOST_PLACEHOLDER}"
+        );
+
+        Self {
+            template: template.trim_start_matches('/').to_string(),
+        }
+    }
+
+    /// Get the directory of the request host, `None` if the host is missing or invalid.
+    pub(crate) fn dir<B>(&self, req: &Request<B>) -> Option<PathBuf> {
+        let host = match req.headers().get(header::HOST) {
+            Some(host) => host.to_str().ok()?,
+            // HTTP/2 requests carry the host in the `:authority`
+            None => req.uri().authority()?.as_str(),
+        };
+        let host = normalize_host(host)?;
+
+        Some(PathBuf::from(
+            self.template.replace(HOST_PLACEHOLDER, &host),
+        ))
+    }
+}
+
+// lowercase the host and strip the port and the trailing dot, only the DNS names and the IPv4
+// addresses are accepted, so the host can't escape from the template
+fn normalize_host(host: &str) -> Option<String> {
+    let host = match host.rsplit_once(':') {
+        None => host,
+        Some((host, port)) => {
+            if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+
+            host
+        }
+    };
+    let host = host.strip_suffix('.').unwrap_or(host);
+
+    if host.is_empty() || host.len() > MAX_HOST_LEN {
+        return None;
+    }
+
+    let valid = host.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= MAX_LABEL_LEN
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+
+    valid.then(|| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_host("Example.COM").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize_host("example.com:8080").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            normalize_host("example.com.").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(normalize_host("127.0.0.1:80").as_deref(), Some("127.0.0.1"));
+        assert_eq!(normalize_host("localhost").as_deref(), Some("localhost"));
+    }
+
+    #[test]
+    fn reject_invalid_host() {
+        for host in [
+            "",
+            ":80",
+            "..",
+            "a..b",
+            ".example.com",
+            "-a.com",
+            "a-.com",
+            "a/b",
+            "a\\b",
+            "example.com:",
+            "example.com:x",
+            "[::1]:80",
+            "exa mple.com",
+            "例子.com",
+        ] {
+            assert_eq!(normalize_host(host), None, "{host}");
+        }
+
+        assert_eq!(normalize_host(&"a".repeat(MAX_LABEL_LEN + 1)), None);
+    }
+
+    #[test]
+    fn template_dir() {
+        let template = HostTemplate::new("/sites/{host}/public");
+
+        let req = Request::builder()
+            .header(header::HOST, "Example.com:443")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            template.dir(&req),
+            Some(PathBuf::from("sites/example.com/public"))
+        );
+
+        let req = Request::builder()
+            .uri("https://example.org/index.html")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            template.dir(&req),
+            Some(PathBuf::from("sites/example.org/public"))
+        );
+
+        let req = Request::builder()
+            .header(header::HOST, "../etc")
+            .body(())
+            .unwrap();
+        assert_eq!(template.dir(&req), None);
+
+        let req = Request::new(());
+        assert_eq!(template.dir(&req), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn template_without_placeholder() {
+        HostTemplate::new("sites");
+    }
+}
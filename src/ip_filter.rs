@@ -0,0 +1,218 @@
+use std::fmt::{Debug, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use http::Extensions;
+
+type Extractor = Arc<dyn Fn(&Extensions) -> Option<IpAddr> + Send + Sync>;
+
+/// Allow or deny the requests by the client address, see
+/// [`ServeDir::ip_filter`](crate::ServeDir::ip_filter).
+///
+/// The client address is taken from the request extensions, a [`SocketAddr`] or an [`IpAddr`]
+/// by default, use [`IpFilter::extractor`] when the address is stored in other types, like the
+/// axum `ConnectInfo`.
+///
+/// The deny rules are checked first, then the request is allowed if there is no allow rule or the
+/// address matches any allow rule. A request without a known address matches no rule.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::IpFilter;
+///
+/// let filter = IpFilter::new()
+///     .allow("10.0.0.0/8")
+///     .allow("fd00::/8")
+///     .deny("10.0.0.1");
+/// ```
+#[derive(Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpCidr>,
+    deny: Vec<IpCidr>,
+    extractor: Option<Extractor>,
+}
+
+impl Debug for IpFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IpFilter")
+            .field("allow", &self.allow)
+            .field("deny", &self.deny)
+            .field("extractor", &self.extractor.is_some())
+            .finish()
+    }
+}
+
+impl IpFilter {
+    /// Create a new [`IpFilter`] allowing any address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the addresses in the CIDR, like `10.0.0.0/8`, a single address is also accepted.
+    ///
+    /// # Panics
+    /// Will panic if the `cidr` is invalid.
+    pub fn allow(mut self, cidr: &str) -> Self {
+        self.allow.push(IpCidr::parse(cidr));
+        self
+    }
+
+    /// Deny the addresses in the CIDR, like `10.0.0.0/8`, a single address is also accepted.
+    ///
+    /// # Panics
+    /// Will panic if the `cidr` is invalid.
+    pub fn deny(mut self, cidr: &str) -> Self {
+        self.deny.push(IpCidr::parse(cidr));
+        self
+    }
+
+    /// Set how to get the client address from the request extensions.
+    pub fn extractor<E>(mut self, extractor: E) -> Self
+    where
+        E: Fn(&Extensions) -> Option<IpAddr> + Send + Sync + 'static,
+    {
+        self.extractor = Some(Arc::new(extractor));
+        self
+    }
+
+    pub(crate) fn is_allowed(&self, extensions: &Extensions) -> bool {
+        let addr = match &self.extractor {
+            None => extensions
+                .get::<SocketAddr>()
+                .map(|addr| addr.ip())
+                .or_else(|| extensions.get::<IpAddr>().copied()),
+            Some(extractor) => extractor(extensions),
+        }
+        .map(to_canonical);
+
+        let matches = |cidrs: &[IpCidr]| {
+            addr.as_ref()
+                .is_some_and(|addr| cidrs.iter().any(|cidr| cidr.contains(addr)))
+        };
+
+        if matches(&self.deny) {
+            return false;
+        }
+
+        self.allow.is_empty() || matches(&self.allow)
+    }
+}
+
+// make the IPv4-mapped IPv6 address match the IPv4 rules
+fn to_canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    fn parse(cidr: &str) -> Self {
+        let (addr, prefix_len) = match cidr.split_once('/') {
+            None => (cidr, None),
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map(to_canonical)
+            .unwrap_or_else(|_| panic!("invalid cidr {cidr}"));
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = match prefix_len {
+            None => max_prefix_len,
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .unwrap_or_else(|| panic!("invalid cidr {cidr}")),
+        };
+
+        Self { addr, prefix_len }
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(*addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    fn extensions(addr: &str) -> Extensions {
+        let mut extensions = Extensions::new();
+        extensions.insert(addr.parse::<IpAddr>().unwrap());
+        extensions
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let cidr = IpCidr::parse("10.1.0.0/16");
+        assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains(&"10.2.0.0".parse().unwrap()));
+
+        let cidr = IpCidr::parse("0.0.0.0/0");
+        assert!(cidr.contains(&"192.168.1.1".parse().unwrap()));
+        assert!(!cidr.contains(&Ipv6Addr::LOCALHOST.into()));
+
+        let cidr = IpCidr::parse("fd00::/8");
+        assert!(cidr.contains(&"fd12::1".parse().unwrap()));
+        assert!(!cidr.contains(&"fe80::1".parse().unwrap()));
+
+        let cidr = IpCidr::parse("127.0.0.1");
+        assert!(cidr.contains(&"127.0.0.1".parse().unwrap()));
+        assert!(!cidr.contains(&"127.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_prefix_len() {
+        IpCidr::parse("10.0.0.0/33");
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filter = IpFilter::new().allow("10.0.0.0/8").deny("10.0.0.1");
+
+        assert!(filter.is_allowed(&extensions("10.0.0.2")));
+        assert!(filter.is_allowed(&extensions("::ffff:10.0.0.2")));
+        assert!(!filter.is_allowed(&extensions("10.0.0.1")));
+        assert!(!filter.is_allowed(&extensions("192.168.0.1")));
+        assert!(!filter.is_allowed(&Extensions::new()));
+    }
+
+    #[test]
+    fn deny_only() {
+        let filter = IpFilter::new().deny("192.168.0.0/16");
+
+        assert!(filter.is_allowed(&extensions("10.0.0.1")));
+        assert!(!filter.is_allowed(&extensions("192.168.1.1")));
+        assert!(filter.is_allowed(&Extensions::new()));
+    }
+}
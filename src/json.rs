@@ -0,0 +1,25 @@
+use std::fmt::Write;
+
+/// Write `value` as a quoted JSON string into `out`
+pub(crate) fn write_str(out: &mut String, value: &str) {
+    out.push('"');
+    write_escaped(out, value);
+    out.push('"');
+}
+
+/// Write `value` escaped for the inside of a JSON string into `out`
+pub(crate) fn write_escaped(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+}
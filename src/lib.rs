@@ -31,19 +31,122 @@
 
 use std::io;
 
+pub use asset_hashes::AssetHashes;
 use bytes::Bytes;
+pub use dir_index::IndexRequest;
+pub use error_pages::ErrorPages;
+#[cfg(feature = "timeout")]
+pub use extensions::Deadline;
+pub use extensions::{ResolvedPath, RootOverride, ServedFile, StatusReason};
 use http_body::combinators::UnsyncBoxBody;
+pub use ip_filter::IpFilter;
+pub use manifest::{ManifestEntry, RouteManifest};
+pub use not_found::NotFoundService;
+pub use not_modified::NotModifiedHeaders;
+pub use query::QueryPolicy;
+pub use range_guard::{RangeGuard, RangeRequest};
+pub use rate_limit::{RateLimiter, TokenBucket};
+pub use request_body::RequestBodyPolicy;
+pub use search::SearchOptions;
 pub use serve_dir::{DefaultServeDirFallback, ServeDir};
 pub use serve_file::ServeFile;
+pub use serve_files::ServeFiles;
+pub use serve_user_dirs::ServeUserDirs;
+pub use surrogate::SurrogateKey;
+pub use transform::{Transform, TransformRequest, Transformed};
 
+mod asset_hashes;
+mod asset_manifest;
 mod async_body;
+mod canonical_path;
+mod clock;
 mod content_encoding;
+#[cfg(feature = "content-digest")]
+mod digest;
+mod dir_index;
+mod error_pages;
+mod extensions;
+mod filter;
+mod forwarded;
 pub mod fs;
+mod glob;
+mod header_hook;
 mod headers;
+mod health;
+mod host;
+mod ip_filter;
+mod json;
+mod lru;
+mod manifest;
+mod mirror;
+mod not_found;
+mod not_modified;
 mod open_file;
+mod outcome;
+mod probe_cache;
+mod purge;
+mod query;
+mod range_guard;
+mod rate_limit;
+mod request_body;
+mod request_id;
+mod search;
 mod serve_dir;
 mod serve_file;
+mod serve_files;
+mod serve_user_dirs;
+#[cfg(feature = "server")]
+pub mod server;
+mod stat;
+mod surrogate;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "timeout")]
+mod timeout;
+#[cfg(feature = "tracing")]
+mod trace_context;
+mod transform;
+#[cfg(feature = "watch")]
+pub mod watch;
 
 pub type ResponseBody = UnsyncBoxBody<Bytes, io::Error>;
+
+/// Check the file at the `path` of the `root` directory exists at the compile time, and expand
+/// to the `path`, so the paths served from an embedded directory can't be missing in the
+/// production.
+///
+/// The `root` is relative to the `CARGO_MANIFEST_DIR` of the calling crate, like the
+/// `$CARGO_MANIFEST_DIR/assets` of the `include_dir!`. The file is read by the `include_bytes!`,
+/// so a missing one fails the build, but it isn't kept in the binary. The files only embedded
+/// compressed by the `include-dir-compressed` feature can't be checked, use the
+/// [`IncludeDirFilesystem::verify`](crate::fs::include_dir::IncludeDirFilesystem::verify) at
+/// the startup instead.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::fs::include_dir::IncludeDirFilesystem;
+/// use http_dir::{embedded_path, ServeFile};
+/// use include_dir::{include_dir, Dir};
+///
+/// static DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/test-files");
+///
+/// let service = ServeFile::new(
+///     embedded_path!("test-files", "index.html"),
+///     IncludeDirFilesystem::new(DIR.clone()),
+/// );
+/// ```
+///
+/// A missing file fails the build:
+///
+/// ```rust,compile_fail
+/// let path = http_dir::embedded_path!("test-files", "missing.html");
+/// ```
+#[macro_export]
+macro_rules! embedded_path {
+    ($root:literal, $path:literal) => {{
+        const _: &[u8] =
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $root, "/", $path));
+        $path
+    }};
+}
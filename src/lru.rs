@@ -0,0 +1,88 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A map holding up to `capacity` entries, the least recently used one is dropped for a new
+/// entry when it is full, every operation takes `O(log n)`, so the keys chosen by the clients
+/// can't make it slow.
+#[derive(Debug)]
+pub(crate) struct Lru<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    // the keys by their last used tick
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V> Lru<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Get the value of the `key`, or insert the one made by `make`, it becomes the most recently
+    /// used one.
+    pub(crate) fn get_or_insert_with<Q>(&mut self, key: &Q, make: impl FnOnce() -> V) -> &mut V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.tick += 1;
+        let tick = self.tick;
+
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            let last_used = std::mem::replace(used, tick);
+            let key = self
+                .order
+                .remove(&last_used)
+                .expect("the used tick of an entry is in the order");
+            self.order.insert(tick, key);
+        } else {
+            if self.entries.len() >= self.capacity {
+                if let Some((_, least_recently_used)) = self.order.pop_first() {
+                    self.entries.remove::<K>(&least_recently_used);
+                }
+            }
+
+            let key = key.to_owned();
+            self.order.insert(tick, key.clone());
+            self.entries.insert(key, (make(), tick));
+        }
+
+        &mut self
+            .entries
+            .get_mut(key)
+            .expect("the entry is just inserted")
+            .0
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_least_recently_used() {
+        let mut lru = Lru::<String, u32>::new(2);
+        *lru.get_or_insert_with("a", || 1) += 10;
+        lru.get_or_insert_with("b", || 2);
+        // `a` is used again, so `b` is dropped for `c`
+        assert_eq!(*lru.get_or_insert_with("a", || 0), 11);
+        lru.get_or_insert_with("c", || 3);
+
+        assert_eq!(lru.len(), 2);
+        assert_eq!(*lru.get_or_insert_with("a", || 0), 11);
+        assert_eq!(*lru.get_or_insert_with("b", || 0), 0);
+        assert_eq!(*lru.get_or_insert_with("b", || 5), 0);
+        assert_eq!(lru.len(), 2);
+    }
+}
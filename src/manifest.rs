@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::fs::Filesystem;
+use crate::headers::ETag;
+
+// the file extensions of the precompressed variants and their content codings
+const PRECOMPRESSED_EXTENSIONS: [(&str, &str); 3] =
+    [("gz", "gzip"), ("br", "br"), ("zz", "deflate")];
+
+/// A static manifest of the files of a [`Filesystem`], built once at startup for the immutable
+/// deployments, see [`ServeDir::route_manifest`](crate::ServeDir::route_manifest).
+///
+/// The files are grouped by their directories, so the directory prefix of the paths is stored
+/// once, and looked up in O(1).
+#[derive(Debug, Clone, Default)]
+pub struct RouteManifest {
+    dirs: Arc<HashMap<PathBuf, HashMap<OsString, ManifestEntry>>>,
+}
+
+/// A file of the [`RouteManifest`]
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    size: Option<u64>,
+    etag: Option<String>,
+    mime: Option<&'static str>,
+    precompressed: Vec<&'static str>,
+}
+
+impl ManifestEntry {
+    /// The file size, `None` if the filesystem can't report it
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The `ETag` header of the file
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// The mime type guessed from the file extension
+    pub fn mime(&self) -> Option<&'static str> {
+        self.mime
+    }
+
+    /// The content codings of the available precompressed variants, like `gzip` for the
+    /// `foo.txt.gz` of the `foo.txt`
+    pub fn precompressed(&self) -> &[&'static str] {
+        &self.precompressed
+    }
+}
+
+impl RouteManifest {
+    /// Walk the whole `filesystem` and record all the files.
+    ///
+    /// Sub dirs which can't be read are skipped.
+    pub async fn build<FS: Filesystem>(filesystem: &FS) -> io::Result<Self> {
+        let mut dirs = HashMap::new();
+        let mut pending = VecDeque::from([PathBuf::new()]);
+
+        while let Some(dir_path) = pending.pop_front() {
+            let entries = match filesystem.read_dir(&dir_path).await {
+                Ok(entries) => entries,
+                Err(err) if dir_path.as_os_str().is_empty() => return Err(err),
+                Err(_) => continue,
+            };
+
+            let mut files = HashMap::with_capacity(entries.len());
+            for entry in entries {
+                let path = dir_path.join(&entry.name);
+                if entry.is_dir {
+                    pending.push_back(path);
+                    continue;
+                }
+
+                let metadata = filesystem.metadata(&path).await?;
+                files.insert(
+                    entry.name,
+                    ManifestEntry {
+                        size: metadata.len,
+                        etag: ETag::new(&metadata).map(ETag::into_string),
+                        mime: mime_guess::from_path(&path).first_raw(),
+                        precompressed: vec![],
+                    },
+                );
+            }
+
+            let variants = files
+                .keys()
+                .filter_map(|name| precompressed_variant(name))
+                .collect::<Vec<_>>();
+            for (name, encoding) in variants {
+                if let Some(file) = files.get_mut(&name) {
+                    file.precompressed.push(encoding);
+                }
+            }
+
+            dirs.insert(dir_path, files);
+        }
+
+        Ok(Self {
+            dirs: Arc::new(dirs),
+        })
+    }
+
+    /// Get the file of the `path`.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&ManifestEntry> {
+        let path = normalize(path.as_ref());
+        let name = path.file_name()?;
+
+        self.dirs.get(path.parent()?)?.get(name)
+    }
+
+    /// Check the `path` is a dir.
+    pub fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.dirs.contains_key(&normalize(path.as_ref()))
+    }
+
+    /// All the files and their paths.
+    pub(crate) fn files(&self) -> impl Iterator<Item = (PathBuf, &ManifestEntry)> {
+        self.dirs
+            .iter()
+            .flat_map(|(dir, files)| files.iter().map(|(name, entry)| (dir.join(name), entry)))
+    }
+
+    /// Check the `path` is a precompressed variant of another file, like `foo.txt.gz`.
+    pub(crate) fn is_precompressed_variant(&self, path: &Path) -> bool {
+        let Some((name, encoding)) = path.file_name().and_then(precompressed_variant) else {
+            return false;
+        };
+
+        self.get(path.with_file_name(name))
+            .is_some_and(|entry| entry.precompressed.contains(&encoding))
+    }
+
+    /// The number of the files.
+    pub fn len(&self) -> usize {
+        self.dirs.values().map(HashMap::len).sum()
+    }
+
+    /// Check there is no file.
+    pub fn is_empty(&self) -> bool {
+        self.dirs.values().all(HashMap::is_empty)
+    }
+}
+
+// the name of the uncompressed file and the content coding of a precompressed variant
+fn precompressed_variant(name: &OsStr) -> Option<(OsString, &'static str)> {
+    let path = Path::new(name);
+    let extension = path.extension()?.to_str()?;
+    let (_, encoding) = PRECOMPRESSED_EXTENSIONS
+        .iter()
+        .find(|(variant_extension, _)| *variant_extension == extension)?;
+
+    Some((path.file_stem()?.to_os_string(), encoding))
+}
+
+// the request paths may contain the leading slash and the `.`
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::RootDir | Component::CurDir))
+        .collect()
+}
@@ -0,0 +1,41 @@
+use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::Request;
+
+type Sink = Arc<dyn Fn(Request<()>) + Send + Sync>;
+
+/// Pass the copies of the requests without their bodies to the sink, see
+/// [`ServeDir::mirror`](crate::ServeDir::mirror).
+#[derive(Clone)]
+pub(crate) struct Mirror(Sink);
+
+impl Debug for Mirror {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mirror").finish_non_exhaustive()
+    }
+}
+
+impl Mirror {
+    pub(crate) fn new<F>(sink: F) -> Self
+    where
+        F: Fn(Request<()>) + Send + Sync + 'static,
+    {
+        Self(Arc::new(sink))
+    }
+
+    pub(crate) fn send<B>(&self, req: &Request<B>) {
+        let mut mirrored = Request::new(());
+        *mirrored.method_mut() = req.method().clone();
+        *mirrored.uri_mut() = req.uri().clone();
+        *mirrored.version_mut() = req.version();
+        *mirrored.headers_mut() = req.headers().clone();
+        // the extensions can't be cloned, only the client address is kept
+        if let Some(addr) = req.extensions().get::<SocketAddr>() {
+            mirrored.extensions_mut().insert(*addr);
+        }
+
+        (self.0)(mirrored);
+    }
+}
@@ -0,0 +1,54 @@
+use std::future::Future;
+use std::task::{Context, Poll};
+
+use http::{header, Request, Response, StatusCode};
+use tower_service::Service;
+
+/// The fallback serving the not found document, see
+/// [`ServeDir::not_found_service`](crate::ServeDir::not_found_service).
+///
+/// The successful responses of the inner service are turned into `404 Not Found`, the
+/// `304 Not Modified` and `412 Precondition Failed` ones are kept, so the caches can revalidate
+/// the stored not found document with its `Last-Modified` like any other file. The `Range` of the
+/// request is dropped, the not found document is always responded in full.
+#[derive(Debug, Clone, Copy)]
+pub struct NotFoundService<S> {
+    inner: S,
+}
+
+impl<S> NotFoundService<S> {
+    /// Create a new [`NotFoundService`] wrapping the service of the not found document.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for NotFoundService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        req.headers_mut().remove(header::RANGE);
+        req.headers_mut().remove(header::IF_RANGE);
+        let response = self.inner.call(req);
+
+        async move {
+            let mut res = response.await?;
+            if res.status().is_success() {
+                *res.status_mut() = StatusCode::NOT_FOUND;
+                res.headers_mut().remove(header::ACCEPT_RANGES);
+            }
+
+            Ok(res)
+        }
+    }
+}
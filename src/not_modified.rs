@@ -0,0 +1,74 @@
+use http::header::{self, HeaderName};
+use http::HeaderMap;
+
+// the headers RFC 9110 requires on the `304 Not Modified` when the `200 OK` would have them
+const REQUIRED: [HeaderName; 6] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::VARY,
+];
+
+/// The headers of the `304 Not Modified` responses, see
+/// [`ServeDir::not_modified_headers`](crate::ServeDir::not_modified_headers).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NotModifiedHeaders {
+    /// The validators and the caching headers of the file, like `Last-Modified`, `Vary` and
+    /// `Content-Location`, with the headers added to all the responses, like the ones of the
+    /// [`ServeDir::response_header`](crate::ServeDir::response_header).
+    #[default]
+    Full,
+
+    /// Only the headers RFC 9110 requires: `Cache-Control`, `Content-Location`, `Date`, `ETag`,
+    /// `Expires` and `Vary`, the others, like `Last-Modified`, are left out.
+    Strict,
+}
+
+impl NotModifiedHeaders {
+    /// Remove the headers which aren't allowed on the `304 Not Modified`.
+    pub(crate) fn apply(self, headers: &mut HeaderMap) {
+        if self == NotModifiedHeaders::Full {
+            return;
+        }
+
+        let mut required = HeaderMap::new();
+        for name in REQUIRED {
+            for value in headers.get_all(&name) {
+                required.append(name.clone(), value.clone());
+            }
+        }
+
+        *headers = required;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn strict() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers.insert(header::LAST_MODIFIED, HeaderValue::from_static("now"));
+        headers.append(header::VARY, HeaderValue::from_static("accept"));
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+        let mut full = headers.clone();
+        NotModifiedHeaders::Full.apply(&mut full);
+        assert_eq!(full, headers);
+
+        NotModifiedHeaders::Strict.apply(&mut headers);
+        assert_eq!(headers.len(), 3);
+        assert!(headers.get(header::LAST_MODIFIED).is_none());
+        assert_eq!(
+            headers.get_all(header::VARY).iter().collect::<Vec<_>>(),
+            ["accept", "accept-encoding"]
+        );
+    }
+}
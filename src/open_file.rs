@@ -1,37 +1,97 @@
 use std::{
     ffi::OsStr,
+    future::Future,
     io::{self, SeekFrom},
+    iter,
     ops::RangeInclusive,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::{Duration, SystemTime},
 };
 
-use bytes::Bytes;
-use http::{header, HeaderValue, Method, Request, Uri};
+use bytes::{Bytes, BytesMut};
+use futures_util::future::join_all;
+use http::uri::{Authority, Scheme};
+use http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode, Uri};
 use http_body::Empty;
 use http_range_header::RangeUnsatisfiableError;
-use mime_guess::mime;
-use tokio::io::AsyncSeekExt;
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
 
-use super::headers::{IfModifiedSince, IfUnmodifiedSince, LastModified};
+use super::headers::{Conditions, ETag, LastModified};
 use crate::content_encoding::{Encoding, QValue};
-use crate::fs::{FileExt, Filesystem, Metadata};
+use crate::dir_index::{DirectoryIndex, IndexRequest};
+use crate::extensions::{ServedFile, StatusReason};
+use crate::forwarded::ForwardedOrigin;
+use crate::fs::{FileExt, Filesystem, Metadata, ReadAt};
+use crate::probe_cache::ProbeCache;
 use crate::serve_dir::ServeVariant;
 
+// the bytes which are invalid in the URIs or in the header values, the `%` is kept, so the
+// percent-encoded paths aren't encoded twice
+const LOCATION_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+// a file name put into a path segment
+pub(crate) const SEGMENT_ENCODE_SET: &AsciiSet =
+    &LOCATION_ENCODE_SET.add(b'%').add(b'/').add(b'?').add(b'#');
+
 pub(super) enum OpenFileOutput<IO> {
     FileOpened(Box<FileOpened<IO>>),
-    Redirect { location: HeaderValue },
+    Redirect {
+        location: HeaderValue,
+    },
     FileNotFound,
-    PreconditionFailed,
-    NotModified,
+    PreconditionFailed(ServedFile),
+    NotModified {
+        served_file: ServedFile,
+        last_modified: Option<LastModified>,
+        etag: Option<ETag>,
+    },
 }
 
 pub(super) struct FileOpened<IO> {
     pub(super) extent: FileRequestExtent<IO>,
+    // the path passed to the filesystem, with the precompressed file extension
+    pub(super) path: PathBuf,
     pub(super) chunk_size: usize,
     pub(super) mime_header_value: HeaderValue,
     pub(super) maybe_encoding: Option<Encoding>,
     pub(super) maybe_range: Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>>,
     pub(super) last_modified: Option<LastModified>,
+    pub(super) etag: Option<ETag>,
+    pub(super) attachment: bool,
+}
+
+impl<IO> FileOpened<IO> {
+    fn metadata(&self) -> &Metadata {
+        match &self.extent {
+            FileRequestExtent::Full(_, meta) | FileRequestExtent::Head(meta) => meta,
+        }
+    }
+
+    pub(super) fn served_file(&self) -> ServedFile {
+        ServedFile::new(
+            self.path.clone(),
+            Some(self.metadata()),
+            StatusReason::Served,
+        )
+    }
+
+    /// The file size, `None` if it is unknown.
+    pub(super) fn len(&self) -> Option<u64> {
+        self.metadata().len
+    }
 }
 
 pub(super) enum FileRequestExtent<IO> {
@@ -39,134 +99,302 @@ pub(super) enum FileRequestExtent<IO> {
     Head(Metadata),
 }
 
-pub(super) async fn open_file<FS: Filesystem>(
+type OpenFuture<F> = Pin<Box<dyn Future<Output = io::Result<F>> + Send>>;
+
+/// A file opened by [`open_file`], or opened on the first read when the open is deferred, it is
+/// read from the start of the range with [`FileExt::read_at`], or seeked to it if unsupported.
+pub(super) struct LazyFile<F> {
+    file: LazyFileState<F>,
+    read: ReadState,
+    pos: u64,
+    // the buffer of the positional reads, it is reused by the next read once copied out
+    data: BytesMut,
+}
+
+enum LazyFileState<F> {
+    Opening(OpenFuture<F>),
+    Open(F),
+}
+
+enum ReadState {
+    // the next read starts at `pos`
+    Positional,
+    Reading(ReadAt),
+    Seeking,
+    Sequential,
+}
+
+impl<F: AsyncRead + AsyncSeek + Send + Unpin + 'static> LazyFile<F> {
+    fn open(file: F, offset: u64) -> Self {
+        Self {
+            file: LazyFileState::Open(file),
+            read: ReadState::Positional,
+            pos: offset,
+            data: BytesMut::new(),
+        }
+    }
+
+    // open the file on the first read
+    fn deferred<FS>(mut filesystem: FS, path: PathBuf, offset: u64) -> Self
+    where
+        FS: Filesystem<File = F> + Send + 'static,
+    {
+        Self {
+            file: LazyFileState::Opening(Box::pin(async move { filesystem.open(&path).await })),
+            read: ReadState::Positional,
+            pos: offset,
+            data: BytesMut::new(),
+        }
+    }
+}
+
+impl<F: AsyncRead + AsyncSeek + FileExt + Unpin> AsyncRead for LazyFile<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let file = match &mut this.file {
+                LazyFileState::Opening(open) => {
+                    let file = ready!(open.as_mut().poll(cx))?;
+                    this.file = LazyFileState::Open(file);
+
+                    continue;
+                }
+                LazyFileState::Open(file) => file,
+            };
+
+            match &mut this.read {
+                ReadState::Positional => {
+                    if buf.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    let mut data = std::mem::take(&mut this.data);
+                    data.reserve(buf.remaining());
+                    this.read = match file.read_at(this.pos, data) {
+                        Some(read) => ReadState::Reading(read),
+                        None if this.pos == 0 => ReadState::Sequential,
+                        None => {
+                            Pin::new(file).start_seek(SeekFrom::Start(this.pos))?;
+
+                            ReadState::Seeking
+                        }
+                    };
+                }
+
+                ReadState::Reading(read) => {
+                    let mut data = ready!(read.as_mut().poll(cx))?;
+                    this.read = ReadState::Positional;
+
+                    // the buf may be smaller than the one the read started with
+                    let n = data.len().min(buf.remaining());
+                    buf.put_slice(&data[..n]);
+                    this.pos += n as u64;
+                    data.clear();
+                    this.data = data;
+
+                    return Poll::Ready(Ok(()));
+                }
+
+                ReadState::Seeking => {
+                    ready!(Pin::new(file).poll_complete(cx))?;
+                    this.read = ReadState::Sequential;
+                }
+
+                ReadState::Sequential => return Pin::new(file).poll_read(cx, buf),
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn open_file<FS: Filesystem + Clone + Send + Sync + 'static>(
     filesystem: &mut FS,
     variant: &ServeVariant,
     mut path_to_file: PathBuf,
     req: Request<Empty<Bytes>>,
     negotiated_encodings: Vec<(Encoding, QValue)>,
-    range_header: Option<String>,
     buf_chunk_size: usize,
-) -> io::Result<OpenFileOutput<FS::File>> {
-    let if_unmodified_since = req
+    defer_open: bool,
+    now: SystemTime,
+    last_modified_min_age: Duration,
+    probe_cache: Option<&ProbeCache>,
+    concurrent_probes: bool,
+) -> io::Result<OpenFileOutput<LazyFile<FS::File>>> {
+    let conditions = Conditions::new(req.headers(), now);
+
+    let range_header = req
         .headers()
-        .get(header::IF_UNMODIFIED_SINCE)
-        .and_then(IfUnmodifiedSince::from_header_value);
-
-    let if_modified_since = req
-        .headers()
-        .get(header::IF_MODIFIED_SINCE)
-        .and_then(IfModifiedSince::from_header_value);
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|s| s.to_owned());
 
+    let mut attachment = false;
     let mime = match variant {
         ServeVariant::Directory {
             append_index_html_on_directories,
+            default_mime,
+            attachment_for_unknown_types,
+            trust_forwarded_headers,
+            relative_redirects,
+            directory_index,
         } => {
             if let Some(output) = maybe_redirect_or_append_path(
                 filesystem,
                 &mut path_to_file,
-                req.uri(),
-                *append_index_html_on_directories,
+                &req,
+                append_index_html_on_directories.then_some(directory_index),
+                *trust_forwarded_headers,
+                *relative_redirects,
             )
             .await
             {
                 return Ok(output);
             }
 
-            mime_guess::from_path(&path_to_file)
-                .first_raw()
-                .map(HeaderValue::from_static)
-                .unwrap_or_else(|| {
-                    HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap()
-                })
+            guess_mime(&path_to_file).unwrap_or_else(|| {
+                attachment = *attachment_for_unknown_types;
+                default_mime.clone()
+            })
         }
-        ServeVariant::SingleFile { mime } => mime.clone(),
+        ServeVariant::SingleFile { mime, .. } => mime.clone(),
     };
 
-    if req.method() == Method::HEAD {
-        let (meta, maybe_encoding) =
-            file_metadata_with_fallback(filesystem, path_to_file, negotiated_encodings).await?;
-
-        let last_modified = meta.modified.map(LastModified::from);
+    let is_head = req.method() == Method::HEAD;
+    if is_head || defer_open {
+        let (meta, maybe_encoding, path) = file_metadata_with_fallback(
+            filesystem,
+            path_to_file,
+            negotiated_encodings,
+            probe_cache,
+            concurrent_probes,
+        )
+        .await?;
+
+        let last_modified = last_modified(&meta, now, last_modified_min_age);
+        let etag = ETag::new(&meta);
         if let Some(output) = check_modified_headers(
+            &path,
+            &meta,
             last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
+            etag.as_ref(),
+            conditions,
         ) {
             return Ok(output);
         }
 
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len);
+        let maybe_range = meta
+            .len
+            .and_then(|len| try_parse_range(range_header.as_deref(), len));
+        let extent = if is_head {
+            FileRequestExtent::Head(meta)
+        } else {
+            let offset = range_offset(maybe_range.as_ref());
+            let file = LazyFile::deferred(filesystem.clone(), path.clone(), offset);
+
+            FileRequestExtent::Full(file, meta)
+        };
 
         Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
-            extent: FileRequestExtent::Head(meta),
+            extent,
+            path,
             chunk_size: buf_chunk_size,
             mime_header_value: mime,
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
+            attachment,
         })))
     } else {
-        let (mut file, maybe_encoding) =
-            open_file_with_fallback(filesystem, path_to_file, negotiated_encodings).await?;
+        let (file, maybe_encoding, path) = open_file_with_fallback(
+            filesystem,
+            path_to_file,
+            negotiated_encodings,
+            probe_cache,
+            concurrent_probes,
+        )
+        .await?;
         let meta = file.metadata().await?;
-        let last_modified = meta.modified.map(LastModified::from);
+        let last_modified = last_modified(&meta, now, last_modified_min_age);
+        let etag = ETag::new(&meta);
         if let Some(output) = check_modified_headers(
+            &path,
+            &meta,
             last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
+            etag.as_ref(),
+            conditions,
         ) {
             return Ok(output);
         }
 
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len);
-        if let Some(Ok(ranges)) = maybe_range.as_ref() {
-            // if there is any other amount of ranges than 1 we'll return an
-            // unsatisfiable later as there isn't yet support for multipart ranges
-            if ranges.len() == 1 {
-                file.seek(SeekFrom::Start(*ranges[0].start())).await?;
-            }
-        }
+        let maybe_range = meta
+            .len
+            .and_then(|len| try_parse_range(range_header.as_deref(), len));
+        let offset = range_offset(maybe_range.as_ref());
 
         Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
-            extent: FileRequestExtent::Full(file, meta),
+            extent: FileRequestExtent::Full(LazyFile::open(file, offset), meta),
+            path,
             chunk_size: buf_chunk_size,
             mime_header_value: mime,
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
+            attachment,
         })))
     }
 }
 
+// The file modified within the min_age isn't given a `Last-Modified`, the header has the precision
+// of a second, so a file modified again in the same second would keep it, and the caches
+// revalidating with it would keep the stale content.
+fn last_modified(meta: &Metadata, now: SystemTime, min_age: Duration) -> Option<LastModified> {
+    let modified = meta.modified?;
+    if !min_age.is_zero()
+        && now
+            .duration_since(modified)
+            .map_or(true, |age| age < min_age)
+    {
+        return None;
+    }
+
+    Some(LastModified::new(modified, now))
+}
+
+pub(super) fn guess_mime(path: &Path) -> Option<HeaderValue> {
+    mime_guess::from_path(path)
+        .first_raw()
+        .map(HeaderValue::from_static)
+}
+
 fn check_modified_headers<IO>(
+    path: &Path,
+    meta: &Metadata,
     modified: Option<&LastModified>,
-    if_unmodified_since: Option<IfUnmodifiedSince>,
-    if_modified_since: Option<IfModifiedSince>,
+    etag: Option<&ETag>,
+    conditions: Conditions,
 ) -> Option<OpenFileOutput<IO>> {
-    if let Some(since) = if_unmodified_since {
-        let precondition = modified
-            .as_ref()
-            .map(|time| since.precondition_passes(time))
-            .unwrap_or(false);
-
-        if !precondition {
-            return Some(OpenFileOutput::PreconditionFailed);
-        }
-    }
-
-    if let Some(since) = if_modified_since {
-        let unmodified = modified
-            .as_ref()
-            .map(|time| !since.is_modified(time))
-            // no last_modified means its always modified
-            .unwrap_or(false);
-        if unmodified {
-            return Some(OpenFileOutput::NotModified);
+    let status = conditions.evaluate(modified, etag)?;
+    let output = if status == StatusCode::PRECONDITION_FAILED {
+        OpenFileOutput::PreconditionFailed(ServedFile::new(
+            path.to_path_buf(),
+            Some(meta),
+            StatusReason::PreconditionFailed,
+        ))
+    } else {
+        OpenFileOutput::NotModified {
+            served_file: ServedFile::new(path.to_path_buf(), Some(meta), StatusReason::NotModified),
+            last_modified: modified.copied(),
+            etag: etag.cloned(),
         }
-    }
+    };
 
-    None
+    Some(output)
 }
 
 // Returns the preferred_encoding encoding and modifies the path extension
@@ -195,88 +423,258 @@ fn preferred_encoding(
     preferred_encoding
 }
 
-// Attempts to open the file with any of the possible negotiated_encodings in the
-// preferred order. If none of the negotiated_encodings have a corresponding precompressed
-// file the uncompressed file is used as a fallback.
-async fn open_file_with_fallback<FS: Filesystem>(
+// Attempts to open the file with any of the possible negotiated_encodings in the preferred
+// order, or all of them at once with the clones of the filesystem when concurrent_probes is
+// enabled, then the first opened one in the preferred order is picked. If none of the
+// negotiated_encodings have a corresponding precompressed file the uncompressed file is used as a
+// fallback. The path of the opened file is returned too. The missing variants are remembered in
+// the probe_cache.
+async fn open_file_with_fallback<FS: Filesystem + Clone>(
     filesystem: &mut FS,
-    mut path: PathBuf,
-    mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(FS::File, Option<Encoding>)> {
-    let (metadata, encoding) = loop {
-        // Get the preferred encoding among the negotiated ones.
-        let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (filesystem.open(&path).await, encoding) {
-            (Ok(metadata), maybe_encoding) => break (metadata, maybe_encoding),
-            (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
-                // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
-                // to reset the path before the next iteration.
-                path.set_extension(OsStr::new(""));
-                // Remove the encoding from the negotiated_encodings since the file doesn't exist
-                negotiated_encoding
-                    .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding);
-                continue;
+    path: PathBuf,
+    negotiated_encoding: Vec<(Encoding, QValue)>,
+    probe_cache: Option<&ProbeCache>,
+    concurrent_probes: bool,
+) -> io::Result<(FS::File, Option<Encoding>, PathBuf)> {
+    let candidates = variant_candidates(path, negotiated_encoding, probe_cache);
+    let mut missing = vec![];
+    if concurrent_probes {
+        let mut filesystems = vec![filesystem.clone(); candidates.len() - 1];
+        let results = join_all(
+            iter::once(filesystem)
+                .chain(filesystems.iter_mut())
+                .zip(&candidates)
+                .map(|(filesystem, (path, _))| filesystem.open(path)),
+        )
+        .await;
+
+        for (candidate, result) in candidates.into_iter().zip(results) {
+            if let Some(found) = settle_probe(result, candidate, &mut missing, probe_cache) {
+                return found;
             }
-            (Err(err), _) => return Err(err),
-        };
-    };
-    Ok((metadata, encoding))
+        }
+    } else {
+        for candidate in candidates {
+            let result = filesystem.open(&candidate.0).await;
+            if let Some(found) = settle_probe(result, candidate, &mut missing, probe_cache) {
+                return found;
+            }
+        }
+    }
+
+    unreachable!("the uncompressed path is always the last candidate")
 }
 
-// Attempts to get the file metadata with any of the possible negotiated_encodings in the
-// preferred order. If none of the negotiated_encodings have a corresponding precompressed
-// file the uncompressed file is used as a fallback.
+// Settles the probe of a candidate variant, `None` if it is missing and the next one is tried,
+// the missing ones before the found one are remembered in the probe_cache.
+fn settle_probe<T>(
+    result: io::Result<T>,
+    (path, encoding): (PathBuf, Option<Encoding>),
+    missing: &mut Vec<PathBuf>,
+    probe_cache: Option<&ProbeCache>,
+) -> Option<io::Result<(T, Option<Encoding>, PathBuf)>> {
+    match (result, encoding) {
+        (Ok(found), maybe_encoding) => {
+            remember_missing(probe_cache, std::mem::take(missing));
+            Some(Ok((found, maybe_encoding, path)))
+        }
+        (Err(err), Some(_)) if err.kind() == io::ErrorKind::NotFound => {
+            missing.push(path);
+            None
+        }
+        (Err(err), _) => Some(Err(err)),
+    }
+}
+
+// Lists the paths of the precompressed variants in the preferred order of the
+// negotiated_encodings, except the ones known missing in the probe_cache, the uncompressed path
+// is always the last one.
+fn variant_candidates(
+    path: PathBuf,
+    mut negotiated_encoding: Vec<(Encoding, QValue)>,
+    probe_cache: Option<&ProbeCache>,
+) -> Vec<(PathBuf, Option<Encoding>)> {
+    let mut candidates = Vec::with_capacity(negotiated_encoding.len() + 1);
+    loop {
+        let mut variant_path = path.clone();
+        let encoding = preferred_encoding(&mut variant_path, &negotiated_encoding);
+        let is_missing = encoding.is_some()
+            && probe_cache.is_some_and(|probe_cache| probe_cache.is_missing(&variant_path));
+        if !is_missing {
+            candidates.push((variant_path, encoding));
+        }
+
+        match encoding {
+            Some(encoding) => negotiated_encoding
+                .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding),
+            None => return candidates,
+        }
+    }
+}
+
+// Gets the file metadata of any of the possible negotiated_encodings in the preferred order, or
+// all of them at once when concurrent_probes is enabled, then the first existing one in the
+// preferred order is picked. If none of the negotiated_encodings have a corresponding
+// precompressed file the uncompressed file is used as a fallback. The path of the file is
+// returned too. The missing variants are remembered in the probe_cache.
 async fn file_metadata_with_fallback<FS: Filesystem>(
     filesystem: &FS,
-    mut path: PathBuf,
-    mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(Metadata, Option<Encoding>)> {
-    let (file, encoding) = loop {
-        // Get the preferred encoding among the negotiated ones.
-        let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (filesystem.metadata(&path).await, encoding) {
-            (Ok(file), maybe_encoding) => break (file, maybe_encoding),
-            (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
-                // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
-                // to reset the path before the next iteration.
-                path.set_extension(OsStr::new(""));
-                // Remove the encoding from the negotiated_encodings since the file doesn't exist
-                negotiated_encoding
-                    .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding);
-                continue;
+    path: PathBuf,
+    negotiated_encoding: Vec<(Encoding, QValue)>,
+    probe_cache: Option<&ProbeCache>,
+    concurrent_probes: bool,
+) -> io::Result<(Metadata, Option<Encoding>, PathBuf)> {
+    let candidates = variant_candidates(path, negotiated_encoding, probe_cache);
+    let mut missing = vec![];
+    if concurrent_probes {
+        let results = join_all(candidates.iter().map(|(path, _)| filesystem.metadata(path))).await;
+
+        for (candidate, result) in candidates.into_iter().zip(results) {
+            if let Some(found) = settle_probe(result, candidate, &mut missing, probe_cache) {
+                return found;
             }
-            (Err(err), _) => return Err(err),
-        };
-    };
-    Ok((file, encoding))
+        }
+    } else {
+        for candidate in candidates {
+            let result = filesystem.metadata(&candidate.0).await;
+            if let Some(found) = settle_probe(result, candidate, &mut missing, probe_cache) {
+                return found;
+            }
+        }
+    }
+
+    unreachable!("the uncompressed path is always the last candidate")
+}
+
+// Opens the uncompressed file and each of its precompressed variants of the encodings, and reads
+// their metadata, so the caches of the filesystem, like the handles of the disk or the metadata
+// snapshot, are populated before the requests. The missing variants are remembered in the
+// probe_cache.
+pub(crate) async fn warm_file<FS: Filesystem + Clone>(
+    filesystem: &mut FS,
+    path: PathBuf,
+    encodings: Vec<Encoding>,
+    probe_cache: Option<&ProbeCache>,
+) -> io::Result<()> {
+    for encoding in encodings.into_iter().map(Some).chain(iter::once(None)) {
+        let negotiated_encoding = encoding
+            .map(|encoding| vec![(encoding, QValue::one())])
+            .unwrap_or_default();
+        let (file, ..) = open_file_with_fallback(
+            filesystem,
+            path.clone(),
+            negotiated_encoding,
+            probe_cache,
+            false,
+        )
+        .await?;
+        file.metadata().await?;
+    }
+
+    Ok(())
 }
 
-async fn maybe_redirect_or_append_path<FS: Filesystem>(
+// only the variants of the existing files are remembered, so the requests of the random paths
+// don't fill the probe_cache
+fn remember_missing(probe_cache: Option<&ProbeCache>, missing: Vec<PathBuf>) {
+    if let Some(probe_cache) = probe_cache {
+        if !missing.is_empty() {
+            probe_cache.insert(missing);
+        }
+    }
+}
+
+// the `directory_index` is `None` if the index isn't appended on the directories
+async fn maybe_redirect_or_append_path<FS: Filesystem + Send + Sync, IO>(
     filesystem: &FS,
     path_to_file: &mut PathBuf,
-    uri: &Uri,
-    append_index_html_on_directories: bool,
-) -> Option<OpenFileOutput<FS::File>> {
+    req: &Request<Empty<Bytes>>,
+    directory_index: Option<&DirectoryIndex>,
+    trust_forwarded_headers: bool,
+    relative_redirects: bool,
+) -> Option<OpenFileOutput<IO>> {
+    let uri = req.uri();
     if !uri.path().ends_with('/') {
         if filesystem.is_dir(path_to_file).await.unwrap_or(false) {
-            let location =
-                HeaderValue::from_str(&append_slash_on_path(uri.clone()).to_string()).unwrap();
+            let location = slash_location(req, trust_forwarded_headers, relative_redirects);
             Some(OpenFileOutput::Redirect { location })
         } else {
             None
         }
     } else if filesystem.is_dir(path_to_file).await.unwrap_or(false) {
-        if append_index_html_on_directories {
-            path_to_file.push("index.html");
-            None
-        } else {
-            Some(OpenFileOutput::FileNotFound)
+        let index = match directory_index {
+            None => None,
+            Some(directory_index) => {
+                let request = IndexRequest {
+                    dir: path_to_file,
+                    headers: req.headers(),
+                    filesystem,
+                };
+
+                directory_index.pick(&request).await
+            }
+        };
+
+        match index {
+            Some(index) => {
+                path_to_file.push(index);
+                None
+            }
+            None => Some(OpenFileOutput::FileNotFound),
         }
     } else {
         None
     }
 }
 
+/// The `Content-Location` of the served file when it isn't the requested one, like the
+/// precompressed variant of the requested file, or the index of the requested directory.
+pub(super) fn content_location(
+    uri_path: &str,
+    requested_path: &Path,
+    served_path: &Path,
+) -> Option<HeaderValue> {
+    let encode = |name: &OsStr| {
+        name.to_str()
+            .map(|name| percent_encode(name.as_bytes(), SEGMENT_ENCODE_SET).to_string())
+    };
+
+    let suffix = match served_path.strip_prefix(requested_path) {
+        Ok(index) if !index.as_os_str().is_empty() => index
+            .iter()
+            .map(encode)
+            .collect::<Option<Vec<_>>>()?
+            .join("/"),
+        Ok(_) => return None,
+        Err(_) => {
+            // the precompressed variant, like `foo.txt.gz` of `foo.txt`
+            if served_path.parent() != requested_path.parent() {
+                return None;
+            }
+            let served = encode(served_path.file_name()?)?;
+            let requested = encode(requested_path.file_name()?)?;
+
+            served.strip_prefix(&requested)?.to_string()
+        }
+    };
+
+    // a path starting with `//` would be read as a URL of another host
+    let location = format!("/{}{suffix}", uri_path.trim_start_matches('/'));
+
+    HeaderValue::try_from(location).ok()
+}
+
+// if there is any other amount of ranges than 1 we'll return an unsatisfiable later as there isn't
+// yet support for multipart ranges
+fn range_offset(
+    maybe_range: Option<&Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>>,
+) -> u64 {
+    match maybe_range {
+        Some(Ok(ranges)) if ranges.len() == 1 => *ranges[0].start(),
+        _ => 0,
+    }
+}
+
 fn try_parse_range(
     maybe_range_ref: Option<&str>,
     file_size: u64,
@@ -287,33 +685,155 @@ fn try_parse_range(
     })
 }
 
-fn append_slash_on_path(uri: Uri) -> Uri {
-    let http::uri::Parts {
-        scheme,
-        authority,
-        path_and_query,
-        ..
-    } = uri.into_parts();
+// the `Location` of the directory redirect, the request path with the slash appended
+fn slash_location(
+    req: &Request<Empty<Bytes>>,
+    trust_forwarded_headers: bool,
+    relative_redirects: bool,
+) -> HeaderValue {
+    let uri = req.uri();
+    let mut location = if relative_redirects {
+        relative_slash_path(uri.path())
+    } else {
+        let origin = if trust_forwarded_headers {
+            forwarded_origin(uri, req.headers())
+        } else {
+            uri.scheme().cloned().zip(uri.authority().cloned())
+        };
+
+        match origin {
+            Some((scheme, authority)) => format!("{scheme}://{authority}{}/", uri.path()),
+            // a path starting with `//` would be read as a URL of another host
+            None => format!("/{}/", uri.path().trim_start_matches('/')),
+        }
+    };
+    if let Some(query) = uri.query() {
+        location.push('?');
+        location.push_str(query);
+    }
 
-    let mut uri_builder = Uri::builder();
+    HeaderValue::try_from(percent_encode(location.as_bytes(), LOCATION_ENCODE_SET).to_string())
+        .expect("the percent-encoded location is a valid header value")
+}
+
+// the last path segment with the slash appended, like `foo/` for `/docs/foo`
+fn relative_slash_path(path: &str) -> String {
+    let segment = path.rsplit('/').next().unwrap_or_default();
 
-    if let Some(scheme) = scheme {
-        uri_builder = uri_builder.scheme(scheme);
+    // a colon in the first segment would be read as a scheme
+    if segment.contains(':') {
+        format!("./{segment}/")
+    } else {
+        format!("{segment}/")
     }
+}
 
-    if let Some(authority) = authority {
-        uri_builder = uri_builder.authority(authority);
+// the scheme and the authority the client used before the proxy, the `Host` header is used if
+// the proxy only forwards the scheme
+fn forwarded_origin(uri: &Uri, headers: &HeaderMap) -> Option<(Scheme, Authority)> {
+    let ForwardedOrigin { proto, host } = ForwardedOrigin::from_headers(headers);
+    if proto.is_none() && host.is_none() {
+        return uri.scheme().cloned().zip(uri.authority().cloned());
     }
 
-    let uri_builder = if let Some(path_and_query) = path_and_query {
-        if let Some(query) = path_and_query.query() {
-            uri_builder.path_and_query(format!("{}/?{}", path_and_query.path(), query))
-        } else {
-            uri_builder.path_and_query(format!("{}/", path_and_query.path()))
+    let host = host.or_else(|| uri.authority().cloned()).or_else(|| {
+        headers
+            .get(header::HOST)
+            .and_then(|host| Authority::try_from(host.as_bytes()).ok())
+    })?;
+    let proto = proto
+        .or_else(|| uri.scheme().cloned())
+        .unwrap_or(Scheme::HTTP);
+
+    Some((proto, host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the bytes accepted by the URI parser, and a few rejected ones
+    const ALPHABET: &[u8] = b"/\\:@.%?&=#\"{}|^`[]!$'()*+,;~-_ aZ09\x7f\xc3\xa9";
+
+    #[test]
+    fn hostile_slash_locations() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut checked = 0;
+        for _ in 0..20_000 {
+            let len = random() % 16;
+            let mut uri = vec![b'/'];
+            uri.extend((0..len).map(|_| ALPHABET[(random() % ALPHABET.len() as u64) as usize]));
+            let Ok(uri) = Uri::from_maybe_shared(Bytes::from(uri)) else {
+                continue;
+            };
+            if uri.path().ends_with('/') {
+                continue;
+            }
+            checked += 1;
+
+            let req = Request::builder()
+                .uri(uri.clone())
+                .header(header::HOST, "example.com")
+                .header("x-forwarded-proto", "https")
+                .body(Empty::new())
+                .unwrap();
+
+            for (trust, relative) in [(false, false), (true, false), (false, true)] {
+                let location = slash_location(&req, trust, relative);
+                let location = location.to_str().unwrap();
+                if relative {
+                    // the parser doesn't accept the relative references
+                    assert!(
+                        format!("/{location}").parse::<Uri>().is_ok(),
+                        "{uri} {location}"
+                    );
+                    assert!(!location.starts_with('/'), "{uri} {location}");
+                    let first_segment = location.split('/').next().unwrap();
+                    assert!(!first_segment.contains(':'), "{uri} {location}");
+                } else if trust {
+                    assert!(location.parse::<Uri>().is_ok(), "{uri} {location}");
+                    assert!(
+                        location.starts_with("https://example.com/"),
+                        "{uri} {location}"
+                    );
+                } else {
+                    assert!(location.parse::<Uri>().is_ok(), "{uri} {location}");
+                    assert!(location.starts_with('/'), "{uri} {location}");
+                    assert!(!location.starts_with("//"), "{uri} {location}");
+                }
+            }
         }
-    } else {
-        uri_builder.path_and_query("/")
-    };
 
-    uri_builder.build().unwrap()
+        assert!(checked > 1_000);
+    }
+
+    #[test]
+    fn content_locations() {
+        let location = |uri_path, requested, served| {
+            content_location(uri_path, Path::new(requested), Path::new(served))
+                .map(|location| location.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(location("/foo.txt", "foo.txt", "foo.txt"), None);
+        assert_eq!(
+            location("/a%20b/foo.txt", "a b/foo.txt", "a b/foo.txt.gz").as_deref(),
+            Some("/a%20b/foo.txt.gz")
+        );
+        assert_eq!(
+            location("/", "", "index.html").as_deref(),
+            Some("/index.html")
+        );
+        assert_eq!(
+            location("//docs/", "docs", "docs/#1/index.html.br").as_deref(),
+            Some("/docs/%231/index.html.br")
+        );
+        assert_eq!(location("/foo.txt", "foo.txt", "bar/foo.txt.gz"), None);
+    }
 }
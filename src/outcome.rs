@@ -0,0 +1,123 @@
+use http::StatusCode;
+
+/// Why a request isn't served with the file, reported as a `tracing` event at the `debug` level
+/// when the `tracing` feature is enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// the client address is rejected by the `IpFilter`
+    Forbidden,
+    /// the request is over the limit of the `RateLimiter`
+    RateLimited,
+    /// the method isn't `GET` or `HEAD`
+    MethodNotAllowed,
+    /// the `GET` or `HEAD` request has a body which is rejected by the `RequestBodyPolicy`
+    UnexpectedBody,
+    /// the request body can't be read while draining
+    InvalidBody,
+    /// the `Host` header is invalid for the host template
+    InvalidHost,
+    /// the query is rejected by the `QueryPolicy`
+    QueryRejected,
+    /// the `RootOverride` is rejected by the filesystem
+    RootRejected,
+    /// the path isn't valid percent encoded UTF-8
+    InvalidPath,
+    /// the path tries to escape from the root, like `..`
+    TraversalRejected,
+    /// the path is hidden by the dot files or exclude filter
+    Hidden,
+    /// the file doesn't exist
+    MissingFile,
+    /// the file can't be read
+    PermissionDenied,
+    /// the file is modified since the `If-Unmodified-Since` time
+    PreconditionFailed,
+    /// the `Range` header can't be satisfied
+    BadRange,
+    /// multipart ranges are not supported
+    MultipartRange,
+    /// the range request is rejected by the `RangeGuard`
+    RangeRejected,
+    /// the search visits more entries than the `SearchOptions::max_entries`
+    SearchTooLarge,
+    /// the search takes longer than the `SearchOptions::timeout`
+    SearchTimedOut,
+    /// the filesystem can't list the dirs to search
+    SearchUnsupported,
+    /// the `PURGE` request doesn't carry the purge token
+    Unauthorized,
+    /// the request fails with an IO error, responded by the `500` error page
+    InternalError,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+impl Outcome {
+    pub(crate) fn status(self) -> StatusCode {
+        match self {
+            Outcome::Forbidden => StatusCode::FORBIDDEN,
+            Outcome::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Outcome::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Outcome::UnexpectedBody => StatusCode::PAYLOAD_TOO_LARGE,
+            Outcome::InvalidBody => StatusCode::BAD_REQUEST,
+            Outcome::InvalidHost => StatusCode::BAD_REQUEST,
+            Outcome::QueryRejected => StatusCode::BAD_REQUEST,
+            Outcome::RootRejected
+            | Outcome::InvalidPath
+            | Outcome::TraversalRejected
+            | Outcome::Hidden
+            | Outcome::MissingFile
+            | Outcome::PermissionDenied => StatusCode::NOT_FOUND,
+            Outcome::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Outcome::BadRange | Outcome::MultipartRange => StatusCode::RANGE_NOT_SATISFIABLE,
+            Outcome::RangeRejected => StatusCode::TOO_MANY_REQUESTS,
+            Outcome::SearchTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Outcome::SearchTimedOut => StatusCode::SERVICE_UNAVAILABLE,
+            Outcome::SearchUnsupported => StatusCode::NOT_IMPLEMENTED,
+            Outcome::Unauthorized => StatusCode::UNAUTHORIZED,
+            Outcome::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Forbidden => "forbidden",
+            Outcome::RateLimited => "rate_limited",
+            Outcome::MethodNotAllowed => "method_not_allowed",
+            Outcome::UnexpectedBody => "unexpected_body",
+            Outcome::InvalidBody => "invalid_body",
+            Outcome::InvalidHost => "invalid_host",
+            Outcome::QueryRejected => "query_rejected",
+            Outcome::RootRejected => "root_rejected",
+            Outcome::InvalidPath => "invalid_path",
+            Outcome::TraversalRejected => "traversal_rejected",
+            Outcome::Hidden => "hidden",
+            Outcome::MissingFile => "missing_file",
+            Outcome::PermissionDenied => "permission_denied",
+            Outcome::PreconditionFailed => "precondition_failed",
+            Outcome::BadRange => "bad_range",
+            Outcome::MultipartRange => "multipart_range",
+            Outcome::RangeRejected => "range_rejected",
+            Outcome::SearchTooLarge => "search_too_large",
+            Outcome::SearchTimedOut => "search_timed_out",
+            Outcome::SearchUnsupported => "search_unsupported",
+            Outcome::Unauthorized => "unauthorized",
+            Outcome::InternalError => "internal_error",
+        }
+    }
+
+    /// Report the outcome of the request `path`, the `404` ones are reported even when the
+    /// fallback is called.
+    #[inline]
+    pub(crate) fn report(self, path: &str) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            status = self.status().as_u16(),
+            reason = self.as_str(),
+            path,
+            "request not served"
+        );
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = path;
+    }
+}
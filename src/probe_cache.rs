@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "watch")]
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+#[cfg(feature = "watch")]
+use crate::watch::FileWatcher;
+
+const CAPACITY: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Inner {
+    // the variant path and when it was found missing
+    missing: HashMap<PathBuf, Instant>,
+    #[cfg(feature = "watch")]
+    events: Option<broadcast::Receiver<String>>,
+}
+
+/// The precompressed variants recently found missing, so they aren't looked up again until the
+/// `ttl` passes, see [`ServeDir::precompressed_probe_cache`](crate::ServeDir::precompressed_probe_cache).
+///
+/// The clones share the same cache.
+#[derive(Debug, Clone)]
+pub(crate) struct ProbeCache {
+    ttl: Duration,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ProbeCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: Default::default(),
+        }
+    }
+
+    /// Forget the variants changed in the watched directory.
+    #[cfg(feature = "watch")]
+    pub(crate) fn watch(&self, watcher: &FileWatcher) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.missing.clear();
+        inner.events = Some(watcher.subscribe());
+    }
+
+    pub(crate) fn is_missing(&self, path: &Path) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        #[cfg(feature = "watch")]
+        inner.drain_events();
+
+        match inner.missing.get(path) {
+            None => false,
+            Some(found_at) if found_at.elapsed() < self.ttl => true,
+            Some(_) => {
+                inner.missing.remove(path);
+
+                false
+            }
+        }
+    }
+
+    pub(crate) fn insert(&self, paths: impl IntoIterator<Item = PathBuf>) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        for path in paths {
+            if inner.missing.len() >= CAPACITY && !inner.missing.contains_key(&path) {
+                let ttl = self.ttl;
+                inner
+                    .missing
+                    .retain(|_, found_at| now.duration_since(*found_at) < ttl);
+                let oldest = inner
+                    .missing
+                    .iter()
+                    .min_by_key(|(_, found_at)| **found_at)
+                    .map(|(path, _)| path.clone());
+                if let Some(oldest) = oldest.filter(|_| inner.missing.len() >= CAPACITY) {
+                    inner.missing.remove(&oldest);
+                }
+            }
+
+            inner.missing.insert(path, now);
+        }
+    }
+
+    /// Forget the variants whose paths aren't kept.
+    pub(crate) fn retain(&self, keep: impl Fn(&Path) -> bool) {
+        self.inner
+            .lock()
+            .unwrap()
+            .missing
+            .retain(|path, _| keep(path));
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Inner {
+    fn drain_events(&mut self) {
+        let Some(events) = &mut self.events else {
+            return;
+        };
+
+        loop {
+            match events.try_recv() {
+                Ok(path) => {
+                    // the changed paths are relative to the watched directory and use `/`
+                    self.missing.remove(Path::new(&path));
+                }
+                Err(TryRecvError::Empty) => return,
+                // some changes are dropped, any variant may be created
+                Err(TryRecvError::Lagged(_)) => self.missing.clear(),
+                Err(TryRecvError::Closed) => {
+                    self.events = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_until_ttl() {
+        let cache = ProbeCache::new(Duration::from_millis(50));
+        cache.insert([PathBuf::from("a.txt.gz"), PathBuf::from("a.txt.br")]);
+
+        assert!(cache.is_missing(Path::new("a.txt.gz")));
+        assert!(cache.is_missing(Path::new("a.txt.br")));
+        assert!(!cache.is_missing(Path::new("b.txt.gz")));
+
+        cache.retain(|path| path != Path::new("a.txt.br"));
+        assert!(!cache.is_missing(Path::new("a.txt.br")));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!cache.is_missing(Path::new("a.txt.gz")));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+
+use http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode};
+
+use crate::fs::immutable::ImmutableFilesystem;
+use crate::fs::{is_purged_by, is_purged_by_prefix, Purge};
+use crate::serve_dir::response_with_status;
+use crate::ResponseBody;
+
+type PurgeFn<FS> = Arc<dyn Fn(&FS, Scope<'_>) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Scope<'a> {
+    Path(&'a Path),
+    Prefix(&'a Path),
+    All,
+}
+
+impl Scope<'_> {
+    /// Check the `cached` path is dropped by the purge of the scope.
+    pub(crate) fn is_purged(&self, cached: &Path) -> bool {
+        match self {
+            Scope::Path(path) => is_purged_by(cached, path),
+            Scope::Prefix(prefix) => is_purged_by_prefix(cached, prefix),
+            Scope::All => true,
+        }
+    }
+}
+
+/// The method purging the filesystem caches
+pub(crate) const PURGE: &str = "PURGE";
+
+pub(crate) fn purge_method() -> Method {
+    Method::from_bytes(PURGE.as_bytes()).unwrap()
+}
+
+/// Answer the `PURGE` requests carrying the token by purging the filesystem serving the request,
+/// see [`ServeDir::purge_method`](crate::ServeDir::purge_method).
+pub(crate) struct PurgeHandler<FS> {
+    authorization: Vec<u8>,
+    purge: PurgeFn<FS>,
+}
+
+impl<FS> Clone for PurgeHandler<FS> {
+    fn clone(&self) -> Self {
+        Self {
+            authorization: self.authorization.clone(),
+            purge: self.purge.clone(),
+        }
+    }
+}
+
+impl<FS> Debug for PurgeHandler<FS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PurgeHandler").finish_non_exhaustive()
+    }
+}
+
+impl<FS> PurgeHandler<FS> {
+    pub(crate) fn new(token: &str) -> Self
+    where
+        FS: Purge,
+    {
+        Self {
+            authorization: format!("Bearer {token}").into_bytes(),
+            purge: Arc::new(|filesystem: &FS, scope: Scope<'_>| match scope {
+                Scope::Path(path) => filesystem.purge(path),
+                Scope::Prefix(prefix) => filesystem.purge_prefix(prefix),
+                Scope::All => filesystem.purge_all(),
+            }),
+        }
+    }
+
+    /// The handler purging the [`ImmutableFilesystem`] wrapping the filesystem, and the
+    /// filesystem itself, see [`ServeDir::immutable`](crate::ServeDir::immutable).
+    pub(crate) fn immutable(self) -> PurgeHandler<ImmutableFilesystem<FS>>
+    where
+        FS: 'static,
+    {
+        let purge = self.purge;
+
+        PurgeHandler {
+            authorization: self.authorization,
+            purge: Arc::new(
+                move |filesystem: &ImmutableFilesystem<FS>, scope: Scope<'_>| {
+                    filesystem.purge_with(
+                        |inner| purge(inner, scope),
+                        |cached| scope.is_purged(cached),
+                    )
+                },
+            ),
+        }
+    }
+
+    // compare all the bytes, so the token can't be guessed from the response time
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(authorization) = headers.get(header::AUTHORIZATION) else {
+            return false;
+        };
+        let authorization = authorization.as_bytes();
+
+        authorization.len() == self.authorization.len()
+            && authorization
+                .iter()
+                .zip(&self.authorization)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    pub(crate) fn handle(
+        &self,
+        filesystem: &FS,
+        headers: &HeaderMap,
+        scope: Scope<'_>,
+    ) -> Response<ResponseBody> {
+        if !self.is_authorized(headers) {
+            let mut res = response_with_status(StatusCode::UNAUTHORIZED);
+            res.headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+
+            return res;
+        }
+
+        (self.purge)(filesystem, scope);
+
+        response_with_status(StatusCode::NO_CONTENT)
+    }
+}
@@ -0,0 +1,41 @@
+use http::Uri;
+
+/// What to do with the query string of the requests, see
+/// [`ServeDir::query_policy`](crate::ServeDir::query_policy).
+///
+/// The query never selects the file, `/app.js?v=2` is always served from `app.js`, and the
+/// directory and canonical redirects keep the query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QueryPolicy {
+    /// Ignore the query, except the parameters of the enabled features, like the `q` of the
+    /// [`ServeDir::search`](crate::ServeDir::search), the `stat` of the
+    /// [`ServeDir::serve_stat`](crate::ServeDir::serve_stat) and the parameters of the
+    /// [`ServeDir::transform`](crate::ServeDir::transform).
+    #[default]
+    Ignore,
+
+    /// Treat the query as a cache buster, like `?v=3f2a`, which only changes the URL the caches
+    /// store the response under, so no parameter is read from it and the same file is served
+    /// whatever the query is.
+    CacheBuster,
+
+    /// Reject the requests with a query by `400 Bad Request`, even an empty one like `/app.js?`.
+    Reject,
+}
+
+/// The `uri` without its query, so the cache busters aren't read as parameters.
+pub(crate) fn without_query(uri: &Uri) -> Uri {
+    if uri.query().is_none() {
+        return uri.clone();
+    }
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(
+        uri.path()
+            .parse()
+            .expect("the path of a valid URI is a valid path"),
+    );
+
+    Uri::from_parts(parts).expect("the URI without the query is a valid URI")
+}
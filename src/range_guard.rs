@@ -0,0 +1,218 @@
+use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::Extensions;
+
+use crate::lru::Lru;
+
+// the connections are never known to be closed, so the counts expire after the connection is idle
+// for a while, and the least recently used one is dropped when there are too many
+const MAX_TRACKED_CONNECTIONS: usize = 65536;
+const DEFAULT_COUNT_TTL: Duration = Duration::from_secs(60);
+
+type Hook = Arc<dyn Fn(&RangeRequest<'_>) -> bool + Send + Sync>;
+type Extractor = Arc<dyn Fn(&Extensions) -> Option<SocketAddr> + Send + Sync>;
+
+/// A range request passed to the [`RangeGuard::hook`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct RangeRequest<'a> {
+    /// The client address, which identifies the connection
+    pub client: Option<SocketAddr>,
+    /// The path of the file passed to the filesystem
+    pub path: &'a Path,
+    /// The parsed ranges of the `Range` header
+    pub ranges: &'a [RangeInclusive<u64>],
+    /// How many range requests are seen on the connection, including this one, it is always 1
+    /// when the client is unknown
+    pub connection_count: usize,
+}
+
+#[derive(Debug)]
+struct Count {
+    count: usize,
+    last_seen: Instant,
+}
+
+/// Watch the range requests to detect the abusive scanners, like the ones requesting thousands of
+/// tiny ranges, the rejected requests are responded with `429 Too Many Requests`, see
+/// [`ServeDir::range_guard`](crate::ServeDir::range_guard).
+///
+/// The connection is identified by the client [`SocketAddr`] in the request extensions by
+/// default, use [`RangeGuard::extractor`] when it is stored in other types, like the axum
+/// `ConnectInfo`. The requests without a known address are not limited by
+/// [`RangeGuard::max_per_connection`], as they can't be told apart. The clones share the same
+/// counts.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::RangeGuard;
+///
+/// let guard = RangeGuard::new()
+///     .max_per_connection(1000)
+///     .hook(|request| request.ranges.iter().all(|range| range.end() - range.start() >= 1024));
+/// ```
+#[derive(Clone)]
+pub struct RangeGuard {
+    hook: Option<Hook>,
+    extractor: Option<Extractor>,
+    max_per_connection: Option<usize>,
+    count_ttl: Duration,
+    counts: Arc<Mutex<Lru<SocketAddr, Count>>>,
+}
+
+impl Default for RangeGuard {
+    fn default() -> Self {
+        Self {
+            hook: None,
+            extractor: None,
+            max_per_connection: None,
+            count_ttl: DEFAULT_COUNT_TTL,
+            counts: Arc::new(Mutex::new(Lru::new(MAX_TRACKED_CONNECTIONS))),
+        }
+    }
+}
+
+impl Debug for RangeGuard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RangeGuard")
+            .field("hook", &self.hook.is_some())
+            .field("extractor", &self.extractor.is_some())
+            .field("max_per_connection", &self.max_per_connection)
+            .field("count_ttl", &self.count_ttl)
+            .finish()
+    }
+}
+
+impl RangeGuard {
+    /// Create a new [`RangeGuard`] allowing any range request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call the `hook` with every range request, return `false` to reject it, the hook can also
+    /// just log the requests.
+    pub fn hook<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&RangeRequest<'_>) -> bool + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Reject the range requests after `max` ones on the same connection.
+    ///
+    /// Defaults to no limit.
+    pub fn max_per_connection(mut self, max: usize) -> Self {
+        self.max_per_connection = Some(max);
+        self
+    }
+
+    /// Reset the count of a connection when it has no range request for `ttl`, as the closed
+    /// connections are not known, and a new connection can reuse the address of a closed one.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn count_ttl(mut self, ttl: Duration) -> Self {
+        self.count_ttl = ttl;
+        self
+    }
+
+    /// Set how to get the client address from the request extensions.
+    pub fn extractor<E>(mut self, extractor: E) -> Self
+    where
+        E: Fn(&Extensions) -> Option<SocketAddr> + Send + Sync + 'static,
+    {
+        self.extractor = Some(Arc::new(extractor));
+        self
+    }
+
+    pub(crate) fn client(&self, extensions: &Extensions) -> Option<SocketAddr> {
+        match &self.extractor {
+            None => extensions.get::<SocketAddr>().copied(),
+            Some(extractor) => extractor(extensions),
+        }
+    }
+
+    pub(crate) fn is_allowed(
+        &self,
+        client: Option<SocketAddr>,
+        path: &Path,
+        ranges: &[RangeInclusive<u64>],
+    ) -> bool {
+        self.is_allowed_at(client, path, ranges, Instant::now())
+    }
+
+    fn is_allowed_at(
+        &self,
+        client: Option<SocketAddr>,
+        path: &Path,
+        ranges: &[RangeInclusive<u64>],
+        now: Instant,
+    ) -> bool {
+        let connection_count = match client {
+            None => 1,
+            Some(client) => {
+                let mut counts = self.counts.lock().unwrap();
+                let count = counts.get_or_insert_with(&client, || Count {
+                    count: 0,
+                    last_seen: now,
+                });
+                if now.saturating_duration_since(count.last_seen) >= self.count_ttl {
+                    count.count = 0;
+                }
+                count.count += 1;
+                count.last_seen = now;
+                count.count
+            }
+        };
+
+        if client.is_some()
+            && self
+                .max_per_connection
+                .is_some_and(|max| connection_count > max)
+        {
+            return false;
+        }
+
+        self.hook.as_ref().map_or(true, |hook| {
+            hook(&RangeRequest {
+                client,
+                path,
+                ranges,
+                connection_count,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_per_connection() {
+        let guard = RangeGuard::new().max_per_connection(2);
+        let client = Some(SocketAddr::from(([127, 0, 0, 1], 8080)));
+        let path = Path::new("test.txt");
+        let ranges = [0..=0];
+        let now = Instant::now();
+
+        assert!(guard.is_allowed_at(client, path, &ranges, now));
+        assert!(guard.is_allowed_at(client, path, &ranges, now));
+        assert!(!guard.is_allowed_at(client, path, &ranges, now + Duration::from_secs(59)));
+        // the connection is idle for the ttl, the count is reset
+        assert!(guard.is_allowed_at(client, path, &ranges, now + Duration::from_secs(119)));
+        assert!(guard.is_allowed_at(client, path, &ranges, now + Duration::from_secs(119)));
+        assert!(!guard.is_allowed_at(client, path, &ranges, now + Duration::from_secs(119)));
+
+        // the requests without a known address are not counted together
+        for _ in 0..3 {
+            assert!(guard.is_allowed_at(None, path, &ranges, now));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::request::Parts;
+
+use crate::lru::Lru;
+
+// the least recently updated bucket is dropped when there are more keys, the clients rotating
+// their addresses can't make the map grow or slow
+const MAX_BUCKETS: usize = 65536;
+
+type KeyExtractor = Arc<dyn Fn(&Parts) -> Option<String> + Send + Sync>;
+
+/// A rate limiter checked by the [`ServeDir`](crate::ServeDir) before any filesystem work, see
+/// [`ServeDir::rate_limit`](crate::ServeDir::rate_limit).
+pub trait RateLimiter: Send + Sync {
+    /// Take a permit for the request with the `key`, return the time to wait before retrying
+    /// when it is over the limit, it is sent as the `Retry-After` header.
+    fn check(&self, key: &str) -> Result<(), Duration>;
+}
+
+/// A token bucket [`RateLimiter`], every key has its own bucket which holds up to `capacity`
+/// tokens and gets a token back every `refill` interval, a request takes a token.
+///
+/// The clones share the same buckets.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: u32,
+    refill: Duration,
+    buckets: Arc<Mutex<Lru<String, Bucket>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: u32,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new [`TokenBucket`].
+    ///
+    /// # Panics
+    /// Will panic if the `capacity` or the `refill` is zero.
+    pub fn new(capacity: u32, refill: Duration) -> Self {
+        assert!(capacity > 0, "capacity must be greater than zero");
+        assert!(!refill.is_zero(), "refill must be greater than zero");
+
+        Self {
+            capacity,
+            refill,
+            buckets: Arc::new(Mutex::new(Lru::new(MAX_BUCKETS))),
+        }
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert_with(key, || Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        *bucket = self.refilled(bucket, now);
+
+        if bucket.tokens == 0 {
+            return Err(self.refill - now.duration_since(bucket.updated));
+        }
+
+        bucket.tokens -= 1;
+
+        Ok(())
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> Bucket {
+        let elapsed = now.duration_since(bucket.updated);
+        let refills = elapsed.as_nanos() / self.refill.as_nanos();
+        if refills == 0 {
+            return *bucket;
+        }
+
+        let tokens = (bucket.tokens as u128 + refills).min(self.capacity as u128) as u32;
+        if tokens == self.capacity {
+            return Bucket {
+                tokens,
+                updated: now,
+            };
+        }
+
+        Bucket {
+            tokens,
+            // keep the partial interval
+            updated: bucket.updated + self.refill * refills as u32,
+        }
+    }
+}
+
+impl RateLimiter for TokenBucket {
+    fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RateLimit {
+    limiter: Arc<dyn RateLimiter>,
+    key: KeyExtractor,
+}
+
+impl Debug for RateLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimit").finish_non_exhaustive()
+    }
+}
+
+impl RateLimit {
+    pub(crate) fn new<L, K>(limiter: L, key: K) -> Self
+    where
+        L: RateLimiter + 'static,
+        K: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            limiter: Arc::new(limiter),
+            key: Arc::new(key),
+        }
+    }
+
+    /// The requests without a key are not limited.
+    pub(crate) fn check(&self, parts: &Parts) -> Result<(), Duration> {
+        match (self.key)(parts) {
+            None => Ok(()),
+            Some(key) => self.limiter.check(&key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let bucket = TokenBucket::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert!(bucket.check_at("a", now).is_ok());
+        assert!(bucket.check_at("a", now).is_ok());
+        assert_eq!(
+            bucket.check_at("a", now + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+        // other keys have their own buckets
+        assert!(bucket.check_at("b", now).is_ok());
+
+        assert!(bucket.check_at("a", now + Duration::from_secs(10)).is_ok());
+        assert!(bucket.check_at("a", now + Duration::from_secs(10)).is_err());
+        assert!(bucket.check_at("a", now + Duration::from_secs(40)).is_ok());
+        assert!(bucket.check_at("a", now + Duration::from_secs(40)).is_ok());
+        assert!(bucket.check_at("a", now + Duration::from_secs(40)).is_err());
+    }
+
+    #[test]
+    fn token_bucket_full_map() {
+        let bucket = TokenBucket::new(1, Duration::from_secs(10));
+        let now = Instant::now();
+        for key in 0..MAX_BUCKETS {
+            assert!(bucket.check_at(&key.to_string(), now).is_ok());
+        }
+
+        // none of the buckets is full, the least recently updated one is dropped for the new key
+        assert!(bucket.check_at("new", now).is_ok());
+        assert!(bucket.check_at("new", now).is_err());
+        assert_eq!(bucket.buckets.lock().unwrap().len(), MAX_BUCKETS);
+        assert!(bucket.check_at("0", now).is_ok());
+        assert!(bucket.check_at("2", now).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use std::pin::pin;
+
+use bytes::Buf;
+use http_body::Body;
+
+/// What to do with the unexpected body of the `GET` and `HEAD` requests, see
+/// [`ServeDir::request_body_policy`](crate::ServeDir::request_body_policy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestBodyPolicy {
+    /// Drop the body without reading it.
+    #[default]
+    Drop,
+
+    /// Read and discard up to the limit of bytes, so the connection can be kept alive, the larger
+    /// bodies are rejected with `413 Payload Too Large`.
+    ///
+    /// When the fallback is set, the body is passed to the fallback instead.
+    Drain(u64),
+
+    /// Reject the requests with a body by `413 Payload Too Large`.
+    Reject,
+}
+
+/// Why the request body is rejected
+pub(crate) enum BodyRejected {
+    TooLarge,
+    Invalid,
+}
+
+/// Drain the body, fail if it is longer than the `limit` or can't be read.
+pub(crate) async fn drain<B: Body>(body: B, limit: u64) -> Result<(), BodyRejected> {
+    let mut body = pin!(body);
+    let mut len = 0u64;
+    while let Some(data) = body.data().await {
+        let data = data.map_err(|_| BodyRejected::Invalid)?;
+        len = len.saturating_add(data.remaining() as u64);
+        if len > limit {
+            return Err(BodyRejected::TooLarge);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+pub(crate) const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+// the longer ids of the clients are replaced, so they can't flood the logs
+const MAX_LEN: usize = 128;
+
+/// The `X-Request-Id` of the request, a new one is generated when it is missing or invalid.
+pub(crate) fn request_id(headers: &HeaderMap) -> HeaderValue {
+    from_request(headers).unwrap_or_else(generate)
+}
+
+/// The valid `X-Request-Id` of the request, without generating one.
+pub(crate) fn from_request(headers: &HeaderMap) -> Option<HeaderValue> {
+    headers
+        .get(X_REQUEST_ID)
+        .filter(|id| is_valid(id.as_bytes()))
+        .cloned()
+}
+
+fn is_valid(id: &[u8]) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.iter().all(|b| b.is_ascii_graphic())
+}
+
+// a random prefix of the process and a counter, unique without a random number generator
+fn generate() -> HeaderValue {
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let prefix = *PREFIX.get_or_init(|| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        hasher.finish()
+    });
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    HeaderValue::try_from(format!("{prefix:016x}{count:016x}")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_or_generate() {
+        let mut headers = HeaderMap::new();
+        let first = request_id(&headers);
+        let second = request_id(&headers);
+        assert_eq!(first.len(), 32);
+        assert_ne!(first, second);
+
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("ticket-42"));
+        assert_eq!(request_id(&headers), "ticket-42");
+
+        for invalid in ["", "has space", &"a".repeat(MAX_LEN + 1)] {
+            headers.insert(X_REQUEST_ID, HeaderValue::from_str(invalid).unwrap());
+            assert_ne!(request_id(&headers), invalid);
+        }
+    }
+}
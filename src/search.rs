@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use http::Uri;
+use percent_encoding::percent_decode_str;
+
+use crate::filter::PathFilter;
+use crate::fs::Filesystem;
+use crate::glob::Glob;
+use crate::json;
+use crate::outcome::Outcome;
+
+const DEFAULT_MAX_DEPTH: usize = 8;
+const DEFAULT_MAX_RESULTS: usize = 256;
+const DEFAULT_MAX_ENTRIES: usize = 100_000;
+
+/// Limits of the recursive search, see [`ServeDir::search`](crate::ServeDir::search)
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    max_depth: usize,
+    max_results: usize,
+    max_entries: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_results: DEFAULT_MAX_RESULTS,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            timeout: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Create a new [`SearchOptions`] with the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many directory levels below the searched directory will be walked, `1` means only
+    /// the direct children are searched.
+    ///
+    /// Defaults to `8`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the max number of matches, the search stops once it is reached and the result is marked
+    /// as truncated.
+    ///
+    /// Defaults to `256`.
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    /// Set the max number of the visited entries, including the ones not matching the pattern,
+    /// the search is aborted with `413 Payload Too Large` once it is exceeded, so a directory with
+    /// millions of files can't keep the service busy, it isn't read further than the limit.
+    ///
+    /// Defaults to `100000`.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Abort the search with `503 Service Unavailable` once it has taken the `timeout`, it is
+    /// checked before reading every directory entry.
+    ///
+    /// Defaults to no timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+pub(crate) struct SearchMatch {
+    path: String,
+    is_dir: bool,
+}
+
+pub(crate) struct SearchResult {
+    matches: Vec<SearchMatch>,
+    truncated: bool,
+}
+
+impl SearchResult {
+    pub(crate) fn to_json(&self) -> String {
+        let mut out = String::from("{\"matches\":[");
+        for (i, search_match) in self.matches.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+
+            out.push_str("{\"path\":");
+            json::write_str(&mut out, &search_match.path);
+            out.push_str(",\"is_dir\":");
+            out.push_str(if search_match.is_dir { "true" } else { "false" });
+            out.push('}');
+        }
+        out.push_str("],\"truncated\":");
+        out.push_str(if self.truncated { "true" } else { "false" });
+        out.push('}');
+
+        out
+    }
+}
+
+/// Get the search pattern from the `q` query parameter, an empty pattern is ignored.
+///
+/// A pattern without any wildcard matches any file name containing it.
+pub(crate) fn search_pattern(uri: &Uri) -> Option<Glob> {
+    let value = uri.query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == "q").then_some(value)
+    })?;
+
+    // query strings are form encoded, so `+` means space
+    let value = value.replace('+', " ");
+    let pattern = percent_decode_str(&value).decode_utf8().ok()?;
+    if pattern.is_empty() {
+        return None;
+    }
+
+    if Glob::has_wildcard(&pattern) {
+        Some(Glob::new(&pattern))
+    } else {
+        Some(Glob::new(&format!("*{pattern}*")))
+    }
+}
+
+/// Walk the dir breadth first, collect the entries whose name matches the pattern.
+///
+/// Sub dirs which can't be read and the entries rejected by the filter are skipped, the filter
+/// checks the paths under the `request_dir`. The search over the `max_entries` or the `timeout`,
+/// or of a filesystem which can't list the dirs, is aborted with the [`Outcome`] to respond.
+pub(crate) async fn search<FS: Filesystem>(
+    filesystem: &FS,
+    dir: &Path,
+    request_dir: &str,
+    pattern: &Glob,
+    filter: &PathFilter,
+    options: SearchOptions,
+) -> io::Result<Result<SearchResult, Outcome>> {
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    let mut visited = 0usize;
+    let mut matches = vec![];
+    let mut pending = VecDeque::from([(dir.to_path_buf(), String::new(), 1)]);
+
+    while let Some((dir_path, relative_path, depth)) = pending.pop_front() {
+        if timed_out() {
+            return Ok(Err(Outcome::SearchTimedOut));
+        }
+
+        // the dir is read up to the remaining entries, one more means the limit is exceeded
+        let remaining = options.max_entries - visited;
+        let mut entries = match filesystem
+            .read_dir_bounded(&dir_path, remaining, deadline)
+            .await
+        {
+            Ok(entries) => entries,
+            Err(_) if timed_out() => return Ok(Err(Outcome::SearchTimedOut)),
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                return Ok(Err(Outcome::SearchUnsupported))
+            }
+            Err(err) if relative_path.is_empty() => return Err(err),
+            Err(_) => continue,
+        };
+        visited += entries.len();
+        if visited > options.max_entries {
+            return Ok(Err(Outcome::SearchTooLarge));
+        }
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        for entry in entries {
+            let name = entry.name.to_string_lossy();
+            let path = if relative_path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{relative_path}/{name}")
+            };
+
+            if !filter.is_allowed(&format!("{request_dir}/{path}")) {
+                continue;
+            }
+
+            if pattern.is_match(&name) {
+                if matches.len() >= options.max_results {
+                    return Ok(Ok(SearchResult {
+                        matches,
+                        truncated: true,
+                    }));
+                }
+
+                matches.push(SearchMatch {
+                    path: path.clone(),
+                    is_dir: entry.is_dir,
+                });
+            }
+
+            if entry.is_dir && depth < options.max_depth {
+                let mut sub_dir: PathBuf = dir_path.clone();
+                sub_dir.push(&entry.name);
+                pending.push_back((sub_dir, path, depth + 1));
+            }
+        }
+    }
+
+    Ok(Ok(SearchResult {
+        matches,
+        truncated: false,
+    }))
+}
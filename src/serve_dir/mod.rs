@@ -1,28 +1,76 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::future::{Future, Ready};
+use std::sync::Arc;
+use std::time::Duration;
 use std::{
     convert::Infallible,
     io,
-    path::Path,
+    path::{Component, Path, PathBuf},
     task::{Context, Poll},
+    time::SystemTime,
 };
 
 use bytes::Bytes;
+use futures_util::future::BoxFuture;
 use futures_util::TryFutureExt;
 use http::header::ALLOW;
-use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http::request::Parts;
+use http::response::Builder;
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
 use http_body::{Body, Empty, Full};
+use mime_guess::{mime, Mime};
 use percent_encoding::percent_decode;
 use tokio::io::AsyncRead;
-use tower_http::set_status::SetStatus;
+#[cfg(feature = "watch")]
+use tokio::io::AsyncReadExt;
 use tower_http::BoxError;
 use tower_service::Service;
 
+use crate::asset_hashes::AssetHashes;
+use crate::asset_manifest::AssetManifest;
 pub use crate::async_body::AsyncReadBody;
-use crate::content_encoding::{encodings, SupportedEncodings};
-use crate::fs::Filesystem;
+use crate::canonical_path::canonical_path;
+use crate::clock::Clock;
+use crate::content_encoding::{encodings, Encoding, QValue, SupportedEncodings};
+#[cfg(feature = "content-digest")]
+use crate::digest::{DigestBody, REPR_DIGEST};
+use crate::dir_index::{DirectoryIndex, IndexRequest};
+use crate::error_pages::{ErrorContext, ErrorPages};
+#[cfg(feature = "timeout")]
+use crate::extensions::Deadline;
+use crate::extensions::{ResolvedPath, RootOverride, ServedFile, StatusReason};
+use crate::filter::PathFilter;
+use crate::fs::immutable::ImmutableFilesystem;
+use crate::fs::{Filesystem, Purge};
+use crate::glob::Glob;
+use crate::header_hook::HeaderHook;
+use crate::headers::{content_range, Conditions, ETag, LastModified};
+use crate::host::HostTemplate;
+use crate::ip_filter::IpFilter;
+use crate::manifest::{ManifestEntry, RouteManifest};
+use crate::mirror::Mirror;
+use crate::not_modified::NotModifiedHeaders;
 use crate::open_file::{FileOpened, FileRequestExtent, OpenFileOutput};
-use crate::{open_file, ResponseBody};
+use crate::outcome::Outcome;
+use crate::probe_cache::ProbeCache;
+use crate::purge::{self, PurgeHandler};
+use crate::query::{self, QueryPolicy};
+use crate::range_guard::RangeGuard;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::request_body::{self, BodyRejected, RequestBodyPolicy};
+use crate::request_id;
+use crate::search::SearchOptions;
+use crate::stat::STAT_CONTENT_TYPE;
+use crate::surrogate::{SurrogateKey, SURROGATE_CONTROL, SURROGATE_KEY};
+#[cfg(feature = "timeout")]
+use crate::timeout::{DeadlineBody, StallTimeoutBody};
+#[cfg(feature = "tracing")]
+use crate::trace_context;
+use crate::transform::Transform;
+#[cfg(feature = "watch")]
+use crate::watch::{self, FileWatcher};
+use crate::{health, open_file, search, stat, NotFoundService, ResponseBody};
 
 // default capacity 64KiB
 const DEFAULT_CAPACITY: usize = 65536;
@@ -37,6 +85,18 @@ const DEFAULT_CAPACITY: usize = 65536;
 /// - Any segment of the path contains `..`
 /// - Any segment of the path contains a backslash
 /// - We don't have necessary permissions to read the file
+/// - The path is hidden by [`ServeDir::hide_dot_files`] or [`ServeDir::exclude`]
+///
+/// The response body is as long as the file was when it was opened. If the file is truncated
+/// while streaming, the body ends with an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) error
+/// and the server aborts the connection, so the client sees an incomplete response instead of a
+/// short one. The bytes appended while streaming are not sent.
+///
+/// With the `tracing` feature, every request is served in an `http_dir.request` span at the
+/// `debug` level, it carries the `trace_id`, `parent_span_id` and `sampled` fields of a valid W3C
+/// `traceparent` header, so the subscriber can attach the span to the trace of the caller, the
+/// `request_id` field of the [`ServeDir::request_id`], and the `vhost` field of the directory
+/// picked by the [`ServeDir::host_template`].
 ///
 /// # Example
 ///
@@ -62,10 +122,109 @@ pub struct ServeDir<FS, F = DefaultServeDirFallback> {
     pub(crate) buf_chunk_size: usize,
     pub(crate) precompressed_variants: Option<PrecompressedVariants>,
     // This is used to specialise implementation for single files
-    variant: ServeVariant,
+    pub(crate) variant: ServeVariant,
     fallback: Option<F>,
     call_fallback_on_method_not_allowed: bool,
-    filesystem: FS,
+    config: Config,
+    purge: Option<PurgeHandler<FS>>,
+    pub(crate) filesystem: FS,
+}
+
+// the options of the `ServeDir` which don't depend on the filesystem or the fallback, so they are
+// moved as a whole when either changes
+#[derive(Debug, Clone)]
+struct Config {
+    conditional_fallback: bool,
+    request_body_policy: RequestBodyPolicy,
+    ip_filter: Option<IpFilter>,
+    rate_limit: Option<RateLimit>,
+    filter: PathFilter,
+    search: Option<SearchOptions>,
+    serve_stat: bool,
+    health_endpoints: bool,
+    host_template: Option<HostTemplate>,
+    clock: Clock,
+    last_modified_min_age: Duration,
+    not_modified_headers: NotModifiedHeaders,
+    response_headers: HeaderMap,
+    route_manifest: Option<RouteManifest>,
+    asset_manifest: Option<AssetManifest>,
+    hashed_assets: Option<AssetHashes>,
+    range_guard: Option<RangeGuard>,
+    surrogate_key: Option<SurrogateKey>,
+    defer_open: bool,
+    identity_content_encoding: bool,
+    media_first_range: Option<u64>,
+    transform: Option<Transform>,
+    precompressed_exclude: Vec<Glob>,
+    probe_cache: Option<ProbeCache>,
+    concurrent_probes: bool,
+    mirror: Option<Mirror>,
+    request_id: bool,
+    deterministic: bool,
+    server_header: ServerHeader,
+    header_hook: Option<HeaderHook>,
+    error_pages: Option<Arc<ErrorPages>>,
+    decode_plus_as_space: bool,
+    canonical_redirects: bool,
+    query_policy: QueryPolicy,
+    #[cfg(feature = "content-digest")]
+    repr_digest_trailer: bool,
+    #[cfg(feature = "timeout")]
+    body_stall_timeout: Option<Duration>,
+    #[cfg(feature = "watch")]
+    watcher: Option<FileWatcher>,
+    #[cfg(feature = "watch")]
+    inject_reload_script: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            conditional_fallback: false,
+            request_body_policy: RequestBodyPolicy::Drop,
+            ip_filter: None,
+            rate_limit: None,
+            filter: PathFilter::default(),
+            search: None,
+            serve_stat: false,
+            health_endpoints: false,
+            host_template: None,
+            clock: Clock::default(),
+            last_modified_min_age: Duration::ZERO,
+            not_modified_headers: NotModifiedHeaders::default(),
+            response_headers: HeaderMap::new(),
+            route_manifest: None,
+            asset_manifest: None,
+            hashed_assets: None,
+            range_guard: None,
+            surrogate_key: None,
+            defer_open: false,
+            identity_content_encoding: false,
+            media_first_range: None,
+            transform: None,
+            precompressed_exclude: vec![],
+            probe_cache: None,
+            concurrent_probes: false,
+            mirror: None,
+            request_id: false,
+            deterministic: false,
+            server_header: ServerHeader::Keep,
+            header_hook: None,
+            error_pages: None,
+            decode_plus_as_space: false,
+            canonical_redirects: false,
+            query_policy: QueryPolicy::Ignore,
+            #[cfg(feature = "content-digest")]
+            repr_digest_trailer: false,
+            #[cfg(feature = "timeout")]
+            body_stall_timeout: None,
+            #[cfg(feature = "watch")]
+            watcher: None,
+            #[cfg(feature = "watch")]
+            inject_reload_script: false,
+        }
+    }
 }
 
 impl<FS> ServeDir<FS, DefaultServeDirFallback> {
@@ -76,20 +235,33 @@ impl<FS> ServeDir<FS, DefaultServeDirFallback> {
             precompressed_variants: None,
             variant: ServeVariant::Directory {
                 append_index_html_on_directories: true,
+                default_mime: HeaderValue::from_static(mime::APPLICATION_OCTET_STREAM.as_ref()),
+                attachment_for_unknown_types: false,
+                trust_forwarded_headers: false,
+                relative_redirects: false,
+                directory_index: DirectoryIndex::default(),
             },
             fallback: None,
             call_fallback_on_method_not_allowed: false,
+            config: Config::default(),
+            purge: None,
             filesystem,
         }
     }
 
-    pub(crate) fn new_single_file(filesystem: FS, mime: HeaderValue) -> Self {
+    pub(crate) fn new_single_file(filesystem: FS, file_path: PathBuf, mime: HeaderValue) -> Self {
         Self {
             buf_chunk_size: DEFAULT_CAPACITY,
             precompressed_variants: None,
-            variant: ServeVariant::SingleFile { mime },
+            variant: ServeVariant::SingleFile {
+                mime,
+                file_path,
+                request_path: None,
+            },
             fallback: None,
             call_fallback_on_method_not_allowed: false,
+            config: Config::default(),
+            purge: None,
             filesystem,
         }
     }
@@ -98,13 +270,16 @@ impl<FS> ServeDir<FS, DefaultServeDirFallback> {
 impl<FS, F> ServeDir<FS, F> {
     /// If the requested path is a directory append `index.html`.
     ///
-    /// This is useful for static sites.
+    /// This is useful for static sites. The precompressed variants of the `index.html`, like
+    /// `index.html.br`, are served like the other files. The served index is pointed at by the
+    /// `Content-Location`.
     ///
     /// Defaults to `true`.
     pub fn append_index_html_on_directories(mut self, append: bool) -> Self {
         match &mut self.variant {
             ServeVariant::Directory {
                 append_index_html_on_directories,
+                ..
             } => {
                 *append_index_html_on_directories = append;
             }
@@ -114,6 +289,136 @@ impl<FS, F> ServeDir<FS, F> {
         self
     }
 
+    /// Set the `Content-Type` of the files whose type can't be guessed from the file extension.
+    ///
+    /// Defaults to `application/octet-stream`.
+    ///
+    /// # Panics
+    /// Will panic if the mime type isn’t a valid
+    /// [header value](https://docs.rs/http/latest/http/header/struct.HeaderValue.html).
+    pub fn default_content_type(mut self, mime: &Mime) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory { default_mime, .. } => {
+                *default_mime =
+                    HeaderValue::from_str(mime.as_ref()).expect("mime isn't a valid header value");
+            }
+            ServeVariant::SingleFile { .. } => {}
+        }
+
+        self
+    }
+
+    /// Respond with `Content-Disposition: attachment` for the files whose type can't be guessed
+    /// from the file extension, so the browsers download them instead of sniffing the content,
+    /// which may render an uploaded file as HTML.
+    ///
+    /// Defaults to `false`.
+    pub fn attachment_for_unknown_types(mut self, attachment: bool) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory {
+                attachment_for_unknown_types,
+                ..
+            } => {
+                *attachment_for_unknown_types = attachment;
+            }
+            ServeVariant::SingleFile { .. } => {}
+        }
+
+        self
+    }
+
+    /// Build the `Location` of the directory redirects, like `/foo` to `/foo/`, from the
+    /// `Forwarded` header, or the `X-Forwarded-Proto` and `X-Forwarded-Host` headers, so the
+    /// redirects behind a TLS terminating proxy point at `https` and the public host.
+    ///
+    /// Only enable it behind a proxy which sets or strips these headers, otherwise the clients
+    /// can choose the redirect target.
+    ///
+    /// Defaults to `false`.
+    pub fn trust_forwarded_headers(mut self, trust: bool) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory {
+                trust_forwarded_headers,
+                ..
+            } => {
+                *trust_forwarded_headers = trust;
+            }
+            ServeVariant::SingleFile { .. } => {}
+        }
+
+        self
+    }
+
+    /// Redirect the directories to a relative `Location`, like `/docs/foo` to `foo/`, which the
+    /// clients resolve against the URL they requested, so the scheme and the host are right
+    /// behind any proxy.
+    ///
+    /// Takes precedence over [`trust_forwarded_headers`](ServeDir::trust_forwarded_headers).
+    ///
+    /// Defaults to `false`.
+    pub fn relative_redirects(mut self, relative: bool) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory {
+                relative_redirects, ..
+            } => {
+                *relative_redirects = relative;
+            }
+            ServeVariant::SingleFile { .. } => {}
+        }
+
+        self
+    }
+
+    /// Pick the index document of the directories with the async `index` closure, instead of
+    /// `index.html`, like a `README.md`, the newest file, or a locale specific index by the
+    /// `Accept-Language`.
+    ///
+    /// The picked path is relative to the directory, and served like the requested file, so its
+    /// precompressed variants are served too. The directory is not found if the closure returns
+    /// `None`, or a path which isn't inside of the directory. The closure is only called when
+    /// [`append_index_html_on_directories`](ServeDir::append_index_html_on_directories) is
+    /// enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::path::PathBuf;
+    ///
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).directory_index(|request| {
+    ///     Box::pin(async move {
+    ///         for index in ["index.html", "README.md"] {
+    ///             let path = request.dir.join(index);
+    ///             if request.filesystem.metadata(&path).await.is_ok() {
+    ///                 return Some(PathBuf::from(index));
+    ///             }
+    ///         }
+    ///
+    ///         None
+    ///     })
+    /// });
+    /// ```
+    pub fn directory_index<I>(mut self, index: I) -> Self
+    where
+        I: for<'a> Fn(&'a IndexRequest<'a>) -> BoxFuture<'a, Option<PathBuf>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        match &mut self.variant {
+            ServeVariant::Directory {
+                directory_index, ..
+            } => {
+                *directory_index = DirectoryIndex::new(index);
+            }
+            ServeVariant::SingleFile { .. } => {}
+        }
+
+        self
+    }
+
     /// Set a specific read buffer chunk size.
     ///
     /// The default capacity is 64kb.
@@ -132,6 +437,9 @@ impl<FS, F> ServeDir<FS, F> {
     /// the uncompressed version will be served instead.
     /// Both the precompressed version and the uncompressed version are expected
     /// to be present in the directory. Different precompressed variants can be combined.
+    ///
+    /// The file responses carry `Vary: accept-encoding` once any variant is enabled, and the
+    /// served variant is pointed at by the `Content-Location`, like `/dir/foo.txt.gz`.
     pub fn precompressed_gzip(mut self) -> Self {
         self.precompressed_variants
             .get_or_insert(Default::default())
@@ -173,6 +481,45 @@ impl<FS, F> ServeDir<FS, F> {
         self
     }
 
+    /// Don't look for the precompressed variants of the paths matching the glob pattern, like
+    /// `*.mp4` or `*.zip`, so the already compressed files don't cost a lookup of each variant.
+    /// The pattern is matched like the one of [`ServeDir::exclude`], against the requested path,
+    /// a directory served with its index is matched by the path of the directory.
+    ///
+    /// Can be called multiple times to exclude more patterns.
+    pub fn precompressed_exclude(mut self, pattern: &str) -> Self {
+        self.config.precompressed_exclude.push(Glob::new(pattern));
+        self
+    }
+
+    /// Remember the precompressed variants found missing for up to `ttl`, so the repeated
+    /// requests of a file without some variants don't look them up again every time. Only the
+    /// variants of the existing files are remembered.
+    ///
+    /// A variant created within the `ttl` is served once it passes, or right away when it is
+    /// reported by the [`ServeDir::events`] watcher, or purged by the
+    /// [`ServeDir::purge_method`].
+    pub fn precompressed_probe_cache(mut self, ttl: Duration) -> Self {
+        let probe_cache = ProbeCache::new(ttl);
+        #[cfg(feature = "watch")]
+        if let Some(watcher) = &self.config.watcher {
+            probe_cache.watch(watcher);
+        }
+        self.config.probe_cache = Some(probe_cache);
+        self
+    }
+
+    /// Look up all the precompressed variants of a file at once, with the clones of the
+    /// filesystem, instead of one by one in the preferred order, so the remote filesystems don't
+    /// pay a round trip for each missing variant. Every variant is looked up even when the
+    /// preferred one exists, so it costs more lookups on the local disk.
+    ///
+    /// Defaults to `false`.
+    pub fn concurrent_probes(mut self, enable: bool) -> Self {
+        self.config.concurrent_probes = enable;
+        self
+    }
+
     /// Set the fallback service.
     ///
     /// This service will be called if there is no file at the path of the request.
@@ -180,6 +527,9 @@ impl<FS, F> ServeDir<FS, F> {
     /// The status code returned by the fallback will not be altered. Use
     /// [`ServeDir::not_found_service`] to set a fallback and always respond with `404 Not Found`.
     ///
+    /// A [`ServeFile`](crate::ServeFile) or [`ServeDir`] fallback without its own precompressed
+    /// variants serves the variants enabled here, so the custom `404` pages are precompressed too.
+    ///
     /// # Example
     ///
     /// This can be used to respond with a different file:
@@ -210,6 +560,8 @@ impl<FS, F> ServeDir<FS, F> {
             variant: self.variant,
             fallback: Some(new_fallback),
             call_fallback_on_method_not_allowed: self.call_fallback_on_method_not_allowed,
+            config: self.config,
+            purge: self.purge,
             filesystem: self.filesystem,
         }
     }
@@ -243,182 +595,1671 @@ impl<FS, F> ServeDir<FS, F> {
     /// ```
     ///
     /// Setups like this are often found in single page applications.
-    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeDir<FS, SetStatus<F2>> {
-        self.fallback(SetStatus::new(new_fallback, StatusCode::NOT_FOUND))
+    ///
+    /// The `304 Not Modified` responses of the fallback are kept, so the not found document is
+    /// revalidated like the other files, see [`NotFoundService`].
+    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeDir<FS, NotFoundService<F2>> {
+        self.fallback(NotFoundService::new(new_fallback))
     }
 
     /// Customize whether or not to call the fallback for requests that aren't `GET` or `HEAD`.
     ///
     /// Defaults to not calling the fallback and instead returning `405 Method Not Allowed`.
+    ///
+    /// `OPTIONS` requests are never passed to the fallback, they are answered with
+    /// `204 No Content` and the `Allow` header.
     pub fn call_fallback_on_method_not_allowed(mut self, call_fallback: bool) -> Self {
         self.call_fallback_on_method_not_allowed = call_fallback;
         self
     }
-}
-
-impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeDir<FS, F>
-where
-    F: Service<Request<ReqBody>, Response = Response<FResBody>> + Clone,
-    F::Error: Into<io::Error>,
-    F::Future: Send,
-    FResBody: Body<Data = Bytes> + Send + 'static,
-    FResBody::Error: Into<Box<dyn Error + Send + Sync>>,
-    FS: Filesystem + Clone + Send + Sync,
-    FS::File: 'static,
-{
-    type Response = Response<ResponseBody>;
-    type Error = io::Error;
-    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
 
-    #[inline]
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        if let Some(fallback) = &mut self.fallback {
-            fallback.poll_ready(cx).map_err(Into::into)
-        } else {
-            Poll::Ready(Ok(()))
-        }
+    /// Evaluate the conditional headers of the requests, like `If-None-Match` and
+    /// `If-Modified-Since`, against the `ETag` and the `Last-Modified` of the successful fallback
+    /// responses too, the same as the files, so a fallback like the index page of a single page
+    /// app is responded `304 Not Modified` or `412 Precondition Failed`.
+    ///
+    /// The `304 Not Modified` keeps the headers of the fallback response except the ones of the
+    /// content, like `Content-Type` and `Content-Length`, see [`ServeDir::not_modified_headers`].
+    ///
+    /// Defaults to `false`, the fallback responses are sent as they are.
+    pub fn conditional_fallback(mut self, conditional: bool) -> Self {
+        self.config.conditional_fallback = conditional;
+        self
     }
 
-    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
-        let mut this = self.clone();
+    /// Set what to do with the body of the `GET` and `HEAD` requests, dropping it unread can poison
+    /// the keep-alive connections on some clients. The rejected requests are responded with the
+    /// `Connection: close` header.
+    ///
+    /// Defaults to [`RequestBodyPolicy::Drop`].
+    pub fn request_body_policy(mut self, policy: RequestBodyPolicy) -> Self {
+        self.config.request_body_policy = policy;
+        self
+    }
 
-        async move {
-            if req.method() != Method::GET && req.method() != Method::HEAD {
-                if this.call_fallback_on_method_not_allowed {
-                    if let Some(fallback) = &mut this.fallback {
-                        return fallback
-                            .call(req)
-                            .err_into()
-                            .map_ok(|response| {
-                                response
-                                    .map(|body| {
-                                        body.map_err(|err| {
-                                            match err.into().downcast::<io::Error>() {
-                                                Ok(err) => *err,
-                                                Err(err) => {
-                                                    io::Error::new(io::ErrorKind::Other, err)
-                                                }
-                                            }
-                                        })
-                                        .boxed_unsync()
-                                    })
-                                    .map(ResponseBody::new)
-                            })
-                            .await;
-                    }
-                } else {
-                    let mut res = response_with_status(StatusCode::METHOD_NOT_ALLOWED);
-                    res.headers_mut()
-                        .insert(ALLOW, HeaderValue::from_static("GET,HEAD"));
+    /// Respond with `403 Forbidden` when the client address is rejected by the [`IpFilter`], before
+    /// anything else is done.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::{IpFilter, ServeDir};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .ip_filter(IpFilter::new().allow("10.0.0.0/8").allow("127.0.0.1"));
+    /// ```
+    pub fn ip_filter(mut self, ip_filter: IpFilter) -> Self {
+        self.config.ip_filter = Some(ip_filter);
+        self
+    }
 
-                    return Ok(res);
-                }
-            }
+    /// Pass a copy of every request to the `sink`, like to send it into a channel for the
+    /// analytics, or to replay it against a shadow deployment.
+    ///
+    /// The copy has the method, URI, version and headers of the request, and the client
+    /// [`SocketAddr`](std::net::SocketAddr) of the request extensions, but not the body. The
+    /// `sink` is called before the request is served and can't change the response, so it should
+    /// hand the request off without blocking.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    /// use std::sync::Mutex;
+    ///
+    /// use http_dir::fs::disk::DiskFilesystem;
+    /// use http_dir::ServeDir;
+    ///
+    /// let (sender, receiver) = mpsc::channel();
+    /// let sender = Mutex::new(sender);
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).mirror(move |request| {
+    ///     // the analytics are lost rather than delaying the response
+    ///     let _ = sender.lock().unwrap().send(request);
+    /// });
+    /// # drop(receiver);
+    /// ```
+    pub fn mirror<M>(mut self, sink: M) -> Self
+    where
+        M: Fn(Request<()>) + Send + Sync + 'static,
+    {
+        self.config.mirror = Some(Mirror::new(sink));
+        self
+    }
 
-            // `ServeDir` doesn't care about the request body but the fallback might. So move out the
-            // body and pass it to the fallback, leaving an empty body in its place
-            //
-            // this is necessary because we cannot clone bodies
-            let (mut parts, body) = req.into_parts();
-            // same goes for extensions
-            let extensions = std::mem::take(&mut parts.extensions);
-            let req = Request::from_parts(parts, Empty::<Bytes>::new());
+    /// Echo the `X-Request-Id` of the request on the response, a new one is generated when the
+    /// request has none, or its id is longer than 128 bytes or not visible ASCII.
+    ///
+    /// The fallback and the [`ServeDir::mirror`] sink get the request with the id, and with the
+    /// `tracing` feature, it is the `request_id` field of the request span, so a failed download
+    /// reported with the id can be found in the logs.
+    ///
+    /// Defaults to `false`.
+    pub fn request_id(mut self, request_id: bool) -> Self {
+        self.config.request_id = request_id;
+        self
+    }
 
-            let mut fallback_and_request = this.fallback.as_mut().map(|fallback| {
-                let mut fallback_req = Request::new(body);
-                *fallback_req.method_mut() = req.method().clone();
-                *fallback_req.uri_mut() = req.uri().clone();
-                *fallback_req.headers_mut() = req.headers().clone();
-                *fallback_req.extensions_mut() = extensions;
+    /// Make the headers of the same response byte-identical across the requests, the processes
+    /// and the versions, so the responses can be compared in the golden tests or across the
+    /// mirrored deployments:
+    ///
+    /// - the headers are sorted by their names, the values of a name keep their order
+    /// - no `X-Request-Id` is generated by the [`ServeDir::request_id`], the one of the request is
+    ///   still echoed
+    /// - the `Server` header is `http_dir`, without the version, unless it is set by the
+    ///   [`ServeDir::server_header`]
+    ///
+    /// The `Date` added by the server still varies, and so does the `Retry-After` of the
+    /// [`ServeDir::rate_limit`], which depends on the timing of the requests.
+    ///
+    /// Defaults to `false`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.config.deterministic = deterministic;
+        self
+    }
 
-                // get the ready fallback and leave a non-ready clone in its place
-                let clone = fallback.clone();
-                let fallback = std::mem::replace(fallback, clone);
+    /// Set the `Server` header of all the responses, including the ones of the fallback and the
+    /// errors, or remove it with `None`, so the server can't be fingerprinted by it.
+    ///
+    /// Defaults to no `Server` header, and the one of the fallback is kept.
+    pub fn server_header(mut self, value: Option<HeaderValue>) -> Self {
+        self.config.server_header = match value {
+            None => ServerHeader::Remove,
+            Some(value) => ServerHeader::Set(value),
+        };
+        self
+    }
 
-                (fallback, fallback_req)
-            });
+    /// Reorder, rename or drop the headers of all the responses right before they are returned,
+    /// including the ones of the fallback, the errors and the redirects, for the legacy clients
+    /// which only accept the headers they know.
+    ///
+    /// The `hook` runs last, after the [`ServeDir::deterministic`] sorting. The header names are
+    /// always lowercase in the [`HeaderMap`], the HTTP/1 clients expecting `Content-Type` can be
+    /// served by a server writing the title case names, like the
+    /// `Server::http1_title_case_headers` of the `server` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http::header::{HeaderName, HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// // only the length and the type, in this order
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).map_response_headers(|headers| {
+    ///     let mut kept = HeaderMap::new();
+    ///     for name in [CONTENT_LENGTH, CONTENT_TYPE] {
+    ///         if let Some(value) = headers.remove(&name) {
+    ///             kept.insert(name, value);
+    ///         }
+    ///     }
+    ///     *headers = kept;
+    /// });
+    /// ```
+    pub fn map_response_headers<M>(mut self, hook: M) -> Self
+    where
+        M: Fn(&mut HeaderMap) + Send + Sync + 'static,
+    {
+        self.config.header_hook = Some(HeaderHook::new(hook));
+        self
+    }
 
-            let path_decoded =
-                match percent_decode(req.uri().path().trim_start_matches('/').as_ref())
-                    .decode_utf8()
-                    .ok()
-                {
-                    None => {
-                        return if let Some((mut fallback, request)) = fallback_and_request.take() {
-                            call_fallback(&mut fallback, request).await
-                        } else {
-                            Ok(not_found())
-                        }
-                    }
+    /// Respond with the bodies rendered from the [`ErrorPages`] templates, instead of the empty
+    /// bodies of the error responses, like the `404 Not Found` of a missing file, the
+    /// `405 Method Not Allowed` or the `416 Range Not Satisfiable`.
+    ///
+    /// The templates apply to the error responses of the fallback without a body too, and the
+    /// headers of the error responses, like the `Allow` or the `Content-Range`, are kept. With a
+    /// `500 Internal Server Error` template, the IO errors of the requests are responded with it,
+    /// instead of being returned by the service. The HTML or the JSON template is chosen by the
+    /// `Accept` header, see [`ErrorPages`].
+    ///
+    /// Defaults to the empty bodies.
+    pub fn error_pages(mut self, pages: ErrorPages) -> Self {
+        self.config.error_pages = Some(Arc::new(pages));
+        self
+    }
 
-                    Some(path) => path,
-                };
-            let path_to_file = Path::new(&*path_decoded).to_path_buf();
+    /// Respond with `429 Too Many Requests` and the `Retry-After` header when the request is over
+    /// the limit of the [`RateLimiter`], right after the [`ServeDir::ip_filter`] and before any
+    /// filesystem work. The `key` is taken from the request, the requests without a key are not
+    /// limited.
+    ///
+    /// # Example
+    ///
+    /// Limit every client address to 100 requests and a new one per 100ms:
+    ///
+    /// ```rust
+    /// use std::net::SocketAddr;
+    /// use std::time::Duration;
+    ///
+    /// use http_dir::{ServeDir, TokenBucket};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).rate_limit(
+    ///     TokenBucket::new(100, Duration::from_millis(100)),
+    ///     |parts| Some(parts.extensions.get::<SocketAddr>()?.ip().to_string()),
+    /// );
+    /// ```
+    pub fn rate_limit<L, K>(mut self, limiter: L, key: K) -> Self
+    where
+        L: RateLimiter + 'static,
+        K: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        self.config.rate_limit = Some(RateLimit::new(limiter, key));
+        self
+    }
 
-            let buf_chunk_size = this.buf_chunk_size;
-            let range_header = req
-                .headers()
-                .get(header::RANGE)
-                .and_then(|value| value.to_str().ok())
-                .map(|s| s.to_owned());
+    /// Respond with `404 Not Found` if any segment of the path starts with `.`, like `/.git/config`
+    /// or `/foo/.env`.
+    ///
+    /// See [`ServeDir::well_known_passthrough`] for `/.well-known/`.
+    ///
+    /// Defaults to `false`.
+    pub fn hide_dot_files(mut self, hide: bool) -> Self {
+        self.config.filter.hide_dot_files = hide;
+        self
+    }
 
-            let negotiated_encodings = encodings(
-                req.headers(),
-                this.precompressed_variants.unwrap_or_default(),
-            );
+    /// Respond with `404 Not Found` for the paths matching the glob pattern, the pattern supports
+    /// `*` and `?`, and is matched against the whole path without the leading `/`, `*` also
+    /// matches `/`, so `*.pem` excludes the `.pem` files in any sub directory.
+    ///
+    /// Can be called multiple times to exclude more patterns.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.config.filter.exclude.push(Glob::new(pattern));
+        self
+    }
 
-            match open_file::open_file(
-                &mut this.filesystem,
-                &this.variant,
-                path_to_file,
-                req,
-                negotiated_encodings,
-                range_header,
-                buf_chunk_size,
-            )
-            .await
-            {
-                Ok(OpenFileOutput::FileOpened(file_output)) => Ok(build_response(*file_output)),
+    /// Keep serving the paths under `/.well-known/`, like the ACME challenges and `security.txt`,
+    /// when the [`ServeDir::hide_dot_files`] is used, so the certificate renewal won't break
+    /// silently. The dot files under `/.well-known/` are still hidden, and the
+    /// [`ServeDir::exclude`] patterns still apply to it.
+    ///
+    /// Defaults to `true`.
+    pub fn well_known_passthrough(mut self, passthrough: bool) -> Self {
+        self.config.filter.well_known_passthrough = passthrough;
+        self
+    }
 
-                Ok(OpenFileOutput::Redirect { location }) => {
-                    let mut res = response_with_status(StatusCode::TEMPORARY_REDIRECT);
-                    res.headers_mut().insert(header::LOCATION, location);
+    /// Decode the `+` in the request path as a space, like the legacy clients encoding the paths
+    /// as form data, so `/my+file.txt` serves `my file.txt`. The `%2B` is still decoded as `+`.
+    ///
+    /// Defaults to `false`, the `+` is served literally.
+    pub fn decode_plus_as_space(mut self, decode: bool) -> Self {
+        self.config.decode_plus_as_space = decode;
+        self
+    }
 
-                    Ok(res)
-                }
+    /// Redirect the `GET` and `HEAD` requests whose path isn't in the canonical percent-encoded
+    /// form to it with `301 Moved Permanently`, like `/%69ndex.html` to `/index.html` or
+    /// `/a%2fb` to `/a%2Fb`, so the caches in front of the service store a file under one URL.
+    ///
+    /// The escaped unreserved characters are decoded, except the ones making a `.` or `..`
+    /// segment, and the other escapes use the uppercase hex digits. The paths with an invalid
+    /// escape aren't redirected.
+    ///
+    /// Defaults to `false`.
+    pub fn canonical_redirects(mut self, redirect: bool) -> Self {
+        self.config.canonical_redirects = redirect;
+        self
+    }
 
-                Ok(OpenFileOutput::FileNotFound) => {
-                    if let Some((mut fallback, request)) = fallback_and_request.take() {
-                        call_fallback(&mut fallback, request).await
-                    } else {
-                        Ok(not_found())
-                    }
-                }
+    /// Set what to do with the query string of the requests, see [`QueryPolicy`].
+    ///
+    /// Defaults to [`QueryPolicy::Ignore`].
+    pub fn query_policy(mut self, policy: QueryPolicy) -> Self {
+        self.config.query_policy = policy;
+        self
+    }
 
-                Ok(OpenFileOutput::PreconditionFailed) => {
-                    Ok(response_with_status(StatusCode::PRECONDITION_FAILED))
-                }
+    /// Enable the recursive search.
+    ///
+    /// A `GET` request for a directory with a `q` query parameter, like `/logs/?q=*.gz`, walks
+    /// the directory and its sub directories within the [`SearchOptions`] limits, and responds with
+    /// the entries whose name matches the glob pattern as JSON:
+    ///
+    /// ```json
+    /// {"matches":[{"path":"2023/app.log.gz","is_dir":false}],"truncated":false}
+    /// ```
+    ///
+    /// The pattern supports `*` and `?`, a pattern without any of them matches any name containing
+    /// it. The `path` of the match is relative to the searched directory.
+    ///
+    /// Requests for files are not affected by the `q` query parameter. The searches visiting too
+    /// many entries or taking too long are aborted, see [`SearchOptions::max_entries`] and
+    /// [`SearchOptions::timeout`]. The filesystems without the [`Filesystem::read_dir`] are
+    /// answered with `501 Not Implemented`.
+    ///
+    /// Defaults to disabled.
+    pub fn search(mut self, options: SearchOptions) -> Self {
+        if let ServeVariant::Directory { .. } = self.variant {
+            self.config.search = Some(options);
+        }
 
-                Ok(OpenFileOutput::NotModified) => {
-                    Ok(response_with_status(StatusCode::NOT_MODIFIED))
-                }
+        self
+    }
 
-                Err(err) => {
+    /// Respond with the file metadata as JSON instead of the file content, when the request has a
+    /// `stat` query parameter, like `/foo.txt?stat`, or accepts the
+    /// `application/vnd.http-dir.stat+json` media type:
+    ///
+    /// ```json
+    /// {"size":23,"mtime":1693526400,"etag":"\"803-1a2b-17-6044b8f8c1a00\"","mime":"text/plain"}
+    /// ```
+    ///
+    /// `mtime` is the unix timestamp in seconds, `size`, `mtime` and `etag` are `null` if the
+    /// [`Filesystem`] doesn't know them.
+    ///
+    /// File responses will carry `Vary: accept` when enabled.
+    ///
+    /// Defaults to `false`.
+    pub fn serve_stat(mut self, serve_stat: bool) -> Self {
+        self.config.serve_stat = serve_stat;
+        self
+    }
+
+    /// Answer the `GET` and `HEAD` requests of `/healthz` and `/readyz` before looking up the
+    /// files, so the load balancers can health-check the service:
+    ///
+    /// - `/healthz` always responds `200 OK`
+    /// - `/readyz` responds `200 OK` if the [`Filesystem`] is reachable, by stat-ing the root dir
+    ///   or the single file, `503 Service Unavailable` otherwise
+    ///
+    /// The files with the same paths are shadowed when enabled.
+    ///
+    /// Defaults to `false`.
+    pub fn health_endpoints(mut self, health_endpoints: bool) -> Self {
+        self.config.health_endpoints = health_endpoints;
+        self
+    }
+
+    /// Serve every host from its own directory, the directory is the `template` with the `{host}`
+    /// replaced by the request host, like `sites/{host}` serves `example.com/index.html` from
+    /// `sites/example.com/index.html` of the [`Filesystem`].
+    ///
+    /// The host is taken from the `Host` header, or the URI authority for HTTP/2, lowercased
+    /// without the port and the trailing dot. Only DNS names and IPv4 addresses are accepted,
+    /// requests without a valid host get `400 Bad Request`.
+    ///
+    /// The `template` is relative to the root of the [`Filesystem`], the paths used by
+    /// [`ServeDir::exclude`] and [`ServeDir::hide_dot_files`] are not affected.
+    ///
+    /// # Panics
+    /// Will panic if the `template` doesn't contain `{host}`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// // serve `/srv/sites/example.com/public` for `example.com`
+    /// let service = ServeDir::new(DiskFilesystem::from("/srv/sites"))
+    ///     .host_template("{host}/public");
+    /// ```
+    pub fn host_template(mut self, template: &str) -> Self {
+        if let ServeVariant::Directory { .. } = self.variant {
+            self.config.host_template = Some(HostTemplate::new(template));
+        }
+
+        self
+    }
+
+    /// Don't give the files modified within the `min_age` a `Last-Modified`, so the caches
+    /// don't revalidate them with `If-Modified-Since`, like the files being deployed.
+    ///
+    /// The `Last-Modified` has the precision of a second, a file modified again in the same
+    /// second keeps the same `Last-Modified`, and a cache which got the first content would be
+    /// responded `304 Not Modified` for the second one. A `min_age` of 1 second is enough to
+    /// avoid it, the age is measured by the [`ServeDir::clock`].
+    ///
+    /// The `ETag` is still sent, so the recently modified files are revalidated with
+    /// `If-None-Match` instead, unless the [`Metadata::etag`](crate::fs::Metadata::etag) of the
+    /// filesystem has the same precision.
+    ///
+    /// Defaults to zero, every file with a modified time has a `Last-Modified`.
+    pub fn last_modified_min_age(mut self, min_age: Duration) -> Self {
+        self.config.last_modified_min_age = min_age;
+        self
+    }
+
+    /// Set the headers of the `304 Not Modified` responses, RFC 9110 requires the ones the
+    /// `200 OK` would have to update the cached response, like `Cache-Control` and `Vary`, and
+    /// advises against the others.
+    ///
+    /// Defaults to [`NotModifiedHeaders::Full`].
+    pub fn not_modified_headers(mut self, headers: NotModifiedHeaders) -> Self {
+        self.config.not_modified_headers = headers;
+        self
+    }
+
+    /// Set the clock used by the conditional requests, like `If-Modified-Since`, the tests can
+    /// use a fixed time to be deterministic.
+    ///
+    /// The file modified time later than the clock, caused by the skewed clocks, is responded as
+    /// the clock time in the `Last-Modified`, and the `If-Modified-Since` later than the clock is
+    /// ignored, as RFC 7232 requires.
+    ///
+    /// Defaults to the system clock.
+    pub fn clock<C>(mut self, now: C) -> Self
+    where
+        C: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        self.config.clock = Clock::new(now);
+        self
+    }
+
+    /// Add a header to the responses of the served files, including the `304 Not Modified` ones,
+    /// like `Cache-Control`. Adding the same header name again appends another value.
+    ///
+    /// The headers of the file from the [`Metadata`](crate::fs::Metadata) take precedence.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http::header::{HeaderValue, CACHE_CONTROL};
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .response_header(CACHE_CONTROL, HeaderValue::from_static("public, max-age=3600"));
+    /// ```
+    pub fn response_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.config.response_headers.append(name, value);
+        self
+    }
+
+    /// Consult the [`RouteManifest`] instead of the filesystem for the existence of the files and
+    /// their precompressed variants, for the deployments whose files never change.
+    ///
+    /// The paths which are neither a file nor a dir in the manifest are not found without touching
+    /// the filesystem, and only the precompressed variants recorded in the manifest are tried.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::{RouteManifest, ServeDir};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// # async {
+    /// let filesystem = DiskFilesystem::from("assets");
+    /// let manifest = RouteManifest::build(&filesystem).await.expect("build manifest failed");
+    ///
+    /// let service = ServeDir::new(filesystem)
+    ///     .precompressed_br()
+    ///     .route_manifest(manifest);
+    /// # };
+    /// ```
+    pub fn route_manifest(mut self, manifest: RouteManifest) -> Self {
+        self.config.route_manifest = Some(manifest);
+        self
+    }
+
+    /// Serve the manifest of the files at the `path`, like `/asset-manifest.json`, so the
+    /// service workers can precache the assets and tell the changed ones:
+    ///
+    /// ```json
+    /// {"files":{"css/site.css":{"size":1024,"etag":"\"803-1a2b-400-6044b8f8c1a00\"","hash":"sha256-..."}}}
+    /// ```
+    ///
+    /// The files hidden by [`ServeDir::hide_dot_files`] or [`ServeDir::exclude`] and the
+    /// precompressed variants aren't listed, the `size` and `etag` are `null` if the
+    /// [`Filesystem`] doesn't know them. The `hash` is the subresource integrity of the file, it is
+    /// only listed with the `content-digest` feature.
+    ///
+    /// The manifest is generated on the first request, and generated again after the files are
+    /// reported changed by the [`ServeDir::events`] watcher, or purged by the
+    /// [`ServeDir::purge_method`]. It is served with an `ETag` and `Cache-Control: no-cache`, and
+    /// the file with the same path is shadowed.
+    ///
+    /// Defaults to disabled.
+    pub fn asset_manifest(mut self, path: &str) -> Self {
+        let manifest = AssetManifest::new(path);
+        #[cfg(feature = "watch")]
+        if let Some(watcher) = &self.config.watcher {
+            manifest.watch(watcher);
+        }
+        self.config.asset_manifest = Some(manifest);
+        self
+    }
+
+    /// Serve the content hashed names of the [`AssetHashes`], like `/css/site.3f2a1b9c.css`, from
+    /// the original files, with `Cache-Control: public, max-age=31536000, immutable` since a
+    /// changed file gets another name. The original names are still served as before.
+    ///
+    /// The hashes aren't updated when the files change, build them again instead.
+    ///
+    /// Defaults to disabled.
+    pub fn hashed_assets(mut self, hashes: AssetHashes) -> Self {
+        self.config.hashed_assets = Some(hashes);
+        self
+    }
+
+    /// Assume the files never change during the process lifetime, like in the containerized
+    /// deployments, the filesystem is wrapped by the [`ImmutableFilesystem`], so the metadata,
+    /// including the one used by the `304 Not Modified` handling, and the missing precompressed
+    /// variants are only looked up once.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .precompressed_br()
+    ///     .immutable();
+    /// ```
+    pub fn immutable(self) -> ServeDir<ImmutableFilesystem<FS>, F>
+    where
+        FS: 'static,
+    {
+        ServeDir {
+            buf_chunk_size: self.buf_chunk_size,
+            precompressed_variants: self.precompressed_variants,
+            variant: self.variant,
+            fallback: self.fallback,
+            call_fallback_on_method_not_allowed: self.call_fallback_on_method_not_allowed,
+            config: self.config,
+            purge: self.purge.map(PurgeHandler::immutable),
+            filesystem: ImmutableFilesystem::new(self.filesystem),
+        }
+    }
+
+    /// Send the SHA-256 of the file as the `Repr-Digest` trailer, which is computed while
+    /// streaming, so the clients can verify the large downloads without pre-hashing the files.
+    ///
+    /// Only the full `200 OK` responses have the trailer, announced by the `Trailer` header. The
+    /// digest is of the sent bytes, so the precompressed variants have the digest of the
+    /// compressed data, as RFC 9530 requires. Note that hyper only sends trailers over HTTP/2.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "content-digest")]
+    pub fn repr_digest_trailer(mut self, enable: bool) -> Self {
+        self.config.repr_digest_trailer = enable;
+        self
+    }
+
+    /// Check the range requests with the [`RangeGuard`], the rejected ones are responded with
+    /// `429 Too Many Requests` before reading the file.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::{RangeGuard, ServeDir};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .range_guard(RangeGuard::new().max_per_connection(1000));
+    /// ```
+    pub fn range_guard(mut self, guard: RangeGuard) -> Self {
+        self.config.range_guard = Some(guard);
+        self
+    }
+
+    /// Add the `Surrogate-Key` header to the responses of the served files, including the
+    /// `304 Not Modified` ones, so the CDNs in front of the service can purge the cached responses
+    /// by their keys.
+    ///
+    /// The header of the file from the [`Metadata`](crate::fs::Metadata) takes precedence.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::{ServeDir, SurrogateKey};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).surrogate_key(SurrogateKey::path());
+    /// ```
+    pub fn surrogate_key(mut self, key: SurrogateKey) -> Self {
+        self.config.surrogate_key = Some(key);
+        self
+    }
+
+    /// Set the `Surrogate-Control` header of the responses of the served files, like
+    /// `max-age=86400`, it controls the caching of the CDNs in front of the service, which strip
+    /// it before responding to the clients, while the `Cache-Control` is left to the browsers.
+    ///
+    /// Setting it again replaces the previous value, the header of the file from the
+    /// [`Metadata`](crate::fs::Metadata) takes precedence.
+    pub fn surrogate_control(mut self, value: HeaderValue) -> Self {
+        self.config
+            .response_headers
+            .insert(SURROGATE_CONTROL, value);
+        self
+    }
+
+    /// Answer the `PURGE` requests authorized with `Authorization: Bearer <token>` by purging the
+    /// caches of the [`Filesystem`], so the deploy pipelines can invalidate them without
+    /// restarting the service:
+    ///
+    /// - `PURGE /css/site.css` purges the file and its precompressed variants
+    /// - `PURGE /css/` purges the dir and everything under it
+    /// - `PURGE /` purges everything
+    ///
+    /// The purged requests are answered with `204 No Content`, the ones without the token with
+    /// `401 Unauthorized`, and the ones of the paths which can't be served, like the hidden ones,
+    /// with `404 Not Found`, they are never passed to the fallback. The filesystem serving the
+    /// request is purged, including the [`ImmutableFilesystem`] of [`ServeDir::immutable`]. Use the
+    /// [`Purge`] methods of the filesystem directly to purge it from the code.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .immutable()
+    ///     .purge_method("secret-token");
+    /// ```
+    pub fn purge_method(mut self, token: &str) -> Self
+    where
+        FS: Purge,
+    {
+        self.purge = Some(PurgeHandler::new(token));
+        self
+    }
+
+    /// Open the file on the first read of the response body instead of before responding, so
+    /// the requests whose bodies are never read, like the ones abandoned by the clients, don't
+    /// hold a file descriptor.
+    ///
+    /// The response headers are built from the [`Filesystem::metadata`], if the file is removed
+    /// before the body is read, the body ends with an error and the connection is aborted.
+    ///
+    /// Defaults to `false`.
+    pub fn defer_open(mut self, defer: bool) -> Self {
+        self.config.defer_open = defer;
+        self
+    }
+
+    /// Send `Content-Encoding: identity` on every response whose content isn't encoded, some
+    /// CDNs key or validate the cached responses by the header and require it to be present or
+    /// absent consistently.
+    ///
+    /// By default the header is never sent for the uncompressed content, even when the client
+    /// asks for `identity` in the `Accept-Encoding`, RFC 9110 section 8.4.1 forbids it. Either
+    /// way the `GET` and the `HEAD` responses carry the same header.
+    pub fn identity_content_encoding(mut self, send: bool) -> Self {
+        self.config.identity_content_encoding = send;
+        self
+    }
+
+    /// Answer the `Range: bytes=0-` requests of the audio and video files with up to `len`
+    /// bytes, instead of the whole file, like the pseudo-streaming servers. The players start
+    /// with the open-ended range, and seek faster when the first response is small, as they
+    /// request the following ranges anyway.
+    ///
+    /// The `206 Partial Content` response tells the size of the file in the `Content-Range`. The
+    /// other ranges and the files of the other types are not affected.
+    ///
+    /// Defaults to `None`, which serves the whole file.
+    pub fn media_first_range(mut self, len: u64) -> Self {
+        self.config.media_first_range = (len > 0).then_some(len);
+        self
+    }
+
+    /// Serve the representations produced by the [`Transform`] for the requests with its query
+    /// parameters, like the thumbnails for `?w=200`, instead of the files.
+    ///
+    /// The representations are made from the whole uncompressed files, the `Range` and the
+    /// precompressed variants are ignored for these requests, even when the file is served as
+    /// is, like the ones which aren't the [`Transform::sources`]. They have the `Last-Modified` of
+    /// the source file, so the conditional requests are answered as usual, and vary by the
+    /// `Accept` header.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http_dir::{ServeDir, Transform};
+    /// use http_dir::fs::disk::DiskFilesystem;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets")).transform(Transform::new(
+    ///     &["w", "h"],
+    ///     |request| async move { Ok(None) },
+    /// ));
+    /// ```
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.config.transform = Some(transform);
+        self
+    }
+
+    /// Abort the file response body when the client doesn't consume any data within the
+    /// `timeout`, and release the file handle, so the slow clients can't pin the files.
+    ///
+    /// Must be used in the tokio runtime, a watchdog task is spawned for every file response.
+    ///
+    /// See [`Deadline`](crate::Deadline) for limiting the whole response time.
+    ///
+    /// Defaults to no timeout.
+    #[cfg(feature = "timeout")]
+    pub fn body_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.config.body_stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Serve the changes of the [`FileWatcher`] as
+    /// [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html) on
+    /// the [`EVENTS_PATH`](crate::watch::EVENTS_PATH), so the dev tooling can reload the page when
+    /// the served files are changed.
+    ///
+    /// The watcher should watch the same directory as the [`Filesystem`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use http_dir::ServeDir;
+    /// use http_dir::fs::disk::DiskFilesystem;
+    /// use http_dir::watch::FileWatcher;
+    ///
+    /// let service = ServeDir::new(DiskFilesystem::from("assets"))
+    ///     .events(FileWatcher::new("assets").expect("watch assets failed"));
+    /// ```
+    #[cfg(feature = "watch")]
+    pub fn events(mut self, watcher: FileWatcher) -> Self {
+        if let Some(probe_cache) = &self.config.probe_cache {
+            probe_cache.watch(&watcher);
+        }
+        if let Some(asset_manifest) = &self.config.asset_manifest {
+            asset_manifest.watch(&watcher);
+        }
+        self.config.watcher = Some(watcher);
+        self
+    }
+
+    /// Inject the [`RELOAD_SCRIPT`](crate::watch::RELOAD_SCRIPT) into the served HTML pages, right
+    /// before `</body>`, so the pages reload themselves when the [`ServeDir::events`] reports a
+    /// change, like what the `live-server` does.
+    ///
+    /// It only takes effect when the [`ServeDir::events`] is enabled. Range requests and
+    /// precompressed pages are served as is. The rewritten page is read into memory, and the
+    /// `Content-Length` of the `HEAD` response of it includes the length of the script.
+    ///
+    /// This is meant for development only.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "watch")]
+    pub fn inject_reload_script(mut self, inject: bool) -> Self {
+        self.config.inject_reload_script = inject;
+        self
+    }
+}
+
+impl<FS, F> ServeDir<FS, F> {
+    // the methods enabled by the configuration, listed in the `Allow` header of the `405` and
+    // `OPTIONS` responses, the write capabilities should add their methods here
+    fn allowed_methods(&self) -> Vec<Method> {
+        let mut methods = vec![Method::GET, Method::HEAD, Method::OPTIONS];
+        if self.purge.is_some() {
+            methods.push(purge::purge_method());
+        }
+
+        methods
+    }
+}
+
+impl<FS: Filesystem + Clone, F> ServeDir<FS, F> {
+    /// Open the files of the request `paths` and read their metadata before serving, so the first
+    /// requests after the startup don't wait for the cold caches, like the
+    /// [`DiskFilesystem::handle_cache`](crate::fs::disk::DiskFilesystem::handle_cache), the
+    /// [`ImmutableFilesystem`](crate::fs::immutable::ImmutableFilesystem) snapshot or the
+    /// [`ServeDir::precompressed_probe_cache`].
+    ///
+    /// The enabled precompressed variants of the files are opened too, and the index file is
+    /// warmed for the directories when it is appended. The hidden and the not found paths are
+    /// skipped, any other error is returned.
+    ///
+    /// Returns the number of the warmed files.
+    pub async fn warm<I, P>(&self, paths: I) -> io::Result<usize>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let mut filesystem = self.filesystem.clone();
+        let mut warmed = 0;
+        for path in paths {
+            let path = path.as_ref().trim_start_matches('/');
+            if !self.config.filter.is_allowed(path) {
+                continue;
+            }
+
+            let mut path_to_file = match &self.variant {
+                ServeVariant::Directory { .. } => PathBuf::from(path),
+                ServeVariant::SingleFile { file_path, .. } => file_path.clone(),
+            };
+            if let ServeVariant::Directory {
+                append_index_html_on_directories: true,
+                directory_index,
+                ..
+            } = &self.variant
+            {
+                if filesystem.is_dir(&path_to_file).await.unwrap_or(false) {
+                    match directory_index.static_index() {
+                        Some(index) => path_to_file.push(index),
+                        // the index picked by the request can't be known before it
+                        None => continue,
+                    }
+                }
+            }
+
+            match open_file::warm_file(
+                &mut filesystem,
+                path_to_file,
+                self.warm_encodings(path),
+                self.config.probe_cache.as_ref(),
+            )
+            .await
+            {
+                Ok(()) => warmed += 1,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(warmed)
+    }
+
+    /// Warm all the files of the filesystem, see [`ServeDir::warm`].
+    ///
+    /// The files are listed from the [`ServeDir::route_manifest`] if it is set, or by walking the
+    /// whole filesystem.
+    pub async fn warm_all(&self) -> io::Result<usize> {
+        let manifest = match &self.config.route_manifest {
+            Some(manifest) => manifest.clone(),
+            None => RouteManifest::build(&self.filesystem).await?,
+        };
+        let paths = manifest
+            .files()
+            .filter(|(path, _)| !manifest.is_precompressed_variant(path))
+            .filter_map(|(path, _)| path.to_str().map(str::to_string))
+            .collect::<Vec<_>>();
+
+        self.warm(paths).await
+    }
+
+    // the precompressed variants enabled for the path
+    fn warm_encodings(&self, path: &str) -> Vec<Encoding> {
+        let Some(variants) = self.precompressed_variants else {
+            return vec![];
+        };
+        if self
+            .config
+            .precompressed_exclude
+            .iter()
+            .any(|glob| glob.is_match(path.trim_matches('/')))
+        {
+            return vec![];
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, deflate, br"),
+        );
+
+        encodings(&headers, variants)
+            .into_iter()
+            .map(|(encoding, _)| encoding)
+            .collect()
+    }
+}
+
+impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeDir<FS, F>
+where
+    ReqBody: Body,
+    F: Service<Request<ReqBody>, Response = Response<FResBody>> + Clone,
+    F::Error: Into<io::Error>,
+    F::Future: Send,
+    FResBody: Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<Box<dyn Error + Send + Sync>>,
+    FS: Filesystem + Clone + Send + Sync + 'static,
+{
+    type Response = Response<ResponseBody>;
+    type Error = io::Error;
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.poll_ready(cx).map_err(Into::into)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let mut this = self.clone();
+        let request_id = match (this.config.request_id, this.config.deterministic) {
+            (false, _) => None,
+            (true, false) => Some(request_id::request_id(req.headers())),
+            (true, true) => request_id::from_request(req.headers()),
+        };
+        let deterministic = this.config.deterministic;
+        let server_header = this.config.server_header.clone();
+        let header_hook = this.config.header_hook.clone();
+        let error_pages = this.config.error_pages.clone();
+        let not_modified_headers = this.config.not_modified_headers;
+        // the clock is read before the request is served, like for the files
+        let fallback_conditions = (this.config.conditional_fallback
+            && this.fallback.is_some()
+            && matches!(*req.method(), Method::GET | Method::HEAD))
+        .then(|| {
+            (
+                Conditions::new(req.headers(), this.config.clock.now()),
+                req.uri().path().to_owned(),
+            )
+        });
+        let error_request = error_pages.is_some().then(|| {
+            let accept = req.headers().get(header::ACCEPT).cloned();
+            (req.method().clone(), req.uri().path().to_owned(), accept)
+        });
+        if let Some(request_id) = &request_id {
+            req.headers_mut()
+                .insert(request_id::X_REQUEST_ID, request_id.clone());
+        }
+        #[cfg(feature = "tracing")]
+        let span = trace_context::request_span(
+            req.method(),
+            req.uri().path(),
+            req.headers(),
+            request_id.as_ref(),
+        );
+
+        let serve = async move {
+            if let Some(mirror) = &this.config.mirror {
+                mirror.send(&req);
+            }
+
+            if let Some(ip_filter) = &this.config.ip_filter {
+                if !ip_filter.is_allowed(req.extensions()) {
+                    Outcome::Forbidden.report(req.uri().path());
+                    return Ok(response_with_status(StatusCode::FORBIDDEN));
+                }
+            }
+
+            let req = match &this.config.rate_limit {
+                None => req,
+                Some(rate_limit) => {
+                    let (parts, body) = req.into_parts();
+                    if let Err(retry_after) = rate_limit.check(&parts) {
+                        Outcome::RateLimited.report(parts.uri.path());
+                        // round up, so the client doesn't retry too early
+                        let seconds =
+                            retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                        let mut res = response_with_status(StatusCode::TOO_MANY_REQUESTS);
+                        res.headers_mut()
+                            .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+
+                        return Ok(res);
+                    }
+
+                    Request::from_parts(parts, body)
+                }
+            };
+
+            let allowed_methods = this.allowed_methods();
+            if req.method() == Method::OPTIONS {
+                let mut res = response_with_status(StatusCode::NO_CONTENT);
+                res.headers_mut()
+                    .insert(ALLOW, allow_header_value(&allowed_methods));
+
+                return Ok(res);
+            }
+
+            if !allowed_methods.contains(req.method()) {
+                if this.call_fallback_on_method_not_allowed {
+                    if let Some(fallback) = &mut this.fallback {
+                        return call_fallback(fallback, req).await;
+                    }
+                } else {
+                    Outcome::MethodNotAllowed.report(req.uri().path());
+                    let mut res = response_with_status(StatusCode::METHOD_NOT_ALLOWED);
+                    res.headers_mut()
+                        .insert(ALLOW, allow_header_value(&allowed_methods));
+
+                    return Ok(res);
+                }
+            }
+
+            if this.config.query_policy == QueryPolicy::Reject && req.uri().query().is_some() {
+                Outcome::QueryRejected.report(req.uri().path());
+                return Ok(response_with_status(StatusCode::BAD_REQUEST));
+            }
+
+            #[cfg(feature = "timeout")]
+            let deadline = req.extensions().get::<Deadline>().copied();
+            let range_client = this
+                .config
+                .range_guard
+                .as_ref()
+                .and_then(|guard| guard.client(req.extensions()));
+
+            if this.config.health_endpoints {
+                match req.uri().path() {
+                    health::HEALTHZ_PATH => return Ok(health::health_response(req.method(), true)),
+                    health::READYZ_PATH => {
+                        let ready = health::is_ready(&this.filesystem, &this.variant).await;
+                        return Ok(health::health_response(req.method(), ready));
+                    }
+                    _ => {}
+                }
+            }
+
+            #[cfg(feature = "watch")]
+            if let Some(watcher) = &this.config.watcher {
+                if req.uri().path() == watch::EVENTS_PATH {
+                    return Ok(watcher.event_stream_response(this.config.filter.clone()));
+                }
+            }
+
+            if this.config.canonical_redirects
+                && matches!(*req.method(), Method::GET | Method::HEAD)
+            {
+                if let Some(path) = canonical_path(req.uri().path()) {
+                    let location = match req.uri().query() {
+                        None => path,
+                        Some(query) => format!("{path}?{query}"),
+                    };
+                    let mut res = response_with_status(StatusCode::MOVED_PERMANENTLY);
+                    res.headers_mut().insert(
+                        header::LOCATION,
+                        HeaderValue::try_from(location)
+                            .expect("the canonical path is a valid header value"),
+                    );
+
+                    return Ok(res);
+                }
+            }
+
+            if let Some(RootOverride(root)) = req.extensions().get::<RootOverride>() {
+                match this.filesystem.reroot(root).await {
+                    None => {
+                        Outcome::RootRejected.report(req.uri().path());
+                        return Ok(not_found());
+                    }
+                    Some(filesystem) => {
+                        this.filesystem = filesystem;
+                        // the missing variants and the manifest are cached for the default root
+                        this.config.probe_cache = None;
+                        this.config.asset_manifest = None;
+                    }
+                }
+            }
+
+            if let Some(asset_manifest) = &this.config.asset_manifest {
+                if matches!(*req.method(), Method::GET | Method::HEAD)
+                    && asset_manifest.is_request(req.uri().path())
+                {
+                    return asset_manifest
+                        .response(
+                            &mut this.filesystem,
+                            &this.config.filter,
+                            req.method(),
+                            req.headers(),
+                        )
+                        .await;
+                }
+            }
+
+            // `ServeDir` doesn't care about the request body but the fallback might. So move out the
+            // body and pass it to the fallback, leaving an empty body in its place
+            //
+            // this is necessary because we cannot clone bodies
+            let (mut parts, body) = req.into_parts();
+            // same goes for extensions
+            let mut extensions = std::mem::take(&mut parts.extensions);
+            let req = Request::from_parts(parts, Empty::<Bytes>::new());
+
+            // a fallback without its own precompressed variants serves the variants negotiated by
+            // the `ServeDir` it is the fallback of
+            let negotiated = match (
+                this.precompressed_variants,
+                extensions.remove::<NegotiatedEncodings>(),
+            ) {
+                (None, Some(negotiated)) => negotiated,
+                (variants, _) => NegotiatedEncodings::new(req.headers(), variants),
+            };
+
+            let body = match this.config.request_body_policy {
+                RequestBodyPolicy::Reject if !body.is_end_stream() => {
+                    return Ok(body_rejected(Outcome::UnexpectedBody, req.uri().path()));
+                }
+
+                RequestBodyPolicy::Drain(limit) if this.fallback.is_none() => {
+                    match request_body::drain(body, limit).await {
+                        Ok(()) => None,
+                        Err(BodyRejected::TooLarge) => {
+                            return Ok(body_rejected(Outcome::UnexpectedBody, req.uri().path()));
+                        }
+                        Err(BodyRejected::Invalid) => {
+                            return Ok(body_rejected(Outcome::InvalidBody, req.uri().path()));
+                        }
+                    }
+                }
+
+                _ => Some(body),
+            };
+
+            // the `PURGE` requests are answered here, even when the path can't be served
+            let is_purge = this.purge.is_some() && req.method().as_str() == purge::PURGE;
+            let mut fallback_and_request = this
+                .fallback
+                .as_mut()
+                .filter(|_| !is_purge)
+                .zip(body)
+                .map(|(fallback, body)| {
+                    let mut fallback_req = Request::new(body);
+                    *fallback_req.method_mut() = req.method().clone();
+                    *fallback_req.uri_mut() = req.uri().clone();
+                    *fallback_req.headers_mut() = req.headers().clone();
+                    *fallback_req.extensions_mut() = extensions;
+                    if negotiated.variants.is_some() {
+                        fallback_req.extensions_mut().insert(negotiated.clone());
+                    }
+
+                    // get the ready fallback and leave a non-ready clone in its place
+                    let clone = fallback.clone();
+                    let fallback = std::mem::replace(fallback, clone);
+
+                    (fallback, fallback_req)
+                });
+
+            let path_encoded = req.uri().path().trim_start_matches('/');
+            // the `+` is replaced before decoding, so the `%2B` is still a `+`
+            let path_encoded = if this.config.decode_plus_as_space {
+                Cow::Owned(path_encoded.replace('+', "%20"))
+            } else {
+                Cow::Borrowed(path_encoded)
+            };
+            let path_decoded = match percent_decode(path_encoded.as_bytes()).decode_utf8().ok() {
+                None => {
+                    Outcome::InvalidPath.report(req.uri().path());
+                    return fallback_or_not_found(fallback_and_request.take()).await;
+                }
+
+                Some(path) => path,
+            };
+            let hashed_original = this
+                .config
+                .hashed_assets
+                .as_ref()
+                .and_then(|hashes| hashes.original(&path_decoded))
+                .map(str::to_string);
+            let path_decoded = match hashed_original {
+                None => path_decoded,
+                Some(original) => {
+                    // the content of a hashed name never changes
+                    this.config.response_headers.insert(
+                        header::CACHE_CONTROL,
+                        HeaderValue::from_static("public, max-age=31536000, immutable"),
+                    );
+
+                    Cow::Owned(original)
+                }
+            };
+            if !this.config.filter.is_allowed(&path_decoded) {
+                Outcome::Hidden.report(req.uri().path());
+                return fallback_or_not_found(fallback_and_request.take()).await;
+            }
+
+            let mut path_to_file = PathBuf::new();
+            if let ServeVariant::SingleFile {
+                file_path,
+                request_path,
+                ..
+            } = &this.variant
+            {
+                if let Some(request_path) = request_path {
+                    if !is_same_request_path(request_path, Path::new(&*path_decoded)) {
+                        Outcome::MissingFile.report(req.uri().path());
+                        return file_not_found(fallback_and_request, PathBuf::from(&*path_decoded))
+                            .await;
+                    }
+                }
+
+                path_to_file.push(file_path);
+            } else {
+                if let Some(host_template) = &this.config.host_template {
+                    match host_template.dir(&req) {
+                        None => {
+                            Outcome::InvalidHost.report(req.uri().path());
+                            return Ok(response_with_status(StatusCode::BAD_REQUEST));
+                        }
+                        Some(dir) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::Span::current()
+                                .record("vhost", tracing::field::display(dir.display()));
+                            path_to_file.push(dir)
+                        }
+                    }
+                }
+                path_to_file.push(&*path_decoded);
+            }
+
+            if let Some(handler) = &this.purge {
+                if is_purge {
+                    let scope = if path_to_file.as_os_str().is_empty() {
+                        purge::Scope::All
+                    } else if req.uri().path().ends_with('/') {
+                        purge::Scope::Prefix(&path_to_file)
+                    } else {
+                        purge::Scope::Path(&path_to_file)
+                    };
+                    let res = handler.handle(&this.filesystem, req.headers(), scope);
+                    if res.status() == StatusCode::UNAUTHORIZED {
+                        Outcome::Unauthorized.report(req.uri().path());
+                    } else {
+                        if let Some(probe_cache) = &this.config.probe_cache {
+                            probe_cache.retain(|cached| !scope.is_purged(cached));
+                        }
+                        if let Some(asset_manifest) = &this.config.asset_manifest {
+                            asset_manifest.invalidate();
+                        }
+                    }
+
+                    return Ok(res);
+                }
+            }
+
+            // the parameters aren't read from the cache busters
+            let params_uri = match this.config.query_policy {
+                QueryPolicy::CacheBuster => Cow::Owned(query::without_query(req.uri())),
+                _ => Cow::Borrowed(req.uri()),
+            };
+
+            if let Some(options) = this.config.search {
+                if let Some(pattern) = search::search_pattern(&params_uri) {
+                    if this.filesystem.is_dir(&path_to_file).await.unwrap_or(false) {
+                        let result = search::search(
+                            &this.filesystem,
+                            &path_to_file,
+                            &path_decoded,
+                            &pattern,
+                            &this.config.filter,
+                            options,
+                        )
+                        .await?;
+
+                        return Ok(match result {
+                            Ok(result) => {
+                                json_response(req.method(), "application/json", result.to_json())
+                            }
+                            Err(outcome) => {
+                                outcome.report(req.uri().path());
+                                response_with_status(outcome.status())
+                            }
+                        });
+                    }
+                }
+            }
+
+            if this.config.serve_stat
+                && stat::is_stat_request(&params_uri, req.headers())
+                && !this.filesystem.is_dir(&path_to_file).await.unwrap_or(false)
+            {
+                if let Ok(metadata) = this.filesystem.metadata(&path_to_file).await {
+                    let mime = match &this.variant {
+                        ServeVariant::Directory { default_mime, .. } => {
+                            open_file::guess_mime(&path_to_file)
+                                .unwrap_or_else(|| default_mime.clone())
+                        }
+                        ServeVariant::SingleFile { mime, .. } => mime.clone(),
+                    };
+
+                    let mut res = json_response(
+                        req.method(),
+                        STAT_CONTENT_TYPE,
+                        stat::stat_json(&metadata, &mime),
+                    );
+                    res.headers_mut()
+                        .insert(header::VARY, HeaderValue::from_static("accept"));
+
+                    return Ok(res);
+                }
+            }
+
+            let buf_chunk_size = this.buf_chunk_size;
+
+            let vary_encoding = negotiated.variants.is_some();
+            let mut negotiated_encodings = negotiated.encodings;
+            let path_trimmed = path_decoded.trim_matches('/');
+            if this
+                .config
+                .precompressed_exclude
+                .iter()
+                .any(|glob| glob.is_match(path_trimmed))
+            {
+                negotiated_encodings.retain(|(encoding, _)| encoding.to_file_extension().is_none());
+            }
+
+            if let Some(manifest) = &this.config.route_manifest {
+                let mut retain_precompressed = |entry: &ManifestEntry| {
+                    negotiated_encodings.retain(|(encoding, _)| {
+                        encoding.to_file_extension().is_none()
+                            || entry.precompressed().contains(&encoding.to_str())
+                    })
+                };
+
+                match manifest.get(&path_to_file) {
+                    Some(entry) => retain_precompressed(entry),
+                    // the dir is served with its index, so are the precompressed variants
+                    None if manifest.is_dir(&path_to_file) => {
+                        let index = match &this.variant {
+                            ServeVariant::Directory {
+                                directory_index, ..
+                            } => directory_index.static_index(),
+                            ServeVariant::SingleFile { .. } => None,
+                        };
+                        if let Some(index) =
+                            index.and_then(|index| manifest.get(path_to_file.join(index)))
+                        {
+                            retain_precompressed(index);
+                        }
+                    }
+                    None => {
+                        Outcome::MissingFile.report(&path_to_file.to_string_lossy());
+                        return file_not_found(fallback_and_request.take(), path_to_file).await;
+                    }
+                }
+            }
+
+            let requested_path = path_to_file.clone();
+            let uri = req.uri().clone();
+            let is_open_ended_range = req
+                .headers()
+                .get(header::RANGE)
+                .and_then(|range| range.to_str().ok())
+                .is_some_and(|range| range.trim() == "bytes=0-");
+            #[cfg(feature = "content-digest")]
+            let is_head = req.method() == Method::HEAD;
+
+            let transform_params = this
+                .config
+                .transform
+                .as_ref()
+                .and_then(|transform| transform.params(&params_uri));
+            let accept = req.headers().get(header::ACCEPT).cloned();
+            let mut req = req;
+            if transform_params.is_some() {
+                // the representation is made from the whole uncompressed file
+                req.headers_mut().remove(header::RANGE);
+                negotiated_encodings.retain(|(encoding, _)| encoding.to_file_extension().is_none());
+            }
+
+            match open_file::open_file(
+                &mut this.filesystem,
+                &this.variant,
+                path_to_file,
+                req,
+                negotiated_encodings,
+                buf_chunk_size,
+                this.config.defer_open,
+                this.config.clock.now(),
+                this.config.last_modified_min_age,
+                this.config.probe_cache.as_ref(),
+                this.config.concurrent_probes,
+            )
+            .await
+            {
+                Ok(OpenFileOutput::FileOpened(mut file_output)) => {
+                    if matches!(file_output.maybe_encoding, None | Some(Encoding::Identity)) {
+                        file_output.maybe_encoding = this
+                            .config
+                            .identity_content_encoding
+                            .then_some(Encoding::Identity);
+                    }
+                    let resolved_path = ResolvedPath {
+                        path: file_output.path.clone(),
+                        encoding: file_output.maybe_encoding.map(Encoding::to_str),
+                    };
+                    let served_file = file_output.served_file();
+                    if let (Some(transform), Some(params)) = (
+                        this.config.transform.as_ref().filter(|transform| {
+                            transform.is_source(
+                                &file_output.path,
+                                &file_output.mime_header_value,
+                                file_output.len(),
+                            )
+                        }),
+                        transform_params,
+                    ) {
+                        let mut res = transform
+                            .response(&mut this.filesystem, *file_output, params, accept)
+                            .await?;
+                        add_surrogate_key(
+                            res.headers_mut(),
+                            &this.config.surrogate_key,
+                            &requested_path,
+                        );
+                        add_response_headers(res.headers_mut(), &this.config.response_headers);
+                        res.extensions_mut().insert(resolved_path);
+                        res.extensions_mut().insert(served_file);
+
+                        return Ok(res);
+                    }
+
+                    let range_outcome = match &file_output.maybe_range {
+                        Some(Err(_)) => Some(Outcome::BadRange),
+                        Some(Ok(ranges)) if ranges.is_empty() => Some(Outcome::BadRange),
+                        Some(Ok(ranges)) if ranges.len() > 1 => Some(Outcome::MultipartRange),
+                        _ => None,
+                    };
+                    if let Some(outcome) = range_outcome {
+                        outcome.report(&served_file.path.to_string_lossy());
+                    }
+
+                    if let (Some(guard), Some(Ok(ranges))) =
+                        (&this.config.range_guard, &file_output.maybe_range)
+                    {
+                        if !guard.is_allowed(range_client, &file_output.path, ranges) {
+                            Outcome::RangeRejected.report(&served_file.path.to_string_lossy());
+                            let mut res = response_with_status(StatusCode::TOO_MANY_REQUESTS);
+                            res.extensions_mut().insert(ServedFile {
+                                status_reason: StatusReason::RangeRejected,
+                                ..served_file
+                            });
+
+                            return Ok(res);
+                        }
+                    }
+                    if let (Some(first_range), true) =
+                        (this.config.media_first_range, is_open_ended_range)
+                    {
+                        if is_media(&file_output.mime_header_value) {
+                            if let Some(Ok(ranges)) = &mut file_output.maybe_range {
+                                if let [range] = &mut ranges[..] {
+                                    let end = (*range.end()).min(first_range - 1);
+                                    *range = 0..=end;
+                                }
+                            }
+                        }
+                    }
+
+                    #[cfg(feature = "watch")]
+                    let mut res =
+                        if this.config.inject_reload_script && this.config.watcher.is_some() {
+                            match reload_script_injected_response(*file_output).await? {
+                                Ok(res) => res,
+                                Err(file_output) => build_response(file_output),
+                            }
+                        } else {
+                            build_response(*file_output)
+                        };
+                    #[cfg(not(feature = "watch"))]
+                    let mut res = build_response(*file_output);
+
+                    if this.config.serve_stat {
+                        res.headers_mut()
+                            .append(header::VARY, HeaderValue::from_static("accept"));
+                    }
+                    if vary_encoding {
+                        res.headers_mut()
+                            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                    }
+                    add_content_location(
+                        res.headers_mut(),
+                        &this.variant,
+                        uri.path(),
+                        &requested_path,
+                        &resolved_path.path,
+                    );
+                    add_surrogate_key(
+                        res.headers_mut(),
+                        &this.config.surrogate_key,
+                        &requested_path,
+                    );
+                    add_response_headers(res.headers_mut(), &this.config.response_headers);
+                    res.extensions_mut().insert(resolved_path);
+                    res.extensions_mut().insert(served_file);
+
+                    #[cfg(feature = "content-digest")]
+                    if this.config.repr_digest_trailer && !is_head && res.status() == StatusCode::OK
+                    {
+                        res.headers_mut()
+                            .insert(header::TRAILER, HeaderValue::from(REPR_DIGEST));
+                        res =
+                            res.map(|body| ResponseBody::new(DigestBody::new(body).boxed_unsync()));
+                    }
+
+                    #[cfg(feature = "timeout")]
+                    if let Some(Deadline(deadline)) = deadline {
+                        res = res.map(|body| {
+                            ResponseBody::new(DeadlineBody::new(body, deadline).boxed_unsync())
+                        });
+                    }
+                    #[cfg(feature = "timeout")]
+                    if let Some(timeout) = this.config.body_stall_timeout {
+                        res = res.map(|body| {
+                            ResponseBody::new(StallTimeoutBody::new(body, timeout).boxed_unsync())
+                        });
+                    }
+
+                    Ok(res)
+                }
+
+                Ok(OpenFileOutput::Redirect { location }) => {
+                    let mut res = response_with_status(StatusCode::TEMPORARY_REDIRECT);
+                    res.headers_mut().insert(header::LOCATION, location);
+                    res.extensions_mut().insert(ServedFile::new(
+                        requested_path,
+                        None,
+                        StatusReason::Redirect,
+                    ));
+
+                    Ok(res)
+                }
+
+                Ok(OpenFileOutput::FileNotFound) => {
+                    Outcome::MissingFile.report(&requested_path.to_string_lossy());
+                    file_not_found(fallback_and_request.take(), requested_path).await
+                }
+
+                Ok(OpenFileOutput::PreconditionFailed(served_file)) => {
+                    Outcome::PreconditionFailed.report(&served_file.path.to_string_lossy());
+                    let mut res = response_with_status(StatusCode::PRECONDITION_FAILED);
+                    res.extensions_mut().insert(served_file);
+
+                    Ok(res)
+                }
+
+                Ok(OpenFileOutput::NotModified {
+                    served_file,
+                    last_modified,
+                    etag,
+                }) => {
+                    let mut res = response_with_status(StatusCode::NOT_MODIFIED);
+                    if let Some(last_modified) = last_modified {
+                        res.headers_mut()
+                            .insert(header::LAST_MODIFIED, last_modified.header_value());
+                    }
+                    if let Some(etag) = etag {
+                        res.headers_mut().insert(header::ETAG, etag.header_value());
+                    }
+                    if this.config.serve_stat {
+                        res.headers_mut()
+                            .append(header::VARY, HeaderValue::from_static("accept"));
+                    }
+                    if vary_encoding {
+                        res.headers_mut()
+                            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                    }
+                    add_content_location(
+                        res.headers_mut(),
+                        &this.variant,
+                        uri.path(),
+                        &requested_path,
+                        &served_file.path,
+                    );
+                    add_surrogate_key(
+                        res.headers_mut(),
+                        &this.config.surrogate_key,
+                        &requested_path,
+                    );
+                    add_response_headers(res.headers_mut(), &this.config.response_headers);
+                    this.config.not_modified_headers.apply(res.headers_mut());
+                    res.extensions_mut().insert(served_file);
+
+                    Ok(res)
+                }
+
+                Err(err) => {
                     if let io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied = err.kind() {
-                        if let Some((mut fallback, request)) = fallback_and_request.take() {
-                            call_fallback(&mut fallback, request).await
+                        let outcome = if !requested_path
+                            .components()
+                            .all(|component| matches!(component, Component::Normal(_)))
+                        {
+                            Outcome::TraversalRejected
+                        } else if err.kind() == io::ErrorKind::PermissionDenied {
+                            Outcome::PermissionDenied
                         } else {
-                            Ok(not_found())
-                        }
+                            Outcome::MissingFile
+                        };
+                        outcome.report(&requested_path.to_string_lossy());
+
+                        file_not_found(fallback_and_request.take(), requested_path).await
                     } else {
                         Err(err)
                     }
                 }
             }
-        }
+        };
+        let future = async move {
+            let mut res = match serve.await {
+                Ok(res) => res,
+                Err(err) => match (&error_pages, &error_request) {
+                    (Some(pages), Some((_, path, _)))
+                        if pages.has(StatusCode::INTERNAL_SERVER_ERROR) =>
+                    {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(%err, path, "request failed");
+                        #[cfg(not(feature = "tracing"))]
+                        let _ = err;
+                        Outcome::InternalError.report(path);
+
+                        response_with_status(StatusCode::INTERNAL_SERVER_ERROR)
+                    }
+                    _ => return Err(err),
+                },
+            };
+            let from_fallback = res.extensions_mut().remove::<FallbackResponse>().is_some();
+            if let (true, Some((conditions, path))) = (from_fallback, fallback_conditions) {
+                if res.status().is_success() {
+                    res = conditional_response(res, conditions, not_modified_headers, &path);
+                }
+            }
+            if let (Some(pages), Some((method, path, accept))) = (&error_pages, &error_request) {
+                let context = ErrorContext {
+                    path,
+                    accept: accept.as_ref(),
+                    request_id: request_id.as_ref(),
+                };
+                pages.render(&mut res, method, &context);
+            }
+            if let Some(request_id) = request_id {
+                res.headers_mut()
+                    .insert(request_id::X_REQUEST_ID, request_id);
+            }
+            match server_header {
+                ServerHeader::Keep if deterministic => {
+                    res.headers_mut()
+                        .insert(header::SERVER, HeaderValue::from_static("http_dir"));
+                }
+                ServerHeader::Keep => {}
+                ServerHeader::Set(value) => {
+                    res.headers_mut().insert(header::SERVER, value);
+                }
+                ServerHeader::Remove => {
+                    res.headers_mut().remove(header::SERVER);
+                }
+            }
+            if deterministic {
+                sort_headers(res.headers_mut());
+            }
+            if let Some(header_hook) = header_hook {
+                header_hook.apply(res.headers_mut());
+            }
+
+            Ok(res)
+        };
+
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(future, span);
+
+        future
     }
 }
 
+#[derive(Debug, Clone)]
+enum ServerHeader {
+    Keep,
+    Set(HeaderValue),
+    Remove,
+}
+
 /// The default fallback service used with [`ServeDir`].
 #[derive(Debug, Clone, Copy)]
 pub struct DefaultServeDirFallback(Infallible);
@@ -444,12 +2285,30 @@ where
 pub enum ServeVariant {
     Directory {
         append_index_html_on_directories: bool,
+        default_mime: HeaderValue,
+        attachment_for_unknown_types: bool,
+        trust_forwarded_headers: bool,
+        relative_redirects: bool,
+        directory_index: DirectoryIndex,
     },
     SingleFile {
         mime: HeaderValue,
+        file_path: PathBuf,
+        // `None` serves the file for any request path
+        request_path: Option<PathBuf>,
     },
 }
 
+// Compare the request paths ignoring the leading slash, `.` and the trailing slash.
+fn is_same_request_path(expected: &Path, path: &Path) -> bool {
+    fn normal_components(path: &Path) -> impl Iterator<Item = Component<'_>> {
+        path.components()
+            .filter(|component| !matches!(component, Component::RootDir | Component::CurDir))
+    }
+
+    normal_components(expected).eq(normal_components(path))
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub(crate) struct PrecompressedVariants {
     pub(crate) gzip: bool,
@@ -471,49 +2330,109 @@ impl SupportedEncodings for PrecompressedVariants {
     }
 }
 
-fn response_with_status(status: StatusCode) -> Response<ResponseBody> {
+/// The encodings negotiated for a request, they are passed to the fallback in the request
+/// extensions
+#[derive(Clone, Debug)]
+pub(crate) struct NegotiatedEncodings {
+    // `None` if the precompressed variants aren't enabled, then the responses don't vary by the
+    // `Accept-Encoding`
+    variants: Option<PrecompressedVariants>,
+    encodings: Vec<(Encoding, QValue)>,
+}
+
+impl NegotiatedEncodings {
+    fn new(headers: &HeaderMap, variants: Option<PrecompressedVariants>) -> Self {
+        Self {
+            variants,
+            encodings: encodings(headers, variants.unwrap_or_default()),
+        }
+    }
+}
+
+fn allow_header_value(methods: &[Method]) -> HeaderValue {
+    let methods = methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    HeaderValue::from_str(&methods).unwrap()
+}
+
+pub(crate) fn response_with_status(status: StatusCode) -> Response<ResponseBody> {
     Response::builder()
         .status(status)
         .body(empty_body())
         .unwrap()
 }
 
-fn empty_body() -> ResponseBody {
+pub(crate) fn empty_body() -> ResponseBody {
     let body = Empty::new().map_err(|err| match err {}).boxed_unsync();
     ResponseBody::new(body)
 }
 
-fn body_from_bytes(bytes: Bytes) -> ResponseBody {
+pub(crate) fn body_from_bytes(bytes: Bytes) -> ResponseBody {
     let body = Full::from(bytes).map_err(|err| match err {}).boxed_unsync();
     ResponseBody::new(body)
 }
 
+fn json_response(
+    method: &Method,
+    content_type: &'static str,
+    json: String,
+) -> Response<ResponseBody> {
+    let len = json.len();
+    let body = if method == Method::HEAD {
+        empty_body()
+    } else {
+        body_from_bytes(Bytes::from(json))
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .body(body)
+        .unwrap()
+}
+
 fn build_response<IO: AsyncRead + Send + 'static>(
     output: FileOpened<IO>,
 ) -> Response<ResponseBody> {
-    let (maybe_file, size) = match output.extent {
-        FileRequestExtent::Full(file, meta) => (Some(file), meta.len),
-        FileRequestExtent::Head(meta) => (None, meta.len),
+    let (maybe_file, meta) = match output.extent {
+        FileRequestExtent::Full(file, meta) => (Some(file), meta),
+        FileRequestExtent::Head(meta) => (None, meta),
     };
+    let mut builder = Response::builder().header(header::CONTENT_TYPE, output.mime_header_value);
 
-    let mut builder = Response::builder()
-        .header(header::CONTENT_TYPE, output.mime_header_value)
-        .header(header::ACCEPT_RANGES, "bytes");
+    // the ranges can't be resolved without the file size
+    if meta.len.is_some() {
+        builder = builder.header(header::ACCEPT_RANGES, "bytes");
+    }
 
     if let Some(encoding) = output.maybe_encoding {
         builder = builder.header(header::CONTENT_ENCODING, encoding.into_header_value());
     }
 
     if let Some(last_modified) = output.last_modified {
-        builder = builder.header(header::LAST_MODIFIED, last_modified.0.to_string());
+        builder = builder.header(header::LAST_MODIFIED, last_modified.header_value());
+    }
+
+    if let Some(etag) = output.etag {
+        builder = builder.header(header::ETAG, etag.header_value());
+    }
+
+    if output.attachment {
+        builder = builder.header(header::CONTENT_DISPOSITION, "attachment");
     }
 
-    match output.maybe_range {
-        Some(Ok(ranges)) => {
+    builder = with_file_headers(builder, meta.headers);
+
+    match output.maybe_range.zip(meta.len) {
+        Some((Ok(ranges), size)) => {
             if let Some(range) = ranges.first() {
                 if ranges.len() > 1 {
                     builder
-                        .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                        .header(header::CONTENT_RANGE, content_range(None, size))
                         .status(StatusCode::RANGE_NOT_SATISFIABLE)
                         .body(body_from_bytes(Bytes::from(
                             "Cannot serve multipart range requests",
@@ -535,10 +2454,7 @@ fn build_response<IO: AsyncRead + Send + 'static>(
                     };
 
                     builder
-                        .header(
-                            header::CONTENT_RANGE,
-                            format!("bytes {}-{}/{}", range.start(), range.end(), size),
-                        )
+                        .header(header::CONTENT_RANGE, content_range(Some(range), size))
                         .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
                         .status(StatusCode::PARTIAL_CONTENT)
                         .body(body)
@@ -546,7 +2462,7 @@ fn build_response<IO: AsyncRead + Send + 'static>(
                 }
             } else {
                 builder
-                    .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                    .header(header::CONTENT_RANGE, content_range(None, size))
                     .status(StatusCode::RANGE_NOT_SATISFIABLE)
                     .body(body_from_bytes(Bytes::from(
                         "No range found after parsing range header, please file an issue",
@@ -555,31 +2471,164 @@ fn build_response<IO: AsyncRead + Send + 'static>(
             }
         }
 
-        Some(Err(_)) => builder
-            .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+        Some((Err(_), size)) => builder
+            .header(header::CONTENT_RANGE, content_range(None, size))
             .status(StatusCode::RANGE_NOT_SATISFIABLE)
             .body(empty_body())
             .unwrap(),
 
         // Not a range request
         None => {
-            let body = if let Some(file) = maybe_file {
-                ResponseBody::new(
+            let body = match (maybe_file, meta.len) {
+                (None, _) => empty_body(),
+                (Some(file), Some(size)) => ResponseBody::new(
+                    AsyncReadBody::with_capacity_limited(file, output.chunk_size, size)
+                        .boxed_unsync(),
+                ),
+                // sent chunked
+                (Some(file), None) => ResponseBody::new(
                     AsyncReadBody::with_capacity(file, output.chunk_size).boxed_unsync(),
-                )
-            } else {
-                empty_body()
+                ),
             };
 
-            builder
-                .header(header::CONTENT_LENGTH, size.to_string())
-                .body(body)
-                .unwrap()
+            if let Some(size) = meta.len {
+                builder = builder.header(header::CONTENT_LENGTH, size);
+            }
+
+            builder.body(body).unwrap()
+        }
+    }
+}
+
+/// Build the response of the HTML page with the reload script injected, or give back the `output`
+/// when it can't be rewritten.
+#[cfg(feature = "watch")]
+async fn reload_script_injected_response<IO: AsyncRead + Unpin>(
+    output: FileOpened<IO>,
+) -> io::Result<Result<Response<ResponseBody>, FileOpened<IO>>> {
+    let is_html = output
+        .mime_header_value
+        .as_bytes()
+        .starts_with(b"text/html");
+    let is_identity = matches!(output.maybe_encoding, None | Some(Encoding::Identity));
+    if !is_html || !is_identity || output.maybe_range.is_some() {
+        return Ok(Err(output));
+    }
+
+    let mut builder = Response::builder().header(header::CONTENT_TYPE, output.mime_header_value);
+    if let Some(encoding) = output.maybe_encoding {
+        builder = builder.header(header::CONTENT_ENCODING, encoding.into_header_value());
+    }
+    if let Some(last_modified) = output.last_modified {
+        builder = builder.header(header::LAST_MODIFIED, last_modified.header_value());
+    }
+
+    let body = match output.extent {
+        FileRequestExtent::Head(meta) => {
+            builder = with_file_headers(builder, meta.headers);
+            // the length of the page the `GET` would get
+            if let Some(len) = meta.len {
+                builder = builder.header(
+                    header::CONTENT_LENGTH,
+                    len + watch::RELOAD_SCRIPT.len() as u64,
+                );
+            }
+            empty_body()
+        }
+        FileRequestExtent::Full(mut file, meta) => {
+            builder = with_file_headers(builder, meta.headers);
+
+            let mut html = Vec::with_capacity(
+                meta.len.unwrap_or_default() as usize + watch::RELOAD_SCRIPT.len(),
+            );
+            file.read_to_end(&mut html).await?;
+            watch::inject_reload_script(&mut html);
+
+            builder = builder.header(header::CONTENT_LENGTH, html.len());
+            body_from_bytes(Bytes::from(html))
+        }
+    };
+
+    Ok(Ok(builder.body(body).unwrap()))
+}
+
+// Add the configured response headers which aren't set by the file headers.
+fn add_response_headers(headers: &mut HeaderMap, response_headers: &HeaderMap) {
+    for name in response_headers.keys() {
+        if !headers.contains_key(name) {
+            for value in response_headers.get_all(name) {
+                headers.append(name, value.clone());
+            }
+        }
+    }
+}
+
+// the values of the same name keep their order
+fn sort_headers(headers: &mut HeaderMap) {
+    let mut names = headers.keys().cloned().collect::<Vec<_>>();
+    names.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    let mut sorted = HeaderMap::with_capacity(headers.len());
+    for name in names {
+        for value in headers.get_all(&name) {
+            sorted.append(name.clone(), value.clone());
+        }
+    }
+    *headers = sorted;
+}
+
+fn is_media(mime: &HeaderValue) -> bool {
+    let mime = mime.as_bytes();
+
+    mime.starts_with(b"audio/") || mime.starts_with(b"video/")
+}
+
+// the `Surrogate-Key` from the file headers takes precedence
+fn add_surrogate_key(headers: &mut HeaderMap, key: &Option<SurrogateKey>, path: &Path) {
+    if let Some(value) = key.as_ref().and_then(|key| key.header_value(path)) {
+        headers.entry(SURROGATE_KEY).or_insert(value);
+    }
+}
+
+// the single file is served for any request path, so it has no own URL
+fn add_content_location(
+    headers: &mut HeaderMap,
+    variant: &ServeVariant,
+    uri_path: &str,
+    requested_path: &Path,
+    served_path: &Path,
+) {
+    if let ServeVariant::Directory { .. } = variant {
+        if let Some(location) = open_file::content_location(uri_path, requested_path, served_path) {
+            headers.insert(header::CONTENT_LOCATION, location);
+        }
+    }
+}
+
+// the headers from the `Metadata` replace the generated ones
+fn with_file_headers(mut builder: Builder, headers: HeaderMap) -> Builder {
+    if let Some(builder_headers) = builder.headers_mut() {
+        let mut last_name = None;
+        for (name, value) in headers {
+            // `None` means the same header name as the previous one
+            match name {
+                Some(name) => {
+                    builder_headers.insert(name.clone(), value);
+                    last_name = Some(name);
+                }
+                None => {
+                    if let Some(name) = &last_name {
+                        builder_headers.append(name, value);
+                    }
+                }
+            }
         }
     }
+
+    builder
 }
 
-async fn call_fallback<F, B, FResBody>(
+pub(crate) async fn call_fallback<F, B, FResBody>(
     fallback: &mut F,
     req: Request<B>,
 ) -> io::Result<Response<ResponseBody>>
@@ -607,6 +2656,102 @@ where
         .await
 }
 
-fn not_found() -> Response<ResponseBody> {
+// the responses of the fallback are marked, so their conditional requests can be evaluated, the
+// mark is removed before the response is sent
+#[derive(Clone, Copy)]
+struct FallbackResponse;
+
+// call the fallback or respond `404`
+async fn fallback_or_not_found<F, B, FResBody>(
+    fallback_and_request: Option<(F, Request<B>)>,
+) -> io::Result<Response<ResponseBody>>
+where
+    F: Service<Request<B>, Response = Response<FResBody>> + Clone,
+    F::Error: Into<io::Error>,
+    F::Future: Send,
+    FResBody: Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<BoxError>,
+{
+    let Some((mut fallback, request)) = fallback_and_request else {
+        return Ok(not_found());
+    };
+    // the fallback, like a `ServeFile`, may have a future as large as the one of the `ServeDir`
+    let mut res = Box::pin(call_fallback(&mut fallback, request)).await?;
+    res.extensions_mut().insert(FallbackResponse);
+
+    Ok(res)
+}
+
+// Evaluate the conditional headers against the validators of the fallback response, the
+// `304 Not Modified` keeps its headers except the ones of the content.
+fn conditional_response(
+    res: Response<ResponseBody>,
+    conditions: Conditions,
+    not_modified_headers: NotModifiedHeaders,
+    path: &str,
+) -> Response<ResponseBody> {
+    let headers = res.headers();
+    let last_modified = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(LastModified::from_header_value);
+    let etag = headers.get(header::ETAG).map(ETag::from_header_value);
+    let Some(status) = conditions.evaluate(last_modified.as_ref(), etag.as_ref()) else {
+        return res;
+    };
+
+    let (mut parts, _) = res.into_parts();
+    parts.status = status;
+    if status == StatusCode::PRECONDITION_FAILED {
+        Outcome::PreconditionFailed.report(path);
+        parts.headers.clear();
+    } else {
+        for name in [
+            header::CONTENT_TYPE,
+            header::CONTENT_LENGTH,
+            header::CONTENT_RANGE,
+            header::CONTENT_ENCODING,
+            header::TRANSFER_ENCODING,
+        ] {
+            parts.headers.remove(name);
+        }
+        not_modified_headers.apply(&mut parts.headers);
+    }
+
+    Response::from_parts(parts, empty_body())
+}
+
+// call the fallback or respond `404`, the response is marked with the not found `ServedFile`
+async fn file_not_found<F, B, FResBody>(
+    fallback_and_request: Option<(F, Request<B>)>,
+    requested_path: PathBuf,
+) -> io::Result<Response<ResponseBody>>
+where
+    F: Service<Request<B>, Response = Response<FResBody>> + Clone,
+    F::Error: Into<io::Error>,
+    F::Future: Send,
+    FResBody: Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<BoxError>,
+{
+    let mut res = fallback_or_not_found(fallback_and_request).await?;
+    res.extensions_mut().insert(ServedFile::new(
+        requested_path,
+        None,
+        StatusReason::NotFound,
+    ));
+
+    Ok(res)
+}
+
+// the unread body is left on the connection, so it is closed
+fn body_rejected(outcome: Outcome, path: &str) -> Response<ResponseBody> {
+    outcome.report(path);
+    let mut res = response_with_status(outcome.status());
+    res.headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+
+    res
+}
+
+pub(crate) fn not_found() -> Response<ResponseBody> {
     response_with_status(StatusCode::NOT_FOUND)
 }
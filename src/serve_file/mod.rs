@@ -3,22 +3,26 @@ use std::future::Future;
 use std::io;
 use std::path::PathBuf;
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 use bytes::Bytes;
-use http::{HeaderValue, Request, Response};
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response};
 use http_body::Body;
 use mime_guess::{mime, Mime};
 use tower_service::Service;
 
-use crate::fs::single_file::SingleFileFilesystem;
 use crate::fs::Filesystem;
-use crate::ResponseBody;
+use crate::serve_dir::ServeVariant;
 use crate::{DefaultServeDirFallback, ServeDir};
+use crate::{
+    ErrorPages, IpFilter, NotFoundService, NotModifiedHeaders, QueryPolicy, ResponseBody,
+    SurrogateKey, Transform,
+};
 
 /// Service that serves a file
 #[derive(Debug, Clone)]
 pub struct ServeFile<FS, F = DefaultServeDirFallback> {
-    inner: ServeDir<SingleFileFilesystem<FS>, F>,
+    inner: ServeDir<FS, F>,
 }
 
 impl<FS> ServeFile<FS, DefaultServeDirFallback> {
@@ -37,7 +41,7 @@ impl<FS> ServeFile<FS, DefaultServeDirFallback> {
             });
 
         ServeFile {
-            inner: ServeDir::new_single_file(SingleFileFilesystem::new(path, filesystem), mime),
+            inner: ServeDir::new_single_file(filesystem, path, mime),
         }
     }
 
@@ -50,15 +54,228 @@ impl<FS> ServeFile<FS, DefaultServeDirFallback> {
         let mime = HeaderValue::from_str(mime.as_ref()).expect("mime isn't a valid header value");
 
         ServeFile {
-            inner: ServeDir::new_single_file(
-                SingleFileFilesystem::new(path.into(), filesystem),
-                mime,
-            ),
+            inner: ServeDir::new_single_file(filesystem, path.into(), mime),
         }
     }
 }
 
+impl<FS: Filesystem, F> ServeFile<FS, F> {
+    /// Check the file exists in the filesystem, so a file left out, like an asset missing from
+    /// an embedded directory, fails the startup instead of being not found in the production.
+    pub async fn verify(&self) -> io::Result<()> {
+        let ServeVariant::SingleFile { file_path, .. } = &self.inner.variant else {
+            unreachable!("ServeFile always serves a single file");
+        };
+
+        self.inner
+            .filesystem
+            .metadata(file_path)
+            .await
+            .map(drop)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", file_path.display())))
+    }
+}
+
+impl<FS: Filesystem + Clone, F> ServeFile<FS, F> {
+    /// Open the file and read its metadata before serving, see [`ServeDir::warm`].
+    pub async fn warm(&self) -> io::Result<()> {
+        self.inner.warm([""]).await.map(drop)
+    }
+}
+
 impl<FS, F> ServeFile<FS, F> {
+    /// Only serve the file for the request path, like `/favicon.ico`, the other request paths are
+    /// not found.
+    ///
+    /// The paths are compared after the percent-decoding, ignoring the `.` segments and the
+    /// trailing slash.
+    ///
+    /// Defaults to serve the file for any request path, like the nginx `alias`.
+    pub fn request_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        if let ServeVariant::SingleFile { request_path, .. } = &mut self.inner.variant {
+            *request_path = Some(path.into());
+        }
+
+        self
+    }
+
+    /// Add a header to the responses of the file, like `Cache-Control`, see
+    /// [`ServeDir::response_header`].
+    pub fn response_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.inner = self.inner.response_header(name, value);
+        self
+    }
+
+    /// Respond with `403 Forbidden` when the client address is rejected by the [`IpFilter`], see
+    /// [`ServeDir::ip_filter`].
+    pub fn ip_filter(mut self, ip_filter: IpFilter) -> Self {
+        self.inner = self.inner.ip_filter(ip_filter);
+        self
+    }
+
+    /// Pass a copy of every request without the body to the `sink`, see [`ServeDir::mirror`].
+    pub fn mirror<M>(mut self, sink: M) -> Self
+    where
+        M: Fn(Request<()>) + Send + Sync + 'static,
+    {
+        self.inner = self.inner.mirror(sink);
+        self
+    }
+
+    /// Echo or generate the `X-Request-Id` of the request, see [`ServeDir::request_id`].
+    pub fn request_id(mut self, request_id: bool) -> Self {
+        self.inner = self.inner.request_id(request_id);
+        self
+    }
+
+    /// Decode the `+` in the request path as a space, see [`ServeDir::decode_plus_as_space`].
+    pub fn decode_plus_as_space(mut self, decode: bool) -> Self {
+        self.inner = self.inner.decode_plus_as_space(decode);
+        self
+    }
+
+    /// Redirect the paths which aren't in the canonical percent-encoded form, see
+    /// [`ServeDir::canonical_redirects`].
+    pub fn canonical_redirects(mut self, redirect: bool) -> Self {
+        self.inner = self.inner.canonical_redirects(redirect);
+        self
+    }
+
+    /// Set what to do with the query string of the requests, see [`ServeDir::query_policy`].
+    pub fn query_policy(mut self, policy: QueryPolicy) -> Self {
+        self.inner = self.inner.query_policy(policy);
+        self
+    }
+
+    /// Make the headers of the same response byte-identical, see [`ServeDir::deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.inner = self.inner.deterministic(deterministic);
+        self
+    }
+
+    /// Set or remove the `Server` header, see [`ServeDir::server_header`].
+    pub fn server_header(mut self, value: Option<HeaderValue>) -> Self {
+        self.inner = self.inner.server_header(value);
+        self
+    }
+
+    /// Change the headers of all the responses, see [`ServeDir::map_response_headers`].
+    pub fn map_response_headers<M>(mut self, hook: M) -> Self
+    where
+        M: Fn(&mut HeaderMap) + Send + Sync + 'static,
+    {
+        self.inner = self.inner.map_response_headers(hook);
+        self
+    }
+
+    /// Respond with the bodies of the error templates, see [`ServeDir::error_pages`].
+    pub fn error_pages(mut self, pages: ErrorPages) -> Self {
+        self.inner = self.inner.error_pages(pages);
+        self
+    }
+
+    /// Don't give the file a `Last-Modified` while it is modified within the `min_age`, see
+    /// [`ServeDir::last_modified_min_age`].
+    pub fn last_modified_min_age(mut self, min_age: Duration) -> Self {
+        self.inner = self.inner.last_modified_min_age(min_age);
+        self
+    }
+
+    /// Evaluate the conditional headers against the successful fallback responses too, see
+    /// [`ServeDir::conditional_fallback`].
+    pub fn conditional_fallback(mut self, conditional: bool) -> Self {
+        self.inner = self.inner.conditional_fallback(conditional);
+        self
+    }
+
+    /// Set the headers of the `304 Not Modified` responses, see
+    /// [`ServeDir::not_modified_headers`].
+    pub fn not_modified_headers(mut self, headers: NotModifiedHeaders) -> Self {
+        self.inner = self.inner.not_modified_headers(headers);
+        self
+    }
+
+    /// Set the clock used by the conditional requests, see [`ServeDir::clock`].
+    pub fn clock<C>(mut self, now: C) -> Self
+    where
+        C: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        self.inner = self.inner.clock(now);
+        self
+    }
+
+    /// Abort the file response body when the client doesn't consume any data within the
+    /// `timeout`, see [`ServeDir::body_stall_timeout`].
+    #[cfg(feature = "timeout")]
+    pub fn body_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.inner = self.inner.body_stall_timeout(timeout);
+        self
+    }
+
+    /// Send `Content-Encoding: identity` on every response whose content isn't encoded, see
+    /// [`ServeDir::identity_content_encoding`].
+    pub fn identity_content_encoding(mut self, send: bool) -> Self {
+        self.inner = self.inner.identity_content_encoding(send);
+        self
+    }
+
+    /// Add the `Surrogate-Key` header to the file responses, see [`ServeDir::surrogate_key`].
+    pub fn surrogate_key(mut self, key: SurrogateKey) -> Self {
+        self.inner = self.inner.surrogate_key(key);
+        self
+    }
+
+    /// Set the `Surrogate-Control` header of the file responses, see
+    /// [`ServeDir::surrogate_control`].
+    pub fn surrogate_control(mut self, value: HeaderValue) -> Self {
+        self.inner = self.inner.surrogate_control(value);
+        self
+    }
+
+    /// Answer the `Range: bytes=0-` requests of the audio and video file with up to `len` bytes,
+    /// see [`ServeDir::media_first_range`].
+    pub fn media_first_range(mut self, len: u64) -> Self {
+        self.inner = self.inner.media_first_range(len);
+        self
+    }
+
+    /// Serve the representations produced by the [`Transform`] for the requests with its query
+    /// parameters, see [`ServeDir::transform`].
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.inner = self.inner.transform(transform);
+        self
+    }
+
+    /// Remember the precompressed variants found missing for up to `ttl`, see
+    /// [`ServeDir::precompressed_probe_cache`].
+    pub fn precompressed_probe_cache(mut self, ttl: Duration) -> Self {
+        self.inner = self.inner.precompressed_probe_cache(ttl);
+        self
+    }
+
+    /// Look up all the precompressed variants of the file at once, see
+    /// [`ServeDir::concurrent_probes`].
+    pub fn concurrent_probes(mut self, enable: bool) -> Self {
+        self.inner = self.inner.concurrent_probes(enable);
+        self
+    }
+
+    /// Set the fallback service, it is called when the request path doesn't match the
+    /// [`ServeFile::request_path`] or the file doesn't exist, see [`ServeDir::fallback`].
+    pub fn fallback<F2>(self, new_fallback: F2) -> ServeFile<FS, F2> {
+        ServeFile {
+            inner: self.inner.fallback(new_fallback),
+        }
+    }
+
+    /// Set the fallback service and override the fallback's status code to `404 Not Found`, see
+    /// [`ServeDir::not_found_service`].
+    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeFile<FS, NotFoundService<F2>> {
+        ServeFile {
+            inner: self.inner.not_found_service(new_fallback),
+        }
+    }
+
     /// Set a specific read buffer chunk size.
     ///
     /// The default capacity is 64kb.
@@ -124,6 +341,7 @@ impl<FS, F> ServeFile<FS, F> {
 
 impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeFile<FS, F>
 where
+    ReqBody: Body,
     F: Service<Request<ReqBody>, Response = Response<FResBody>> + Clone,
     F::Error: Into<io::Error>,
     F::Future: Send,
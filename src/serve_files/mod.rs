@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::{Request, Response};
+use http_body::Body;
+use mime_guess::Mime;
+use percent_encoding::percent_decode;
+use tower_service::Service;
+
+use crate::fs::Filesystem;
+use crate::serve_dir::{call_fallback, not_found};
+use crate::{DefaultServeDirFallback, NotFoundService, ResponseBody, ServeFile};
+
+/// Service that serves a fixed set of files by their request paths, a middle ground between
+/// [`ServeFile`] and [`ServeDir`](crate::ServeDir) for a handful of known assets.
+///
+/// The files are looked up by the exact request path, there are no directory semantics, like
+/// the `index.html` or the redirect.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::ServeFiles;
+/// use http_dir::fs::disk::DiskFilesystem;
+///
+/// let filesystem = DiskFilesystem::from("assets");
+///
+/// let service = ServeFiles::new()
+///     .file("/favicon.ico", "icons/favicon.ico", filesystem.clone())
+///     .file("/robots.txt", "robots.txt", filesystem);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServeFiles<FS, F = DefaultServeDirFallback> {
+    files: Arc<HashMap<String, ServeFile<FS>>>,
+    fallback: Option<F>,
+}
+
+impl<FS> ServeFiles<FS, DefaultServeDirFallback> {
+    /// Create a new [`ServeFiles`] without any file.
+    pub fn new() -> Self {
+        Self {
+            files: Default::default(),
+            fallback: None,
+        }
+    }
+}
+
+impl<FS> Default for ServeFiles<FS, DefaultServeDirFallback> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<FS: Clone, F> ServeFiles<FS, F> {
+    /// Serve the file at the `path` of the `filesystem` for the `request_path`.
+    ///
+    /// The Content-Type will be guessed from the file extension.
+    pub fn file<R: AsRef<str>, P: Into<PathBuf>>(
+        self,
+        request_path: R,
+        path: P,
+        filesystem: FS,
+    ) -> Self {
+        self.service(request_path, ServeFile::new(path, filesystem))
+    }
+
+    /// Serve the file at the `path` of the `filesystem` for the `request_path` with a specific
+    /// mime type.
+    ///
+    /// # Panics
+    /// Will panic if the mime type isn’t a valid
+    /// [header value](https://docs.rs/http/latest/http/header/struct.HeaderValue.html).
+    pub fn file_with_mime<R: AsRef<str>, P: Into<PathBuf>>(
+        self,
+        request_path: R,
+        path: P,
+        mime: &Mime,
+        filesystem: FS,
+    ) -> Self {
+        self.service(
+            request_path,
+            ServeFile::new_with_mime(path, mime, filesystem),
+        )
+    }
+
+    /// Serve the `request_path` with a configured [`ServeFile`], like the one with
+    /// [`ServeFile::precompressed_br`], the later one replaces the former one for the same path.
+    pub fn service<R: AsRef<str>>(mut self, request_path: R, file: ServeFile<FS>) -> Self {
+        Arc::make_mut(&mut self.files).insert(normalize_request_path(request_path.as_ref()), file);
+        self
+    }
+
+    /// Set the fallback service.
+    ///
+    /// This service will be called if there is no file for the path of the request.
+    pub fn fallback<F2>(self, new_fallback: F2) -> ServeFiles<FS, F2> {
+        ServeFiles {
+            files: self.files,
+            fallback: Some(new_fallback),
+        }
+    }
+
+    /// Set the fallback service and override the fallback's status code to `404 Not Found`, see
+    /// [`NotFoundService`].
+    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeFiles<FS, NotFoundService<F2>> {
+        self.fallback(NotFoundService::new(new_fallback))
+    }
+}
+
+// The request paths are compared without the leading and trailing slash and the `.` segments.
+fn normalize_request_path(path: &str) -> String {
+    path.split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeFiles<FS, F>
+where
+    ReqBody: Body + Send + 'static,
+    F: Service<Request<ReqBody>, Response = Response<FResBody>> + Clone,
+    F::Error: Into<io::Error>,
+    F::Future: Send,
+    FResBody: Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<Box<dyn Error + Send + Sync>>,
+    FS: Filesystem + Clone + Send + Sync + 'static,
+    FS::File: 'static,
+{
+    type Response = Response<ResponseBody>;
+    type Error = io::Error;
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.poll_ready(cx).map_err(Into::into)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let file = percent_decode(req.uri().path().as_bytes())
+            .decode_utf8()
+            .ok()
+            .and_then(|path| self.files.get(&normalize_request_path(&path)))
+            .cloned();
+
+        // get the ready fallback and leave a non-ready clone in its place
+        let fallback = match file {
+            Some(_) => None,
+            None => self.fallback.as_mut().map(|fallback| {
+                let clone = fallback.clone();
+                std::mem::replace(fallback, clone)
+            }),
+        };
+
+        async move {
+            match (file, fallback) {
+                // the `ServeFile` without fallback is always ready
+                (Some(mut file), _) => file.call(req).await,
+                (None, Some(mut fallback)) => call_fallback(&mut fallback, req).await,
+                (None, None) => Ok(not_found()),
+            }
+        }
+    }
+}
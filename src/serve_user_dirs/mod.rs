@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::uri::PathAndQuery;
+use http::{header, HeaderMap, HeaderValue, Request, Response, StatusCode, Uri};
+use percent_encoding::percent_decode_str;
+use tower_service::Service;
+
+use crate::serve_dir::{not_found, response_with_status};
+use crate::{DefaultServeDirFallback, ResponseBody, RootOverride, ServeDir};
+
+const USER_PLACEHOLDER: &str = "{user}";
+const MAX_USER_LEN: usize = 32;
+
+/// Service that serves the directory of every user under `/~user/`, like the `mod_userdir` of
+/// Apache, `/~alice/notes.txt` is served from `/home/alice/public_html/notes.txt` with the
+/// `/home/{user}/public_html` template.
+///
+/// The requests are served by the [`ServeDir`] with a [`RootOverride`] of the user directory, so
+/// all its options apply, and a user can't reach the files outside of the own directory, the
+/// [`DiskFilesystem`](crate::fs::disk::DiskFilesystem) doesn't follow the symlinks leaving it or
+/// linking it to the files of another owner. The filesystem of the [`ServeDir`] must support the
+/// [`Filesystem::reroot`], like the [`DiskFilesystem`](crate::fs::disk::DiskFilesystem), its own
+/// root is never served.
+///
+/// The user names are the ASCII letters, digits, `_`, `-` and `.`, up to 32 characters, not
+/// starting with `-` or `.`, the `root` user is never served. The requests of the other paths
+/// and the invalid or not allowed users get `404 Not Found`, and `/~alice` is redirected to
+/// `/~alice/`. The `Location` and `Content-Location` of the responses are prefixed with `/~user`.
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::{ServeDir, ServeUserDirs};
+/// use http_dir::fs::disk::DiskFilesystem;
+///
+/// let service = ServeUserDirs::new(
+///     "/home/{user}/public_html",
+///     ServeDir::new(DiskFilesystem::from("/home")).hide_dot_files(true),
+/// )
+/// .allow_users(["alice", "bob"]);
+/// ```
+///
+/// [`Filesystem::reroot`]: crate::fs::Filesystem::reroot
+#[derive(Debug, Clone)]
+pub struct ServeUserDirs<FS, F = DefaultServeDirFallback> {
+    template: Arc<str>,
+    users: Option<Arc<HashSet<String>>>,
+    inner: ServeDir<FS, F>,
+}
+
+impl<FS, F> ServeUserDirs<FS, F> {
+    /// Create a new [`ServeUserDirs`], the directory of a user is the `template` with the
+    /// `{user}` replaced by the user name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `template` has no `{user}`.
+    pub fn new(template: &str, serve_dir: ServeDir<FS, F>) -> Self {
+        assert!(
+            template.contains(USER_PLACEHOLDER),
+            "user dir template {template} has no {USER_PLACEHOLDER}"
+        );
+
+        Self {
+            template: template.into(),
+            users: None,
+            inner: serve_dir,
+        }
+    }
+
+    /// Only serve the directories of the `users`.
+    ///
+    /// Defaults to any valid user except `root`.
+    pub fn allow_users<I, S>(mut self, users: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.users = Some(Arc::new(users.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    // split the `/~user` off the request, or respond to it directly
+    fn route<B>(&self, mut req: Request<B>) -> Route<B> {
+        let path = req.uri().path().trim_start_matches('/');
+        let (segment, rest) = match path.split_once('/') {
+            Some((segment, rest)) => (segment, Some(rest)),
+            None => (path, None),
+        };
+        let user = percent_decode_str(segment)
+            .decode_utf8()
+            .ok()
+            .and_then(|segment| Some(segment.strip_prefix('~')?.to_string()))
+            .filter(|user| self.is_allowed(user));
+        let Some(user) = user else {
+            return Route::Respond(not_found());
+        };
+        let prefix = format!("/~{user}");
+
+        let Some(rest) = rest else {
+            let location = match req.uri().query() {
+                None => format!("{prefix}/"),
+                Some(query) => format!("{prefix}/?{query}"),
+            };
+            let mut res = response_with_status(StatusCode::TEMPORARY_REDIRECT);
+            res.headers_mut().insert(
+                header::LOCATION,
+                HeaderValue::try_from(location).expect("the user path is a valid header value"),
+            );
+
+            return Route::Respond(res);
+        };
+
+        let path_and_query = match req.uri().query() {
+            None => format!("/{rest}"),
+            Some(query) => format!("/{rest}?{query}"),
+        };
+        let mut parts = req.uri().clone().into_parts();
+        parts.path_and_query = Some(
+            PathAndQuery::try_from(path_and_query)
+                .expect("the rest of a valid path is a valid path"),
+        );
+        *req.uri_mut() = Uri::from_parts(parts).expect("the URI without the user is a valid URI");
+
+        let root = PathBuf::from(self.template.replace(USER_PLACEHOLDER, &user));
+        req.extensions_mut().insert(RootOverride(root));
+
+        Route::User { prefix, req }
+    }
+
+    fn is_allowed(&self, user: &str) -> bool {
+        let valid = !user.is_empty()
+            && user.len() <= MAX_USER_LEN
+            && !user.starts_with(['-', '.'])
+            && user
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'));
+
+        valid
+            && user != "root"
+            && self
+                .users
+                .as_ref()
+                .map_or(true, |users| users.contains(user))
+    }
+}
+
+enum Route<B> {
+    User { prefix: String, req: Request<B> },
+    Respond(Response<ResponseBody>),
+}
+
+// the paths of the `ServeDir` are relative to the user directory
+fn prefix_locations(headers: &mut HeaderMap, prefix: &str) {
+    for name in [header::LOCATION, header::CONTENT_LOCATION] {
+        let Some(location) = headers.get(&name).and_then(|value| value.to_str().ok()) else {
+            continue;
+        };
+
+        let prefixed = if location.starts_with('/') && !location.starts_with("//") {
+            format!("{prefix}{location}")
+        } else {
+            match location.parse::<Uri>() {
+                Ok(uri) if uri.scheme().is_some() => {
+                    let mut parts = uri.into_parts();
+                    let path_and_query = parts.path_and_query.as_ref().map_or("/", |p| p.as_str());
+                    parts.path_and_query =
+                        PathAndQuery::try_from(format!("{prefix}{path_and_query}")).ok();
+                    match Uri::from_parts(parts) {
+                        Ok(uri) => uri.to_string(),
+                        Err(_) => continue,
+                    }
+                }
+                // the relative ones are resolved against the URL with the prefix
+                _ => continue,
+            }
+        };
+
+        if let Ok(value) = HeaderValue::try_from(prefixed) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+impl<ReqBody, FS, F> Service<Request<ReqBody>> for ServeUserDirs<FS, F>
+where
+    ServeDir<FS, F>:
+        Service<Request<ReqBody>, Response = Response<ResponseBody>, Error = io::Error>,
+{
+    type Response = Response<ResponseBody>;
+    type Error = io::Error;
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let routed = match self.route(req) {
+            Route::User { prefix, req } => Ok((prefix, self.inner.call(req))),
+            Route::Respond(res) => Err(res),
+        };
+
+        async move {
+            match routed {
+                Err(res) => Ok(res),
+                Ok((prefix, future)) => {
+                    let mut res = future.await?;
+                    prefix_locations(res.headers_mut(), &prefix);
+
+                    Ok(res)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! HTTP/3 over QUIC, the requests and the responses are converted between the `http` 0.2 types
+//! of the service and the `http` 1 types of `h3`
+
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use futures_util::future::{select, Either};
+use h3::server::RequestResolver;
+use http::{header, HeaderMap, Request, Response};
+use http_body::Body as HttpBody;
+use hyper::Body;
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::rustls::ServerConfig;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower_service::Service;
+
+type H3Connection = h3_quinn::Connection;
+
+// the connection-specific headers are forbidden in HTTP/3
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub(super) async fn serve<S, B>(
+    addr: SocketAddr,
+    config: Arc<ServerConfig>,
+    service: S,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    S::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let config = QuicServerConfig::try_from(config)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let endpoint =
+        quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(config)), addr)?;
+
+    // the receivers see an error when the sender is dropped
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let mut connections = JoinSet::new();
+    let mut shutdown = pin!(shutdown);
+    loop {
+        let incoming = match select(pin!(endpoint.accept()), shutdown.as_mut()).await {
+            Either::Left((Some(incoming), _)) => incoming,
+            _ => break,
+        };
+
+        connections.spawn(serve_connection(
+            incoming,
+            service.clone(),
+            shutdown_rx.clone(),
+        ));
+    }
+
+    // reject the new connections and wait for the in-flight requests
+    endpoint.set_server_config(None);
+    drop(shutdown_tx);
+    while connections.join_next().await.is_some() {}
+
+    endpoint.close(0u32.into(), b"");
+    endpoint.wait_idle().await;
+
+    Ok(())
+}
+
+async fn serve_connection<S, B>(
+    incoming: quinn::Incoming,
+    service: S,
+    mut shutdown: watch::Receiver<()>,
+) where
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    S::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let Ok(connection) = incoming.await else {
+        return;
+    };
+    let remote = connection.remote_address();
+    let Ok(mut connection) =
+        h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(connection)).await
+    else {
+        return;
+    };
+
+    let mut requests = JoinSet::new();
+    let shutdown = loop {
+        let resolver = match select(pin!(connection.accept()), pin!(shutdown.changed())).await {
+            Either::Left((Ok(Some(resolver)), _)) => resolver,
+            // the connection is closed
+            Either::Left(_) => break false,
+            Either::Right(_) => break true,
+        };
+
+        requests.spawn(serve_request(resolver, service.clone(), remote));
+    };
+
+    if shutdown {
+        // send GOAWAY, the accepted requests are still served
+        let _ = connection.shutdown(0).await;
+    }
+
+    // the requests are aborted when the `JoinSet` is dropped
+    while requests.join_next().await.is_some() {}
+}
+
+async fn serve_request<S, B>(
+    resolver: RequestResolver<H3Connection, Bytes>,
+    mut service: S,
+    remote: SocketAddr,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let (req, stream) = resolver.resolve_request().await?;
+    let (mut send, mut recv) = stream.split();
+
+    // the body of the requests without any data, like most `GET`, is ended, so the
+    // `RequestBodyPolicy::Reject` sees it is empty
+    let body = match recv.recv_data().await {
+        Ok(Some(mut first)) => {
+            let (mut body_tx, body) = Body::channel();
+            let first = first.copy_to_bytes(first.remaining());
+            tokio::spawn(async move {
+                if body_tx.send_data(first).await.is_err() {
+                    return;
+                }
+                while let Ok(Some(mut data)) = recv.recv_data().await {
+                    let data = data.copy_to_bytes(data.remaining());
+                    if body_tx.send_data(data).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            body
+        }
+        Ok(None) | Err(_) => Body::empty(),
+    };
+
+    let mut req = to_request(req, body)?;
+    req.extensions_mut().insert(remote);
+
+    futures_util::future::poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(Into::into)?;
+    let res = service.call(req).await.map_err(Into::into)?;
+    let (parts, body) = res.into_parts();
+
+    let mut builder = http1::Response::builder().status(parts.status.as_u16());
+    for (name, value) in headers(&parts.headers) {
+        builder = builder.header(name, value);
+    }
+    send.send_response(builder.body(())?).await?;
+
+    let mut body = pin!(body);
+    loop {
+        // the body errors may not be `Send`, so they can't be held across the awaits
+        let data = match body.data().await {
+            None => break,
+            Some(Ok(mut data)) => data.copy_to_bytes(data.remaining()),
+            Some(Err(err)) => return Err(err.into()),
+        };
+        send.send_data(data).await?;
+    }
+
+    if let Some(trailers) = body.trailers().await.map_err(Into::into)? {
+        let mut h3_trailers = http1::HeaderMap::new();
+        for (name, value) in headers(&trailers) {
+            h3_trailers.append(
+                http1::HeaderName::from_bytes(name.as_bytes())?,
+                http1::HeaderValue::from_bytes(value)?,
+            );
+        }
+        send.send_trailers(h3_trailers).await?;
+    }
+
+    send.finish().await?;
+
+    Ok(())
+}
+
+fn to_request(
+    req: http1::Request<()>,
+    body: Body,
+) -> Result<Request<Body>, Box<dyn Error + Send + Sync>> {
+    let (parts, ()) = req.into_parts();
+    let mut builder = Request::builder()
+        .method(parts.method.as_str())
+        .uri(parts.uri.to_string())
+        .version(http::Version::HTTP_3);
+
+    // the `Host` header is replaced by the `:authority` pseudo header
+    if let Some(authority) = parts.uri.authority() {
+        if !parts.headers.contains_key(http1::header::HOST) {
+            builder = builder.header(header::HOST, authority.as_str());
+        }
+    }
+    for (name, value) in &parts.headers {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+
+    Ok(builder.body(body)?)
+}
+
+fn headers(headers: &HeaderMap) -> impl Iterator<Item = (&str, &[u8])> {
+    headers
+        .iter()
+        .filter(|(name, _)| !CONNECTION_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| (name.as_str(), value.as_bytes()))
+}
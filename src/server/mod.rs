@@ -0,0 +1,533 @@
+//! Run a service like the [`ServeDir`](crate::ServeDir) on an address, without the `hyper`
+//! boilerplate
+
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(feature = "server-tls")]
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "server-tls")]
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[cfg(feature = "server-tls")]
+use futures_util::future::ready;
+#[cfg(unix)]
+use futures_util::future::Either;
+#[cfg(feature = "server-h3")]
+use futures_util::FutureExt;
+use futures_util::{stream, Stream, StreamExt};
+use http::{Request, Response};
+use http_body::Body as HttpBody;
+use hyper::service::make_service_fn;
+use hyper::Body;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+#[cfg(feature = "server-tls")]
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+#[cfg(feature = "server-tls")]
+use tokio_rustls::TlsAcceptor;
+use tower_service::Service;
+
+#[cfg(feature = "server-h3")]
+mod http3;
+
+// the clients can't hold the handshake slots forever
+#[cfg(feature = "server-tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(feature = "server-tls")]
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// An HTTP/1.1 and HTTP/2 server, optionally terminating TLS and serving HTTP/3
+///
+/// HTTP/2 is negotiated by ALPN over TLS, or used with the prior knowledge (h2c) over plain TCP.
+///
+/// The client address is inserted into the request extensions as a [`SocketAddr`], so the
+/// [`IpFilter`](crate::IpFilter) and the [`RangeGuard`](crate::RangeGuard) work out of the box.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use http_dir::ServeDir;
+/// use http_dir::fs::disk::DiskFilesystem;
+/// use http_dir::server::Server;
+///
+/// # async {
+/// let service = ServeDir::new(DiskFilesystem::from("assets"));
+///
+/// Server::bind(([127, 0, 0, 1], 3000).into())
+///     .serve(service, async {
+///         tokio::signal::ctrl_c().await.unwrap();
+///     })
+///     .await
+///     .expect("server error");
+/// # };
+/// ```
+#[derive(Debug, Clone)]
+pub struct Server {
+    listen: Listen,
+    #[cfg(feature = "server-tls")]
+    tls: Option<Arc<ServerConfig>>,
+    title_case_headers: bool,
+    #[cfg(feature = "server-h3")]
+    http3: bool,
+    #[cfg(feature = "server-h3")]
+    http3_tls: Option<Arc<quinn::rustls::ServerConfig>>,
+}
+
+#[derive(Debug, Clone)]
+enum Listen {
+    Addr(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    #[cfg(unix)]
+    Systemd,
+}
+
+impl Server {
+    /// Create a new [`Server`] listening on the `addr`.
+    pub fn bind(addr: SocketAddr) -> Self {
+        Self::new(Listen::Addr(addr))
+    }
+
+    /// Create a new [`Server`] listening on the Unix socket `path`, like the upstream of a nginx
+    /// or caddy reverse proxy.
+    ///
+    /// A socket left on the `path` is replaced, and the socket is removed when the server stops.
+    /// The requests have no client address, the proxy should pass it by the `X-Forwarded-For`
+    /// header instead.
+    #[cfg(unix)]
+    pub fn bind_unix(path: impl Into<PathBuf>) -> Self {
+        Self::new(Listen::Unix(path.into()))
+    }
+
+    /// Create a new [`Server`] listening on the first socket passed by the systemd socket
+    /// activation, a TCP or a Unix one.
+    ///
+    /// [`serve`](Server::serve) returns a [`NotFound`](io::ErrorKind::NotFound) error if the
+    /// process isn't activated by systemd, and the socket can only be served once.
+    #[cfg(unix)]
+    pub fn systemd() -> Self {
+        Self::new(Listen::Systemd)
+    }
+
+    fn new(listen: Listen) -> Self {
+        Self {
+            listen,
+            #[cfg(feature = "server-tls")]
+            tls: None,
+            title_case_headers: false,
+            #[cfg(feature = "server-h3")]
+            http3: false,
+            #[cfg(feature = "server-h3")]
+            http3_tls: None,
+        }
+    }
+
+    /// Terminate TLS with the rustls `config`, its `alpn_protocols` should contain `h2` to enable
+    /// HTTP/2.
+    #[cfg(feature = "server-tls")]
+    pub fn tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Terminate TLS with the PEM encoded certificate chain and private key files, the key can be
+    /// a PKCS #8, PKCS #1 or SEC1 one. Both HTTP/2 and HTTP/1.1 are offered by ALPN.
+    ///
+    /// The files are also used by the [`http3`](Server::http3) endpoint.
+    #[cfg(feature = "server-tls")]
+    pub fn tls_pem_files(
+        #[cfg_attr(not(feature = "server-h3"), allow(unused_mut))] mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let invalid_data =
+            |err: Box<dyn Error + Send + Sync>| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        let cert_chain =
+            rustls_pemfile::certs(&mut io::BufReader::new(std::fs::File::open(cert_path)?))?;
+
+        let mut key_reader = io::BufReader::new(std::fs::File::open(key_path)?);
+        let key = loop {
+            match rustls_pemfile::read_one(&mut key_reader)? {
+                None => return Err(invalid_data("no private key found".into())),
+                Some(
+                    rustls_pemfile::Item::PKCS8Key(key)
+                    | rustls_pemfile::Item::RSAKey(key)
+                    | rustls_pemfile::Item::ECKey(key),
+                ) => break key,
+                Some(_) => {}
+            }
+        };
+
+        #[cfg(feature = "server-h3")]
+        {
+            use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+            let provider = Arc::new(quinn::rustls::crypto::ring::default_provider());
+            let mut config = quinn::rustls::ServerConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[&quinn::rustls::version::TLS13])
+                .and_then(|builder| {
+                    builder.with_no_client_auth().with_single_cert(
+                        cert_chain
+                            .iter()
+                            .cloned()
+                            .map(CertificateDer::from)
+                            .collect(),
+                        PrivateKeyDer::try_from(key.clone())
+                            .map_err(|err| quinn::rustls::Error::General(err.to_string()))?,
+                    )
+                })
+                .map_err(|err| invalid_data(err.into()))?;
+            config.alpn_protocols = vec![b"h3".to_vec()];
+            self.http3_tls = Some(Arc::new(config));
+        }
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                cert_chain.into_iter().map(Certificate).collect(),
+                PrivateKey(key),
+            )
+            .map_err(|err| invalid_data(err.into()))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(self.tls(Arc::new(config)))
+    }
+
+    /// Write the header names of the HTTP/1 responses in the title case, like `Content-Type`, for
+    /// the legacy clients matching the names case-sensitively. The HTTP/2 and HTTP/3 names are
+    /// always lowercase.
+    ///
+    /// Defaults to `false`, the names are written lowercase.
+    pub fn http1_title_case_headers(mut self, title_case: bool) -> Self {
+        self.title_case_headers = title_case;
+        self
+    }
+
+    /// Also serve HTTP/3 over QUIC on the same UDP port, the TLS must be set by
+    /// [`tls_pem_files`](Server::tls_pem_files).
+    ///
+    /// The clients find the HTTP/3 endpoint by the `Alt-Svc` header, like `h3=":443"`, which can
+    /// be added by [`ServeDir::response_header`](crate::ServeDir::response_header).
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "server-h3")]
+    pub fn http3(mut self, http3: bool) -> Self {
+        self.http3 = http3;
+        self
+    }
+
+    /// Serve the `service` until the `shutdown` future completes, then wait for the in-flight
+    /// requests to finish.
+    pub async fn serve<S, B>(self, service: S, shutdown: impl Future<Output = ()>) -> io::Result<()>
+    where
+        S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        S::Future: Send + 'static,
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        #[cfg(feature = "server-h3")]
+        if self.http3 {
+            let config = self.http3_tls.clone().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "HTTP/3 requires the TLS set by the tls_pem_files",
+                )
+            })?;
+            let Listen::Addr(addr) = self.listen else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "HTTP/3 requires an address to listen on",
+                ));
+            };
+            let shutdown = shutdown.shared();
+            let (tcp, udp) = futures_util::future::join(
+                self.serve_tcp(service.clone(), shutdown.clone()),
+                http3::serve(addr, config, service, shutdown),
+            )
+            .await;
+
+            return tcp.and(udp);
+        }
+
+        self.serve_tcp(service, shutdown).await
+    }
+
+    async fn serve_tcp<S, B>(self, service: S, shutdown: impl Future<Output = ()>) -> io::Result<()>
+    where
+        S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        S::Future: Send + 'static,
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        #[cfg(feature = "server-tls")]
+        let tls = self.tls;
+        #[cfg(not(feature = "server-tls"))]
+        let tls = None;
+        let title_case = self.title_case_headers;
+
+        match self.listen {
+            Listen::Addr(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                serve_incoming(incoming(listener), tls, title_case, service, shutdown).await
+            }
+
+            #[cfg(unix)]
+            Listen::Unix(path) => {
+                // a socket left by the previous process
+                if std::fs::symlink_metadata(&path)
+                    .is_ok_and(|metadata| metadata.file_type().is_socket())
+                {
+                    std::fs::remove_file(&path)?;
+                }
+
+                let listener = UnixListener::bind(&path)?;
+                let result =
+                    serve_incoming(incoming(listener), tls, title_case, service, shutdown).await;
+                let _ = std::fs::remove_file(&path);
+
+                result
+            }
+
+            #[cfg(unix)]
+            Listen::Systemd => match systemd_listener()? {
+                Either::Left(listener) => {
+                    serve_incoming(incoming(listener), tls, title_case, service, shutdown).await
+                }
+                Either::Right(listener) => {
+                    serve_incoming(incoming(listener), tls, title_case, service, shutdown).await
+                }
+            },
+        }
+    }
+}
+
+// the fds passed by systemd start from 3
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+#[cfg(unix)]
+static SYSTEMD_LISTENER_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Take the first socket passed by the systemd socket activation, a TCP or a Unix one.
+#[cfg(unix)]
+fn systemd_listener() -> io::Result<Either<TcpListener, UnixListener>> {
+    let env_number = |name| std::env::var(name).ok()?.parse::<u32>().ok();
+    let activated = env_number("LISTEN_PID") == Some(std::process::id())
+        && env_number("LISTEN_FDS").is_some_and(|fds| fds >= 1);
+    if !activated {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no socket passed by the systemd socket activation",
+        ));
+    }
+    if SYSTEMD_LISTENER_TAKEN.swap(true, Ordering::SeqCst) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "the systemd socket is already taken",
+        ));
+    }
+
+    // Safety: the fd is passed by systemd to this process, and it is only taken once
+    let fd = unsafe { OwnedFd::from_raw_fd(SD_LISTEN_FDS_START) };
+
+    // the address of a Unix socket can't be converted to a `SocketAddr`
+    let listener = std::net::TcpListener::from(fd);
+    if listener.local_addr().is_ok() {
+        listener.set_nonblocking(true)?;
+
+        return Ok(Either::Left(TcpListener::from_std(listener)?));
+    }
+
+    let listener = std::os::unix::net::UnixListener::from(OwnedFd::from(listener));
+    listener.set_nonblocking(true)?;
+
+    Ok(Either::Right(UnixListener::from_std(listener)?))
+}
+
+trait Listener: Send + Sync + 'static {
+    type Conn: Connection;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Self::Conn>>;
+}
+
+impl Listener for TcpListener {
+    type Conn = TcpStream;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Self::Conn>> {
+        self.poll_accept(cx).map_ok(|(stream, _)| {
+            let _ = stream.set_nodelay(true);
+            stream
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Self::Conn>> {
+        self.poll_accept(cx).map_ok(|(stream, _)| stream)
+    }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    fn remote_addr(&self) -> Option<SocketAddr>;
+}
+
+impl Connection for TcpStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+#[cfg(feature = "server-tls")]
+impl<IO: Connection> Connection for tokio_rustls::server::TlsStream<IO> {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().0.remote_addr()
+    }
+}
+
+fn incoming<L: Listener>(listener: L) -> impl Stream<Item = L::Conn> + Send {
+    stream::unfold(listener, |listener| async move {
+        loop {
+            match poll_fn(|cx| listener.poll_accept(cx)).await {
+                Ok(conn) => return Some((conn, listener)),
+                // like running out of the file descriptors, wait for the other connections to end
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    })
+}
+
+async fn serve_incoming<IO, S, B>(
+    incoming: impl Stream<Item = IO> + Send + 'static,
+    #[cfg(feature = "server-tls")] tls: Option<Arc<ServerConfig>>,
+    #[cfg(not(feature = "server-tls"))] _tls: Option<Infallible>,
+    title_case: bool,
+    service: S,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()>
+where
+    IO: Connection,
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    S::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    #[cfg(feature = "server-tls")]
+    if let Some(config) = tls {
+        let acceptor = TlsAcceptor::from(config);
+        let incoming = incoming
+            .map(move |stream| {
+                let acceptor = acceptor.clone();
+
+                async move {
+                    tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                        .await
+                        .ok()?
+                        .ok()
+                }
+            })
+            .buffer_unordered(MAX_PENDING_HANDSHAKES)
+            // the failed handshakes are dropped without stopping the server
+            .filter_map(ready);
+
+        return serve_connections(incoming, title_case, service, shutdown).await;
+    }
+
+    serve_connections(incoming, title_case, service, shutdown).await
+}
+
+async fn serve_connections<IO, S, B>(
+    incoming: impl Stream<Item = IO> + Send + 'static,
+    title_case: bool,
+    service: S,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()>
+where
+    IO: Connection,
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    S::Future: Send + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let make_service = make_service_fn(move |conn: &IO| {
+        let service = RemoteAddrService {
+            inner: service.clone(),
+            remote: conn.remote_addr(),
+        };
+
+        async move { Ok::<_, Infallible>(service) }
+    });
+
+    hyper::Server::builder(hyper::server::accept::from_stream(
+        incoming.map(Ok::<_, io::Error>),
+    ))
+    .http1_title_case_headers(title_case)
+    .serve(make_service)
+    .with_graceful_shutdown(shutdown)
+    .await
+    .map_err(io_error)
+}
+
+fn io_error(err: hyper::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+// insert the client address into the request extensions
+#[derive(Debug, Clone)]
+struct RemoteAddrService<S> {
+    inner: S,
+    remote: Option<SocketAddr>,
+}
+
+impl<S: Service<Request<Body>>> Service<Request<Body>> for RemoteAddrService<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if let Some(remote) = self.remote {
+            req.extensions_mut().insert(remote);
+        }
+
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::UNIX_EPOCH;
+
+use http::{header, HeaderMap, HeaderValue, Uri};
+
+use crate::fs::Metadata;
+use crate::headers::ETag;
+use crate::json;
+
+pub(crate) const STAT_CONTENT_TYPE: &str = "application/vnd.http-dir.stat+json";
+
+/// Check if the request asks for the file metadata instead of the content, by the `stat` query
+/// parameter or the `Accept` header.
+pub(crate) fn is_stat_request(uri: &Uri, headers: &HeaderMap) -> bool {
+    let by_query = uri.query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| pair.split_once('=').map_or(pair, |(key, _)| key) == "stat")
+    });
+
+    by_query
+        || headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media_range| {
+                media_range
+                    .split(';')
+                    .next()
+                    .unwrap()
+                    .trim()
+                    .eq_ignore_ascii_case(STAT_CONTENT_TYPE)
+            })
+}
+
+pub(crate) fn stat_json(metadata: &Metadata, mime: &HeaderValue) -> String {
+    let mut out = String::from("{\"size\":");
+    match metadata.len {
+        None => out.push_str("null"),
+        Some(len) => out.push_str(&len.to_string()),
+    }
+    out.push_str(",\"mtime\":");
+    match metadata
+        .modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+    {
+        None => out.push_str("null"),
+        Some(mtime) => out.push_str(&mtime.as_secs().to_string()),
+    }
+    out.push_str(",\"etag\":");
+    match ETag::new(metadata) {
+        None => out.push_str("null"),
+        Some(etag) => json::write_str(&mut out, &etag.into_string()),
+    }
+    out.push_str(",\"mime\":");
+    json::write_str(&mut out, mime.to_str().unwrap_or_default());
+    out.push('}');
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn generated_etag() {
+        let metadata = Metadata::new(Some(UNIX_EPOCH + Duration::from_micros(0x1234)), Some(0x2f));
+
+        assert_eq!(
+            stat_json(&metadata, &HeaderValue::from_static("text/plain")),
+            r#"{"size":47,"mtime":0,"etag":"\"2f-1234\"","mime":"text/plain"}"#
+        );
+    }
+}
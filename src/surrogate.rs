@@ -0,0 +1,119 @@
+use std::fmt::{Debug, Formatter};
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use http::{HeaderName, HeaderValue};
+use percent_encoding::percent_encode;
+
+use crate::open_file::SEGMENT_ENCODE_SET;
+
+pub(crate) const SURROGATE_KEY: HeaderName = HeaderName::from_static("surrogate-key");
+pub(crate) const SURROGATE_CONTROL: HeaderName = HeaderName::from_static("surrogate-control");
+
+type Keys = Arc<dyn Fn(&Path) -> Vec<String> + Send + Sync>;
+
+/// The `Surrogate-Key` of the file responses, the Fastly and Varnish style CDNs purge the cached
+/// responses by their keys, see [`ServeDir::surrogate_key`](crate::ServeDir::surrogate_key).
+///
+/// # Example
+///
+/// ```rust
+/// use http_dir::SurrogateKey;
+///
+/// // purge all the styles at once
+/// let key = SurrogateKey::from_fn(|path| {
+///     let mut keys = vec![path.display().to_string()];
+///     if path.extension().is_some_and(|extension| extension == "css") {
+///         keys.push("css".to_string());
+///     }
+///     keys
+/// });
+/// ```
+#[derive(Clone)]
+pub struct SurrogateKey(Keys);
+
+impl Debug for SurrogateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SurrogateKey").finish_non_exhaustive()
+    }
+}
+
+impl SurrogateKey {
+    /// Key the responses by the path of the requested file, like `/css/site.css`, the
+    /// precompressed variants of a file and the index of a directory share the key of the
+    /// requested path.
+    pub fn path() -> Self {
+        Self::from_fn(|path| {
+            let segments = path
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(segment) => Some(
+                        percent_encode(segment.to_string_lossy().as_bytes(), SEGMENT_ENCODE_SET)
+                            .to_string(),
+                    ),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            vec![format!("/{}", segments.join("/"))]
+        })
+    }
+
+    /// Key the responses by the keys returned from the `keys`, which is called with the path of
+    /// the requested file relative to the root.
+    ///
+    /// The keys are separated by spaces in the header, the ones containing whitespace or other
+    /// characters invalid in a header are dropped.
+    pub fn from_fn<F>(keys: F) -> Self
+    where
+        F: Fn(&Path) -> Vec<String> + Send + Sync + 'static,
+    {
+        Self(Arc::new(keys))
+    }
+
+    pub(crate) fn header_value(&self, path: &Path) -> Option<HeaderValue> {
+        let keys = (self.0)(path)
+            .into_iter()
+            .filter(|key| !key.is_empty() && key.bytes().all(|b| b.is_ascii_graphic()))
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&keys.join(" ")).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_values() {
+        let key = SurrogateKey::path();
+        assert_eq!(key.header_value(Path::new("")).unwrap(), "/");
+        assert_eq!(
+            key.header_value(Path::new("css/site.css")).unwrap(),
+            "/css/site.css"
+        );
+        assert_eq!(
+            key.header_value(Path::new("filename with space.txt"))
+                .unwrap(),
+            "/filename%20with%20space.txt"
+        );
+
+        let key = SurrogateKey::from_fn(|_| {
+            vec![
+                "a".to_string(),
+                String::new(),
+                "b c".to_string(),
+                "\u{4f60}".to_string(),
+                "d".to_string(),
+            ]
+        });
+        assert_eq!(key.header_value(Path::new("x")).unwrap(), "a d");
+
+        let key = SurrogateKey::from_fn(|_| vec!["b c".to_string()]);
+        assert!(key.header_value(Path::new("x")).is_none());
+    }
+}
@@ -1,19 +1,38 @@
 use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use brotli::BrotliDecompress;
 use bytes::Bytes;
 use flate2::bufread::{DeflateDecoder, GzDecoder};
 use http::header::ALLOW;
-use http::{header, Method, Response};
+use http::{header, HeaderMap, HeaderValue, Method, Response};
 use http::{Request, StatusCode};
 use http_body::Body as HttpBody;
 use hyper::Body;
 use include_dir::Dir;
+use mime_guess::mime;
 use tower::{service_fn, ServiceExt};
 
-use crate::fs::disk::DiskFilesystem;
+use crate::fs::blocking::{BlockingFilesystem, BlockingStorage};
+use crate::fs::disk::{DiskFile, DiskFilesystem};
+use crate::fs::generated::GeneratedFilesystem;
+use crate::fs::immutable::ImmutableLayer;
 use crate::fs::include_dir::IncludeDirFilesystem;
-use crate::{ServeDir, ServeFile};
+use crate::fs::{layer_fn, DirEntry, FileExt, Filesystem, Metadata};
+use crate::server::Server;
+use crate::watch::{FileWatcher, RELOAD_SCRIPT};
+use crate::{
+    AssetHashes, Deadline, ErrorPages, IpFilter, NotModifiedHeaders, QueryPolicy, RangeGuard,
+    RequestBodyPolicy, ResolvedPath, RootOverride, RouteManifest, SearchOptions, ServeDir,
+    ServeFile, ServeFiles, ServeUserDirs, ServedFile, StatusReason, SurrogateKey, TokenBucket,
+    Transform, Transformed,
+};
+
+mod conformance;
 
 #[tokio::test]
 async fn basic() {
@@ -43,11 +62,81 @@ async fn basic_with_index() {
 
     assert_eq!(res.status(), StatusCode::OK);
     assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+    assert_eq!(res.headers()[header::CONTENT_LOCATION], "/index.html");
 
     let body = body_into_text(res.into_body()).await;
     assert_eq!(body, "<b>HTML!</b>\n");
 }
 
+#[tokio::test]
+async fn directory_index() {
+    let dir = std::env::temp_dir().join(format!("http_dir-dir-index-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    std::fs::create_dir_all(dir.join("empty")).unwrap();
+    std::fs::write(dir.join("index.html"), "index").unwrap();
+    std::fs::write(dir.join("index.fr.html"), "index fr").unwrap();
+    std::fs::write(dir.join("docs/README.md"), "readme").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).directory_index(|request| {
+        Box::pin(async move {
+            if request.dir == Path::new("empty") {
+                return Some(PathBuf::from("../index.html"));
+            }
+
+            let french = request
+                .headers
+                .get(header::ACCEPT_LANGUAGE)
+                .is_some_and(|value| value.as_bytes().starts_with(b"fr"));
+            let entries = request.filesystem.read_dir(request.dir).await.ok()?;
+            let names = entries
+                .iter()
+                .map(|entry| entry.name.to_str().unwrap())
+                .collect::<Vec<_>>();
+
+            let candidates = if french {
+                &["index.fr.html", "index.html"][..]
+            } else {
+                &["index.html", "README.md"][..]
+            };
+            candidates
+                .iter()
+                .find(|candidate| names.contains(candidate))
+                .map(PathBuf::from)
+        })
+    });
+
+    for (uri, language, status, body) in [
+        ("/", "en", StatusCode::OK, "index"),
+        ("/", "fr-CH, fr;q=0.9", StatusCode::OK, "index fr"),
+        ("/docs/", "en", StatusCode::OK, "readme"),
+        // outside of the directory
+        ("/empty/", "en", StatusCode::NOT_FOUND, ""),
+    ] {
+        let req = Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT_LANGUAGE, language)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{uri} {language}");
+        assert_eq!(body_into_text(res.into_body()).await, body);
+    }
+
+    let req = Request::builder()
+        .uri("/docs/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/markdown");
+    assert_eq!(
+        res.extensions().get::<ResolvedPath>().unwrap().path,
+        Path::new("docs/README.md")
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[tokio::test]
 async fn head_request() {
     let svc = ServeDir::new(DiskFilesystem::from("test-files"));
@@ -106,6 +195,69 @@ async fn with_custom_chunk_size() {
     assert_eq!(body, contents);
 }
 
+#[tokio::test]
+async fn read_ahead_chunks() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).with_buf_chunk_size(4);
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let mut body = svc.oneshot(req).await.unwrap().into_body();
+
+    let mut chunks = vec![];
+    let mut remaining = 13;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        remaining -= chunk.len() as u64;
+        assert_eq!(body.size_hint().exact(), Some(remaining));
+        chunks.push(chunk);
+    }
+
+    assert_eq!(chunks, ["<b>H", "TML!", "</b>", "\n"]);
+    assert_eq!(body.size_hint().exact(), Some(0));
+}
+
+#[tokio::test]
+async fn body_size_hint() {
+    let svc = ServeDir::new(
+        GeneratedFilesystem::new(DiskFilesystem::from("test-files")).file("/empty.txt", ""),
+    );
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let body = svc.clone().oneshot(req).await.unwrap().into_body();
+    assert_eq!(body.size_hint().exact(), Some(13));
+    assert!(!body.is_end_stream());
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=3-7")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body().size_hint().exact(), Some(5));
+
+    let req = Request::builder()
+        .uri("/empty.txt")
+        .body(Body::empty())
+        .unwrap();
+    let body = svc.clone().oneshot(req).await.unwrap().into_body();
+    assert_eq!(body.size_hint().exact(), Some(0));
+    assert!(body.is_end_stream());
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let body = svc.oneshot(req).await.unwrap().into_body();
+    assert!(body.is_end_stream());
+}
+
 #[tokio::test]
 async fn precompressed_gzip() {
     let svc = ServeDir::new(DiskFilesystem::from("test-files")).precompressed_gzip();
@@ -119,12 +271,322 @@ async fn precompressed_gzip() {
 
     assert_eq!(res.headers()["content-type"], "text/plain");
     assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(res.headers()["content-location"], "/precompressed.txt.gz");
+
+    let body = res.into_body().data().await.unwrap().unwrap();
+    let mut decoder = GzDecoder::new(&body[..]);
+    let mut decompressed = String::new();
+    decoder.read_to_string(&mut decompressed).unwrap();
+    assert!(decompressed.starts_with("\"This is a test file!\""));
+}
+
+#[tokio::test]
+async fn precompressed_exclude() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .precompressed_exclude("*.zip")
+        .precompressed_exclude("precompressed.*");
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert!(res.headers().get("content-encoding").is_none());
+    assert!(res.headers().get("content-location").is_none());
+
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.starts_with("\"This is a test file!\""));
+}
+
+#[tokio::test]
+async fn precompressed_fallback() {
+    let fallback = ServeFile::new("precompressed.txt", DiskFilesystem::from("test-files"));
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .not_found_service(fallback);
+
+    let req = Request::builder()
+        .uri("/not-found")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(res.headers()["vary"], "accept-encoding");
+    // the fallback file has no URL
+    assert!(res.headers().get("content-location").is_none());
 
     let body = res.into_body().data().await.unwrap().unwrap();
     let mut decoder = GzDecoder::new(&body[..]);
     let mut decompressed = String::new();
     decoder.read_to_string(&mut decompressed).unwrap();
     assert!(decompressed.starts_with("\"This is a test file!\""));
+
+    // the identity response varies too
+    let req = Request::builder()
+        .uri("/not-found")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers()["vary"], "accept-encoding");
+
+    // the fallback's own variants take precedence
+    let fallback =
+        ServeFile::new("precompressed.txt", DiskFilesystem::from("test-files")).precompressed_br();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .not_found_service(fallback);
+
+    let req = Request::builder()
+        .uri("/not-found")
+        .header("Accept-Encoding", "gzip, br")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-encoding"], "br");
+
+    // the responses of the files without the precompressed variants don't vary
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert!(res.headers().get("vary").is_none());
+}
+
+#[tokio::test]
+async fn identity_content_encoding() {
+    let request = |method: Method, accept_encoding: &'static str| {
+        Request::builder()
+            .method(method)
+            .uri("/precompressed.txt")
+            .header("Accept-Encoding", accept_encoding)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // the header is never sent for the uncompressed content by default
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).precompressed_gzip();
+    for method in [Method::GET, Method::HEAD] {
+        for accept_encoding in ["identity", "identity, gzip;q=0.5", "br"] {
+            let res = svc
+                .clone()
+                .oneshot(request(method.clone(), accept_encoding))
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get("content-encoding").is_none());
+            assert_eq!(
+                res.extensions().get::<ResolvedPath>().unwrap().encoding,
+                None
+            );
+        }
+    }
+
+    // or always
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .identity_content_encoding(true);
+    for method in [Method::GET, Method::HEAD] {
+        for accept_encoding in ["identity", "identity, gzip;q=0.5", "br"] {
+            let res = svc
+                .clone()
+                .oneshot(request(method.clone(), accept_encoding))
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()["content-encoding"], "identity");
+            assert_eq!(
+                res.extensions().get::<ResolvedPath>().unwrap().encoding,
+                Some("identity")
+            );
+        }
+
+        // the encoded variants are unaffected
+        let res = svc.clone().oneshot(request(method, "gzip")).await.unwrap();
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+    }
+}
+
+#[tokio::test]
+async fn surrogate_headers() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .surrogate_key(SurrogateKey::path())
+        .surrogate_control(HeaderValue::from_static("max-age=86400"));
+
+    // the variant shares the key of the requested file
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(res.headers()["surrogate-key"], "/precompressed.txt");
+    assert_eq!(res.headers()["surrogate-control"], "max-age=86400");
+
+    let last_modified = res.headers()["last-modified"].clone();
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("If-Modified-Since", last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()["surrogate-key"], "/precompressed.txt");
+    assert_eq!(res.headers()["surrogate-control"], "max-age=86400");
+
+    // the missing files are left to the fallback
+    let req = Request::builder()
+        .uri("/not-found")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get("surrogate-key").is_none());
+    assert!(res.headers().get("surrogate-control").is_none());
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).surrogate_key(
+        SurrogateKey::from_fn(|path| vec!["static".to_string(), path.display().to_string()]),
+    );
+    let req = Request::builder()
+        .uri("/filename%20with%20space.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    // the key with a space is dropped
+    assert_eq!(res.headers()["surrogate-key"], "static");
+}
+
+#[tokio::test]
+async fn precompressed_index_html() {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+
+    let dir = std::env::temp_dir().join(format!("http_dir-index-gz-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    for index in [dir.join("index.html"), dir.join("sub/index.html")] {
+        std::fs::write(&index, "<b>index</b>").unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"<b>index</b>").unwrap();
+        std::fs::write(index.with_extension("html.gz"), encoder.finish().unwrap()).unwrap();
+    }
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).precompressed_gzip();
+
+    for uri in ["/", "/sub/"] {
+        for method in [Method::GET, Method::HEAD] {
+            let req = Request::builder()
+                .method(method.clone())
+                .uri(uri)
+                .header("Accept-Encoding", "gzip")
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.clone().oneshot(req).await.unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK, "{method} {uri}");
+            assert_eq!(res.headers()["content-type"], "text/html");
+            assert_eq!(res.headers()["content-encoding"], "gzip");
+            assert_eq!(res.headers()["vary"], "accept-encoding");
+            let resolved_path = res.extensions().get::<ResolvedPath>().unwrap();
+            assert_eq!(
+                resolved_path.path,
+                Path::new(uri.trim_start_matches('/')).join("index.html.gz")
+            );
+            assert_eq!(resolved_path.encoding, Some("gzip"));
+            assert_eq!(
+                res.headers()["content-location"],
+                format!("{uri}index.html.gz").as_str()
+            );
+
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            if method == Method::GET {
+                let mut decompressed = String::new();
+                GzDecoder::new(&body[..])
+                    .read_to_string(&mut decompressed)
+                    .unwrap();
+                assert_eq!(decompressed, "<b>index</b>");
+            } else {
+                assert!(body.is_empty());
+            }
+        }
+
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers()["vary"], "accept-encoding");
+        assert_eq!(
+            res.headers()["content-location"],
+            format!("{uri}index.html").as_str()
+        );
+        assert_eq!(body_into_text(res.into_body()).await, "<b>index</b>");
+    }
+
+    // the redirect to the directory doesn't depend on the encoding
+    let req = Request::builder()
+        .uri("/sub")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert!(res.headers().get("vary").is_none());
+
+    // the variants of the index are taken from the route manifest
+    let sub_index_gz = dir.join("sub/index.html.gz");
+    let sub_index_gz_content = std::fs::read(&sub_index_gz).unwrap();
+    std::fs::remove_file(&sub_index_gz).unwrap();
+    let filesystem = DiskFilesystem::from(dir.as_path());
+    let manifest = RouteManifest::build(&filesystem).await.unwrap();
+    std::fs::write(&sub_index_gz, sub_index_gz_content).unwrap();
+    let svc = ServeDir::new(filesystem)
+        .precompressed_gzip()
+        .route_manifest(manifest);
+
+    for (uri, encoding) in [("/", Some("gzip")), ("/sub/", None)] {
+        let req = Request::builder()
+            .uri(uri)
+            .header("Accept-Encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()
+                .get("content-encoding")
+                .map(|value| value.to_str().unwrap()),
+            encoding,
+            "{uri}"
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
 }
 
 #[tokio::test]
@@ -368,6 +830,79 @@ async fn redirect_to_trailing_slash_on_dir() {
     assert_eq!(location, "/src/");
 }
 
+#[tokio::test]
+async fn redirect_hostile_uri() {
+    let svc = ServeDir::new(DiskFilesystem::from("."));
+
+    for (uri, expected) in [
+        // not a URL of the `src` host
+        ("//src", "/src/"),
+        ("///src", "/src/"),
+        ("/src?a=%0d%0a|{}", "/src/?a=%0d%0a%7C%7B%7D"),
+        ("/src/fs?a={}^", "/src/fs/?a=%7B%7D%5E"),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT, "{uri}");
+        assert_eq!(res.headers()[header::LOCATION], expected, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn relative_redirect() {
+    let svc = ServeDir::new(DiskFilesystem::from("."))
+        .relative_redirects(true)
+        .trust_forwarded_headers(true);
+
+    let req = Request::builder()
+        .uri("http://backend/src/fs?a=1")
+        .header("x-forwarded-proto", "https")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "fs/?a=1");
+}
+
+#[tokio::test]
+async fn redirect_with_forwarded_headers() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).trust_forwarded_headers(true);
+
+    let req = Request::builder()
+        .uri("/src?a=1")
+        .header(header::HOST, "backend:8080")
+        .header("x-forwarded-proto", "https")
+        .header("x-forwarded-host", "example.com")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        res.headers()[header::LOCATION],
+        "https://example.com/src/?a=1"
+    );
+
+    // only the scheme is forwarded
+    let req = Request::builder()
+        .uri("/src")
+        .header(header::HOST, "example.com")
+        .header(header::FORWARDED, "for=192.0.2.60;proto=https")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::LOCATION], "https://example.com/src/");
+
+    // ignored unless trusted
+    let svc = ServeDir::new(DiskFilesystem::from("."));
+    let req = Request::builder()
+        .uri("/src")
+        .header("x-forwarded-host", "evil.com")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::LOCATION], "/src/");
+}
+
 #[tokio::test]
 async fn empty_directory_without_index() {
     let svc = ServeDir::new(DiskFilesystem::from(".")).append_index_html_on_directories(false);
@@ -425,48 +960,354 @@ async fn access_space_percent_encoded_uri_path() {
 }
 
 #[tokio::test]
-async fn read_partial_in_bounds() {
-    let svc = ServeDir::new(DiskFilesystem::from("."));
-    let bytes_start_incl = 9;
-    let bytes_end_incl = 10;
+async fn decode_plus_as_space() {
+    let dir = std::env::temp_dir().join(format!("http_dir-plus-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a b.txt"), "space").unwrap();
+    std::fs::write(dir.join("a+b.txt"), "plus").unwrap();
+
+    for (decode, uri, body) in [
+        (false, "/a+b.txt", "plus"),
+        (false, "/a%2Bb.txt", "plus"),
+        (false, "/a%20b.txt", "space"),
+        (true, "/a+b.txt", "space"),
+        (true, "/a%2Bb.txt", "plus"),
+        (true, "/a%20b.txt", "space"),
+    ] {
+        let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).decode_plus_as_space(decode);
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK, "{decode} {uri}");
+        assert_eq!(
+            body_into_text(res.into_body()).await,
+            body,
+            "{decode} {uri}"
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn canonical_redirects() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).canonical_redirects(true);
+
+    for (uri, location) in [
+        ("/%69ndex.html", "/index.html"),
+        ("/%69ndex.html?v=1", "/index.html?v=1"),
+        (
+            "/filename%20with%20space%2etxt",
+            "/filename%20with%20space.txt",
+        ),
+        (
+            "/%e4%bd%a0%e5%a5%bd%e4%b8%96%e7%95%8c.txt",
+            "/%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt",
+        ),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY, "{uri}");
+        assert_eq!(res.headers()["location"], location, "{uri}");
+
+        let req = Request::builder()
+            .uri(location)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK, "{location}");
+    }
 
     let req = Request::builder()
-        .uri("/README.md")
-        .header(
-            "Range",
-            format!("bytes={bytes_start_incl}-{bytes_end_incl}"),
-        )
+        .uri("/%69ndex.html")
         .body(Body::empty())
         .unwrap();
-    let res = svc.oneshot(req).await.unwrap();
-
-    let file_contents = std::fs::read("./README.md").unwrap();
-    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
-    assert_eq!(
-        res.headers()["content-length"],
-        (bytes_end_incl - bytes_start_incl + 1).to_string()
-    );
-    assert!(res.headers()["content-range"]
-        .to_str()
-        .unwrap()
-        .starts_with(&format!(
-            "bytes {}-{}/{}",
-            bytes_start_incl,
-            bytes_end_incl,
-            file_contents.len()
-        )));
-    assert_eq!(res.headers()["content-type"], "text/markdown");
+    let res = ServeDir::new(DiskFilesystem::from("test-files"))
+        .oneshot(req)
+        .await
+        .unwrap();
 
-    let body = hyper::body::to_bytes(res.into_body()).await.ok().unwrap();
-    let source = Bytes::from(file_contents[bytes_start_incl..=bytes_end_incl].to_vec());
-    assert_eq!(body, source);
+    assert_eq!(res.status(), StatusCode::OK);
 }
 
 #[tokio::test]
-async fn read_partial_truncate_out_of_bounds_range() {
-    let svc = ServeDir::new(DiskFilesystem::from("./test-files"));
-    let bytes_start_incl = 0;
-    let bytes_end_excl = 9999999;
+async fn query_policy() {
+    let svc = |policy| {
+        ServeDir::new(DiskFilesystem::from("test-files"))
+            .serve_stat(true)
+            .query_policy(policy)
+    };
+
+    for (policy, uri, status, content_type) in [
+        (
+            QueryPolicy::Ignore,
+            "/index.html?v=1",
+            StatusCode::OK,
+            "text/html",
+        ),
+        (
+            QueryPolicy::Ignore,
+            "/index.html?stat",
+            StatusCode::OK,
+            "application/vnd.http-dir.stat+json",
+        ),
+        (
+            QueryPolicy::CacheBuster,
+            "/index.html?v=1",
+            StatusCode::OK,
+            "text/html",
+        ),
+        (
+            QueryPolicy::CacheBuster,
+            "/index.html?stat",
+            StatusCode::OK,
+            "text/html",
+        ),
+        (
+            QueryPolicy::Reject,
+            "/index.html",
+            StatusCode::OK,
+            "text/html",
+        ),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc(policy).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{policy:?} {uri}");
+        assert_eq!(
+            res.headers()["content-type"],
+            content_type,
+            "{policy:?} {uri}"
+        );
+    }
+
+    for uri in ["/index.html?v=1", "/index.html?", "/missing?v=1"] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc(QueryPolicy::Reject).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+    }
+
+    // the cache buster is kept on the directory redirects
+    let req = Request::builder()
+        .uri("/src?v=1")
+        .body(Body::empty())
+        .unwrap();
+    let res = ServeDir::new(DiskFilesystem::from("."))
+        .query_policy(QueryPolicy::CacheBuster)
+        .oneshot(req)
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()["location"], "/src/?v=1");
+}
+
+#[tokio::test]
+async fn root_override() {
+    let dir = std::env::temp_dir().join(format!("http_dir-root-override-{}", std::process::id()));
+    for user in ["alice", "bob"] {
+        std::fs::create_dir_all(dir.join(user)).unwrap();
+        std::fs::write(dir.join(user).join("index.html"), user).unwrap();
+    }
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.join("alice")));
+    for (root, status, body) in [
+        (None, StatusCode::OK, "alice"),
+        (Some(dir.join("bob")), StatusCode::OK, "bob"),
+        (
+            Some(dir.join("bob").join("..").join("alice")),
+            StatusCode::NOT_FOUND,
+            "",
+        ),
+        (Some(PathBuf::from("bob")), StatusCode::NOT_FOUND, ""),
+    ] {
+        let mut req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        if let Some(root) = &root {
+            req.extensions_mut().insert(RootOverride(root.clone()));
+        }
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{root:?}");
+        assert_eq!(body_into_text(res.into_body()).await, body, "{root:?}");
+    }
+
+    // the filesystems which can't be rerooted reject any root
+    let req = Request::builder()
+        .uri("/index.html")
+        .extension(RootOverride(dir.join("bob")))
+        .body(Body::empty())
+        .unwrap();
+    let res = ServeDir::new(DiskFilesystem::from(dir.join("alice")))
+        .immutable()
+        .oneshot(req)
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn serve_user_dirs() {
+    let dir = std::env::temp_dir().join(format!("http_dir-user-dirs-{}", std::process::id()));
+    for user in ["alice", "bob", "root", "carol"] {
+        std::fs::create_dir_all(dir.join(user).join("www").join("docs")).unwrap();
+        std::fs::write(dir.join(user).join("www").join("index.html"), user).unwrap();
+    }
+    std::fs::write(dir.join("alice").join("secret.txt"), "secret").unwrap();
+
+    let template = format!("{}/{{user}}/www", dir.display());
+    let svc = ServeUserDirs::new(
+        &template,
+        ServeDir::new(DiskFilesystem::from(dir.as_path())),
+    )
+    .allow_users(["alice", "bob", "root"]);
+
+    for (uri, status, body) in [
+        ("/~alice/", StatusCode::OK, "alice"),
+        ("/~bob/index.html", StatusCode::OK, "bob"),
+        ("/%7Ealice/", StatusCode::OK, "alice"),
+        ("/~alice/../secret.txt", StatusCode::NOT_FOUND, ""),
+        ("/~alice/%2e%2e/secret.txt", StatusCode::NOT_FOUND, ""),
+        ("/~..%2falice/secret.txt", StatusCode::NOT_FOUND, ""),
+        ("/~root/", StatusCode::NOT_FOUND, ""),
+        ("/~carol/", StatusCode::NOT_FOUND, ""),
+        ("/~dave/", StatusCode::NOT_FOUND, ""),
+        ("/alice/", StatusCode::NOT_FOUND, ""),
+        ("/", StatusCode::NOT_FOUND, ""),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{uri}");
+        assert_eq!(body_into_text(res.into_body()).await, body, "{uri}");
+    }
+
+    for (uri, location) in [
+        ("/~alice", "/~alice/"),
+        ("/~alice?v=1", "/~alice/?v=1"),
+        ("/~alice/docs", "/~alice/docs/"),
+        ("/~alice/docs?v=1", "/~alice/docs/?v=1"),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT, "{uri}");
+        assert_eq!(res.headers()["location"], location, "{uri}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn serve_user_dirs_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = std::env::temp_dir().join(format!(
+        "http_dir-user-dirs-symlinks-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(dir.join("alice").join("www")).unwrap();
+    std::fs::create_dir_all(dir.join("bob")).unwrap();
+    std::fs::write(dir.join("alice").join("www").join("index.html"), "alice").unwrap();
+    std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+    symlink("/", dir.join("alice").join("www").join("root")).unwrap();
+    symlink(&dir, dir.join("alice").join("www").join("parent")).unwrap();
+    symlink(
+        "index.html",
+        dir.join("alice").join("www").join("link.html"),
+    )
+    .unwrap();
+    symlink(dir.join("alice").join("www"), dir.join("bob").join("www")).unwrap();
+
+    let template = format!("{}/{{user}}/www", dir.display());
+    let svc = ServeUserDirs::new(
+        &template,
+        ServeDir::new(DiskFilesystem::from(dir.as_path())),
+    );
+
+    for (uri, status, body) in [
+        ("/~alice/link.html", StatusCode::OK, "alice"),
+        ("/~alice/root/etc/passwd", StatusCode::NOT_FOUND, ""),
+        ("/~alice/parent/secret.txt", StatusCode::NOT_FOUND, ""),
+        ("/~alice/parent/", StatusCode::NOT_FOUND, ""),
+        // the symlink and its target are owned by the same user
+        ("/~bob/index.html", StatusCode::OK, "alice"),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{uri}");
+        assert_eq!(body_into_text(res.into_body()).await, body, "{uri}");
+    }
+
+    // the directory of bob is linked to the files of another user, which needs `chown`
+    let chowned = std::process::Command::new("chown")
+        .args(["-h", "65534:65534"])
+        .arg(dir.join("bob").join("www"))
+        .status()
+        .is_ok_and(|status| status.success());
+    if chowned {
+        let req = Request::builder()
+            .uri("/~bob/index.html")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn read_partial_in_bounds() {
+    let svc = ServeDir::new(DiskFilesystem::from("."));
+    let bytes_start_incl = 9;
+    let bytes_end_incl = 10;
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(
+            "Range",
+            format!("bytes={bytes_start_incl}-{bytes_end_incl}"),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let file_contents = std::fs::read("./README.md").unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        res.headers()["content-length"],
+        (bytes_end_incl - bytes_start_incl + 1).to_string()
+    );
+    assert!(res.headers()["content-range"]
+        .to_str()
+        .unwrap()
+        .starts_with(&format!(
+            "bytes {}-{}/{}",
+            bytes_start_incl,
+            bytes_end_incl,
+            file_contents.len()
+        )));
+    assert_eq!(res.headers()["content-type"], "text/markdown");
+
+    let body = hyper::body::to_bytes(res.into_body()).await.ok().unwrap();
+    let source = Bytes::from(file_contents[bytes_start_incl..=bytes_end_incl].to_vec());
+    assert_eq!(body, source);
+}
+
+#[tokio::test]
+async fn read_partial_truncate_out_of_bounds_range() {
+    let svc = ServeDir::new(DiskFilesystem::from("./test-files"));
+    let bytes_start_incl = 0;
+    let bytes_end_excl = 9999999;
     let requested_len = bytes_end_excl - bytes_start_incl;
 
     let req = Request::builder()
@@ -663,7 +1504,26 @@ async fn method_not_allowed() {
     let res = svc.oneshot(req).await.unwrap();
 
     assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
-    assert_eq!(res.headers()[ALLOW], "GET,HEAD");
+    assert_eq!(res.headers()[ALLOW], "GET,HEAD,OPTIONS");
+}
+
+#[tokio::test]
+async fn options() {
+    let svc = ServeDir::new(DiskFilesystem::from("."))
+        .call_fallback_on_method_not_allowed(true)
+        .fallback(service_fn(|_| async {
+            Ok::<_, io::Error>(Response::new(Body::from("from fallback")))
+        }));
+
+    let req = Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(res.headers()[ALLOW], "GET,HEAD,OPTIONS");
 }
 
 #[tokio::test]
@@ -736,6 +1596,77 @@ async fn calls_fallback_on_invalid_paths() {
     assert_eq!(res.headers()["from-fallback"], "1");
 }
 
+#[tokio::test]
+async fn warm() {
+    let dir = std::env::temp_dir().join(format!("http_dir-warm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "index").unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+
+    let serve_dir = || {
+        ServeDir::new(DiskFilesystem::from(dir.as_path()))
+            .precompressed_gzip()
+            .precompressed_probe_cache(Duration::from_secs(60))
+    };
+    let request = || {
+        Request::builder()
+            .uri("/a.txt")
+            .header("Accept-Encoding", "gzip")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let svc = serve_dir();
+    assert_eq!(svc.warm(["/a.txt", "/", "/missing.txt"]).await.unwrap(), 2);
+
+    // the missing variant is remembered by the warming
+    std::fs::write(dir.join("a.txt.gz"), "gz").unwrap();
+    let res = svc.oneshot(request()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+
+    // the precompressed variant isn't warmed as a file of its own
+    let svc = serve_dir();
+    assert_eq!(svc.warm_all().await.unwrap(), 2);
+    let res = svc.oneshot(request()).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn verify_embedded_files() {
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+    let filesystem = IncludeDirFilesystem::new(ROOT.clone());
+
+    filesystem
+        .verify(["/index.html", "precompressed.txt"])
+        .unwrap();
+    let err = filesystem
+        .verify(["index.html", "missing.txt", "/missing.css"])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(
+        err.to_string(),
+        "files not embedded: missing.txt, missing.css"
+    );
+
+    let svc = ServeFile::new(
+        crate::embedded_path!("test-files", "index.html"),
+        filesystem.clone(),
+    );
+    svc.verify().await.unwrap();
+    let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let err = ServeFile::new("missing.html", filesystem)
+        .verify()
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().starts_with("missing.html: "), "{err}");
+}
+
 #[tokio::test]
 async fn include_dir_basic_with_index() {
     static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
@@ -789,3 +1720,3592 @@ async fn serve_file_not_found() {
     let contents = std::fs::read_to_string("./README.md").unwrap();
     assert_eq!(body, contents);
 }
+
+#[tokio::test]
+async fn serve_file_request_path() {
+    let svc =
+        ServeFile::new("README.md", DiskFilesystem::from(".")).request_path("/readme/file.md");
+
+    for uri in [
+        "/readme/file.md",
+        "/./readme/file.md",
+        "/readme/file.md/",
+        "/readme/%66ile.md",
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        assert_eq!(res.headers()["content-type"], "text/markdown");
+    }
+
+    let req = Request::builder()
+        .uri("/readme/other.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn serve_file_precompressed() {
+    let svc = ServeFile::new("precompressed.txt", DiskFilesystem::from("test-files"))
+        .precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/any")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+
+    let body = res.into_body().data().await.unwrap().unwrap();
+    let mut decoder = GzDecoder::new(&body[..]);
+    let mut decompressed = String::new();
+    decoder.read_to_string(&mut decompressed).unwrap();
+    assert!(decompressed.starts_with("\"This is a test file!\""));
+}
+
+#[tokio::test]
+async fn serve_file_range_and_not_modified() {
+    let svc = ServeFile::new("README.md", DiskFilesystem::from("."))
+        .response_header(header::CACHE_CONTROL, "max-age=60".parse().unwrap());
+    let contents = std::fs::read_to_string("./README.md").unwrap();
+
+    let req = Request::builder()
+        .uri("/favicon.ico")
+        .header(header::RANGE, "bytes=0-9")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        res.headers()[header::CONTENT_RANGE],
+        format!("bytes 0-9/{}", contents.len())
+    );
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "max-age=60");
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    assert_eq!(body_into_text(res.into_body()).await, contents[..10]);
+
+    let req = Request::builder()
+        .uri("/favicon.ico")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "max-age=60");
+}
+
+#[tokio::test]
+async fn serve_file_fallback() {
+    let filesystem = DiskFilesystem::from(".");
+    let svc = ServeFile::new("README.md", filesystem.clone())
+        .request_path("/readme")
+        .not_found_service(ServeFile::new("Cargo.toml", filesystem));
+
+    let req = Request::builder()
+        .uri("/other")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, std::fs::read_to_string("./Cargo.toml").unwrap());
+}
+
+#[tokio::test]
+async fn not_found_service_revalidation() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).not_found_service(ServeFile::new(
+        "index.html",
+        DiskFilesystem::from("test-files"),
+    ));
+
+    let req = Request::builder()
+        .uri("/not-found")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get(header::ACCEPT_RANGES).is_none());
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        std::fs::read_to_string("test-files/index.html").unwrap()
+    );
+
+    // the stored not found document is still valid
+    let req = Request::builder()
+        .uri("/not-found")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(body_into_text(res.into_body()).await.is_empty());
+
+    // the ranges of the not found document aren't served
+    let req = Request::builder()
+        .uri("/not-found")
+        .header(header::RANGE, "bytes=0-4")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get(header::CONTENT_RANGE).is_none());
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        std::fs::read_to_string("test-files/index.html").unwrap()
+    );
+}
+
+#[tokio::test]
+async fn serve_files() {
+    let filesystem = DiskFilesystem::from(".");
+    let svc = ServeFiles::new()
+        .file("/readme", "README.md", filesystem.clone())
+        .file_with_mime(
+            "/manifest",
+            "Cargo.toml",
+            &mime::TEXT_PLAIN,
+            filesystem.clone(),
+        )
+        .not_found_service(ServeFile::new("LICENSE", filesystem));
+
+    for uri in ["/readme", "/./readme/", "/%72eadme"] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        assert_eq!(res.headers()["content-type"], "text/markdown");
+        let body = body_into_text(res.into_body()).await;
+        assert_eq!(body, std::fs::read_to_string("./README.md").unwrap());
+    }
+
+    let req = Request::builder()
+        .uri("/manifest")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+
+    // no directory semantics, the file is only served for its own path
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, std::fs::read_to_string("./LICENSE").unwrap());
+}
+
+#[tokio::test]
+async fn search() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).search(SearchOptions::new());
+
+    let req = Request::builder()
+        .uri("/?q=precompressed_br*")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/json");
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        r#"{"matches":[{"path":"precompressed_br.txt","is_dir":false},{"path":"precompressed_br.txt.br","is_dir":false}],"truncated":false}"#
+    );
+}
+
+#[tokio::test]
+async fn search_sub_dirs() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).search(SearchOptions::new().max_depth(2));
+
+    let req = Request::builder()
+        .uri("/src/?q=mod.rs")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains(r#"{"path":"fs/mod.rs","is_dir":false}"#));
+    assert!(body.contains(r#"{"path":"serve_dir/mod.rs","is_dir":false}"#));
+    assert!(body.ends_with(r#""truncated":false}"#));
+
+    let svc = ServeDir::new(DiskFilesystem::from(".")).search(SearchOptions::new().max_depth(1));
+
+    let req = Request::builder()
+        .uri("/src/?q=mod.rs")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, r#"{"matches":[],"truncated":false}"#);
+}
+
+#[tokio::test]
+async fn search_truncated() {
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+
+    let svc = ServeDir::new(IncludeDirFilesystem::new(ROOT.clone()))
+        .search(SearchOptions::new().max_results(1));
+
+    let req = Request::builder()
+        .uri("/?q=precompressed%20*")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, r#"{"matches":[],"truncated":false}"#);
+
+    let req = Request::builder()
+        .uri("/?q=precompressed")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        r#"{"matches":[{"path":"missing_precompressed.txt","is_dir":false}],"truncated":true}"#
+    );
+}
+
+#[tokio::test]
+async fn search_limits() {
+    for (options, status) in [
+        (
+            SearchOptions::new().max_entries(3),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            SearchOptions::new().timeout(Duration::ZERO),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (SearchOptions::new().max_entries(64), StatusCode::OK),
+    ] {
+        let svc = ServeDir::new(DiskFilesystem::from("test-files")).search(options);
+
+        let req = Request::builder()
+            .uri("/?q=precompressed*")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), status, "{options:?}");
+    }
+}
+
+#[tokio::test]
+async fn search_unsupported() {
+    // the filesystem can't list the dirs
+    let svc = ServeDir::new(NoSeekFilesystem(DiskFilesystem::from("test-files")))
+        .search(SearchOptions::new());
+
+    let req = Request::builder()
+        .uri("/?q=precompressed")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED);
+}
+
+#[tokio::test]
+async fn disk_read_dir_bounded() {
+    let filesystem = DiskFilesystem::from("test-files");
+    let all = filesystem.read_dir(Path::new("")).await.unwrap();
+    assert!(all.len() > 4);
+
+    // one more than the max is read, so the caller knows the dir has more
+    let entries = filesystem
+        .read_dir_bounded(Path::new(""), 3, None)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 4);
+    let entries = filesystem
+        .read_dir_bounded(Path::new(""), all.len(), None)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), all.len());
+
+    let err = filesystem
+        .read_dir_bounded(Path::new(""), 3, Some(Instant::now()))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn search_ignored_for_files_and_when_disabled() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).search(SearchOptions::new());
+
+    let req = Request::builder()
+        .uri("/index.html?q=precompressed")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/html");
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+
+    let req = Request::builder()
+        .uri("/?q=precompressed")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/html");
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+}
+
+#[tokio::test]
+async fn stat() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).serve_stat(true);
+
+    let mtime = std::fs::metadata("test-files/precompressed.txt")
+        .unwrap()
+        .modified()
+        .unwrap()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let etag = DiskFilesystem::from("test-files")
+        .metadata(Path::new("precompressed.txt"))
+        .await
+        .unwrap()
+        .etag
+        .unwrap()
+        .replace('"', "\\\"");
+
+    let req = Request::builder()
+        .uri("/precompressed.txt?stat")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()["content-type"],
+        "application/vnd.http-dir.stat+json"
+    );
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        format!(r#"{{"size":23,"mtime":{mtime},"etag":"{etag}","mime":"text/plain"}}"#)
+    );
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(
+            "Accept",
+            "text/html, application/vnd.http-dir.stat+json;q=0.9",
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        format!(r#"{{"size":23,"mtime":{mtime},"etag":"{etag}","mime":"text/plain"}}"#)
+    );
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["vary"], "accept");
+}
+
+#[tokio::test]
+async fn stat_disabled() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+
+    let req = Request::builder()
+        .uri("/precompressed.txt?stat")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert!(res.headers().get("vary").is_none());
+
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.starts_with("\"This is a test file!\""));
+}
+
+#[tokio::test]
+async fn stat_not_found() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).serve_stat(true);
+
+    let req = Request::builder()
+        .uri("/not-found?stat")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn events() {
+    let dir = std::env::temp_dir().join(format!("http_dir-events-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+
+    let svc =
+        ServeDir::new(DiskFilesystem::from(dir.as_path())).events(FileWatcher::new(&dir).unwrap());
+
+    let req = Request::builder()
+        .uri("/__events")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+
+    std::fs::write(dir.join("css/index.css"), "body {}").unwrap();
+
+    let mut body = res.into_body();
+    let event = tokio::time::timeout(Duration::from_secs(5), body.data())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        event,
+        "event: change\ndata: {\"path\":\"css/index.css\"}\n\n"
+    );
+}
+
+#[tokio::test]
+async fn events_hidden_paths() {
+    let dir = std::env::temp_dir().join(format!("http_dir-events-hidden-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::create_dir_all(dir.join("private")).unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .hide_dot_files(true)
+        .exclude("private/**")
+        .events(FileWatcher::new(&dir).unwrap());
+
+    let req = Request::builder()
+        .uri("/__events")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    std::fs::write(dir.join(".env"), "SECRET=1").unwrap();
+    std::fs::write(dir.join("private/key.pem"), "key").unwrap();
+    std::fs::write(dir.join("css/index.css"), "body {}").unwrap();
+
+    // the changes of the hidden paths are skipped
+    let mut body = res.into_body();
+    let event = tokio::time::timeout(Duration::from_secs(5), body.data())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        event,
+        "event: change\ndata: {\"path\":\"css/index.css\"}\n\n"
+    );
+}
+
+#[tokio::test]
+async fn head_content_length() {
+    let precompressed = |svc: ServeDir<DiskFilesystem>| {
+        svc.precompressed_gzip()
+            .precompressed_br()
+            .precompressed_deflate()
+    };
+    let services = [
+        precompressed(ServeDir::new(DiskFilesystem::from("test-files"))),
+        precompressed(ServeDir::new(DiskFilesystem::from("test-files"))).defer_open(true),
+        precompressed(ServeDir::new(DiskFilesystem::from("test-files")))
+            .identity_content_encoding(true),
+        precompressed(ServeDir::new(DiskFilesystem::from("test-files")))
+            .precompressed_probe_cache(Duration::from_secs(60)),
+        precompressed(ServeDir::new(DiskFilesystem::from("test-files")))
+            .events(FileWatcher::new("test-files").unwrap())
+            .inject_reload_script(true),
+    ];
+    let requests: [(&str, &[(&str, &str)]); 9] = [
+        ("/", &[]),
+        ("/precompressed.txt", &[]),
+        ("/precompressed.txt", &[("accept-encoding", "gzip")]),
+        (
+            "/precompressed.txt",
+            &[("accept-encoding", "br;q=0.5, deflate")],
+        ),
+        (
+            "/precompressed.txt",
+            &[("accept-encoding", "br"), ("range", "bytes=2-9")],
+        ),
+        ("/precompressed.txt", &[("range", "bytes=-5")]),
+        (
+            "/missing_precompressed.txt",
+            &[("accept-encoding", "gzip, br")],
+        ),
+        ("/only_gzipped.txt", &[("accept-encoding", "gzip")]),
+        ("/index.html", &[("range", "bytes=0-")]),
+    ];
+
+    for svc in services {
+        for (uri, headers) in requests {
+            let request = |method| {
+                let mut req = Request::builder().method(method).uri(uri);
+                for (name, value) in headers {
+                    req = req.header(*name, *value);
+                }
+                req.body(Body::empty()).unwrap()
+            };
+            let head = svc.clone().oneshot(request(Method::HEAD)).await.unwrap();
+            let get = svc.clone().oneshot(request(Method::GET)).await.unwrap();
+
+            assert_eq!(head.status(), get.status(), "{uri} {headers:?}");
+            for name in [
+                header::CONTENT_LENGTH,
+                header::CONTENT_ENCODING,
+                header::CONTENT_RANGE,
+            ] {
+                assert_eq!(
+                    head.headers().get(&name),
+                    get.headers().get(&name),
+                    "{name} of {uri} {headers:?}"
+                );
+            }
+
+            let content_length = get.headers()[header::CONTENT_LENGTH].clone();
+            let body = hyper::body::to_bytes(get.into_body()).await.unwrap();
+            assert_eq!(content_length, body.len().to_string(), "{uri} {headers:?}");
+            let head_body = hyper::body::to_bytes(head.into_body()).await.unwrap();
+            assert!(head_body.is_empty());
+        }
+    }
+}
+
+#[tokio::test]
+async fn inject_reload_script() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .events(FileWatcher::new("test-files").unwrap())
+        .inject_reload_script(true);
+
+    let req = Request::new(Body::empty());
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html");
+    assert_eq!(
+        res.headers()["content-length"],
+        ("<b>HTML!</b>\n".len() + RELOAD_SCRIPT.len()).to_string()
+    );
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, format!("<b>HTML!</b>\n{RELOAD_SCRIPT}"));
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert!(!body.contains(RELOAD_SCRIPT));
+}
+
+#[tokio::test]
+async fn inject_reload_script_requires_events() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).inject_reload_script(true);
+
+    let req = Request::new(Body::empty());
+    let res = svc.oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+}
+
+#[tokio::test]
+async fn default_content_type() {
+    let svc = ServeDir::new(DiskFilesystem::from("."));
+
+    let req = Request::builder()
+        .uri("/LICENSE")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "application/octet-stream");
+    assert!(res.headers().get("content-disposition").is_none());
+
+    let svc = ServeDir::new(DiskFilesystem::from(".")).default_content_type(&mime::TEXT_PLAIN);
+
+    let req = Request::builder()
+        .uri("/LICENSE")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/plain");
+}
+
+#[tokio::test]
+async fn attachment_for_unknown_types() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).attachment_for_unknown_types(true);
+
+    let req = Request::builder()
+        .uri("/LICENSE")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/octet-stream");
+    assert_eq!(res.headers()["content-disposition"], "attachment");
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()["content-type"], "text/markdown");
+    assert!(res.headers().get("content-disposition").is_none());
+}
+
+#[tokio::test]
+async fn hide_dot_files() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).hide_dot_files(true);
+
+    let req = Request::builder()
+        .uri("/.gitignore")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let req = Request::builder()
+        .uri("/test-files/.well-known/acme-challenge/token")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).hide_dot_files(true);
+
+    let req = Request::builder()
+        .uri("/.well-known/acme-challenge/token")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "token\n");
+}
+
+#[tokio::test]
+async fn exclude() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .exclude("*.gz")
+        .exclude("*token");
+
+    let req = Request::builder()
+        .uri("/precompressed.txt.gz")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .uri("/.well-known/acme-challenge/token")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn without_well_known_passthrough() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .hide_dot_files(true)
+        .well_known_passthrough(false);
+
+    let req = Request::builder()
+        .uri("/.well-known/acme-challenge/token")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn search_skips_filtered_entries() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .exclude("*.br")
+        .search(SearchOptions::new());
+
+    let req = Request::builder()
+        .uri("/?q=precompressed_br*")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(
+        body,
+        r#"{"matches":[{"path":"precompressed_br.txt","is_dir":false}],"truncated":false}"#
+    );
+}
+
+#[tokio::test]
+async fn mirror() {
+    let mirrored = Arc::new(std::sync::Mutex::new(vec![]));
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .ip_filter(IpFilter::new().allow("127.0.0.1"))
+        .mirror({
+            let mirrored = mirrored.clone();
+            move |request| mirrored.lock().unwrap().push(request)
+        });
+
+    for ip in [[127, 0, 0, 1], [10, 0, 0, 1]] {
+        let mut req = Request::builder()
+            .method(Method::HEAD)
+            .uri("/index.html?utm=1")
+            .header(header::USER_AGENT, "test")
+            .body(Body::from("body"))
+            .unwrap();
+        req.extensions_mut().insert(SocketAddr::from((ip, 12345)));
+        svc.clone().oneshot(req).await.unwrap();
+    }
+
+    // the rejected requests are mirrored too
+    let mirrored = mirrored.lock().unwrap();
+    assert_eq!(mirrored.len(), 2);
+    for (request, ip) in mirrored.iter().zip([[127, 0, 0, 1], [10, 0, 0, 1]]) {
+        assert_eq!(request.method(), Method::HEAD);
+        assert_eq!(request.uri(), "/index.html?utm=1");
+        assert_eq!(request.headers()[header::USER_AGENT], "test");
+        assert_eq!(
+            request.extensions().get::<SocketAddr>(),
+            Some(&SocketAddr::from((ip, 12345)))
+        );
+    }
+}
+
+#[tokio::test]
+async fn request_id() {
+    let fallback = service_fn(|req: Request<Body>| async move {
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        res.headers_mut().insert(
+            "x-fallback-request-id",
+            req.headers()["x-request-id"].clone(),
+        );
+        Ok::<_, io::Error>(res)
+    });
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .fallback(fallback)
+        .request_id(true);
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header("x-request-id", "ticket-42")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["x-request-id"], "ticket-42");
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .header("x-request-id", "invalid id")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let request_id = &res.headers()["x-request-id"];
+    assert_ne!(request_id, "invalid id");
+    assert_eq!(request_id, res.headers()["x-fallback-request-id"]);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let req = Request::new(Body::empty());
+    let res = svc.oneshot(req).await.unwrap();
+    assert!(res.headers().get("x-request-id").is_none());
+}
+
+#[tokio::test]
+async fn deterministic() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .response_header(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+        .request_id(true)
+        .deterministic(true);
+    let get = |request_id: Option<&'static str>| {
+        let mut req = Request::builder().uri("/index.html");
+        if let Some(request_id) = request_id {
+            req = req.header("x-request-id", request_id);
+        }
+        svc.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+
+    let res = get(None).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["server"], "http_dir");
+    assert!(res.headers().get("x-request-id").is_none());
+    let modified = std::fs::metadata("test-files/index.html")
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(
+        res.headers()["last-modified"],
+        httpdate::fmt_http_date(modified).as_str()
+    );
+    let names = res
+        .headers()
+        .keys()
+        .map(|name| name.as_str())
+        .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort_unstable();
+    assert_eq!(names, sorted);
+
+    let headers = res.headers().clone();
+    assert_eq!(get(None).await.unwrap().headers(), &headers);
+
+    let res = get(Some("ticket-42")).await.unwrap();
+    assert_eq!(res.headers()["x-request-id"], "ticket-42");
+}
+
+#[tokio::test]
+async fn server_header() {
+    let fallback = service_fn(|_: Request<Body>| async move {
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        res.headers_mut()
+            .insert(header::SERVER, HeaderValue::from_static("upstream/1.2"));
+        Ok::<_, io::Error>(res)
+    });
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).fallback(fallback);
+    let get = |svc: ServeDir<_, _>, path: &'static str| {
+        svc.oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+    };
+
+    let res = get(svc.clone(), "/index.html").await.unwrap();
+    assert!(res.headers().get(header::SERVER).is_none());
+    let res = get(svc.clone(), "/missing.txt").await.unwrap();
+    assert_eq!(res.headers()[header::SERVER], "upstream/1.2");
+
+    let branded = svc
+        .clone()
+        .server_header(Some(HeaderValue::from_static("files")));
+    for path in ["/index.html", "/missing.txt", "/"] {
+        let res = get(branded.clone(), path).await.unwrap();
+        assert_eq!(res.headers()[header::SERVER], "files", "{path}");
+    }
+
+    let hidden = svc.server_header(None).deterministic(true);
+    for path in ["/index.html", "/missing.txt"] {
+        let res = get(hidden.clone(), path).await.unwrap();
+        assert!(res.headers().get(header::SERVER).is_none(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn map_response_headers() {
+    let dir = std::env::temp_dir().join(format!(
+        "http_dir-map-response-headers-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("index.html"), "hello").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .server_header(Some(HeaderValue::from_static("files")))
+        .map_response_headers(|headers| {
+            headers.remove(header::SERVER);
+            if let Some(location) = headers.remove(header::LOCATION) {
+                headers.insert(header::CONTENT_LOCATION, location);
+            }
+            // the length first
+            let mut reordered = HeaderMap::new();
+            if let Some(length) = headers.remove(header::CONTENT_LENGTH) {
+                reordered.insert(header::CONTENT_LENGTH, length);
+            }
+            reordered.extend(headers.drain());
+            *headers = reordered;
+        });
+    let get = |path: &'static str| {
+        svc.clone()
+            .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+    };
+
+    let res = get("/index.html").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().keys().next(), Some(&header::CONTENT_LENGTH));
+    assert!(res.headers().get(header::SERVER).is_none());
+
+    let res = get("/missing.txt").await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get(header::SERVER).is_none());
+
+    let res = get("/sub").await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert!(res.headers().get(header::LOCATION).is_none());
+    assert_eq!(res.headers()[header::CONTENT_LOCATION], "/sub/");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn error_pages() {
+    let pages = ErrorPages::new()
+        .html(StatusCode::NOT_FOUND, "<h1>{status} {reason}: {path}</h1>")
+        .json(
+            StatusCode::METHOD_NOT_ALLOWED,
+            r#"{"error":"{reason}","path":"{path}"}"#,
+        )
+        .html(StatusCode::RANGE_NOT_SATISFIABLE, "{reason}")
+        .html(StatusCode::INTERNAL_SERVER_ERROR, "{status} ({request_id})");
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).error_pages(pages.clone());
+    let request = |method: Method, path: &'static str| {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = svc
+        .clone()
+        .oneshot(request(Method::GET, "/missing%3Cb%3E.txt"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        "<h1>404 Not Found: /missing%3Cb%3E.txt</h1>"
+    );
+
+    let res = svc
+        .clone()
+        .oneshot(request(Method::HEAD, "/missing.txt"))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()["content-length"], "36");
+    assert!(body_into_text(res.into_body()).await.is_empty());
+
+    let res = svc
+        .clone()
+        .oneshot(request(Method::POST, "/index.html"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(res.headers()["content-type"], "application/json");
+    assert_eq!(res.headers()[ALLOW], "GET,HEAD,OPTIONS");
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        r#"{"error":"Method Not Allowed","path":"/index.html"}"#
+    );
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=100-200")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(res.headers()["content-range"], "bytes */13");
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        "Range Not Satisfiable"
+    );
+
+    let res = svc
+        .clone()
+        .oneshot(request(Method::GET, "/index.html"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    // the body of the fallback is kept
+    let fallback = service_fn(|_: Request<Body>| async move {
+        let mut res = Response::new(Body::from("custom"));
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        Ok::<_, io::Error>(res)
+    });
+    let res = svc
+        .fallback(fallback)
+        .oneshot(request(Method::GET, "/missing.txt"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(body_into_text(res.into_body()).await, "custom");
+
+    struct FailingStorage;
+
+    impl BlockingStorage for FailingStorage {
+        type File = std::fs::File;
+
+        fn open(&self, _path: &Path) -> io::Result<Self::File> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk failure"))
+        }
+
+        fn is_dir(&self, _path: &Path) -> io::Result<bool> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk failure"))
+        }
+
+        fn metadata(&self, _path: &Path) -> io::Result<Metadata> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk failure"))
+        }
+
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<DirEntry>> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk failure"))
+        }
+    }
+
+    let failing = ServeDir::new(BlockingFilesystem::new(FailingStorage));
+    let err = failing
+        .clone()
+        .oneshot(request(Method::GET, "/index.html"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "disk failure");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header("x-request-id", "ticket-42")
+        .body(Body::empty())
+        .unwrap();
+    let res = failing
+        .request_id(true)
+        .error_pages(pages)
+        .oneshot(req)
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(res.headers()["x-request-id"], "ticket-42");
+    assert_eq!(body_into_text(res.into_body()).await, "500 (ticket-42)");
+}
+
+#[tokio::test]
+async fn error_pages_negotiation() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).error_pages(
+        ErrorPages::new()
+            .html(StatusCode::NOT_FOUND, "<h1>{reason}</h1>")
+            .html(StatusCode::METHOD_NOT_ALLOWED, "<h1>{reason}</h1>")
+            .json(
+                StatusCode::METHOD_NOT_ALLOWED,
+                r#"{"error":{"status":{status}}}"#,
+            ),
+    );
+    let request = |method: Method, accept: &'static str| {
+        Request::builder()
+            .method(method)
+            .uri("/missing.txt")
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    let res = svc
+        .clone()
+        .oneshot(request(Method::GET, browser))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    assert_eq!(res.headers()["vary"], "accept");
+    assert_eq!(body_into_text(res.into_body()).await, "<h1>Not Found</h1>");
+
+    let res = svc
+        .clone()
+        .oneshot(request(Method::GET, "application/json"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()["content-type"], "application/json");
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        r#"{"error":"Not Found"}"#
+    );
+
+    let res = svc
+        .oneshot(request(Method::DELETE, "application/json"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        r#"{"error":{"status":405}}"#
+    );
+}
+
+#[tokio::test]
+async fn ip_filter() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .ip_filter(IpFilter::new().allow("127.0.0.0/8"));
+
+    let mut req = Request::new(Body::empty());
+    req.extensions_mut()
+        .insert(SocketAddr::from(([127, 0, 0, 1], 12345)));
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut req = Request::new(Body::empty());
+    req.extensions_mut()
+        .insert(SocketAddr::from(([10, 0, 0, 1], 12345)));
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = Request::new(Body::empty());
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn ip_filter_with_extractor() {
+    #[derive(Clone)]
+    struct ConnectInfo(SocketAddr);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).ip_filter(
+        IpFilter::new()
+            .deny("10.0.0.0/8")
+            .extractor(|extensions| extensions.get::<ConnectInfo>().map(|info| info.0.ip())),
+    );
+
+    let mut req = Request::new(Body::empty());
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 12345))));
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let mut req = Request::new(Body::empty());
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([192, 168, 0, 1], 12345))));
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn resolved_path() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let resolved_path = res.extensions().get::<ResolvedPath>().unwrap();
+    assert_eq!(resolved_path.path, Path::new("precompressed.txt"));
+    assert_eq!(resolved_path.encoding, None);
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let resolved_path = res.extensions().get::<ResolvedPath>().unwrap();
+    assert_eq!(resolved_path.path, Path::new("precompressed.txt.gz"));
+    assert_eq!(resolved_path.encoding, Some("gzip"));
+
+    let req = Request::new(Body::empty());
+    let res = svc.oneshot(req).await.unwrap();
+
+    let resolved_path = res.extensions().get::<ResolvedPath>().unwrap();
+    assert_eq!(resolved_path.path, Path::new("index.html"));
+}
+
+#[tokio::test]
+async fn served_file() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let len = std::fs::metadata("test-files/precompressed.txt")
+        .unwrap()
+        .len();
+    let served_file = res.extensions().get::<ServedFile>().unwrap();
+    assert_eq!(served_file.path, Path::new("precompressed.txt"));
+    assert_eq!(served_file.len, Some(len));
+    assert!(served_file.mtime.is_some());
+    assert_eq!(served_file.status_reason, StatusReason::Served);
+
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let served_file = res.extensions().get::<ServedFile>().unwrap();
+    assert_eq!(served_file.len, Some(len));
+    assert_eq!(served_file.status_reason, StatusReason::NotModified);
+
+    let req = Request::builder()
+        .uri("/not-found")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let served_file = res.extensions().get::<ServedFile>().unwrap();
+    assert_eq!(served_file.path, Path::new("not-found"));
+    assert_eq!(served_file.len, None);
+    assert_eq!(served_file.status_reason, StatusReason::NotFound);
+}
+
+#[tokio::test]
+async fn host_template() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).host_template("{host}");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::HOST, "Test-Files:8080")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::HOST, "..")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn disk_etag() {
+    let filesystem = DiskFilesystem::from("test-files");
+
+    let metadata = filesystem
+        .metadata(Path::new("precompressed.txt"))
+        .await
+        .unwrap();
+    let etag = metadata.etag.unwrap();
+    assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+    let metadata = filesystem.metadata(Path::new("index.html")).await.unwrap();
+    assert_ne!(metadata.etag.as_ref(), Some(&etag));
+
+    let svc = ServeDir::new(filesystem);
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let served_file = res.extensions().get::<ServedFile>().unwrap();
+    assert_eq!(served_file.etag.as_ref(), Some(&etag));
+}
+
+#[tokio::test]
+async fn xattr_headers() {
+    let dir = std::env::temp_dir().join(format!("http_dir-xattr-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.bin");
+    std::fs::write(&path, "{}").unwrap();
+    xattr::set(&path, "user.mime_type", b"application/json").unwrap();
+    xattr::set(&path, "user.cache_control", b"no-store").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()).xattr_headers(true));
+
+    for method in [Method::GET, Method::HEAD] {
+        let req = Request::builder()
+            .method(method)
+            .uri("/data.bin")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get_all(header::CONTENT_TYPE).iter().count(),
+            1
+        );
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    }
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()));
+    let req = Request::builder()
+        .uri("/data.bin")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/octet-stream"
+    );
+    assert!(res.headers().get(header::CACHE_CONTROL).is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn world_readable_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("http_dir-mode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let public = dir.join("public.txt");
+    let private = dir.join("private.pem");
+    let writable = dir.join("writable.txt");
+    std::fs::write(&public, "public").unwrap();
+    std::fs::write(&private, "private").unwrap();
+    std::fs::write(&writable, "writable").unwrap();
+    std::fs::set_permissions(&public, std::fs::Permissions::from_mode(0o644)).unwrap();
+    std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o600)).unwrap();
+    std::fs::set_permissions(&writable, std::fs::Permissions::from_mode(0o666)).unwrap();
+
+    let svc = ServeDir::new(
+        DiskFilesystem::from(dir.as_path())
+            .world_readable_only(true)
+            .deny_mode(0o002),
+    );
+
+    for method in [Method::GET, Method::HEAD] {
+        for (uri, status) in [
+            ("/public.txt", StatusCode::OK),
+            ("/private.pem", StatusCode::NOT_FOUND),
+            ("/writable.txt", StatusCode::NOT_FOUND),
+        ] {
+            let req = Request::builder()
+                .method(method.clone())
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.clone().oneshot(req).await.unwrap();
+
+            assert_eq!(res.status(), status, "{method} {uri}");
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn handle_cache() {
+    let dir = std::env::temp_dir().join(format!("http_dir-handle-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(dir.join(name), format!("{name} 0123456789")).unwrap();
+    }
+
+    let svc =
+        ServeDir::new(DiskFilesystem::from(dir.as_path()).handle_cache(2)).with_buf_chunk_size(4);
+
+    // the requests sharing a handle read from their own positions
+    let mut bodies = vec![];
+    for range in ["bytes=0-", "bytes=6-9", "bytes=-3"] {
+        let req = Request::builder()
+            .uri("/a.txt")
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        bodies.push(svc.clone().oneshot(req).await.unwrap().into_body());
+    }
+    let bodies = futures_util::future::join_all(bodies.into_iter().map(body_into_text)).await;
+    assert_eq!(bodies, ["a.txt 0123456789", "0123", "789"]);
+
+    // more files than the capacity
+    for name in ["b.txt", "c.txt", "a.txt"] {
+        let req = Request::builder()
+            .uri(format!("/{name}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            body_into_text(res.into_body()).await,
+            format!("{name} 0123456789")
+        );
+    }
+
+    // the replaced file is reopened
+    let replacement = dir.join("replacement.txt");
+    std::fs::write(&replacement, "replaced").unwrap();
+    std::fs::rename(&replacement, dir.join("a.txt")).unwrap();
+    let req = Request::builder()
+        .uri("/a.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "8");
+    assert_eq!(body_into_text(res.into_body()).await, "replaced");
+
+    std::fs::remove_file(dir.join("a.txt")).unwrap();
+    let req = Request::builder()
+        .uri("/a.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn body_stall_timeout() {
+    let svc = ServeDir::new(DiskFilesystem::from("."))
+        .with_buf_chunk_size(16)
+        .body_stall_timeout(Duration::from_millis(50));
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, std::fs::read_to_string("README.md").unwrap());
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    let mut body = res.into_body();
+    body.data().await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let err = body.data().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn deadline() {
+    let svc = ServeDir::new(DiskFilesystem::from(".")).with_buf_chunk_size(16);
+
+    let mut req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(Deadline(Instant::now() + Duration::from_secs(60)));
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, std::fs::read_to_string("README.md").unwrap());
+
+    let mut req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(Deadline(Instant::now() + Duration::from_millis(50)));
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut body = res.into_body();
+    body.data().await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let err = body.data().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn last_modified_min_age() {
+    let modified = std::fs::metadata("README.md").unwrap().modified().unwrap();
+    let request = |if_modified_since: Option<String>| {
+        let mut req = Request::builder().uri("/README.md");
+        if let Some(since) = if_modified_since {
+            req = req.header(header::IF_MODIFIED_SINCE, since);
+        }
+
+        req.body(Body::empty()).unwrap()
+    };
+    let svc = |now: SystemTime| {
+        ServeDir::new(DiskFilesystem::from("."))
+            .last_modified_min_age(Duration::from_secs(1))
+            .clock(move || now)
+    };
+
+    // modified in the same second, it may be modified again without changing the date
+    let svc_fresh = svc(modified + Duration::from_millis(500));
+    let res = svc_fresh.clone().oneshot(request(None)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::LAST_MODIFIED).is_none());
+
+    let since = httpdate::fmt_http_date(modified);
+    let res = svc_fresh
+        .oneshot(request(Some(since.clone())))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let svc_old = svc(modified + Duration::from_secs(2));
+    let res = svc_old.clone().oneshot(request(None)).await.unwrap();
+    assert_eq!(res.headers()[header::LAST_MODIFIED], since.as_str());
+
+    let res = svc_old.oneshot(request(Some(since))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn not_modified_headers() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .response_header(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+        .response_header(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+    let res = svc
+        .clone()
+        .oneshot(Request::new(Body::empty()))
+        .await
+        .unwrap();
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    let request = || {
+        Request::builder()
+            .header(header::IF_MODIFIED_SINCE, &last_modified)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = svc.clone().oneshot(request()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::LAST_MODIFIED], last_modified);
+    assert_eq!(res.headers()[header::CONTENT_LOCATION], "/index.html");
+    assert_eq!(res.headers()[header::VARY], "accept-encoding");
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+    assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+    let svc = svc.not_modified_headers(NotModifiedHeaders::Strict);
+    let res = svc.oneshot(request()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(res.headers().get(header::LAST_MODIFIED).is_none());
+    assert!(res.headers().get(header::X_CONTENT_TYPE_OPTIONS).is_none());
+    assert_eq!(res.headers()[header::CONTENT_LOCATION], "/index.html");
+    assert_eq!(res.headers()[header::VARY], "accept-encoding");
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+}
+
+#[tokio::test]
+async fn etag() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).precompressed_gzip();
+    let request = |if_none_match: &str, accept_encoding: &str| {
+        Request::builder()
+            .uri("/precompressed.txt")
+            .header(header::IF_NONE_MATCH, if_none_match)
+            .header(header::ACCEPT_ENCODING, accept_encoding)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = svc.clone().oneshot(request("\"x\"", "")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res.headers()[header::ETAG].clone();
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+
+    let res = svc
+        .clone()
+        .oneshot(request(&format!("\"x\", W/{}", etag.to_str().unwrap()), ""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::ETAG], etag);
+
+    // the precompressed variant is another representation
+    let res = svc
+        .clone()
+        .oneshot(request(etag.to_str().unwrap(), "gzip"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_ne!(res.headers()[header::ETAG], etag);
+
+    // the If-Modified-Since is ignored with the If-None-Match
+    let mut req = request("\"x\"", "");
+    req.headers_mut()
+        .insert(header::IF_MODIFIED_SINCE, last_modified);
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = svc.oneshot(request("*", "")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // made of the size and the modified time without the filesystem ETag
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+    let svc = ServeDir::new(IncludeDirFilesystem::new(ROOT.clone()));
+    let res = svc.clone().oneshot(request("\"x\"", "")).await.unwrap();
+    let etag = res.headers()[header::ETAG].clone();
+    let res = svc
+        .oneshot(request(etag.to_str().unwrap(), ""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn conditional_fallback() {
+    let fallback = service_fn(|_| async {
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "text/html")
+            .header(header::ETAG, "\"app\"")
+            .header(header::LAST_MODIFIED, "Sun, 01 Jan 2023 00:00:00 GMT")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from("app"))
+            .unwrap();
+
+        Ok::<_, io::Error>(res)
+    });
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).fallback(fallback);
+    let request = |name: header::HeaderName, value: &str| {
+        Request::builder()
+            .uri("/app/page")
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // sent as is by default
+    let res = svc
+        .clone()
+        .oneshot(request(header::IF_NONE_MATCH, "\"app\""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "app");
+
+    let svc = svc.conditional_fallback(true);
+    let res = svc
+        .clone()
+        .oneshot(request(header::IF_NONE_MATCH, "\"app\""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::ETAG], "\"app\"");
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+    assert!(res.headers().get(header::CONTENT_TYPE).is_none());
+    assert!(res.into_body().data().await.is_none());
+
+    let res = svc
+        .clone()
+        .oneshot(request(
+            header::IF_MODIFIED_SINCE,
+            "Sun, 01 Jan 2023 00:00:00 GMT",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let res = svc
+        .clone()
+        .oneshot(request(
+            header::IF_UNMODIFIED_SINCE,
+            "Sat, 31 Dec 2022 00:00:00 GMT",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    let res = svc
+        .oneshot(request(header::IF_NONE_MATCH, "\"old\""))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "app");
+}
+
+#[tokio::test]
+async fn clock_clamps_future_last_modified() {
+    let modified = std::fs::metadata("README.md").unwrap().modified().unwrap();
+    // the file looks modified in the future by the clock
+    let now = modified - Duration::from_secs(24 * 60 * 60);
+    let svc = ServeDir::new(DiskFilesystem::from(".")).clock(move || now);
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    assert_eq!(last_modified, httpdate::fmt_http_date(now));
+
+    // the deterministic responses are clamped too
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().deterministic(true).oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()[header::LAST_MODIFIED], last_modified);
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // later than the clock, ignored
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(
+            header::IF_MODIFIED_SINCE,
+            httpdate::fmt_http_date(now + Duration::from_secs(60 * 60)),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn include_dir_build_time() {
+    static ENTRIES: &[include_dir::DirEntry<'static>] = &[include_dir::DirEntry::File(
+        include_dir::File::new("no-metadata.txt", b"no metadata"),
+    )];
+    static ROOT: Dir<'static> = Dir::new("", ENTRIES);
+
+    let build_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let svc = ServeDir::new(IncludeDirFilesystem::new(ROOT.clone()).build_time(build_time));
+
+    let req = Request::builder()
+        .uri("/no-metadata.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    assert_eq!(last_modified, httpdate::fmt_http_date(build_time));
+
+    let req = Request::builder()
+        .uri("/no-metadata.txt")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn include_dir_modified() {
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+
+    let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let build_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let filesystem = IncludeDirFilesystem::new(ROOT.clone())
+        .build_time(build_time)
+        .modified("index.html", modified);
+
+    let metadata = filesystem.metadata(Path::new("index.html")).await.unwrap();
+    assert_eq!(metadata.modified, Some(modified));
+
+    // the metadata of include_dir is preferred to the build time
+    let metadata = filesystem
+        .metadata(Path::new("precompressed.txt"))
+        .await
+        .unwrap();
+    assert!(metadata.modified.is_some());
+    assert_ne!(metadata.modified, Some(build_time));
+}
+
+#[tokio::test]
+async fn include_dir_compressed_only() {
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+
+    let svc = ServeDir::new(IncludeDirFilesystem::new(ROOT.clone())).precompressed_gzip();
+
+    let request = Request::builder()
+        .uri("/only_gzipped.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+
+    // decompressed for the clients which don't accept gzip
+    let request = Request::builder()
+        .uri("/only_gzipped.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(request).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert!(res.headers().get("content-encoding").is_none());
+
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.starts_with("\"This is a test file\""));
+}
+
+#[tokio::test]
+async fn precompressed_head_request_prefers_existing_variant() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .precompressed_br();
+
+    // the preferred gzip variant is missing, the br one is used
+    let req = Request::builder()
+        .uri("/precompressed_br.txt")
+        .header("Accept-Encoding", "gzip, br;q=0.5")
+        .method(Method::HEAD)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "br");
+
+    // both variants exist, the preferred one is used
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip;q=0.5, br")
+        .method(Method::HEAD)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "br");
+}
+
+#[tokio::test]
+async fn precompressed_get_request_prefers_existing_variant() {
+    for concurrent_probes in [false, true] {
+        let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+            .precompressed_gzip()
+            .precompressed_br()
+            .concurrent_probes(concurrent_probes);
+
+        // the preferred gzip variant is missing, the br one is used
+        let req = Request::builder()
+            .uri("/precompressed_br.txt")
+            .header("Accept-Encoding", "gzip, br;q=0.5")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-encoding"], "br");
+
+        let body = res.into_body().data().await.unwrap().unwrap();
+        let mut decompressed = Vec::new();
+        BrotliDecompress(&mut &body[..], &mut decompressed).unwrap();
+        assert!(decompressed.starts_with(b"Test file"));
+
+        // the uncompressed file is used when no variant exists
+        let req = Request::builder()
+            .uri("/missing_precompressed.txt")
+            .header("Accept-Encoding", "gzip, br")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("content-encoding").is_none());
+    }
+}
+
+#[tokio::test]
+async fn route_manifest() {
+    let filesystem = DiskFilesystem::from("test-files");
+    let manifest = RouteManifest::build(&filesystem).await.unwrap();
+
+    let entry = manifest.get("/precompressed.txt").unwrap();
+    assert_eq!(
+        entry.size(),
+        Some(
+            std::fs::metadata("test-files/precompressed.txt")
+                .unwrap()
+                .len()
+        )
+    );
+    assert_eq!(entry.mime(), Some("text/plain"));
+    let mut precompressed = entry.precompressed().to_vec();
+    precompressed.sort_unstable();
+    assert_eq!(precompressed, ["br", "deflate", "gzip"]);
+    assert!(manifest
+        .get("missing_precompressed.txt")
+        .unwrap()
+        .precompressed()
+        .is_empty());
+    assert!(manifest.is_dir(""));
+
+    let svc = ServeDir::new(filesystem)
+        .precompressed_gzip()
+        .route_manifest(manifest);
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+
+    let req = Request::builder()
+        .uri("/missing_precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("content-encoding").is_none());
+
+    let req = Request::builder()
+        .uri("/not-exist.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn route_manifest_include_dir() {
+    static ROOT: Dir<'_> = include_dir::include_dir!("test-files");
+
+    let manifest = RouteManifest::build(&IncludeDirFilesystem::new(ROOT.clone()))
+        .await
+        .unwrap();
+
+    // the files of the sub dirs are included
+    assert_eq!(manifest.len(), ROOT.files().count() + 1);
+    assert!(manifest.get("index.html").is_some());
+    assert!(manifest.get(".well-known/acme-challenge/token").is_some());
+    assert!(manifest.is_dir(".well-known"));
+}
+
+#[tokio::test]
+async fn immutable() {
+    let dir = std::env::temp_dir().join(format!("http_dir-immutable-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "first").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).immutable();
+
+    let req = Request::builder()
+        .uri("/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    assert_eq!(body_into_text(res.into_body()).await, "first");
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // the changes after the snapshot are not seen
+    std::fs::write(dir.join("file.txt"), "changed file").unwrap();
+    std::fs::write(dir.join("missing.txt"), "created").unwrap();
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+
+    let req = Request::builder()
+        .uri("/file.txt")
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn asset_manifest() {
+    let dir = std::env::temp_dir().join(format!("http_dir-asset-manifest-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::write(dir.join("app.js"), "hello").unwrap();
+    std::fs::write(dir.join("app.js.gz"), "gzipped").unwrap();
+    std::fs::write(dir.join("css/site.css"), "hello").unwrap();
+    std::fs::write(dir.join(".env"), "secret").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .hide_dot_files(true)
+        .asset_manifest("/asset-manifest.json")
+        .purge_method("token");
+    let get = |if_none_match: Option<HeaderValue>| {
+        let mut req = Request::builder().uri("/asset-manifest.json");
+        if let Some(etag) = if_none_match {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        svc.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+
+    let res = get(None).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/json");
+    assert_eq!(res.headers()["cache-control"], "no-cache");
+    let etag = res.headers()["etag"].clone();
+
+    let body = body_into_text(res.into_body()).await;
+    let hash = "sha256-LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=";
+    assert!(
+        body.starts_with(r#"{"files":{"app.js":{"size":5,"etag":""#),
+        "{body}"
+    );
+    assert!(
+        body.contains(&format!(r#""hash":"{hash}"}},"css/site.css":{{"size":5,"#)),
+        "{body}"
+    );
+    assert!(
+        !body.contains(".env") && !body.contains("app.js.gz"),
+        "{body}"
+    );
+
+    let res = get(Some(etag.clone())).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()["etag"], etag);
+
+    // the manifest is kept until the files are purged
+    std::fs::write(dir.join("new.js"), "new").unwrap();
+    assert_eq!(
+        get(Some(etag.clone())).await.unwrap().status(),
+        StatusCode::NOT_MODIFIED
+    );
+
+    let req = Request::builder()
+        .method("PURGE")
+        .uri("/")
+        .header(header::AUTHORIZATION, "Bearer token")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        svc.clone().oneshot(req).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+
+    let res = get(Some(etag.clone())).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_ne!(res.headers()["etag"], etag);
+    assert!(body_into_text(res.into_body())
+        .await
+        .contains(r#""new.js":{"size":3,"#));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn hashed_assets() {
+    let dir = std::env::temp_dir().join(format!("http_dir-hashed-assets-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("js")).unwrap();
+    std::fs::write(dir.join("js/app.js"), "hello").unwrap();
+    std::fs::write(dir.join("js/app.js.gz"), "gzipped").unwrap();
+    std::fs::write(
+        dir.join("index.html"),
+        r#"<script src="js/app.js"></script>"#,
+    )
+    .unwrap();
+
+    let mut filesystem = DiskFilesystem::from(dir.as_path());
+    let hashes = AssetHashes::build(&mut filesystem).await.unwrap();
+    assert_eq!(hashes.iter().count(), 1);
+    let hashed = hashes.hashed("js/app.js").unwrap().to_string();
+    assert!(
+        hashed.starts_with("js/app.") && hashed.ends_with(".js"),
+        "{hashed}"
+    );
+    assert_eq!(hashes.original(&hashed), Some("js/app.js"));
+    assert_eq!(
+        hashes.rewrite_html(
+            "/index.html",
+            &std::fs::read_to_string(dir.join("index.html")).unwrap()
+        ),
+        format!(r#"<script src="{hashed}"></script>"#)
+    );
+
+    let svc = ServeDir::new(filesystem).hashed_assets(hashes);
+    let get = |path: String| {
+        svc.clone()
+            .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+    };
+
+    let res = get(format!("/{hashed}")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()["cache-control"],
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(body_into_text(res.into_body()).await, "hello");
+
+    let res = get("/js/app.js".to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("cache-control").is_none());
+
+    assert_eq!(
+        get("/js/app.00000000.js".to_string())
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::NOT_FOUND
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn purge() {
+    let dir = std::env::temp_dir().join(format!("http_dir-purge-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("file.txt"), "first").unwrap();
+    std::fs::write(dir.join("sub/file.txt"), "first").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .immutable()
+        .purge_method("token");
+    let content_length = |uri: &'static str| {
+        let svc = svc.clone();
+        async move {
+            let req = Request::builder()
+                .method(Method::HEAD)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.oneshot(req).await.unwrap();
+            res.headers()
+                .get(header::CONTENT_LENGTH)
+                .map(|len| len.to_str().unwrap().to_string())
+        }
+    };
+    let purge = |uri: &'static str, token: Option<&'static str>| {
+        let mut req = Request::builder().method("PURGE").uri(uri);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        svc.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(content_length("/file.txt").await.unwrap(), "5");
+    assert_eq!(content_length("/sub/file.txt").await.unwrap(), "5");
+    assert_eq!(content_length("/new.txt").await, None);
+    std::fs::write(dir.join("file.txt"), "changed file").unwrap();
+    std::fs::write(dir.join("sub/file.txt"), "changed file").unwrap();
+    std::fs::write(dir.join("new.txt"), "new").unwrap();
+
+    for token in [None, Some("wrong")] {
+        let res = purge("/file.txt", token).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+    assert_eq!(content_length("/file.txt").await.unwrap(), "5");
+
+    let res = purge("/file.txt", Some("token")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(content_length("/file.txt").await.unwrap(), "12");
+    assert_eq!(content_length("/sub/file.txt").await.unwrap(), "5");
+    assert_eq!(content_length("/new.txt").await, None);
+
+    let res = purge("/sub/", Some("token")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(content_length("/sub/file.txt").await.unwrap(), "12");
+    assert_eq!(content_length("/new.txt").await, None);
+
+    let res = purge("/", Some("token")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(content_length("/new.txt").await.unwrap(), "3");
+
+    let req = Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[ALLOW], "GET,HEAD,OPTIONS,PURGE");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn purge_method_before_immutable() {
+    let dir = std::env::temp_dir().join(format!("http_dir-purge-order-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "first").unwrap();
+
+    let fallback = service_fn(|_: Request<Body>| async move {
+        Ok::<_, io::Error>(Response::new(Body::from("fallback")))
+    });
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .purge_method("token")
+        .hide_dot_files(true)
+        .fallback(fallback)
+        .immutable();
+    let request = |method: &'static str, uri: &'static str| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+        svc.clone().oneshot(req)
+    };
+
+    let res = request("HEAD", "/file.txt").await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+    std::fs::write(dir.join("file.txt"), "changed file").unwrap();
+
+    // the snapshot of the immutable filesystem is purged too
+    let res = request("PURGE", "/file.txt").await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = request("HEAD", "/file.txt").await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "12");
+
+    // the paths which can't be served aren't passed to the fallback
+    for uri in ["/.env", "/%FF"] {
+        let res = request("PURGE", uri).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{uri}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn precompressed_probe_cache() {
+    let dir = std::env::temp_dir().join(format!("http_dir-probe-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), "uncompressed").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path()))
+        .precompressed_gzip()
+        .precompressed_probe_cache(Duration::from_secs(60))
+        .purge_method("token");
+    let content_encoding = || {
+        let svc = svc.clone();
+        async move {
+            let req = Request::builder()
+                .uri("/file.txt")
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            res.headers()
+                .get(header::CONTENT_ENCODING)
+                .map(|encoding| encoding.to_str().unwrap().to_string())
+        }
+    };
+
+    assert_eq!(content_encoding().await, None);
+    std::fs::copy("test-files/precompressed.txt.gz", dir.join("file.txt.gz")).unwrap();
+    // the missing variant is remembered
+    assert_eq!(content_encoding().await, None);
+
+    let req = Request::builder()
+        .method("PURGE")
+        .uri("/file.txt")
+        .header(header::AUTHORIZATION, "Bearer token")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(content_encoding().await.unwrap(), "gzip");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_changed_mid_stream() {
+    let dir = std::env::temp_dir().join(format!("http_dir-mid-stream-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let truncated = dir.join("truncated.txt");
+    let grown = dir.join("grown.txt");
+    std::fs::write(&truncated, "0123456789").unwrap();
+    std::fs::write(&grown, "0123456789").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).with_buf_chunk_size(4);
+
+    let req = Request::builder()
+        .uri("/truncated.txt")
+        .body(Body::empty())
+        .unwrap();
+    let mut body = svc.clone().oneshot(req).await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), "0123");
+
+    std::fs::write(&truncated, "").unwrap();
+    let err = loop {
+        match body.data().await.unwrap() {
+            Ok(_) => continue,
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let req = Request::builder()
+        .uri("/grown.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    std::fs::write(&grown, "0123456789 and more").unwrap();
+
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "10");
+    assert_eq!(body_into_text(res.into_body()).await, "0123456789");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn defer_open() {
+    let dir = std::env::temp_dir().join(format!("http_dir-defer-open-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    std::fs::write(&file, "0123456789").unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).defer_open(true);
+
+    let req = Request::builder()
+        .uri("/file.txt")
+        .header(header::RANGE, "bytes=2-5")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "2345");
+
+    // the file isn't opened until the body is read
+    let req = Request::builder()
+        .uri("/file.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "10");
+
+    std::fs::remove_file(&file).unwrap();
+    let err = res.into_body().data().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn repr_digest_trailer() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).repr_digest_trailer(true);
+
+    let req = Request::builder()
+        .uri("/precompressed_br.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.headers()[header::TRAILER], "repr-digest");
+    let mut body = res.into_body();
+    while let Some(data) = body.data().await {
+        data.unwrap();
+    }
+    let trailers = body.trailers().await.unwrap().unwrap();
+    assert_eq!(
+        trailers["repr-digest"],
+        "sha-256=:EUgRsLiZjLmFOlN5WYAhQQ/t32m7Lue3FF0FKn6bXUU=:"
+    );
+
+    // the range responses are not the whole representation
+    let req = Request::builder()
+        .uri("/precompressed_br.txt")
+        .header(header::RANGE, "bytes=0-3")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(res.headers().get(header::TRAILER).is_none());
+}
+
+#[tokio::test]
+async fn range_guard() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).range_guard(
+        RangeGuard::new().max_per_connection(2).hook(|request| {
+            request
+                .ranges
+                .iter()
+                .all(|range| range.end() > range.start())
+        }),
+    );
+
+    let range_request = |addr: &str, range: &str| {
+        let mut req = Request::builder()
+            .uri("/precompressed_br.txt")
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(addr.parse::<SocketAddr>().unwrap());
+        req
+    };
+
+    // the tiny range is rejected by the hook
+    let res = svc
+        .clone()
+        .oneshot(range_request("10.0.0.1:1000", "bytes=0-0"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        res.extensions().get::<ServedFile>().unwrap().status_reason,
+        StatusReason::RangeRejected
+    );
+
+    let res = svc
+        .clone()
+        .oneshot(range_request("10.0.0.1:1000", "bytes=0-1"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+    // over the limit of the connection
+    let res = svc
+        .clone()
+        .oneshot(range_request("10.0.0.1:1000", "bytes=0-1"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // another connection has its own count
+    let res = svc
+        .oneshot(range_request("10.0.0.1:1001", "bytes=0-1"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[tokio::test]
+async fn media_first_range() {
+    let dir = std::env::temp_dir().join(format!("http_dir-media-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let content = (0..100).map(|i| i as u8).collect::<Vec<_>>();
+    std::fs::write(dir.join("clip.mp4"), &content).unwrap();
+    std::fs::write(dir.join("song.mp3"), &content[..5]).unwrap();
+    std::fs::write(dir.join("data.bin"), &content).unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from(dir.as_path())).media_first_range(10);
+    let range_request = |uri: &str, range: &str| {
+        Request::builder()
+            .uri(uri)
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = svc
+        .clone()
+        .oneshot(range_request("/clip.mp4", "bytes=0-"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 0-9/100");
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "10");
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body, content[..10]);
+
+    // the file is smaller than the first range
+    let res = svc
+        .clone()
+        .oneshot(range_request("/song.mp3", "bytes=0-"))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 0-4/5");
+
+    // the other ranges and types are served as requested
+    for (uri, range, content_range) in [
+        ("/clip.mp4", "bytes=0-49", "bytes 0-49/100"),
+        ("/clip.mp4", "bytes=50-", "bytes 50-99/100"),
+        ("/data.bin", "bytes=0-", "bytes 0-99/100"),
+    ] {
+        let res = svc
+            .clone()
+            .oneshot(range_request(uri, range))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], content_range);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn transform() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let transform = Transform::new(&["case"], {
+        let calls = calls.clone();
+        move |request| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if request.params != [("case".to_string(), "upper".to_string())] {
+                    return Ok(None);
+                }
+                let upper = String::from_utf8(request.contents.to_vec())
+                    .unwrap()
+                    .to_uppercase();
+
+                Ok(Some(Transformed::new(
+                    upper,
+                    HeaderValue::from_static("text/plain"),
+                )))
+            }
+        }
+    });
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .precompressed_gzip()
+        .transform(transform);
+    let original = std::fs::read_to_string("test-files/index.html").unwrap();
+
+    for method in [Method::GET, Method::HEAD, Method::GET] {
+        let req = Request::builder()
+            .method(method.clone())
+            .uri("/index.html?case=upper")
+            .header(header::RANGE, "bytes=0-1")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(
+            res.headers()[header::CONTENT_LENGTH],
+            original.len().to_string()
+        );
+        assert_eq!(res.headers()[header::VARY], "accept");
+        let body = body_into_text(res.into_body()).await;
+        if method == Method::GET {
+            assert_eq!(body, original.to_uppercase());
+        }
+    }
+    // the representation is cached
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // the file is served as is when it isn't transformed
+    for uri in [
+        "/index.html?case=lower",
+        "/index.html?other=1",
+        "/index.html",
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(body_into_text(res.into_body()).await, original);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // the precompressed variants aren't transformed
+    let req = Request::builder()
+        .uri("/precompressed.txt?case=upper")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        std::fs::read_to_string("test-files/precompressed.txt")
+            .unwrap()
+            .to_uppercase()
+    );
+}
+
+#[tokio::test]
+async fn transform_formats() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let transform = Transform::new(&["w"], {
+        let calls = calls.clone();
+        move |request| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(request
+                    .format
+                    .map(|format| Transformed::new(format.as_bytes().to_vec(), format)))
+            }
+        }
+    })
+    .formats(&["image/avif", "image/webp"]);
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).transform(transform);
+
+    for (accept, content_type) in [
+        ("image/avif,image/webp,*/*;q=0.8", "image/avif"),
+        ("image/avif", "image/avif"),
+        ("image/webp,*/*", "image/webp"),
+        ("*/*", "text/html"),
+    ] {
+        let req = Request::builder()
+            .uri("/index.html?w=200")
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            content_type,
+            "{accept}"
+        );
+        assert_eq!(res.headers()[header::VARY], "accept");
+    }
+    // cached by the negotiated format, the source format isn't cached
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn transform_sources() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let transform = |max_source_size| {
+        let calls = calls.clone();
+        Transform::new(&["case"], move |request| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(Some(Transformed::new(
+                    request.contents.to_ascii_uppercase(),
+                    HeaderValue::from_static("text/plain"),
+                )))
+            }
+        })
+        .sources(|path, _| path.extension().is_some_and(|extension| extension == "txt"))
+        .max_source_size(max_source_size)
+    };
+    let len = std::fs::metadata("test-files/precompressed.txt")
+        .unwrap()
+        .len();
+
+    for (max_source_size, uri, content_type) in [
+        (len, "/precompressed.txt?case=upper", "text/plain"),
+        (len, "/index.html?case=upper", "text/html"),
+        (len - 1, "/precompressed.txt?case=upper", "text/plain"),
+    ] {
+        let svc =
+            ServeDir::new(DiskFilesystem::from("test-files")).transform(transform(max_source_size));
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], content_type, "{uri}");
+    }
+    // the function is only called with the accepted sources not larger than the max size
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .rate_limit(TokenBucket::new(1, Duration::from_secs(60)), |parts| {
+            Some(parts.extensions.get::<SocketAddr>()?.ip().to_string())
+        });
+
+    let request = |addr: &str| {
+        let mut req = Request::builder()
+            .uri("/index.html")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(addr.parse::<SocketAddr>().unwrap());
+        req
+    };
+
+    let res = svc.clone().oneshot(request("10.0.0.1:1000")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = svc.clone().oneshot(request("10.0.0.1:1001")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+
+    let res = svc.oneshot(request("10.0.0.2:1000")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn request_body_policy() {
+    let request = |body: &'static str| {
+        Request::builder()
+            .uri("/index.html")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let res = svc.oneshot(request("ignored")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .request_body_policy(RequestBodyPolicy::Reject);
+    let res = svc.clone().oneshot(request("")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = svc.oneshot(request("unexpected")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(res.headers()[header::CONNECTION], "close");
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .request_body_policy(RequestBodyPolicy::Drain(4));
+    let res = svc.clone().oneshot(request("1234")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CONNECTION).is_none());
+
+    let res = svc.oneshot(request("12345")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(res.headers()[header::CONNECTION], "close");
+}
+
+// a filesystem which can't report the file size
+#[derive(Clone)]
+struct UnknownLenFilesystem(DiskFilesystem);
+
+struct UnknownLenFile(DiskFile);
+
+impl tokio::io::AsyncRead for UnknownLenFile {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl tokio::io::AsyncSeek for UnknownLenFile {
+    fn start_seek(mut self: std::pin::Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        std::pin::Pin::new(&mut self.0).start_seek(position)
+    }
+
+    fn poll_complete(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<u64>> {
+        std::pin::Pin::new(&mut self.0).poll_complete(cx)
+    }
+}
+
+impl FileExt for UnknownLenFile {
+    type Metadata<'a> = impl std::future::Future<Output = io::Result<Metadata>> + Send + Sync + 'a;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        async move {
+            let mut metadata = self.0.metadata().await?;
+            metadata.len = None;
+            Ok(metadata)
+        }
+    }
+}
+
+impl Filesystem for UnknownLenFilesystem {
+    type File = UnknownLenFile;
+    type OpenFile<'a> =
+        impl std::future::Future<Output = io::Result<UnknownLenFile>> + Send + Sync + 'a;
+    type IsDir<'a> = <DiskFilesystem as Filesystem>::IsDir<'a>;
+    type Metadata<'a> = impl std::future::Future<Output = io::Result<Metadata>> + Send + Sync + 'a;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        async move { self.0.open(path).await.map(UnknownLenFile) }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        self.0.is_dir(path)
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        async move {
+            let mut metadata = self.0.metadata(path).await?;
+            metadata.len = None;
+            Ok(metadata)
+        }
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> crate::fs::ReadDir<'a> {
+        self.0.read_dir(path)
+    }
+}
+
+#[tokio::test]
+async fn unknown_file_len() {
+    let svc = ServeDir::new(UnknownLenFilesystem(DiskFilesystem::from("test-files")));
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=0-3")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+    assert!(res.headers().get(header::ACCEPT_RANGES).is_none());
+    assert_eq!(res.extensions().get::<ServedFile>().unwrap().len, None);
+
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+}
+
+// a filesystem whose files can't be seeked, the ranges are read with `FileExt::read_at`
+#[derive(Clone)]
+struct NoSeekFilesystem(DiskFilesystem);
+
+struct NoSeekFile(DiskFile);
+
+impl tokio::io::AsyncRead for NoSeekFile {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl tokio::io::AsyncSeek for NoSeekFile {
+    fn start_seek(self: std::pin::Pin<&mut Self>, _position: io::SeekFrom) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "no seek"))
+    }
+
+    fn poll_complete(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<u64>> {
+        std::task::Poll::Ready(Ok(0))
+    }
+}
+
+impl FileExt for NoSeekFile {
+    type Metadata<'a> = <DiskFile as FileExt>::Metadata<'a>;
+
+    fn metadata(&self) -> Self::Metadata<'_> {
+        self.0.metadata()
+    }
+
+    fn read_at(&self, offset: u64, buf: bytes::BytesMut) -> Option<crate::fs::ReadAt> {
+        self.0.read_at(offset, buf)
+    }
+}
+
+impl Filesystem for NoSeekFilesystem {
+    type File = NoSeekFile;
+    type OpenFile<'a> =
+        impl std::future::Future<Output = io::Result<NoSeekFile>> + Send + Sync + 'a;
+    type IsDir<'a> = <DiskFilesystem as Filesystem>::IsDir<'a>;
+    type Metadata<'a> = <DiskFilesystem as Filesystem>::Metadata<'a>;
+
+    fn open<'a>(&'a mut self, path: &'a Path) -> Self::OpenFile<'a> {
+        async move { self.0.open(path).await.map(NoSeekFile) }
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Self::IsDir<'a> {
+        self.0.is_dir(path)
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> Self::Metadata<'a> {
+        self.0.metadata(path)
+    }
+}
+
+#[tokio::test]
+async fn positional_range_reads() {
+    let request = || {
+        Request::builder()
+            .uri("/index.html")
+            .header(header::RANGE, "bytes=3-7")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let filesystem = NoSeekFilesystem(DiskFilesystem::from("test-files").handle_cache(1));
+    for defer in [false, true] {
+        let svc = ServeDir::new(filesystem.clone())
+            .with_buf_chunk_size(2)
+            .defer_open(defer);
+        let res = svc.oneshot(request()).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body_into_text(res.into_body()).await, "HTML!");
+    }
+
+    // without the handle cache the file is seeked
+    let svc = ServeDir::new(NoSeekFilesystem(DiskFilesystem::from("test-files")));
+    let res = svc.oneshot(request()).await.unwrap();
+    let err = res.into_body().data().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[tokio::test]
+async fn positional_reads() {
+    let svc = ServeDir::new(DiskFilesystem::from("test-files").positional_reads(true))
+        .precompressed_gzip();
+
+    let req = Request::new(Body::empty());
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "13");
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=3-6")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "HTML");
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+}
+
+#[tokio::test]
+async fn positional_reads_poll_read() {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut filesystem = DiskFilesystem::from("test-files").positional_reads(true);
+    let mut file = filesystem.open(Path::new("index.html")).await.unwrap();
+
+    // the reused buffer is larger than the later reads
+    let mut contents = vec![0; 64];
+    let n = file.read(&mut contents).await.unwrap();
+    assert_eq!(&contents[..n], b"<b>HTML!</b>\n");
+
+    file.seek(io::SeekFrom::Start(3)).await.unwrap();
+    let mut chunk = [0; 2];
+    let mut contents = vec![];
+    loop {
+        let n = file.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        contents.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(contents, b"HTML!</b>\n");
+}
+
+#[tokio::test]
+async fn blocking_filesystem() {
+    struct StdStorage(PathBuf);
+
+    impl BlockingStorage for StdStorage {
+        type File = std::fs::File;
+
+        fn open(&self, path: &Path) -> io::Result<Self::File> {
+            std::fs::File::open(self.0.join(path))
+        }
+
+        fn is_dir(&self, path: &Path) -> io::Result<bool> {
+            Ok(std::fs::metadata(self.0.join(path))?.is_dir())
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+            let metadata = std::fs::metadata(self.0.join(path))?;
+            if metadata.is_dir() {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            Ok(Metadata::new(
+                metadata.modified().ok(),
+                Some(metadata.len()),
+            ))
+        }
+
+        fn read_dir(&self, _path: &Path) -> io::Result<Vec<DirEntry>> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    let filesystem =
+        BlockingFilesystem::new(StdStorage(PathBuf::from("test-files"))).concurrency(1);
+    let svc = ServeDir::new(filesystem).precompressed_gzip();
+
+    let req = Request::new(Body::empty());
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "13");
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=3-6")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "HTML");
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn filesystem_layer() {
+    let filesystem = DiskFilesystem::from("test-files")
+        .layer(ImmutableLayer::new())
+        .layer(layer_fn(|inner| {
+            GeneratedFilesystem::new(inner).file("/version.txt", "1.0.0")
+        }));
+    let svc = ServeDir::new(filesystem);
+
+    for (uri, body) in [("/version.txt", "1.0.0"), ("/", "<b>HTML!</b>\n")] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_into_text(res.into_body()).await, body);
+    }
+}
+
+#[tokio::test]
+async fn generated_filesystem() {
+    let mut headers = http::HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
+    let filesystem = GeneratedFilesystem::new(DiskFilesystem::from("test-files"))
+        .route("/version.json", || {
+            futures_util::stream::iter([
+                Ok(Bytes::from("{\"version\":")),
+                Ok(Bytes::from("\"1.0.0\"}")),
+            ])
+        })
+        .route_with_headers("/healthz", headers, || {
+            futures_util::stream::once(async { Ok(Bytes::from("ok")) })
+        });
+    let svc = ServeDir::new(filesystem);
+
+    let req = Request::builder()
+        .uri("/version.json")
+        .header(header::RANGE, "bytes=0-3")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "{\"version\":\"1.0.0\"}");
+
+    let req = Request::builder()
+        .uri("/healthz")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "ok");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+}
+
+#[tokio::test]
+async fn health_endpoints() {
+    let request = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let res = svc.oneshot(request("/healthz")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files")).health_endpoints(true);
+    let res = svc.clone().oneshot(request("/healthz")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+    assert_eq!(body_into_text(res.into_body()).await, "ok");
+
+    let res = svc.oneshot(request("/readyz")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files/missing")).health_endpoints(true);
+    let res = svc.clone().oneshot(request("/healthz")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = svc.oneshot(request("/readyz")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body_into_text(res.into_body()).await, "unavailable");
+}
+
+#[tokio::test]
+async fn generated_filesystem_in_memory_files() {
+    let filesystem = GeneratedFilesystem::new(DiskFilesystem::from("test-files"))
+        .file("/robots.txt", "User-agent: *\nDisallow:\n")
+        .file(
+            "/.well-known/security.txt",
+            "Contact: mailto:security@example.com\n",
+        );
+    let svc = ServeDir::new(filesystem);
+
+    let res = svc
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/robots.txt")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "24");
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    let served_file = res.extensions().get::<ServedFile>().unwrap().clone();
+    assert!(served_file.etag.is_some());
+    assert_eq!(
+        body_into_text(res.into_body()).await,
+        "User-agent: *\nDisallow:\n"
+    );
+
+    let res = svc
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/robots.txt")
+                .header(header::IF_MODIFIED_SINCE, last_modified)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let res = svc
+        .oneshot(
+            Request::builder()
+                .uri("/.well-known/security.txt")
+                .header(header::RANGE, "bytes=0-7")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "Contact:");
+}
+
+#[tokio::test]
+async fn server() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .ip_filter(IpFilter::new().allow("127.0.0.1"));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Server::bind(addr).serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let mut stream = loop {
+        match tokio::net::TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\n<b>HTML!</b>\n"));
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn server_title_case_headers() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = Server::bind(addr).http1_title_case_headers(true);
+    let server = tokio::spawn(server.serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let mut stream = loop {
+        match tokio::net::TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(
+        response.contains("\r\nContent-Type: text/html\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("\r\nContent-Length: "), "{response}");
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn server_tls() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let server = Server::bind(addr)
+        .tls_pem_files("test-certs/cert.pem", "test-certs/key.pem")
+        .unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(server.serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let mut roots = RootCertStore::empty();
+    let cert = std::fs::read("test-certs/cert.pem").unwrap();
+    for cert in rustls_pemfile::certs(&mut cert.as_slice()).unwrap() {
+        roots.add(&Certificate(cert)).unwrap();
+    }
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+
+    let stream = loop {
+        match tokio::net::TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    match stream.read_to_end(&mut response).await {
+        Ok(_) => {}
+        // the server may close without the TLS close_notify
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+        Err(err) => panic!("{err}"),
+    }
+    let response = String::from_utf8(response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\n<b>HTML!</b>\n"));
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn server_unix() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let path = std::env::temp_dir().join(format!("http_dir-{}.sock", std::process::id()));
+    // a stale socket is replaced
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Server::bind_unix(&path).serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let mut stream = loop {
+        match tokio::net::UnixStream::connect(&path).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\n<b>HTML!</b>\n"));
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+
+    // not activated by systemd
+    let err = Server::systemd()
+        .serve(ServeDir::new(DiskFilesystem::from("test-files")), async {})
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn server_h2c() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(Server::bind(addr).serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let client = hyper::Client::builder()
+        .http2_only(true)
+        .build_http::<Body>();
+    let uri: http::Uri = format!("http://{addr}/index.html").parse().unwrap();
+    let res = loop {
+        match client.get(uri.clone()).await {
+            Ok(res) => break res,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.version(), http::Version::HTTP_2);
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    drop(client);
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn server_http3() {
+    use bytes::Buf;
+    use quinn::crypto::rustls::QuicClientConfig;
+    use quinn::rustls::pki_types::CertificateDer;
+    use quinn::rustls::{self, ClientConfig, RootCertStore};
+
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    // the requests without a body aren't rejected
+    let svc = ServeDir::new(DiskFilesystem::from("test-files"))
+        .request_body_policy(RequestBodyPolicy::Reject);
+    let server = Server::bind(addr)
+        .tls_pem_files("test-certs/cert.pem", "test-certs/key.pem")
+        .unwrap()
+        .http3(true);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(server.serve(svc, async {
+        shutdown_rx.await.ok();
+    }));
+
+    let mut roots = RootCertStore::empty();
+    let cert = std::fs::read("test-certs/cert.pem").unwrap();
+    for cert in rustls_pemfile::certs(&mut cert.as_slice()).unwrap() {
+        roots.add(CertificateDer::from(cert)).unwrap();
+    }
+    let mut config = ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(std::sync::Arc::new(
+        QuicClientConfig::try_from(config).unwrap(),
+    )));
+    let connection = loop {
+        match endpoint.connect(addr, "localhost").unwrap().await {
+            Ok(connection) => break connection,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection))
+        .await
+        .unwrap();
+    let driver = tokio::spawn(async move { driver.wait_idle().await });
+
+    let req = http1::Request::get("https://localhost/index.html")
+        .body(())
+        .unwrap();
+    let mut stream = send_request.send_request(req).await.unwrap();
+    stream.finish().await.unwrap();
+
+    let res = stream.recv_response().await.unwrap();
+    assert_eq!(res.status(), http1::StatusCode::OK);
+    assert_eq!(res.headers()[http1::header::CONTENT_LENGTH], "13");
+
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    assert_eq!(body, b"<b>HTML!</b>\n");
+
+    let req = http1::Request::get("https://localhost/index.html")
+        .body(())
+        .unwrap();
+    let mut stream = send_request.send_request(req).await.unwrap();
+    stream
+        .send_data(Bytes::from_static(b"unexpected"))
+        .await
+        .unwrap();
+    stream.finish().await.unwrap();
+
+    let res = stream.recv_response().await.unwrap();
+    assert_eq!(res.status(), http1::StatusCode::PAYLOAD_TOO_LARGE);
+
+    drop(stream);
+    drop(send_request);
+    driver.await.unwrap();
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
@@ -0,0 +1,230 @@
+//! Runs the same requests against this crate and the tower-http `ServeDir`, which this crate is
+//! forked from, and diffs the responses, so the changes of the upstream semantics are noticed.
+
+use std::fmt::Write;
+
+use bytes::Bytes;
+use http::{header, HeaderName, Method, Request, Response};
+use http_body::Body as HttpBody;
+use hyper::Body;
+use tower::ServiceExt;
+
+use crate::fs::disk::DiskFilesystem;
+use crate::ServeDir;
+
+const ROOT: &str = "test-files";
+
+const COMPARED_HEADERS: [HeaderName; 7] = [
+    header::CONTENT_TYPE,
+    header::CONTENT_LENGTH,
+    header::CONTENT_ENCODING,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+    header::LAST_MODIFIED,
+    header::LOCATION,
+];
+
+// (method, uri, headers)
+type Case = (
+    &'static str,
+    &'static str,
+    &'static [(&'static str, &'static str)],
+);
+
+const CORPUS: &[Case] = &[
+    ("GET", "/", &[]),
+    ("GET", "/index.html", &[]),
+    ("HEAD", "/index.html", &[]),
+    ("GET", "/precompressed.txt", &[]),
+    ("GET", "/filename%20with%20space.txt", &[]),
+    ("GET", "/%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt", &[]),
+    ("GET", "/not-found.txt", &[]),
+    ("GET", "/../Cargo.toml", &[]),
+    ("GET", "/index.html?a=1", &[]),
+    ("POST", "/index.html", &[]),
+    // precompressed
+    ("GET", "/precompressed.txt", &[("accept-encoding", "gzip")]),
+    ("GET", "/precompressed.txt", &[("accept-encoding", "br")]),
+    (
+        "GET",
+        "/precompressed.txt",
+        &[("accept-encoding", "deflate")],
+    ),
+    (
+        "GET",
+        "/precompressed.txt",
+        &[("accept-encoding", "gzip;q=0.5, br")],
+    ),
+    (
+        "GET",
+        "/precompressed.txt",
+        &[("accept-encoding", "br;q=0")],
+    ),
+    (
+        "GET",
+        "/precompressed_br.txt",
+        &[("accept-encoding", "gzip")],
+    ),
+    (
+        "GET",
+        "/missing_precompressed.txt",
+        &[("accept-encoding", "gzip")],
+    ),
+    ("GET", "/only_gzipped.txt", &[("accept-encoding", "gzip")]),
+    ("HEAD", "/precompressed.txt", &[("accept-encoding", "gzip")]),
+    (
+        "GET",
+        "/precompressed.txt",
+        &[("accept-encoding", "identity")],
+    ),
+    // ranges
+    ("GET", "/index.html", &[("range", "bytes=0-4")]),
+    ("GET", "/index.html", &[("range", "bytes=-3")]),
+    ("GET", "/index.html", &[("range", "bytes=5-")]),
+    ("GET", "/index.html", &[("range", "bytes=100-200")]),
+    ("GET", "/index.html", &[("range", "bytes=4-1")]),
+    ("GET", "/index.html", &[("range", "lines=0-1")]),
+    ("HEAD", "/index.html", &[("range", "bytes=0-4")]),
+    // conditional
+    (
+        "GET",
+        "/index.html",
+        &[("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")],
+    ),
+    (
+        "GET",
+        "/index.html",
+        &[("if-modified-since", "Fri, 01 Jan 2100 00:00:00 GMT")],
+    ),
+    (
+        "GET",
+        "/index.html",
+        &[("if-unmodified-since", "Thu, 01 Jan 1970 00:00:00 GMT")],
+    ),
+    (
+        "GET",
+        "/index.html",
+        &[("if-unmodified-since", "Fri, 01 Jan 2100 00:00:00 GMT")],
+    ),
+    ("GET", "/index.html", &[("if-modified-since", "garbage")]),
+];
+
+// the requests answered differently from the upstream on purpose
+const KNOWN_DIFFERENCES: &[Case] = &[
+    // a date later than now is invalid, RFC 9110 section 13.1.3
+    (
+        "GET",
+        "/index.html",
+        &[("if-modified-since", "Fri, 01 Jan 2100 00:00:00 GMT")],
+    ),
+];
+
+struct Summary {
+    status: u16,
+    headers: Vec<(HeaderName, Option<String>)>,
+    body: Bytes,
+}
+
+impl Summary {
+    async fn new<B>(res: Response<B>) -> Self
+    where
+        B: HttpBody<Data = Bytes> + Unpin,
+        B::Error: std::fmt::Debug,
+    {
+        let headers = COMPARED_HEADERS
+            .iter()
+            .map(|name| {
+                let value = res
+                    .headers()
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string());
+                (name.clone(), value)
+            })
+            .collect();
+        let status = res.status().as_u16();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+fn request(method: &str, uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(Method::from_bytes(method.as_bytes()).unwrap())
+        .uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn same_as_tower_http() {
+    let ours = ServeDir::new(DiskFilesystem::from(ROOT))
+        .precompressed_gzip()
+        .precompressed_br()
+        .precompressed_deflate();
+    let upstream = tower_http::services::ServeDir::new(ROOT)
+        .precompressed_gzip()
+        .precompressed_br()
+        .precompressed_deflate();
+
+    let mut diffs = String::new();
+    for case @ (method, uri, headers) in CORPUS {
+        let ours = Summary::new(
+            ours.clone()
+                .oneshot(request(method, uri, headers))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let upstream = Summary::new(
+            upstream
+                .clone()
+                .oneshot(request(method, uri, headers))
+                .await
+                .unwrap(),
+        )
+        .await;
+
+        let mut case_diffs = String::new();
+        let mut diff = |what: &str, ours: &dyn std::fmt::Debug, upstream: &dyn std::fmt::Debug| {
+            writeln!(
+                case_diffs,
+                "{method} {uri} {headers:?} {what}: {ours:?} != upstream {upstream:?}"
+            )
+            .unwrap();
+        };
+
+        if ours.status != upstream.status {
+            diff("status", &ours.status, &upstream.status);
+        }
+        for ((name, ours), (_, upstream)) in ours.headers.iter().zip(&upstream.headers) {
+            if ours != upstream {
+                diff(name.as_str(), ours, upstream);
+            }
+        }
+        if ours.body != upstream.body {
+            diff("body", &ours.body.len(), &upstream.body.len());
+        }
+
+        match (KNOWN_DIFFERENCES.contains(case), case_diffs.is_empty()) {
+            (false, _) => diffs.push_str(&case_diffs),
+            // the upstream caught up, the known difference should be removed
+            (true, true) => {
+                writeln!(diffs, "{method} {uri} {headers:?} no longer differs").unwrap();
+            }
+            (true, false) => {}
+        }
+    }
+
+    assert!(
+        diffs.is_empty(),
+        "the responses differ from tower-http:\n{diffs}"
+    );
+}
@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::Body;
+use tokio::time::{self, Instant, Sleep};
+
+/// Abort the body when the client doesn't consume any data within the `timeout`.
+///
+/// The body is not polled while the client is stalled, so a watchdog task drops the inner body,
+/// which releases the file handle, and the next poll fails with [`io::ErrorKind::TimedOut`].
+pub(crate) struct StallTimeoutBody<B> {
+    state: Arc<Mutex<StallState<B>>>,
+}
+
+struct StallState<B> {
+    body: Option<B>,
+    last_progress: Instant,
+}
+
+impl<B> StallTimeoutBody<B>
+where
+    B: Send + 'static,
+{
+    /// Must be called in the tokio runtime, as the watchdog task is spawned.
+    pub(crate) fn new(body: B, timeout: Duration) -> Self {
+        let state = Arc::new(Mutex::new(StallState {
+            body: Some(body),
+            last_progress: Instant::now(),
+        }));
+
+        tokio::spawn(watchdog(Arc::downgrade(&state), timeout));
+
+        Self { state }
+    }
+}
+
+// exit when the body is dropped or timed out
+async fn watchdog<B>(state: Weak<Mutex<StallState<B>>>, timeout: Duration) {
+    loop {
+        let deadline = match state.upgrade() {
+            None => return,
+            Some(state) => state.lock().unwrap().last_progress + timeout,
+        };
+
+        time::sleep_until(deadline).await;
+
+        let state = match state.upgrade() {
+            None => return,
+            Some(state) => state,
+        };
+        let mut state = state.lock().unwrap();
+        if state.last_progress + timeout <= Instant::now() {
+            state.body = None;
+            return;
+        }
+    }
+}
+
+impl<B> Body for StallTimeoutBody<B>
+where
+    B: Body<Data = Bytes, Error = io::Error> + Unpin,
+{
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut state = self.state.lock().unwrap();
+        let body = match state.body.as_mut() {
+            None => return Poll::Ready(Some(Err(timed_out("response body stalled")))),
+            Some(body) => body,
+        };
+
+        let poll = Pin::new(body).poll_data(cx);
+        if let Poll::Ready(Some(Ok(_))) = poll {
+            state.last_progress = Instant::now();
+        }
+
+        poll
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let mut state = self.state.lock().unwrap();
+        match state.body.as_mut() {
+            None => Poll::Ready(Err(timed_out("response body stalled"))),
+            Some(body) => Pin::new(body).poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .body
+            .as_ref()
+            .is_some_and(|body| body.is_end_stream())
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        match &self.state.lock().unwrap().body {
+            None => http_body::SizeHint::default(),
+            Some(body) => body.size_hint(),
+        }
+    }
+}
+
+/// Abort the body when the deadline passes, the inner body is dropped at that time.
+pub(crate) struct DeadlineBody<B> {
+    body: Option<B>,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<B> DeadlineBody<B> {
+    pub(crate) fn new(body: B, deadline: std::time::Instant) -> Self {
+        Self {
+            body: Some(body),
+            sleep: Box::pin(time::sleep_until(deadline.into())),
+        }
+    }
+}
+
+impl<B> Body for DeadlineBody<B>
+where
+    B: Body<Data = Bytes, Error = io::Error> + Unpin,
+{
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = &mut *self;
+        if this.sleep.as_mut().poll(cx).is_ready() {
+            this.body = None;
+
+            return Poll::Ready(Some(Err(timed_out("response deadline passed"))));
+        }
+
+        match this.body.as_mut() {
+            None => Poll::Ready(Some(Err(timed_out("response deadline passed")))),
+            Some(body) => Pin::new(body).poll_data(cx),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.body.as_mut() {
+            None => Poll::Ready(Err(timed_out("response deadline passed"))),
+            Some(body) => Pin::new(body).poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.as_ref().is_some_and(|body| body.is_end_stream())
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        match &self.body {
+            None => http_body::SizeHint::default(),
+            Some(body) => body.size_hint(),
+        }
+    }
+}
+
+fn timed_out(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, msg)
+}
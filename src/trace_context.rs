@@ -0,0 +1,137 @@
+use http::{HeaderMap, HeaderName, HeaderValue};
+use tracing::Span;
+
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+/// The W3C `traceparent` of the request, like
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TraceParent<'a> {
+    pub(crate) trace_id: &'a str,
+    pub(crate) parent_id: &'a str,
+    pub(crate) sampled: bool,
+}
+
+impl<'a> TraceParent<'a> {
+    pub(crate) fn from_headers(headers: &'a HeaderMap) -> Option<Self> {
+        // more than one `traceparent` is invalid
+        let mut values = headers.get_all(TRACEPARENT).iter();
+        let value = values.next()?;
+        if values.next().is_some() {
+            return None;
+        }
+
+        Self::parse(value.to_str().ok()?.trim())
+    }
+
+    fn parse(value: &'a str) -> Option<Self> {
+        let mut parts = value.split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+
+        let is_hex = |part: &str, len: usize| {
+            part.len() == len && part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        if !is_hex(version, 2) || version == "ff" {
+            return None;
+        }
+        // the version 00 has exactly 4 parts, the later versions may append more
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        if trace_id.bytes().all(|b| b == b'0') || parent_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+
+        Some(Self {
+            trace_id,
+            parent_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+}
+
+/// The span of a request, it carries the trace context of the `traceparent` header, so the
+/// subscribers can attach it to the distributed trace of the caller.
+pub(crate) fn request_span(
+    method: &http::Method,
+    path: &str,
+    headers: &HeaderMap,
+    request_id: Option<&HeaderValue>,
+) -> Span {
+    let span = tracing::debug_span!(
+        "http_dir.request",
+        method = %method,
+        path,
+        request_id = request_id.and_then(|id| id.to_str().ok()),
+        vhost = tracing::field::Empty,
+        trace_id = tracing::field::Empty,
+        parent_span_id = tracing::field::Empty,
+        sampled = tracing::field::Empty,
+    );
+    if let Some(trace_parent) = TraceParent::from_headers(headers) {
+        span.record("trace_id", trace_parent.trace_id);
+        span.record("parent_span_id", trace_parent.parent_id);
+        span.record("sampled", trace_parent.sampled);
+    }
+
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_traceparent() {
+        assert_eq!(
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some(TraceParent {
+                trace_id: "4bf92f3577b34da6a3ce929d0e0e4736",
+                parent_id: "00f067aa0ba902b7",
+                sampled: true,
+            })
+        );
+        assert!(
+            !TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00")
+                .unwrap()
+                .sampled
+        );
+        // a later version may have more parts
+        assert!(TraceParent::parse(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra"
+        )
+        .is_some());
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(TraceParent::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn duplicated_traceparent() {
+        let mut headers = HeaderMap::new();
+        let value = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        headers.append(TRACEPARENT, value.parse().unwrap());
+        assert!(TraceParent::from_headers(&headers).is_some());
+
+        headers.append(TRACEPARENT, value.parse().unwrap());
+        assert_eq!(TraceParent::from_headers(&headers), None);
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http::{header, HeaderValue, Response, Uri};
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::content_encoding::QValue;
+use crate::fs::{Filesystem, Metadata};
+use crate::open_file::{FileOpened, FileRequestExtent};
+use crate::serve_dir::{body_from_bytes, empty_body};
+use crate::ResponseBody;
+
+const DEFAULT_CACHE_CAPACITY: usize = 128;
+const DEFAULT_MAX_SOURCE_SIZE: u64 = 16 * 1024 * 1024;
+
+type TransformFn = Arc<
+    dyn Fn(TransformRequest) -> BoxFuture<'static, io::Result<Option<Transformed>>> + Send + Sync,
+>;
+type SourceFn = Arc<dyn Fn(&Path, &HeaderValue) -> bool + Send + Sync>;
+
+/// A request of a derived representation passed to the [`Transform`] function
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TransformRequest {
+    /// The path of the source file passed to the filesystem
+    pub path: PathBuf,
+    /// The recognized query parameters, in the order of the query, like `[("w", "200")]`
+    pub params: Vec<(String, String)>,
+    /// The `Content-Type` of the source file
+    pub content_type: HeaderValue,
+    /// The `Accept` header of the request, to pick the output format
+    pub accept: Option<HeaderValue>,
+    /// The most preferred type of the [`Transform::formats`] accepted by the client, `None` if
+    /// there is none, then the source format should be kept
+    pub format: Option<HeaderValue>,
+    /// The contents of the source file
+    pub contents: Bytes,
+}
+
+/// A derived representation produced by the [`Transform`] function
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Transformed {
+    /// The contents of the representation
+    pub contents: Bytes,
+    /// The `Content-Type` of the representation
+    pub content_type: HeaderValue,
+}
+
+impl Transformed {
+    /// Create a new [`Transformed`].
+    pub fn new(contents: impl Into<Bytes>, content_type: HeaderValue) -> Self {
+        Self {
+            contents: contents.into(),
+            content_type,
+        }
+    }
+}
+
+// the representation is derived from the same source file, params and `Accept`, or only the
+// negotiated format of it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: PathBuf,
+    params: Vec<(String, String)>,
+    accept: Option<HeaderValue>,
+    modified: Option<SystemTime>,
+    len: Option<u64>,
+    etag: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<Key, (Transformed, u64)>,
+    tick: u64,
+}
+
+/// Produce the derived representations of the files, like the thumbnails of the images, for the
+/// requests with the recognized query parameters, see
+/// [`ServeDir::transform`](crate::ServeDir::transform).
+///
+/// The function is called with the whole source file, for the files accepted by
+/// [`Transform::sources`] up to the [`Transform::max_source_size`], the others are served as is
+/// without reading them. It returns `None` to serve the file as is too. The representations are cached by the source file, the recognized parameters and the
+/// `Accept` header, up to 128 of them by default, a changed file is transformed again. The
+/// clones share the same cache.
+///
+/// # Example
+///
+/// ```rust
+/// use http::HeaderValue;
+/// use http_dir::{Transform, Transformed};
+///
+/// let transform = Transform::new(&["w"], |request| async move {
+///     // resize the image to the width of the `w` parameter
+///     let thumbnail = request.contents;
+///
+///     Ok(Some(Transformed::new(thumbnail, HeaderValue::from_static("image/png"))))
+/// })
+/// .sources(|_, content_type| content_type.as_bytes().starts_with(b"image/"));
+/// ```
+#[derive(Clone)]
+pub struct Transform {
+    params: Arc<[String]>,
+    formats: Arc<[HeaderValue]>,
+    transform: TransformFn,
+    sources: Option<SourceFn>,
+    max_source_size: u64,
+    capacity: usize,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Debug for Transform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transform")
+            .field("params", &self.params)
+            .field("formats", &self.formats)
+            .field("sources", &self.sources.is_some())
+            .field("max_source_size", &self.max_source_size)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transform {
+    /// Create a new [`Transform`] calling the `transform` for the requests with any of the
+    /// `params` in the query.
+    pub fn new<F, Fut>(params: &[&str], transform: F) -> Self
+    where
+        F: Fn(TransformRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<Option<Transformed>>> + Send + 'static,
+    {
+        Self {
+            params: params.iter().map(|param| param.to_string()).collect(),
+            formats: Arc::new([]),
+            transform: Arc::new(move |request| Box::pin(transform(request))),
+            sources: None,
+            max_source_size: DEFAULT_MAX_SOURCE_SIZE,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            cache: Default::default(),
+        }
+    }
+
+    /// Keep up to `capacity` representations, the least recently used one is dropped when it is
+    /// full.
+    ///
+    /// Defaults to `128`, `0` disables the cache.
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Only transform the source files accepted by the `predicate` with their path and
+    /// `Content-Type`, like the images, the others are served as is without reading them.
+    ///
+    /// Defaults to any file.
+    pub fn sources<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&Path, &HeaderValue) -> bool + Send + Sync + 'static,
+    {
+        self.sources = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Serve the source files larger than `size` bytes, or of unknown size, as is without reading
+    /// them, so a request can't make the whole of a huge file read into the memory.
+    ///
+    /// Defaults to 16 MiB.
+    pub fn max_source_size(mut self, size: u64) -> Self {
+        self.max_source_size = size;
+        self
+    }
+
+    /// Check the file is transformed, see [`Transform::sources`] and
+    /// [`Transform::max_source_size`].
+    pub(crate) fn is_source(
+        &self,
+        path: &Path,
+        content_type: &HeaderValue,
+        len: Option<u64>,
+    ) -> bool {
+        len.is_some_and(|len| len <= self.max_source_size)
+            && self
+                .sources
+                .as_ref()
+                .map_or(true, |sources| sources(path, content_type))
+    }
+
+    /// Negotiate the output format from the `Accept` header among the media `types`, in the order
+    /// of preference, like `["image/avif", "image/webp"]`, the picked one is passed in the
+    /// [`TransformRequest::format`].
+    ///
+    /// Only the types listed in the `Accept` are picked, not the ones matched by a wildcard like
+    /// `image/*`, as it is sent by the clients which can't decode the newer formats too. The
+    /// representations are cached by the picked format instead of the whole `Accept` header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a type isn't a valid header value.
+    pub fn formats(mut self, types: &[&'static str]) -> Self {
+        self.formats = types.iter().map(|t| HeaderValue::from_static(t)).collect();
+        self
+    }
+
+    // the accepted format with the highest q-value, the earlier one in the formats wins the ties
+    fn negotiate(&self, accept: Option<&HeaderValue>) -> Option<HeaderValue> {
+        let accept = accept?.to_str().ok()?;
+        let mut best: Option<(&HeaderValue, QValue)> = None;
+        for format in self.formats.iter() {
+            let qvalue = accept
+                .split(',')
+                .filter_map(|media_range| {
+                    let mut parts = media_range.split(';');
+                    let media_type = parts.next().unwrap().trim();
+                    if !media_type.eq_ignore_ascii_case(format.to_str().ok()?) {
+                        return None;
+                    }
+
+                    Some(
+                        parts
+                            .find_map(|param| QValue::parse(param.trim()))
+                            .unwrap_or_else(QValue::one),
+                    )
+                })
+                .max();
+
+            if let Some(qvalue) = qvalue.filter(|qvalue| *qvalue > QValue::zero()) {
+                if best.map_or(true, |(_, best)| qvalue > best) {
+                    best = Some((format, qvalue));
+                }
+            }
+        }
+
+        best.map(|(format, _)| format.clone())
+    }
+
+    /// The recognized query parameters of the `uri`, `None` if there is none.
+    pub(crate) fn params(&self, uri: &Uri) -> Option<Vec<(String, String)>> {
+        let params = uri
+            .query()?
+            .split('&')
+            .filter_map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                // query strings are form encoded, so `+` means space
+                let decode = |s: &str| {
+                    percent_decode_str(&s.replace('+', " "))
+                        .decode_utf8()
+                        .ok()
+                        .map(|s| s.into_owned())
+                };
+                let key = decode(key)?;
+                if !self.params.contains(&key) {
+                    return None;
+                }
+
+                Some((key, decode(value)?))
+            })
+            .collect::<Vec<_>>();
+
+        (!params.is_empty()).then_some(params)
+    }
+
+    fn get(&self, key: &Key) -> Option<Transformed> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.tick += 1;
+        let tick = cache.tick;
+        let (transformed, last_used) = cache.entries.get_mut(key)?;
+        *last_used = tick;
+
+        Some(transformed.clone())
+    }
+
+    fn insert(&self, key: Key, transformed: Transformed) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.entries.len() >= self.capacity && !cache.entries.contains_key(&key) {
+            let least_recently_used = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                cache.entries.remove(&least_recently_used);
+            }
+        }
+
+        cache.tick += 1;
+        let tick = cache.tick;
+        cache.entries.insert(key, (transformed, tick));
+    }
+
+    /// Respond with the representation of the opened file, which is read from the start in full,
+    /// the file is checked with [`Transform::is_source`] first.
+    pub(crate) async fn response<FS>(
+        &self,
+        filesystem: &mut FS,
+        output: FileOpened<impl AsyncRead + Unpin>,
+        params: Vec<(String, String)>,
+        accept: Option<HeaderValue>,
+    ) -> io::Result<Response<ResponseBody>>
+    where
+        FS: Filesystem,
+    {
+        let (maybe_file, meta) = match output.extent {
+            FileRequestExtent::Full(file, meta) => (Some(file), meta),
+            FileRequestExtent::Head(meta) => (None, meta),
+        };
+        let is_head = maybe_file.is_none();
+        let format = self.negotiate(accept.as_ref());
+        let key = Key {
+            path: output.path.clone(),
+            params,
+            accept: if self.formats.is_empty() {
+                accept.clone()
+            } else {
+                format.clone()
+            },
+            modified: meta.modified,
+            len: meta.len,
+            etag: meta.etag.clone(),
+        };
+
+        let transformed = match self.get(&key) {
+            Some(transformed) => transformed,
+            None => {
+                let contents = match maybe_file {
+                    Some(file) => self.read_source(file, &meta).await?,
+                    // the `HEAD` response needs the length of the representation
+                    None => {
+                        self.read_source(filesystem.open(&output.path).await?, &meta)
+                            .await?
+                    }
+                };
+                let request = TransformRequest {
+                    path: key.path.clone(),
+                    params: key.params.clone(),
+                    content_type: output.mime_header_value.clone(),
+                    accept,
+                    format,
+                    contents: contents.clone(),
+                };
+
+                match (self.transform)(request).await? {
+                    Some(transformed) => {
+                        self.insert(key, transformed.clone());
+                        transformed
+                    }
+                    None => Transformed::new(contents, output.mime_header_value),
+                }
+            }
+        };
+
+        let mut builder = Response::builder()
+            .header(header::CONTENT_TYPE, transformed.content_type)
+            .header(header::CONTENT_LENGTH, transformed.contents.len())
+            .header(header::VARY, HeaderValue::from_static("accept"));
+        if let Some(last_modified) = output.last_modified {
+            builder = builder.header(header::LAST_MODIFIED, last_modified.header_value());
+        }
+        let body = if is_head {
+            empty_body()
+        } else {
+            body_from_bytes(transformed.contents)
+        };
+
+        Ok(builder.body(body).unwrap())
+    }
+
+    // the file may grow after its metadata is read, so it is read up to the max source size
+    async fn read_source(
+        &self,
+        file: impl AsyncRead + Unpin,
+        meta: &Metadata,
+    ) -> io::Result<Bytes> {
+        let len = meta.len.unwrap_or_default().min(self.max_source_size);
+        let mut contents = Vec::with_capacity(len as usize);
+        file.take(self.max_source_size)
+            .read_to_end(&mut contents)
+            .await?;
+
+        Ok(Bytes::from(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let transform =
+            Transform::new(&["w"], |_| async { Ok(None) }).formats(&["image/avif", "image/webp"]);
+        let negotiate = |accept: &'static str| {
+            transform
+                .negotiate(Some(&HeaderValue::from_static(accept)))
+                .map(|format| format.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(
+            negotiate("image/avif,image/webp,image/*,*/*;q=0.8").unwrap(),
+            "image/avif"
+        );
+        assert_eq!(
+            negotiate("image/webp, image/avif;q=0.5").unwrap(),
+            "image/webp"
+        );
+        assert_eq!(negotiate("IMAGE/WEBP;foo=bar;q=0.9").unwrap(), "image/webp");
+        assert_eq!(
+            negotiate("image/avif;q=0, image/webp").unwrap(),
+            "image/webp"
+        );
+        assert_eq!(negotiate("image/avif;q=0, image/webp;q=0"), None);
+        assert_eq!(negotiate("image/*,*/*"), None);
+        assert_eq!(transform.negotiate(None), None);
+    }
+}
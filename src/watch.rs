@@ -0,0 +1,232 @@
+//! Watch the files changes, to notify the clients through the
+//! [`ServeDir::events`](crate::ServeDir::events) endpoint
+
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::path::{Component, Path};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use http::{header, HeaderMap, HeaderValue, Response};
+use http_body::Body;
+use notify::event::EventKind;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::filter::PathFilter;
+use crate::{json, ResponseBody};
+
+/// The path of the change events endpoint
+pub const EVENTS_PATH: &str = "/__events";
+
+/// The script injected into the HTML pages by
+/// [`ServeDir::inject_reload_script`](crate::ServeDir::inject_reload_script), it reloads the page
+/// on any change event
+pub const RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__events\")\
+    .addEventListener(\"change\", () => location.reload());</script>";
+
+// a slow client will lose the oldest events instead of blocking the watcher
+const EVENTS_CAPACITY: usize = 64;
+
+/// A recursive watcher of a disk directory, the changed paths are relative to the watched
+/// directory.
+///
+/// Clones share the same underlying watcher, it is stopped when the last clone is dropped.
+#[derive(Clone)]
+pub struct FileWatcher {
+    sender: broadcast::Sender<String>,
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl Debug for FileWatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatcher")
+            .field("receivers", &self.sender.receiver_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileWatcher {
+    /// Start to watch the `dir` and all its sub directories.
+    pub fn new<P: AsRef<Path>>(dir: P) -> notify::Result<Self> {
+        let base = dir.as_ref().canonicalize()?;
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        let event_sender = sender.clone();
+        let event_base = base.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Err(_) => return,
+                    Ok(event) => event,
+                };
+
+                if !matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    return;
+                }
+
+                for path in event.paths {
+                    if let Some(path) = relative_path(&event_base, &path) {
+                        // no receiver is fine
+                        let _ = event_sender.send(path);
+                    }
+                }
+            })?;
+
+        watcher.watch(&base, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            sender,
+            _watcher: Arc::new(Mutex::new(watcher)),
+        })
+    }
+
+    /// Receive the changed paths.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.sender.subscribe()
+    }
+
+    /// Build the `text/event-stream` response, every change is sent as a `change` event with the
+    /// changed path:
+    ///
+    /// ```text
+    /// event: change
+    /// data: {"path":"css/index.css"}
+    /// ```
+    ///
+    /// The paths the `filter` doesn't allow, like the dot files, are not sent, so their names
+    /// don't leak.
+    pub(crate) fn event_stream_response(&self, filter: PathFilter) -> Response<ResponseBody> {
+        // keep the watcher alive while the client is connected
+        let state = (self.sender.subscribe(), self.clone(), filter);
+        let stream =
+            futures_util::stream::unfold(state, |(mut receiver, watcher, filter)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(path) if !filter.is_allowed(&path) => continue,
+                        Ok(path) => {
+                            let mut event = String::from("event: change\ndata: {\"path\":");
+                            json::write_str(&mut event, &path);
+                            event.push_str("}\n\n");
+
+                            return Some((Ok(Bytes::from(event)), (receiver, watcher, filter)));
+                        }
+
+                        // the dropped events are not important, the client only needs to know there
+                        // are some changes
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            });
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+            .body(ResponseBody::new(
+                EventStreamBody {
+                    stream: stream.boxed(),
+                }
+                .boxed_unsync(),
+            ))
+            .unwrap()
+    }
+}
+
+/// Insert the [`RELOAD_SCRIPT`] before the last `</body>`, or append it when the page has no
+/// `</body>`.
+pub(crate) fn inject_reload_script(html: &mut Vec<u8>) {
+    const BODY_END: &[u8] = b"</body>";
+
+    let position = html
+        .windows(BODY_END.len())
+        .rposition(|window| window.eq_ignore_ascii_case(BODY_END))
+        .unwrap_or(html.len());
+
+    html.splice(position..position, RELOAD_SCRIPT.bytes());
+}
+
+fn relative_path(base: &Path, path: &Path) -> Option<String> {
+    let mut relative_path = String::new();
+    for component in path.strip_prefix(base).ok()?.components() {
+        match component {
+            Component::Normal(comp) => {
+                if !relative_path.is_empty() {
+                    relative_path.push('/');
+                }
+                relative_path.push_str(&comp.to_string_lossy());
+            }
+            _ => return None,
+        }
+    }
+
+    Some(relative_path)
+}
+
+struct EventStreamBody {
+    stream: BoxStream<'static, io::Result<Bytes>>,
+}
+
+impl Body for EventStreamBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.stream.poll_next_unpin(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inject_before_last_body_end() {
+        let mut html = b"<html><BODY><p>hi</p></body></html>".to_vec();
+        inject_reload_script(&mut html);
+
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            format!("<html><BODY><p>hi</p>{RELOAD_SCRIPT}</body></html>")
+        );
+    }
+
+    #[test]
+    fn inject_without_body_end() {
+        let mut html = b"<p>hi</p>".to_vec();
+        inject_reload_script(&mut html);
+
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            format!("<p>hi</p>{RELOAD_SCRIPT}")
+        );
+    }
+
+    #[test]
+    fn relative_changed_path() {
+        let base = Path::new("/srv/assets");
+
+        assert_eq!(
+            relative_path(base, Path::new("/srv/assets/css/index.css")).as_deref(),
+            Some("css/index.css")
+        );
+        assert_eq!(relative_path(base, Path::new("/srv/other")), None);
+    }
+}